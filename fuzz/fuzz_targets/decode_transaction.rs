@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tiny_rollup::transaction_processor::wire_transaction::WireTransaction;
+
+// The RPC ingress decoder: arbitrary bytes must be rejected, never panic
+fuzz_target!(|data: &[u8]| {
+    let _ = WireTransaction::from_bytes(data.to_vec());
+});
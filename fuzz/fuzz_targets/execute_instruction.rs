@@ -0,0 +1,54 @@
+#![no_main]
+
+use std::sync::{Arc, LazyLock};
+
+use libfuzzer_sys::fuzz_target;
+use solana_sdk::{hash::Hash, signature::{Keypair, Signer}};
+use tiny_rollup::{
+    state_manager::state_manager::{L2Account, StateManager},
+    transaction_processor::{
+        access_policy::AccessPolicy,
+        transaction_processor::{TransactionProcessor, UnknownProgramPolicy},
+    },
+};
+use tiny_rollup_fuzz::{SYSTEM_PROGRAM, fee_payer, instruction_transaction};
+
+struct Harness {
+    runtime: tokio::runtime::Runtime,
+    processor: TransactionProcessor,
+    payer: Keypair,
+    /// Valid until the first block, which the harness never produces
+    blockhash: Hash,
+}
+
+static HARNESS: LazyLock<Harness> = LazyLock::new(|| {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let db_path = std::env::temp_dir().join(format!("tiny-rollup-fuzz-{}", std::process::id()));
+    let state_manager = Arc::new(StateManager::new(db_path.to_str().unwrap(), None, false).unwrap());
+
+    // Fund the payer so well-formed transfers get past the balance check
+    let payer = fee_payer();
+    runtime.block_on(state_manager.update_account(&payer.pubkey(), L2Account {
+        lamports: u64::MAX / 2,
+        data: vec![],
+        owner: SYSTEM_PROGRAM,
+        executable: false,
+        rent_epoch: 0,
+    })).unwrap();
+
+    let blockhash = Hash::new_from_array(state_manager.genesis_hash().unwrap().unwrap());
+    Harness {
+        runtime,
+        processor: TransactionProcessor::new(state_manager, AccessPolicy::default(), UnknownProgramPolicy::Reject),
+        payer,
+        blockhash,
+    }
+});
+
+// Validation, instruction decoding and execution: errors are fine, panics are bugs
+fuzz_target!(|data: &[u8]| {
+    let harness = &*HARNESS;
+    if let Some(tx) = instruction_transaction(&harness.payer, harness.blockhash, data) {
+        let _ = harness.runtime.block_on(harness.processor.process_transaction(&tx));
+    }
+});
@@ -0,0 +1,49 @@
+//! Writes seed inputs for both fuzz targets into `corpus/<target>/`.
+//!
+//! Run with `cargo run --bin build_corpus` from the `fuzz` directory.
+
+use std::{fs, path::Path};
+
+use solana_sdk::hash::Hash;
+use tiny_rollup_fuzz::{fee_payer, instruction_transaction};
+
+fn main() -> std::io::Result<()> {
+    let payer = fee_payer();
+
+    // SystemInstruction::Transfer { lamports: 1000 }
+    let transfer = [&2u32.to_le_bytes()[..], &1_000u64.to_le_bytes()].concat();
+    let mut seeds: Vec<Vec<u8>> = Vec::new();
+
+    // Transfers addressed to the system program key and to index 0
+    for program_id_index in [2u8, 0] {
+        seeds.push([&[0, program_id_index, 2, 0, 1][..], &transfer].concat());
+    }
+    // Transfer data cut short at every length, and a bare instruction tag
+    for len in 0..transfer.len() {
+        seeds.push([&[0, 0, 2, 0, 1][..], &transfer[..len]].concat());
+    }
+    // Missing recipient account and an out-of-range recipient index
+    seeds.push([&[0, 0, 1, 0][..], &transfer].concat());
+    seeds.push([&[3, 0, 2, 0, 200][..], &transfer].concat());
+
+    write_seeds("execute_instruction", &seeds)?;
+
+    // Signed transactions in wire format seed the decoder
+    let wire: Vec<Vec<u8>> = seeds.iter()
+        .filter_map(|input| instruction_transaction(&payer, Hash::default(), input))
+        .map(|tx| bincode::serialize(&tx).expect("transactions always serialize"))
+        .collect();
+    write_seeds("decode_transaction", &wire)?;
+
+    println!("Wrote {} execute_instruction and {} decode_transaction seeds", seeds.len(), wire.len());
+    Ok(())
+}
+
+fn write_seeds(target: &str, seeds: &[Vec<u8>]) -> std::io::Result<()> {
+    let dir = Path::new("corpus").join(target);
+    fs::create_dir_all(&dir)?;
+    for (i, seed) in seeds.iter().enumerate() {
+        fs::write(dir.join(format!("seed-{:03}", i)), seed)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Shared input format for the fuzz targets and the corpus builder.
+//!
+//! `execute_instruction` inputs are laid out as:
+//! `[extra_keys, program_id_index, num_accounts, account_indexes.., data..]`
+//! and are turned into a single-instruction transaction signed by a fixed
+//! fee payer. Indexes are used as-is, so out-of-range ones are exercised too.
+
+use solana_sdk::{
+    hash::Hash,
+    message::{Message, MessageHeader, compiled_instruction::CompiledInstruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+pub const SYSTEM_PROGRAM: Pubkey = Pubkey::from_str_const("11111111111111111111111111111111");
+pub const RECIPIENT: Pubkey = Pubkey::new_from_array([7u8; 32]);
+
+pub fn fee_payer() -> Keypair {
+    Keypair::new_from_array([1u8; 32])
+}
+
+/// Builds a signed transaction against `recent_blockhash` from fuzzer input,
+/// or `None` if the input is too short.
+pub fn instruction_transaction(payer: &Keypair, recent_blockhash: Hash, input: &[u8]) -> Option<Transaction> {
+    let [extra_keys, program_id_index, num_accounts, rest @ ..] = input else {
+        return None;
+    };
+    let num_accounts = (*num_accounts as usize % 8).min(rest.len());
+    let (accounts, data) = rest.split_at(num_accounts);
+
+    let mut account_keys = vec![payer.pubkey(), RECIPIENT, SYSTEM_PROGRAM];
+    account_keys.extend((0..*extra_keys % 4).map(|i| Pubkey::new_from_array([0x10 + i; 32])));
+
+    let message = Message {
+        header: MessageHeader {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 1,
+        },
+        account_keys,
+        recent_blockhash,
+        instructions: vec![CompiledInstruction {
+            program_id_index: *program_id_index,
+            accounts: accounts.to_vec(),
+            data: data.to_vec(),
+        }],
+    };
+
+    let mut tx = Transaction::new_unsigned(message);
+    tx.try_sign(&[payer], recent_blockhash).ok()?;
+    Some(tx)
+}
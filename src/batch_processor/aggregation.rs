@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use crate::data_availability::data_availability::DaPublisher;
+
+/// How sealed batches are committed to L1, trading finality latency for L1
+/// cost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum CommitmentStrategy {
+    /// Post every batch's full commitment (`Commit` or `CommitChunks`) as
+    /// soon as it is sealed
+    #[default]
+    EveryBatch,
+    /// Publish each batch's commitment to an object store and post one
+    /// `CommitAggregate` root over several batches
+    Aggregated,
+}
+
+/// Settings of the aggregated commitment strategy. A batch waits for at most
+/// `max_wait` for the rest of its aggregate, so finality is delayed by no
+/// more than that.
+pub struct Aggregation {
+    /// Batches committed per aggregate
+    pub batches: usize,
+    pub max_wait: Duration,
+    /// Where each batch's commitment is published before the aggregate
+    /// covering it is posted
+    pub publisher: DaPublisher,
+}
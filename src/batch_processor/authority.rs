@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer, read_keypair, read_keypair_file},
+};
+
+/// Environment variable holding an authority keypair spec, used when no
+/// `--authority-keypair` is given, so deployments can inject the key as a
+/// secret rather than a file
+pub const AUTHORITY_KEYPAIR_ENV: &str = "TINY_ROLLUP_AUTHORITY_KEYPAIR";
+
+/// A batch authority key and the first batch it may sign.
+pub struct AuthorityKey {
+    pub keypair: Arc<Keypair>,
+    pub activation_batch: u64,
+}
+
+impl AuthorityKey {
+    /// Parses `KEYPAIR` or `KEYPAIR@BATCH`, where KEYPAIR is the path of a
+    /// Solana keypair file or, as in `AUTHORITY_KEYPAIR_ENV`, the file's JSON
+    /// byte array itself. Without a height the key is active from genesis.
+    pub fn from_spec(spec: &str) -> anyhow::Result<Self> {
+        let (keypair, activation_batch) = match spec.rsplit_once('@') {
+            Some((keypair, height)) => (keypair, height.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid activation height after {}: {}", redact(keypair), e))?),
+            None => (spec, 0),
+        };
+
+        let keypair = if keypair.trim_start().starts_with('[') {
+            read_keypair(&mut keypair.as_bytes())
+                .map_err(|e| anyhow::anyhow!("Invalid authority keypair bytes: {}", e))?
+        } else {
+            read_keypair_file(keypair)
+                .map_err(|e| anyhow::anyhow!("Failed to read authority keypair {}: {}", keypair, e))?
+        };
+
+        Ok(Self { keypair: Arc::new(keypair), activation_batch })
+    }
+}
+
+/// A keypair spec as it can be logged: paths as is, key bytes not at all.
+fn redact(keypair: &str) -> &str {
+    if keypair.trim_start().starts_with('[') { "the keypair bytes" } else { keypair }
+}
+
+/// Ordered set of authority keys. The active key only ever moves forward,
+/// either when a batch reaches the next key's activation height or when an
+/// operator rotates early.
+pub struct AuthoritySchedule {
+    keys: Vec<AuthorityKey>,
+    current: usize,
+}
+
+impl AuthoritySchedule {
+    pub fn new(mut keys: Vec<AuthorityKey>) -> anyhow::Result<Self> {
+        if keys.is_empty() {
+            anyhow::bail!("At least one authority key is required");
+        }
+        keys.sort_by_key(|k| k.activation_batch);
+
+        Ok(Self { keys, current: 0 })
+    }
+
+    /// A single throwaway key, for local development without configured keys.
+    pub fn ephemeral() -> Self {
+        Self {
+            keys: vec![AuthorityKey { keypair: Arc::new(Keypair::new()), activation_batch: 0 }],
+            current: 0,
+        }
+    }
+
+    /// Key that signs submissions for `batch_id`.
+    pub fn for_batch(&mut self, batch_id: u64) -> Arc<Keypair> {
+        if let Some(scheduled) = self.keys.iter().rposition(|k| k.activation_batch <= batch_id) {
+            self.current = self.current.max(scheduled);
+        }
+        self.keys[self.current].keypair.clone()
+    }
+
+    /// Key scheduled to sign `batch_id`, not counting early rotations.
+    pub fn scheduled(&self, batch_id: u64) -> Pubkey {
+        let scheduled = self.keys.iter().rposition(|k| k.activation_batch <= batch_id).unwrap_or(0);
+        self.keys[scheduled].keypair.pubkey()
+    }
+
+    pub fn current(&self) -> Pubkey {
+        self.keys[self.current].keypair.pubkey()
+    }
+
+    /// Switches to the next configured key ahead of its activation height.
+    pub fn rotate(&mut self) -> anyhow::Result<Pubkey> {
+        if self.current + 1 >= self.keys.len() {
+            anyhow::bail!("No authority key configured after {}", self.current());
+        }
+        self.current += 1;
+        Ok(self.current())
+    }
+}
@@ -1,54 +1,1047 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use solana_client::{
     nonblocking::rpc_client::RpcClient,
     rpc_config::RpcSendTransactionConfig
 };
 use solana_commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_compute_budget_interface::ComputeBudgetInstruction;
+use solana_packet::PACKET_DATA_SIZE;
 use solana_sdk::{
-    pubkey::Pubkey, 
-    signature::{Keypair, Signer},
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
     transaction::Transaction
 };
-use tokio::sync::mpsc;
+use solana_sdk_ids::system_program;
+use tokio::sync::{mpsc, Notify};
+
+use crate::{
+    batch_processor::{
+        aggregation::Aggregation,
+        authority::AuthoritySchedule,
+        compaction::{compact_batch, CompactedBatch, NetTransfer},
+        compression::{decompress, BatchCompression},
+        cosigning::CosignerSet,
+        health::L1Health,
+    },
+    chaos::chaos::{inject, FaultPoint},
+    clock::clock::{Clock, SystemClock},
+    dead_letters::dead_letters::DeadLetterReason,
+    epochs::epochs::{close_epoch, EpochConfig},
+    events::events::RollupEvent,
+    l1_watcher::l1_watcher::L1Watcher,
+    metrics::metrics,
+    notifications::notifications::Notifier,
+    state_manager::{state_hasher::merkle_root, state_manager::StateManager},
+    transaction_processor::wire_transaction::WireTransaction,
+    withdrawals::withdrawals::withdrawal_root,
+};
+
+const MAX_SUBMIT_ATTEMPTS: u32 = 3;
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_CONFIRMATION_POLLS: u32 = 30;
+const BLOCKHASH_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+/// L1 blockhashes live for ~60s; older cached ones are refetched so a
+/// submission always has most of that window left to land
+const BLOCKHASH_MAX_AGE: Duration = Duration::from_secs(20);
+/// How often L1 reachability and the authority balance are probed, so
+/// failures are noticed even while no batches are being posted
+const HEALTH_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Bytes a BatchCommitment adds on top of its framed transactions payload:
+/// genesis hash, batch id, pre- and post-state roots, withdrawal root,
+/// payload length and the lengths of the (possibly empty) netted transfer
+/// vectors.
+pub const BATCH_COMMITMENT_OVERHEAD: usize = 32 + 8 + 32 + 32 + 32 + 8 + 8 + 8;
+
+/// Bytes the transactions payload adds on top of the serialized
+/// transactions, before compression: the transaction vector's length
+pub const TRANSACTIONS_PAYLOAD_OVERHEAD: usize = 8;
+
+/// Seed, with the little-endian batch id, of the rollup program account
+/// each batch's commitment is recorded in
+pub const COMMITMENT_ACCOUNT_SEED: &[u8] = b"commitment";
+
+/// Commitment bytes carried by each chunk of a batch too large for one L1
+/// transaction, leaving room for the signature, account keys, compute price
+/// and chunk header within the packet size
+const CHUNK_LEN: usize = 900;
+
+/// Version of the rollup protocol: the batch commitment format posted to L1
+/// and the execution rules batches are replayed with.
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Record of everything that happened while posting a batch to L1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchReport {
+    pub batch_id: u64,
+    pub transaction_count: usize,
+    pub attempts: u32,
+    pub signatures: Vec<String>,
+    pub compute_unit_price: u64,
+    pub final_slot: Option<u64>,
+    pub total_cost_lamports: u64,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+/// A sealed batch as kept locally, with the state root observed when it was
+/// sealed, so it can be re-executed and checked later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredBatch {
+    pub transactions: Vec<WireTransaction>,
+    pub state_root: [u8; 32],
+}
+
+/// What is kept about a batch's posted commitment even once the batch
+/// itself is pruned: enough to verify and rebuild it from a DA source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostedCommitment {
+    /// sha256 of the serialized BatchCommitment
+    pub digest: [u8; 32],
+    pub state_root: [u8; 32],
+    /// Transactions compaction replaced with net transfers; they are not in
+    /// the commitment, so a batch with any can't be rebuilt from DA
+    pub netted_transactions: usize,
+}
+
+/// Instruction data posted to the rollup program for every batch. The
+/// genesis hash and batch id lead the payload, so the signed instruction is
+/// only valid for one deployment at one position: the program rejects a
+/// genesis hash other than the one it was initialized with, and any batch id
+/// but the next one it expects. The state roots are what fraud and validity
+/// proofs check a batch's execution against; the program records them in
+/// the batch's commitment account.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchCommitment {
+    pub genesis_hash: [u8; 32],
+    pub batch_id: u64,
+    /// State root the batch executed on top of, i.e. the previous batch's
+    /// post-state root
+    pub pre_state_root: [u8; 32],
+    /// State root once the batch's transactions, deposits and withdrawals
+    /// are applied
+    pub post_state_root: [u8; 32],
+    pub withdrawal_root: [u8; 32],
+    /// The transactions as a bincode `Vec<Transaction>`, compressed and
+    /// framed by `BatchCompression`
+    pub transactions: Vec<u8>,
+    /// Net effect of the opposing transfers removed by compaction
+    pub net_transfers: Vec<NetTransfer>,
+    pub netted_signatures: Vec<Signature>,
+}
+
+/// Instruction data posted to the rollup program. Co-signatures go in the
+/// transaction of whichever instruction commits the batch.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum RollupInstruction {
+    /// A serialized BatchCommitment that fits in one L1 transaction
+    Commit { commitment: Vec<u8> },
+    /// Piece `index` of `count` of a serialized BatchCommitment too large
+    /// for one transaction, buffered by the program until it is committed
+    WriteChunk { batch_id: u64, index: u16, count: u16, data: Vec<u8> },
+    /// Commits a batch from its `count` buffered chunks, which must join
+    /// into `len` bytes hashing (sha256) to `digest`
+    CommitChunks { batch_id: u64, count: u16, len: u32, digest: [u8; 32] },
+    /// Commits `count` consecutive batches from `first_batch_id` at once.
+    /// `root` is the merkle root over the sha256 digests of their serialized
+    /// BatchCommitments, which are published to an object store rather than
+    /// posted to L1.
+    CommitAggregate { genesis_hash: [u8; 32], first_batch_id: u64, count: u32, root: [u8; 32] },
+}
+
+/// Confirmed batches waiting for their L1 slot to be finalized.
+#[derive(Debug, Default)]
+struct Finality {
+    /// Where each awaiting batch's commitment stands, keyed by batch id
+    awaiting: BTreeMap<u64, Awaiting>,
+    last_finalized_batch: Option<u64>,
+}
+
+/// Where an awaiting batch's commitment stands on L1.
+#[derive(Debug, Clone, Copy)]
+enum Awaiting {
+    Landed(Landed),
+    /// Dropped from L1 and not posted again yet
+    Repost,
+}
+
+/// A confirmed commitment transaction and the slot it landed in.
+#[derive(Debug, Clone, Copy)]
+struct Landed {
+    slot: u64,
+    signature: Signature,
+}
+
+/// What L1 says about a commitment whose slot is finalized.
+enum CommitmentStatus {
+    Finalized,
+    /// Known to the RPC node, but not finalized there yet
+    Pending,
+    /// Not on the finalized chain: the fork it landed on was abandoned, or
+    /// it failed on the one that was kept
+    Dropped,
+}
+
+impl BatchCommitment {
+    /// The batch's transactions, decoded from the posted payload.
+    pub fn decode_transactions(&self) -> anyhow::Result<Vec<WireTransaction>> {
+        let transactions: Vec<Transaction> = bincode::deserialize(&decompress(&self.transactions)?)?;
+        transactions.iter()
+            .map(|tx| WireTransaction::from_bytes(bincode::serialize(tx)?))
+            .collect()
+    }
+}
+
+/// A batch whose commitment is published and waiting for the aggregate
+/// covering it to be posted.
+struct StagedBatch {
+    stored: StoredBatch,
+    digest: [u8; 32],
+    report: BatchReport,
+    started: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CachedBlockhash {
+    hash: Hash,
+    fetched_at: Instant,
+}
 
 pub struct BatchProcessor {
     solana_client: RpcClient,
     rollup_program_id: Pubkey,
-    authority: Keypair
+    authorities: RwLock<AuthoritySchedule>,
+    state_manager: Arc<StateManager>,
+    compute_unit_price: u64,
+    epochs: EpochConfig,
+    compact_transfers: bool,
+    compression: BatchCompression,
+    blockhash: RwLock<Option<CachedBlockhash>>,
+    health: L1Health,
+    cosigners: Option<CosignerSet>,
+    aggregation: Option<Aggregation>,
+    l1_watcher: Option<Arc<L1Watcher>>,
+    notifier: Option<Arc<Notifier>>,
+    finality: Mutex<Finality>,
+    /// Wakes `track_finality` when a batch starts awaiting finality
+    landed: Notify,
+    clock: Arc<dyn Clock>
 }
 
 impl BatchProcessor {
-    pub fn new(solana_rpc_url: String) -> Self {
+    pub fn new(
+        solana_rpc_url: String,
+        rollup_program_id: Pubkey,
+        state_manager: Arc<StateManager>,
+        authorities: AuthoritySchedule,
+        compute_unit_price: u64,
+        epochs: EpochConfig,
+        compact_transfers: bool,
+        health: L1Health
+    ) -> Self {
         Self {
             solana_client: RpcClient::new_with_commitment(solana_rpc_url, CommitmentConfig::confirmed()),
-            rollup_program_id: Pubkey::new_unique(), // Rollup program id
-            authority: Keypair::new(), // Load from config
+            rollup_program_id,
+            authorities: RwLock::new(authorities),
+            state_manager,
+            compute_unit_price,
+            epochs,
+            compact_transfers,
+            compression: BatchCompression::default(),
+            blockhash: RwLock::new(None),
+            health,
+            cosigners: None,
+            aggregation: None,
+            l1_watcher: None,
+            notifier: None,
+            finality: Mutex::new(Finality::default()),
+            landed: Notify::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Compresses posted batches with `compression` instead of zstd at the
+    /// default level.
+    pub fn with_compression(mut self, compression: BatchCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Requires a threshold of co-signatures on every batch before it is sent.
+    pub fn with_cosigners(mut self, cosigners: CosignerSet) -> Self {
+        self.cosigners = Some(cosigners);
+        self
+    }
+
+    /// Publishes batch commitments through the aggregation's publisher and
+    /// posts one aggregate root for several batches, instead of posting
+    /// every batch to L1.
+    pub fn with_aggregation(mut self, aggregation: Aggregation) -> Self {
+        self.aggregation = Some(aggregation);
+        self
+    }
+
+    /// Only finalizes batches once the L1 slot they landed in is finalized,
+    /// as reported by `watcher`. Without one, a batch counts as finalized as
+    /// soon as it is confirmed.
+    pub fn with_l1_watcher(mut self, watcher: Arc<L1Watcher>) -> Self {
+        self.l1_watcher = Some(watcher);
+        self
+    }
+
+    /// Reports failed submissions and the authority's balance to `notifier`,
+    /// which alerts the operator once they cross its thresholds.
+    pub fn with_notifier(mut self, notifier: Arc<Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Times how long staged batches wait for their aggregate by `clock`
+    /// instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn l1_watcher(&self) -> Option<&L1Watcher> {
+        self.l1_watcher.as_deref()
+    }
+
+    /// Latest batch whose L1 transaction is finalized.
+    pub fn last_finalized_batch(&self) -> Option<u64> {
+        self.finality.lock().unwrap().last_finalized_batch
+    }
+
+    /// L1 program batches are posted to.
+    pub fn rollup_program_id(&self) -> Pubkey {
+        self.rollup_program_id
+    }
+
+    /// Key signing the next submission.
+    pub fn authority(&self) -> Pubkey {
+        self.authorities.read().unwrap().current()
+    }
+
+    pub async fn authority_balance(&self) -> anyhow::Result<u64> {
+        Ok(self.solana_client.get_balance(&self.authority()).await?)
+    }
+
+    pub fn health(&self) -> &L1Health {
+        &self.health
+    }
+
+    /// Periodically checks that L1 is reachable and the authority can pay
+    /// for submissions, feeding failures into the node's health. A passing
+    /// check only clears failures it reported itself.
+    pub async fn monitor_health(&self) {
+        let mut interval = tokio::time::interval(HEALTH_PROBE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let balance = self.authority_balance().await;
+            if let (Some(notifier), Ok(lamports)) = (&self.notifier, &balance) {
+                notifier.record_authority_balance(self.authority(), *lamports);
+            }
+            match balance {
+                Ok(0) => self.health.record_failure(format!("Authority {} is unfunded", self.authority())),
+                Ok(_) => self.health.record_probe_success(),
+                Err(e) => self.health.record_failure(format!("L1 RPC unreachable: {}", e)),
+            }
+        }
+    }
+
+    /// Moves to the next configured authority key. Submissions already in
+    /// flight keep the key they were signed with until they confirm.
+    pub fn rotate_authority(&self) -> anyhow::Result<Pubkey> {
+        self.authorities.write().unwrap().rotate()
+    }
+
+    /// Keeps the cached L1 blockhash fresh so submissions don't wait on it.
+    pub async fn refresh_blockhash(&self) {
+        let mut interval = tokio::time::interval(BLOCKHASH_REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.fetch_blockhash().await {
+                eprintln!("Failed to refresh L1 blockhash: {}", e);
+            }
+        }
+    }
+
+    async fn fetch_blockhash(&self) -> anyhow::Result<Hash> {
+        let hash = self.solana_client.get_latest_blockhash().await?;
+        *self.blockhash.write().unwrap() = Some(CachedBlockhash { hash, fetched_at: Instant::now() });
+
+        Ok(hash)
+    }
+
+    /// Cached blockhash if it's young enough, otherwise a freshly fetched one.
+    async fn recent_blockhash(&self) -> anyhow::Result<Hash> {
+        let cached = *self.blockhash.read().unwrap();
+        match cached {
+            Some(cached) if cached.fetched_at.elapsed() < BLOCKHASH_MAX_AGE => Ok(cached.hash),
+            _ => self.fetch_blockhash().await,
+        }
+    }
+
+    /// Forces the next submission onto a new blockhash, so a retry isn't the
+    /// same transaction again.
+    fn invalidate_blockhash(&self, used: &Hash) {
+        let mut blockhash = self.blockhash.write().unwrap();
+        if blockhash.is_some_and(|cached| cached.hash == *used) {
+            *blockhash = None;
+        }
+    }
+
+    pub async fn process_batches(&self, mut batch_reciever: mpsc::Receiver<(u64, StoredBatch)>) {
+        if let Some(aggregation) = &self.aggregation {
+            return self.process_aggregated(aggregation, batch_reciever).await;
+        }
+
+        while let Some((batch_id, stored)) = batch_reciever.recv().await {
+            metrics::set_gauge("pipeline_commit_queue_depth", batch_reciever.len() as u64);
+            metrics::increment("pipeline_commit_processed");
+
+            let report = self.submit_batch_to_l1(batch_id, &stored).await;
+            self.finish_batch(&stored, report).await;
+        }
+    }
+
+    /// Publishes each batch as it arrives and posts an aggregate once
+    /// `aggregation.batches` are staged, the oldest has waited
+    /// `aggregation.max_wait`, or the channel closes.
+    async fn process_aggregated(
+        &self,
+        aggregation: &Aggregation,
+        mut batch_reciever: mpsc::Receiver<(u64, StoredBatch)>
+    ) {
+        let mut staged: Vec<StagedBatch> = Vec::new();
+        loop {
+            let next = match staged.first() {
+                Some(oldest) => {
+                    let deadline = oldest.started + aggregation.max_wait;
+                    tokio::select! {
+                        next = batch_reciever.recv() => next,
+                        _ = self.clock.sleep_until(deadline) => {
+                            self.post_aggregate(std::mem::take(&mut staged)).await;
+                            continue;
+                        }
+                    }
+                }
+                None => batch_reciever.recv().await,
+            };
+            let Some((batch_id, stored)) = next else {
+                break;
+            };
+            metrics::set_gauge("pipeline_commit_queue_depth", batch_reciever.len() as u64);
+            metrics::increment("pipeline_commit_processed");
+
+            match self.stage_batch(aggregation, batch_id, stored).await {
+                Ok(batch) => staged.push(batch),
+                Err((stored, report)) => {
+                    // Aggregates cover consecutive batches, so the ones
+                    // before the failed batch go out on their own
+                    if !staged.is_empty() {
+                        self.post_aggregate(std::mem::take(&mut staged)).await;
+                    }
+                    self.finish_batch(&stored, report).await;
+                }
+            }
+            if staged.len() >= aggregation.batches {
+                self.post_aggregate(std::mem::take(&mut staged)).await;
+            }
+        }
+
+        if !staged.is_empty() {
+            self.post_aggregate(staged).await;
+        }
+    }
+
+    /// Records the outcome of posting a batch and runs everything that
+    /// follows it: dead letters, events, finality and epoch closing.
+    async fn finish_batch(&self, stored: &StoredBatch, report: BatchReport) {
+        let batch_id = report.batch_id;
+        self.report_outcome(&report);
+        if let Some(e) = &report.error {
+            self.record_dead_letters(batch_id, e, &stored.transactions);
+        }
+        self.store_report(&report);
+        self.await_finality(&report);
+
+        if self.epochs.is_epoch_end(batch_id) {
+            let epoch = self.epochs.epoch(batch_id);
+            let anchor = report.final_slot.and(report.signatures.last().cloned());
+            match close_epoch(&self.state_manager, &self.epochs, epoch, anchor).await {
+                Ok(record) => println!(
+                    "Closed epoch {} at {}: collected {} lamports rent from {} accounts, reaped {} empty accounts",
+                    epoch,
+                    hex::encode(record.checkpoint_root),
+                    record.rent_collected,
+                    record.accounts_charged,
+                    record.accounts_reaped.unwrap_or(0)
+                ),
+                Err(e) => eprintln!("Failed to close epoch {}: {}", epoch, e),
+            }
+            self.migrate_old_epochs(epoch);
+            self.prune_old_epochs(epoch);
         }
     }
 
-    pub async fn process_batches(&self, mut batch_reciever: mpsc::Receiver<Vec<Transaction>>) {
-        while let Some(batch) = batch_reciever.recv().await {
-            if let Err(e) = self.submit_batch_to_l1(batch).await {
-                eprint!("Failed to submit batch to L1: {}", e)
+    /// Reports the outcome of posting a batch to health, the notifier and
+    /// subscribers.
+    fn report_outcome(&self, report: &BatchReport) {
+        let batch_id = report.batch_id;
+        match &report.error {
+            Some(e) => {
+                eprintln!("Failed to submit batch {} to L1: {}", batch_id, e);
+                self.health.record_batch_failure(format!("Batch {} failed: {}", batch_id, e));
+                if let Some(notifier) = &self.notifier {
+                    notifier.record_batch_failure(batch_id, e);
+                }
+            }
+            None => {
+                self.health.record_success();
+                if let Some(notifier) = &self.notifier {
+                    notifier.record_batch_success();
+                }
+            }
+        }
+        self.state_manager.events().publish(|| RollupEvent::BatchSubmitted { report: report.clone() });
+    }
+
+    fn store_report(&self, report: &BatchReport) {
+        if let Err(e) = self.state_manager.put_batch_report(report) {
+            eprintln!("Failed to persist report for batch {}: {}", report.batch_id, e);
+        }
+    }
+
+    /// Picks finality up where the last run left it: batches posted after
+    /// the last one finalized wait for their commitment to be finalized
+    /// again, as reported by their stored reports.
+    pub fn restore_finality(&self) -> anyhow::Result<()> {
+        // Databases from before the finalized batch was kept start afresh
+        let Some(last_finalized_batch) = self.state_manager.finalized_batch()? else {
+            return Ok(());
+        };
+
+        self.finality.lock().unwrap().last_finalized_batch = Some(last_finalized_batch);
+        for batch_id in last_finalized_batch + 1..self.state_manager.batch_count()? {
+            if let Some(report) = self.state_manager.get_batch_report(batch_id)? {
+                self.await_finality(&report);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Advances the finalized view as the L1 watcher reports newly finalized
+    /// slots and batches land. The only caller of `finalize_through`, so a
+    /// dropped commitment is posted again once.
+    pub async fn track_finality(&self) {
+        let Some(watcher) = &self.l1_watcher else {
+            return;
+        };
+
+        let mut finalized = watcher.subscribe();
+        loop {
+            tokio::select! {
+                changed = finalized.changed() => if changed.is_err() {
+                    return;
+                },
+                _ = self.landed.notified() => {}
+            }
+            let latest = *finalized.borrow_and_update();
+            if let Some(latest) = latest {
+                self.finalize_through(latest.slot).await;
+            }
+        }
+    }
+
+    /// Waits for the commitment `report` records as landed to be finalized,
+    /// or finalizes it right away without an L1 watcher.
+    fn await_finality(&self, report: &BatchReport) {
+        let Some(slot) = report.final_slot else {
+            return;
+        };
+        let mut finality = self.finality.lock().unwrap();
+        if self.l1_watcher.is_none() {
+            self.finalize_batch(&mut finality, report.batch_id);
+            return;
+        }
+
+        match report.signatures.last().and_then(|signature| signature.parse().ok()) {
+            Some(signature) => {
+                finality.awaiting.insert(report.batch_id, Awaiting::Landed(Landed { slot, signature }));
+                self.landed.notify_one();
+            }
+            None => eprintln!("Batch {} landed without a commitment signature to finalize", report.batch_id),
+        }
+    }
+
+    /// Finalizes awaiting batches in order, up to the first one that landed
+    /// after `finalized_slot` or isn't finalized yet. A commitment's slot
+    /// being finalized doesn't mean it is: the fork it landed on may have
+    /// been abandoned, so its signature is checked at finalized commitment,
+    /// and a dropped one is posted again. No batch after a dropped one is
+    /// finalized until it has been.
+    async fn finalize_through(&self, finalized_slot: u64) {
+        loop {
+            let next = self.finality.lock().unwrap().awaiting
+                .first_key_value()
+                .map(|(batch_id, awaiting)| (*batch_id, *awaiting));
+            let Some((batch_id, awaiting)) = next else {
+                return;
+            };
+            let landed = match awaiting {
+                Awaiting::Landed(landed) => landed,
+                // Posted again, it lands after `finalized_slot` if at all
+                Awaiting::Repost => return self.repost(batch_id).await,
+            };
+            if landed.slot > finalized_slot {
+                return;
+            }
+
+            match self.commitment_status(&landed.signature).await {
+                Ok(CommitmentStatus::Finalized) => {
+                    let mut finality = self.finality.lock().unwrap();
+                    finality.awaiting.remove(&batch_id);
+                    self.finalize_batch(&mut finality, batch_id);
+                }
+                Ok(CommitmentStatus::Pending) => return,
+                Ok(CommitmentStatus::Dropped) => {
+                    eprintln!("⚠️  Batch {} commitment is not on the finalized L1 chain; posting it again", batch_id);
+                    self.finality.lock().unwrap().awaiting.insert(batch_id, Awaiting::Repost);
+                    return self.repost(batch_id).await;
+                }
+                Err(e) => {
+                    eprintln!("Failed to check batch {} commitment {}: {}", batch_id, landed.signature, e);
+                    return;
+                }
             }
         }
     }
 
-    async fn submit_batch_to_l1(&self, batch: Vec<Transaction>) -> anyhow::Result<()> {
-        let batch_data = self.compress_batch(&batch)?;
+    async fn commitment_status(&self, signature: &Signature) -> anyhow::Result<CommitmentStatus> {
+        let status = self.solana_client.get_signature_statuses_with_history(&[*signature]).await?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
 
-        let instruction = solana_sdk::instruction::Instruction::new_with_bytes(
-            self.rollup_program_id,
-            &batch_data,
-            vec![] // Account metas for rollup program
+        Ok(match status {
+            Some(status) if status.err.is_none() && status.satisfies_commitment(CommitmentConfig::finalized()) => {
+                CommitmentStatus::Finalized
+            }
+            Some(status) if status.err.is_none() => CommitmentStatus::Pending,
+            _ => CommitmentStatus::Dropped,
+        })
+    }
+
+    /// Posts `batch_id` again after its commitment was dropped from L1, and
+    /// waits for the new one to be finalized instead. A batch that fails to
+    /// post stays marked for reposting, and is tried again as finality next
+    /// advances. Its stored report keeps the dropped commitment, so a restart
+    /// finds it dropped again. Its transactions are already applied, so
+    /// they aren't dead-lettered.
+    async fn repost(&self, batch_id: u64) {
+        metrics::increment("batches_reposted");
+        let stored = match self.state_manager.get_batch(batch_id) {
+            Ok(Some(stored)) => stored,
+            Ok(None) => {
+                eprintln!("Batch {} is no longer stored and can't be posted again", batch_id);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Failed to load batch {} to post it again: {}", batch_id, e);
+                return;
+            }
+        };
+        let report = self.submit_batch_to_l1(batch_id, &stored).await;
+        self.report_outcome(&report);
+        if report.error.is_none() {
+            self.store_report(&report);
+            self.await_finality(&report);
+        }
+    }
+
+    fn finalize_batch(&self, finality: &mut Finality, batch_id: u64) {
+        if let Err(e) = self.state_manager.finalize_snapshot(batch_id) {
+            eprintln!("Failed to forget pre-images of finalized batch {}: {}", batch_id, e);
+        }
+        finality.last_finalized_batch = Some(batch_id);
+        if let Err(e) = self.state_manager.set_finalized_batch(batch_id) {
+            eprintln!("Failed to persist finalized batch {}: {}", batch_id, e);
+        }
+        self.state_manager.events().publish(|| RollupEvent::BatchFinalized { batch_id });
+    }
+
+    fn record_dead_letters(&self, batch_id: u64, error: &str, transactions: &[WireTransaction]) {
+        for tx in transactions {
+            let reason = DeadLetterReason::BatchNotPosted { batch_id, error: error.to_string() };
+            if let Err(e) = self.state_manager.put_dead_letter(reason, tx) {
+                eprintln!("Failed to record dead letter for batch {}: {}", batch_id, e);
+            }
+        }
+    }
+
+    /// Moves batches older than the last `hot_epochs` epochs (counting the
+    /// one just closed) to cold storage, if any is attached.
+    fn migrate_old_epochs(&self, closed_epoch: u64) {
+        let Some(first_cold_epoch) = (closed_epoch + 1).checked_sub(self.epochs.hot_epochs) else {
+            return;
+        };
+
+        let before_batch = self.epochs.first_batch(first_cold_epoch);
+        match self.state_manager.migrate_to_cold(before_batch) {
+            Ok(0) => {}
+            Ok(moved) => println!("Moved {} batches before batch {} to cold storage", moved, before_batch),
+            Err(e) => eprintln!("Failed to move batches before {} to cold storage: {}", before_batch, e),
+        }
+    }
+
+    /// Deletes local data of batches older than the last
+    /// `prune_batches_after_epochs` epochs that can be fetched back from DA.
+    fn prune_old_epochs(&self, closed_epoch: u64) {
+        let Some(first_kept_epoch) = self.epochs.prune_batches_after_epochs
+            .and_then(|kept| (closed_epoch + 1).checked_sub(kept))
+        else {
+            return;
+        };
+
+        let before_batch = self.epochs.first_batch(first_kept_epoch);
+        match self.state_manager.prune_batches(before_batch) {
+            Ok(0) => {}
+            Ok(pruned) => println!("Pruned {} batches before batch {}", pruned, before_batch),
+            Err(e) => eprintln!("Failed to prune batches before {}: {}", before_batch, e),
+        }
+    }
+
+    fn new_report(&self, batch_id: u64, batch: &StoredBatch) -> BatchReport {
+        BatchReport {
+            batch_id,
+            transaction_count: batch.transactions.len(),
+            attempts: 0,
+            signatures: vec![],
+            compute_unit_price: self.compute_unit_price,
+            final_slot: None,
+            total_cost_lamports: 0,
+            duration_ms: 0,
+            error: None,
+        }
+    }
+
+    async fn submit_batch_to_l1(&self, batch_id: u64, batch: &StoredBatch) -> BatchReport {
+        let started = Instant::now();
+        let mut report = self.new_report(batch_id, batch);
+
+        if let Err(e) = self.try_submit_batch(batch, &mut report).await {
+            report.error = Some(e.to_string());
+        }
+        report.duration_ms = started.elapsed().as_millis() as u64;
+
+        report
+    }
+
+    /// Builds the batch's commitment and records it as posted, returning the
+    /// serialized commitment and its digest.
+    fn prepare_commitment(&self, batch_id: u64, batch: &StoredBatch) -> anyhow::Result<(Vec<u8>, [u8; 32])> {
+        // Sealed with the block; blocks from before withdrawals were sealed have none
+        let withdrawals = self.state_manager.get_batch_withdrawals(batch_id)?.unwrap_or_default();
+
+        let compacted = if self.compact_transfers {
+            compact_batch(&batch.transactions)
+        } else {
+            CompactedBatch::uncompacted(&batch.transactions)
+        };
+        if !compacted.netted_signatures.is_empty() {
+            println!(
+                "Batch {}: netted {} transfers into {}",
+                batch_id, compacted.netted_signatures.len(), compacted.net_transfers.len()
+            );
+        }
+
+        let commitment = BatchCommitment {
+            genesis_hash: self.genesis_hash()?,
+            batch_id,
+            pre_state_root: self.state_manager.pre_state_root(batch_id)?,
+            post_state_root: batch.state_root,
+            withdrawal_root: withdrawal_root(self.state_manager.hasher(), &withdrawals)?,
+            transactions: self.compress_batch(&compacted.transactions)?,
+            net_transfers: compacted.net_transfers,
+            netted_signatures: compacted.netted_signatures,
+        };
+        let batch_data = bincode::serialize(&commitment)?;
+        let digest: [u8; 32] = Sha256::digest(&batch_data).into();
+        self.state_manager.put_posted_commitment(batch_id, &PostedCommitment {
+            digest,
+            state_root: batch.state_root,
+            netted_transactions: commitment.netted_signatures.len(),
+        })?;
+
+        Ok((batch_data, digest))
+    }
+
+    fn genesis_hash(&self) -> anyhow::Result<[u8; 32]> {
+        self.state_manager.genesis_hash()?
+            .ok_or_else(|| anyhow::anyhow!("State database has no genesis hash"))
+    }
+
+    async fn try_submit_batch(&self, batch: &StoredBatch, report: &mut BatchReport) -> anyhow::Result<()> {
+        let (batch_data, digest) = self.prepare_commitment(report.batch_id, batch)?;
+
+        // Collected once; the signatures don't depend on the blockhash
+        let cosignatures = match &self.cosigners {
+            Some(cosigners) => cosigners.collect(report.batch_id, &batch_data).await?,
+            None => vec![],
+        };
+
+        let single = bincode::serialize(&RollupInstruction::Commit { commitment: batch_data.clone() })?;
+        let commitment_account = Some(commitment_account(&self.rollup_program_id, report.batch_id));
+        let (chunks, commit) = if self.transaction_size(&single, commitment_account, &cosignatures)? <= PACKET_DATA_SIZE {
+            (vec![], single)
+        } else {
+            let chunks = chunk_commitment(report.batch_id, &batch_data)?;
+            let commit = bincode::serialize(&RollupInstruction::CommitChunks {
+                batch_id: report.batch_id,
+                count: chunks.len() as u16,
+                len: batch_data.len() as u32,
+                digest,
+            })?;
+            println!("Batch {}: posting {} bytes in {} chunks", report.batch_id, batch_data.len(), chunks.len());
+            (chunks, commit)
+        };
+
+        // Chunks that landed aren't written again on a retry
+        let mut written = 0;
+        let mut last_error = None;
+        while report.attempts < MAX_SUBMIT_ATTEMPTS {
+            report.attempts += 1;
+
+            match self.post_commitment(&chunks, &mut written, &commit, commitment_account, &cosignatures, report).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    eprintln!("Batch {} attempt {} failed: {}", report.batch_id, report.attempts, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Batch was never submitted")))
+    }
+
+    /// Writes the chunks from `written` on, then sends the commit.
+    async fn post_commitment(
+        &self,
+        chunks: &[Vec<u8>],
+        written: &mut usize,
+        commit: &[u8],
+        commitment_account: Option<Pubkey>,
+        cosignatures: &[Instruction],
+        report: &mut BatchReport
+    ) -> anyhow::Result<()> {
+        while let Some(chunk) = chunks.get(*written) {
+            self.send_and_confirm(chunk, None, &[], report).await?;
+            *written += 1;
+            println!("Batch {}: wrote chunk {}/{} to L1", report.batch_id, written, chunks.len());
+        }
+
+        let slot = self.send_and_confirm(commit, commitment_account, cosignatures, report).await?;
+        report.final_slot = Some(slot);
+        println!(
+            "Batch {} submitted to L1 by {}: {}",
+            report.batch_id,
+            self.authorities.read().unwrap().scheduled(report.batch_id),
+            report.signatures.last().map(String::as_str).unwrap_or_default()
         );
 
-        let recent_blockhash = self.solana_client.get_latest_blockhash().await?;
+        Ok(())
+    }
+
+    /// Builds and publishes a batch's commitment, handing the batch back
+    /// with its failed report if either step fails.
+    async fn stage_batch(
+        &self,
+        aggregation: &Aggregation,
+        batch_id: u64,
+        stored: StoredBatch
+    ) -> Result<StagedBatch, (StoredBatch, BatchReport)> {
+        let started = self.clock.now();
+        let mut report = self.new_report(batch_id, &stored);
+
+        let published = async {
+            let (batch_data, digest) = self.prepare_commitment(batch_id, &stored)?;
+            aggregation.publisher.publish(batch_id, &batch_data).await?;
+            anyhow::Ok(digest)
+        }.await;
+
+        match published {
+            Ok(digest) => Ok(StagedBatch { stored, digest, report, started }),
+            Err(e) => {
+                report.error = Some(format!("Failed to publish commitment: {}", e));
+                report.duration_ms = self.clock.now().saturating_duration_since(started).as_millis() as u64;
+                Err((stored, report))
+            }
+        }
+    }
+
+    /// Posts one aggregate over `batches` and finishes each of them with the
+    /// outcome. The L1 cost is split evenly between the batches.
+    async fn post_aggregate(&self, batches: Vec<StagedBatch>) {
+        let (Some(first), Some(last)) = (batches.first(), batches.last()) else {
+            return;
+        };
+
+        // The aggregate's own attempts are tracked under its last batch, so
+        // it is signed by the authority scheduled for that batch
+        let mut aggregate = self.new_report(last.report.batch_id, &last.stored);
+        let first_batch_id = first.report.batch_id;
+        let result = self.try_post_aggregate(first_batch_id, &batches, &mut aggregate).await;
+
+        let count = batches.len() as u64;
+        for (index, mut batch) in batches.into_iter().enumerate() {
+            batch.report.attempts = aggregate.attempts;
+            batch.report.signatures = aggregate.signatures.clone();
+            batch.report.final_slot = aggregate.final_slot;
+            batch.report.total_cost_lamports = aggregate.total_cost_lamports / count
+                + if index == 0 { aggregate.total_cost_lamports % count } else { 0 };
+            batch.report.error = result.as_ref().err().map(|e| e.to_string());
+            batch.report.duration_ms = self.clock.now().saturating_duration_since(batch.started).as_millis() as u64;
+
+            self.finish_batch(&batch.stored, batch.report).await;
+        }
+    }
+
+    async fn try_post_aggregate(
+        &self,
+        first_batch_id: u64,
+        batches: &[StagedBatch],
+        report: &mut BatchReport
+    ) -> anyhow::Result<()> {
+        let leaves = batches.iter().map(|batch| batch.digest).collect();
+        let commit = bincode::serialize(&RollupInstruction::CommitAggregate {
+            genesis_hash: self.genesis_hash()?,
+            first_batch_id,
+            count: batches.len() as u32,
+            root: merkle_root(self.state_manager.hasher(), leaves),
+        })?;
+
+        let mut last_error = None;
+        while report.attempts < MAX_SUBMIT_ATTEMPTS {
+            report.attempts += 1;
+
+            // The aggregate is recorded in the account of its first batch
+            let commitment_account = Some(commitment_account(&self.rollup_program_id, first_batch_id));
+            match self.send_and_confirm(&commit, commitment_account, &[], report).await {
+                Ok(slot) => {
+                    report.final_slot = Some(slot);
+                    println!(
+                        "Aggregate of batches {}-{} submitted to L1 by {}: {}",
+                        first_batch_id,
+                        report.batch_id,
+                        self.authorities.read().unwrap().scheduled(report.batch_id),
+                        report.signatures.last().map(String::as_str).unwrap_or_default()
+                    );
+                    metrics::increment("aggregates_submitted");
+                    return Ok(());
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Aggregate of batches {}-{} attempt {} failed: {}",
+                        first_batch_id, report.batch_id, report.attempts, e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Aggregate was never submitted")))
+    }
+
+    /// The instructions posting `data`. Instructions that commit a batch
+    /// also pass the commitment account the program writes, created if
+    /// needed with `authority` paying.
+    fn instructions(
+        &self,
+        data: &[u8],
+        commitment_account: Option<Pubkey>,
+        cosignatures: &[Instruction],
+        authority: &Pubkey
+    ) -> Vec<Instruction> {
+        let mut instructions = Vec::new();
+        if self.compute_unit_price > 0 {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(self.compute_unit_price));
+        }
+        // The rollup program checks these by introspecting earlier instructions
+        instructions.extend_from_slice(cosignatures);
+        let accounts = match commitment_account {
+            Some(account) => vec![
+                AccountMeta::new(*authority, true),
+                AccountMeta::new(account, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            None => vec![],
+        };
+        instructions.push(Instruction::new_with_bytes(self.rollup_program_id, data, accounts));
+
+        instructions
+    }
+
+    /// Serialized size of the L1 transaction posting `data`.
+    fn transaction_size(
+        &self,
+        data: &[u8],
+        commitment_account: Option<Pubkey>,
+        cosignatures: &[Instruction]
+    ) -> anyhow::Result<usize> {
+        let authority = self.authority();
+        let message = Message::new(&self.instructions(data, commitment_account, cosignatures, &authority), Some(&authority));
+        Ok(bincode::serialized_size(&Transaction::new_unsigned(message))? as usize)
+    }
+
+    /// Sends one rollup instruction to L1, returning the slot it landed in.
+    async fn send_and_confirm(
+        &self,
+        data: &[u8],
+        commitment_account: Option<Pubkey>,
+        cosignatures: &[Instruction],
+        report: &mut BatchReport
+    ) -> anyhow::Result<u64> {
+        inject(FaultPoint::L1Submit).await?;
+
+        // Picked per attempt, so a retry after a rotation uses the new key
+        let authority = self.authorities.write().unwrap().for_batch(report.batch_id);
+        let instructions = self.instructions(data, commitment_account, cosignatures, &authority.pubkey());
+
+        let recent_blockhash = self.recent_blockhash().await?;
+        let result = self.sign_send_and_confirm(&instructions, &authority, recent_blockhash, report).await;
+        if result.is_err() {
+            self.invalidate_blockhash(&recent_blockhash);
+        }
+
+        result
+    }
+
+    async fn sign_send_and_confirm(
+        &self,
+        instructions: &[Instruction],
+        authority: &Keypair,
+        recent_blockhash: Hash,
+        report: &mut BatchReport
+    ) -> anyhow::Result<u64> {
         let tx = Transaction::new_signed_with_payer(
-            &[instruction],
-            Some(&self.authority.pubkey()),
-            &[&self.authority],
+            instructions,
+            Some(&authority.pubkey()),
+            &[authority],
             recent_blockhash
         );
+        let fee = self.solana_client.get_fee_for_message(&tx.message).await?;
 
         let config = RpcSendTransactionConfig {
             skip_preflight: true,
@@ -57,17 +1050,157 @@ impl BatchProcessor {
         };
 
         let signature = self.solana_client.send_transaction_with_config(&tx, config).await?;
+        report.signatures.push(signature.to_string());
 
-        println!("Batch submitted to L1: {}", signature);
+        for _ in 0..MAX_CONFIRMATION_POLLS {
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
 
-        Ok(())
+            let statuses = self.solana_client.get_signature_statuses(&[signature]).await?.value;
+            let Some(Some(status)) = statuses.into_iter().next() else {
+                continue;
+            };
+
+            // Landed transactions pay their fee whether they succeeded or not
+            if let Some(err) = status.err {
+                report.total_cost_lamports += fee;
+                anyhow::bail!("Batch transaction {} failed on L1: {}", signature, err);
+            }
 
+            if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                report.total_cost_lamports += fee;
+                return Ok(status.slot);
+            }
+        }
+
+        anyhow::bail!("Batch transaction {} was not confirmed in time", signature)
+    }
+
+    fn compress_batch(&self, batch: &[&WireTransaction]) -> anyhow::Result<Vec<u8>> {
+        // Same layout as bincode's Vec<Transaction>, built from the wire bytes
+        let payload_len: usize = batch.iter().map(|tx| tx.wire_bytes().len()).sum();
+        let mut serialzed = Vec::with_capacity(8 + payload_len);
+        serialzed.extend_from_slice(&(batch.len() as u64).to_le_bytes());
+        for tx in batch {
+            serialzed.extend_from_slice(tx.wire_bytes());
+        }
+
+        self.compression.compress(&serialzed)
+    }
+}
+
+/// Rollup program account a batch's commitment is recorded in.
+pub fn commitment_account(rollup_program_id: &Pubkey, batch_id: u64) -> Pubkey {
+    Pubkey::find_program_address(&[COMMITMENT_ACCOUNT_SEED, &batch_id.to_le_bytes()], rollup_program_id).0
+}
+
+/// Splits a serialized commitment into `WriteChunk` instructions.
+fn chunk_commitment(batch_id: u64, batch_data: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+    let count = u16::try_from(batch_data.len().div_ceil(CHUNK_LEN))
+        .map_err(|_| anyhow::anyhow!("Batch commitment of {} bytes needs too many chunks", batch_data.len()))?;
+
+    batch_data.chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(index, data)| Ok(bincode::serialize(&RollupInstruction::WriteChunk {
+            batch_id,
+            index: index as u16,
+            count,
+            data: data.to_vec(),
+        })?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+
+    use super::*;
+    use crate::batch_processor::health::DegradedMode;
+
+    fn landed_report(batch_id: u64, slot: u64) -> BatchReport {
+        BatchReport {
+            batch_id,
+            transaction_count: 0,
+            attempts: 1,
+            signatures: vec![Signature::from([batch_id as u8 + 1; 64]).to_string()],
+            compute_unit_price: 0,
+            final_slot: Some(slot),
+            total_cost_lamports: 0,
+            duration_ms: 0,
+            error: None,
+        }
+    }
+
+    fn processor(state_manager: Arc<StateManager>, compute_unit_price: u64) -> BatchProcessor {
+        let epochs = EpochConfig {
+            batches_per_epoch: 100,
+            rent_lamports_per_byte: 0,
+            fee_collector: None,
+            hot_epochs: 1,
+            reap_empty_accounts_from: None,
+            prune_batches_after_epochs: None,
+        };
+        let health = L1Health::new(Duration::from_secs(60), DegradedMode::Reject);
+        BatchProcessor::new(
+            "fails".to_string(), Pubkey::new_unique(), state_manager, AuthoritySchedule::ephemeral(), compute_unit_price, epochs, false, health
+        )
+    }
+
+    #[tokio::test]
+    async fn a_dropped_batch_that_fails_to_post_again_holds_back_finality() {
+        let state_manager = StateManager::temporary();
+        state_manager.put_empty_block();
+        state_manager.put_empty_block();
+
+        let mut processor = processor(state_manager.clone(), 0)
+            .with_l1_watcher(Arc::new(L1Watcher::new("fails".to_string(), Duration::from_secs(1))));
+        // L1 doesn't know batch 0's commitment, and every other request fails
+        let statuses = json!({ "context": { "slot": 1 }, "value": [null] });
+        processor.solana_client = RpcClient::new_mock_with_mocks(
+            "fails".to_string(),
+            HashMap::from([(RpcRequest::GetSignatureStatuses, statuses)])
+        );
+
+        for report in [landed_report(0, 5), landed_report(1, 6)] {
+            state_manager.put_batch_report(&report).unwrap();
+            processor.await_finality(&report);
+        }
+        processor.finalize_through(10).await;
+        // Tried again as finality advances, without getting past batch 0
+        processor.finalize_through(11).await;
+
+        assert_eq!(processor.last_finalized_batch(), None);
+        {
+            let finality = processor.finality.lock().unwrap();
+            assert!(matches!(finality.awaiting.get(&0), Some(Awaiting::Repost)));
+            assert!(matches!(finality.awaiting.get(&1), Some(Awaiting::Landed(_))));
+        }
+        let stored = state_manager.get_batch_report(0).unwrap().unwrap();
+        assert_eq!(stored.signatures, landed_report(0, 5).signatures);
+        assert!(state_manager.dead_letters(None, 10).unwrap().is_empty());
     }
 
-    fn compress_batch(&self, batch: &[Transaction]) -> anyhow::Result<Vec<u8>> {
-        let serialzed = bincode::serialize(batch)?;
+    #[test]
+    fn chunks_rejoin_into_the_commitment_and_each_fits_in_a_packet() {
+        let processor = processor(StateManager::temporary(), 1_000);
+        let batch_data: Vec<u8> = (0..2 * CHUNK_LEN + 1).map(|i| i as u8).collect();
+
+        let chunks = chunk_commitment(7, &batch_data).unwrap();
+        assert_eq!(chunks.len(), 3);
+        let mut joined = Vec::new();
+        for (position, chunk) in chunks.iter().enumerate() {
+            assert!(processor.transaction_size(chunk, None, &[]).unwrap() <= PACKET_DATA_SIZE);
+            let Ok(RollupInstruction::WriteChunk { batch_id, index, count, data }) = bincode::deserialize(chunk) else {
+                panic!("chunk {} isn't a WriteChunk", position);
+            };
+            assert_eq!((batch_id, index as usize, count), (7, position, 3));
+            joined.extend(data);
+        }
+        assert_eq!(joined, batch_data);
 
-        // TODO: Use LZ4 or similar
-        Ok(serialzed)
+        assert_eq!(chunk_commitment(7, &batch_data[..CHUNK_LEN]).unwrap().len(), 1);
+        assert!(chunk_commitment(7, &vec![0; CHUNK_LEN * (u16::MAX as usize + 1)]).is_err());
     }
 }
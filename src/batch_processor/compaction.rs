@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature, transaction::Transaction};
+
+use crate::transaction_processor::wire_transaction::WireTransaction;
+
+/// Posted in place of opposing transfers between two accounts that were
+/// netted out within a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetTransfer {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub lamports: u64,
+}
+
+/// What actually gets posted for a batch. The original transactions stay in
+/// the locally stored batch, so receipts and replay are unaffected.
+#[derive(Debug, Default)]
+pub struct CompactedBatch<'a> {
+    /// Transactions posted as they are, in batch order
+    pub transactions: Vec<&'a WireTransaction>,
+    pub net_transfers: Vec<NetTransfer>,
+    /// Signatures of the transactions folded into `net_transfers`
+    pub netted_signatures: Vec<Signature>,
+}
+
+impl<'a> CompactedBatch<'a> {
+    /// Posts every transaction as is.
+    pub fn uncompacted(batch: &'a [WireTransaction]) -> Self {
+        Self {
+            transactions: batch.iter().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Nets out opposing system transfers between the same pair of accounts.
+/// Transfers between a pair that only flows one way are left alone, since
+/// replacing them would save nothing.
+pub fn compact_batch(batch: &[WireTransaction]) -> CompactedBatch<'_> {
+    let transfers: Vec<_> = batch.iter().map(|tx| system_transfer(tx)).collect();
+
+    let mut flows: HashMap<(Pubkey, Pubkey), u128> = HashMap::new();
+    for &(from, to, lamports) in transfers.iter().flatten() {
+        *flows.entry((from, to)).or_default() += lamports as u128;
+    }
+    let nettable = |from: Pubkey, to: Pubkey| {
+        from != to && flows.contains_key(&(from, to)) && flows.contains_key(&(to, from))
+    };
+
+    // Pairs in order of first appearance, so the output is deterministic
+    let mut pairs = Vec::new();
+    let mut compacted = CompactedBatch::default();
+    for (tx, transfer) in batch.iter().zip(transfers) {
+        match transfer {
+            Some((from, to, _)) if nettable(from, to) => {
+                compacted.netted_signatures.push(tx.signatures.first().copied().unwrap_or_default());
+                let pair = (from.min(to), from.max(to));
+                if !pairs.contains(&pair) {
+                    pairs.push(pair);
+                }
+            }
+            _ => compacted.transactions.push(tx),
+        }
+    }
+
+    for (a, b) in pairs {
+        let forward = flows[&(a, b)];
+        let backward = flows[&(b, a)];
+        let (from, to, mut net) = if forward >= backward {
+            (a, b, forward - backward)
+        } else {
+            (b, a, backward - forward)
+        };
+
+        while net > 0 {
+            let lamports = u64::try_from(net).unwrap_or(u64::MAX);
+            compacted.net_transfers.push(NetTransfer { from, to, lamports });
+            net -= lamports as u128;
+        }
+    }
+
+    compacted
+}
+
+/// Source, destination and amount of a transaction that does nothing but a
+/// system transfer (ComputeBudget instructions aside).
+fn system_transfer(tx: &Transaction) -> Option<(Pubkey, Pubkey, u64)> {
+    let keys = &tx.message.account_keys;
+    let mut instructions = tx.message.instructions.iter()
+        .filter(|ix| !keys.get(ix.program_id_index as usize).is_some_and(solana_compute_budget_interface::check_id));
+
+    let ix = instructions.next()?;
+    if instructions.next().is_some() || *keys.get(ix.program_id_index as usize)? != Pubkey::default() {
+        return None;
+    }
+
+    let lamports = match ix.data.as_slice() {
+        [2, 0, 0, 0, lamports @ ..] => u64::from_le_bytes(lamports.try_into().ok()?),
+        _ => return None,
+    };
+    let from = *keys.get(*ix.accounts.first()? as usize)?;
+    let to = *keys.get(*ix.accounts.get(1)? as usize)?;
+
+    Some((from, to, lamports))
+}
@@ -0,0 +1,194 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Leads every framed payload. Payloads posted before framing start with
+/// the transaction count as a little-endian u64 instead, which would need
+/// millions of transactions to collide with it.
+const MAGIC: [u8; 3] = *b"TRB";
+
+/// Version of the header layout below
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Magic, version, compression, flags, uncompressed length (u32 LE)
+const HEADER_LEN: usize = MAGIC.len() + 3 + 4;
+
+/// Largest payload a decoder will inflate, far above any batch's byte budget
+const MAX_UNCOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// How a batch's transactions are compressed before posting to L1. The id
+/// is written into the payload header, so it must never change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionAlgorithm {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl CompressionAlgorithm {
+    fn id(&self) -> u8 {
+        match self {
+            CompressionAlgorithm::None => 0,
+            CompressionAlgorithm::Lz4 => 1,
+            CompressionAlgorithm::Zstd => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> anyhow::Result<Self> {
+        match id {
+            0 => Ok(CompressionAlgorithm::None),
+            1 => Ok(CompressionAlgorithm::Lz4),
+            2 => Ok(CompressionAlgorithm::Zstd),
+            _ => anyhow::bail!("Unknown batch compression {}", id),
+        }
+    }
+}
+
+/// Compression applied to batch payloads, with the zstd level (1-22).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchCompression {
+    pub algorithm: CompressionAlgorithm,
+    pub zstd_level: i32,
+}
+
+impl Default for BatchCompression {
+    fn default() -> Self {
+        Self { algorithm: CompressionAlgorithm::Zstd, zstd_level: DEFAULT_ZSTD_LEVEL }
+    }
+}
+
+impl BatchCompression {
+    /// Compresses `payload` and frames it with a header naming the format
+    /// version and compression, so the rollup program and verifiers can
+    /// decode it without knowing how the node was configured.
+    pub fn compress(&self, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if payload.len() > MAX_UNCOMPRESSED_LEN {
+            anyhow::bail!("Batch payload of {} bytes exceeds the {} byte limit", payload.len(), MAX_UNCOMPRESSED_LEN);
+        }
+
+        let compressed = match self.algorithm {
+            CompressionAlgorithm::None => payload.to_vec(),
+            CompressionAlgorithm::Lz4 => lz4::block::compress(payload, None, false)?,
+            CompressionAlgorithm::Zstd => zstd::bulk::compress(payload, self.zstd_level)?,
+        };
+
+        let mut framed = Vec::with_capacity(HEADER_LEN + compressed.len());
+        framed.extend_from_slice(&MAGIC);
+        framed.push(FORMAT_VERSION);
+        framed.push(self.algorithm.id());
+        // No flags are defined yet
+        framed.push(0);
+        framed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        framed.extend_from_slice(&compressed);
+
+        Ok(framed)
+    }
+
+    /// Longest framed payload `compress` can produce from `payload_len`
+    /// bytes: the header plus the algorithm's worst case, since
+    /// incompressible input comes out slightly larger than it went in.
+    pub fn max_framed_len(&self, payload_len: usize) -> usize {
+        let bound = match self.algorithm {
+            CompressionAlgorithm::None => payload_len,
+            CompressionAlgorithm::Lz4 => lz4::block::compress_bound(payload_len).unwrap_or(usize::MAX),
+            CompressionAlgorithm::Zstd => zstd::zstd_safe::compress_bound(payload_len),
+        };
+
+        bound.saturating_add(HEADER_LEN)
+    }
+}
+
+/// The payload `BatchCompression::compress` framed, or an unframed payload
+/// from before compression as is.
+pub fn decompress(framed: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if !framed.starts_with(&MAGIC) {
+        return Ok(framed.to_vec());
+    }
+    if framed.len() < HEADER_LEN {
+        anyhow::bail!("Batch payload header is truncated");
+    }
+
+    let version = framed[3];
+    if version != FORMAT_VERSION {
+        anyhow::bail!("Unsupported batch payload format version {}", version);
+    }
+    let algorithm = CompressionAlgorithm::from_id(framed[4])?;
+    let flags = framed[5];
+    if flags != 0 {
+        anyhow::bail!("Unsupported batch payload flags {:#04x}", flags);
+    }
+    let len = u32::from_le_bytes(framed[6..10].try_into().unwrap()) as usize;
+    if len > MAX_UNCOMPRESSED_LEN {
+        anyhow::bail!("Batch payload claims {} bytes, over the {} byte limit", len, MAX_UNCOMPRESSED_LEN);
+    }
+
+    let compressed = &framed[HEADER_LEN..];
+    let payload = match algorithm {
+        CompressionAlgorithm::None => compressed.to_vec(),
+        CompressionAlgorithm::Lz4 => lz4::block::decompress(compressed, Some(len as i32))?,
+        CompressionAlgorithm::Zstd => zstd::bulk::decompress(compressed, len)?,
+    };
+    if payload.len() != len {
+        anyhow::bail!("Batch payload inflated to {} bytes, header says {}", payload.len(), len);
+    }
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..4096u32).flat_map(|i| (i % 97).to_le_bytes()).collect()
+    }
+
+    fn compression(algorithm: CompressionAlgorithm) -> BatchCompression {
+        BatchCompression { algorithm, ..BatchCompression::default() }
+    }
+
+    #[test]
+    fn frames_and_restores_payloads_with_every_algorithm() {
+        let payload = payload();
+        for algorithm in CompressionAlgorithm::value_variants() {
+            let framed = compression(*algorithm).compress(&payload).unwrap();
+            assert_eq!(&framed[..3], &MAGIC);
+            assert_eq!(framed[3], FORMAT_VERSION);
+            assert_eq!(framed[4], algorithm.id());
+            assert_eq!(framed[5], 0);
+            assert_eq!(framed[6..10], (payload.len() as u32).to_le_bytes());
+            assert!(framed.len() <= compression(*algorithm).max_framed_len(payload.len()));
+
+            assert_eq!(decompress(&framed).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn passes_unframed_payloads_through() {
+        // A payload from before framing leads with its transaction count
+        let legacy = [3u64.to_le_bytes().as_slice(), b"transactions"].concat();
+        assert_eq!(decompress(&legacy).unwrap(), legacy);
+    }
+
+    #[test]
+    fn rejects_headers_it_cannot_decode() {
+        let framed = compression(CompressionAlgorithm::Zstd).compress(&payload()).unwrap();
+        let with = |offset: usize, byte: u8| {
+            let mut framed = framed.clone();
+            framed[offset] = byte;
+            framed
+        };
+
+        assert!(decompress(&framed[..HEADER_LEN - 1]).is_err());
+        assert!(decompress(&with(3, FORMAT_VERSION + 1)).is_err());
+        assert!(decompress(&with(4, 3)).is_err());
+        assert!(decompress(&with(5, 1)).is_err());
+        // An uncompressed length other than the header's
+        assert!(decompress(&with(6, framed[6].wrapping_add(1))).is_err());
+        let mut oversized = framed.clone();
+        oversized[6..10].copy_from_slice(&(MAX_UNCOMPRESSED_LEN as u32 + 1).to_le_bytes());
+        assert!(decompress(&oversized).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::{Keypair, Signature, Signer}};
+use tokio::task::JoinSet;
+
+/// Prefix of every co-signed digest, so a co-signer key can't be tricked
+/// into signing anything but a batch
+const BATCH_DIGEST_DOMAIN: &[u8] = b"tiny-rollup-batch";
+/// Prefix of what the sequencer signs to ask for a co-signature, so the
+/// request can't pass for anything else its identity signs
+const COSIGN_REQUEST_DOMAIN: &[u8] = b"tiny-rollup-cosign-request";
+const COSIGN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What co-signers sign: the batch id and the exact commitment posted to L1.
+pub fn batch_digest(batch_id: u64, batch_data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(BATCH_DIGEST_DOMAIN);
+    hasher.update(batch_id.to_le_bytes());
+    hasher.update(batch_data);
+    hasher.finalize().into()
+}
+
+/// What the sequencer signs with its identity key to ask for a co-signature
+/// of `batch_digest`, so co-signers only sign for it.
+pub fn request_digest(batch_digest: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(COSIGN_REQUEST_DOMAIN);
+    hasher.update(batch_digest);
+    hasher.finalize().into()
+}
+
+/// A co-signer node and the key it is expected to sign with.
+#[derive(Debug, Clone)]
+pub struct Cosigner {
+    pub pubkey: Pubkey,
+    pub url: String,
+}
+
+impl Cosigner {
+    /// Parses `PUBKEY@URL`.
+    pub fn from_spec(spec: &str) -> anyhow::Result<Self> {
+        let (pubkey, url) = spec.split_once('@')
+            .ok_or_else(|| anyhow::anyhow!("Invalid co-signer {}: expected PUBKEY@URL", spec))?;
+        let pubkey = Pubkey::from_str(pubkey)
+            .map_err(|e| anyhow::anyhow!("Invalid co-signer key in {}: {}", spec, e))?;
+
+        Ok(Self { pubkey, url: url.to_string() })
+    }
+}
+
+#[derive(Deserialize)]
+struct CosignResponse {
+    result: Option<Cosignature>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Cosignature {
+    pubkey: String,
+    signature: String,
+}
+
+/// Co-signers of batch submissions. A batch is only sent once `threshold` of
+/// them have signed its digest; their signatures go into the L1 transaction
+/// as ed25519 verification instructions ahead of the commitment, where the
+/// rollup program checks them. Requests are signed with the sequencer's
+/// identity key, which co-signers check.
+pub struct CosignerSet {
+    cosigners: Vec<Cosigner>,
+    threshold: usize,
+    identity: Keypair,
+    client: reqwest::Client,
+}
+
+impl CosignerSet {
+    pub fn new(cosigners: Vec<Cosigner>, threshold: usize, identity: Keypair) -> anyhow::Result<Self> {
+        if threshold == 0 || threshold > cosigners.len() {
+            anyhow::bail!("Co-signer threshold must be between 1 and {}, got {}", cosigners.len(), threshold);
+        }
+
+        Ok(Self {
+            cosigners,
+            threshold,
+            identity,
+            client: reqwest::Client::builder().timeout(COSIGN_TIMEOUT).build()?,
+        })
+    }
+
+    /// Asks every co-signer to sign the batch and returns one ed25519
+    /// instruction per signature once `threshold` valid ones are in.
+    pub async fn collect(&self, batch_id: u64, batch_data: &[u8]) -> anyhow::Result<Vec<Instruction>> {
+        let digest = batch_digest(batch_id, batch_data);
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "cosignBatch",
+            "params": [batch_id, hex::encode(batch_data), self.identity.sign_message(&request_digest(&digest)).to_string()],
+        });
+
+        let mut requests = JoinSet::new();
+        for cosigner in self.cosigners.clone() {
+            let client = self.client.clone();
+            let request = request.clone();
+            requests.spawn(async move {
+                let result = request_signature(&client, &cosigner, &request, &digest).await;
+                (cosigner, result)
+            });
+        }
+
+        let mut instructions = Vec::new();
+        let mut failures = Vec::new();
+        while let Some(joined) = requests.join_next().await {
+            let (cosigner, result) = joined?;
+            match result {
+                Ok(signature) => {
+                    instructions.push(solana_ed25519_program::new_ed25519_instruction_with_signature(
+                        &digest,
+                        &<[u8; 64]>::from(signature),
+                        &cosigner.pubkey.to_bytes()
+                    ));
+                    if instructions.len() == self.threshold {
+                        // Dropping the set cancels requests still in flight
+                        return Ok(instructions);
+                    }
+                }
+                Err(e) => failures.push(format!("{}: {}", cosigner.pubkey, e)),
+            }
+        }
+
+        anyhow::bail!(
+            "Only {} of {} required co-signatures for batch {}: {}",
+            instructions.len(), self.threshold, batch_id, failures.join("; ")
+        )
+    }
+}
+
+async fn request_signature(
+    client: &reqwest::Client,
+    cosigner: &Cosigner,
+    request: &serde_json::Value,
+    digest: &[u8; 32]
+) -> anyhow::Result<Signature> {
+    let response: CosignResponse = client.post(&cosigner.url)
+        .json(request)
+        .send().await?
+        .error_for_status()?
+        .json().await?;
+
+    let cosignature = match (response.result, response.error) {
+        (Some(cosignature), _) => cosignature,
+        (None, error) => anyhow::bail!("Refused: {}", error.unwrap_or_default()),
+    };
+    if cosignature.pubkey != cosigner.pubkey.to_string() {
+        anyhow::bail!("Signed with unexpected key {}", cosignature.pubkey);
+    }
+
+    let signature = Signature::from_str(&cosignature.signature)?;
+    if !signature.verify(cosigner.pubkey.as_ref(), digest) {
+        anyhow::bail!("Invalid signature");
+    }
+
+    Ok(signature)
+}
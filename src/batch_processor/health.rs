@@ -0,0 +1,113 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{clock::clock::{Clock, SystemClock}, metrics::metrics};
+
+/// What sendTransaction does while the node can't post batches to L1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DegradedMode {
+    /// Refuse new transactions until L1 posting recovers
+    #[default]
+    Reject,
+    /// Keep accepting transactions, logging a warning for each
+    Warn,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    /// First failure since the last successful L1 interaction
+    failing_since: Option<Instant>,
+    last_error: Option<String>,
+    /// A batch submission failed since the last one confirmed
+    batch_failing: bool,
+}
+
+/// Tracks whether batches can currently be posted to L1. Every L1
+/// interaction (batch submissions and the periodic probe) reports failures;
+/// the node is degraded once they have gone on for longer than `threshold`.
+/// Only a confirmed batch submission clears a failed one: L1 answering the
+/// probe doesn't mean batches land.
+pub struct L1Health {
+    threshold: Duration,
+    mode: DegradedMode,
+    state: Mutex<HealthState>,
+    clock: Arc<dyn Clock>,
+}
+
+impl L1Health {
+    pub fn new(threshold: Duration, mode: DegradedMode) -> Self {
+        Self {
+            threshold,
+            mode,
+            state: Mutex::new(HealthState::default()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Times failures by `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn mode(&self) -> DegradedMode {
+        self.mode
+    }
+
+    /// A batch submission confirmed on L1.
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.recover(&mut state);
+    }
+
+    /// The probe reached L1 with a funded authority. Clears failures the
+    /// probe itself reported, but not failed batch submissions.
+    pub fn record_probe_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.batch_failing {
+            self.recover(&mut state);
+        }
+    }
+
+    pub fn record_failure(&self, error: String) {
+        let mut state = self.state.lock().unwrap();
+        let since = *state.failing_since.get_or_insert_with(|| self.clock.now());
+        state.last_error = Some(error);
+        metrics::set_gauge("l1_degraded", (self.failing_for(since) >= self.threshold) as u64);
+    }
+
+    /// A batch submission failed; only a confirmed one clears it.
+    pub fn record_batch_failure(&self, error: String) {
+        self.state.lock().unwrap().batch_failing = true;
+        self.record_failure(error);
+    }
+
+    fn recover(&self, state: &mut HealthState) {
+        if state.failing_since.take().is_some_and(|since| self.failing_for(since) >= self.threshold) {
+            println!("L1 posting recovered, leaving degraded mode");
+        }
+        state.last_error = None;
+        state.batch_failing = false;
+        metrics::set_gauge("l1_degraded", 0);
+    }
+
+    /// Why the node is degraded, or None while it is healthy (including
+    /// while failures are still within the threshold).
+    pub fn degraded_reason(&self) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        let failing_for = self.failing_for(state.failing_since?);
+        if failing_for < self.threshold {
+            return None;
+        }
+
+        Some(format!(
+            "L1 batch posting has been failing for {}s: {}",
+            failing_for.as_secs(),
+            state.last_error.as_deref().unwrap_or("unknown error")
+        ))
+    }
+
+    fn failing_for(&self, since: Instant) -> Duration {
+        self.clock.now().saturating_duration_since(since)
+    }
+}
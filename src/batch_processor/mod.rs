@@ -1 +1,7 @@
+pub mod aggregation;
+pub mod authority;
 pub mod batch_processor;
+pub mod compaction;
+pub mod compression;
+pub mod cosigning;
+pub mod health;
@@ -0,0 +1,94 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+use crate::{
+    batch_processor::compression::{decompress, BatchCompression},
+    rpc_server::attestation::ResponseAttestor,
+    sequencer::block::BlockHeader,
+    state_manager::state_manager::StateDiff,
+    transaction_processor::{transaction_record::TransactionRecord, wire_transaction::WireTransaction},
+};
+
+pub const BLOCK_STREAM_VERSION: u32 = 1;
+
+/// Prefix of every signed frame, so a frame signature can't be passed off
+/// as a signature over anything else made with the identity key
+const FRAME_DOMAIN: &[u8] = b"tiny-rollup:block-stream:v1";
+
+/// A sealed block as replicas receive it: everything needed to store it and
+/// reach its state root without executing it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamedBlock {
+    pub header: BlockHeader,
+    /// In block order, as the header's transactions hash commits to them
+    pub transactions: Vec<WireTransaction>,
+    /// Execution records of the transactions, for getTransaction and
+    /// address activity
+    pub records: Vec<TransactionRecord>,
+    pub diff: StateDiff,
+}
+
+/// A block on the wire: the bincode `StreamedBlock`, compressed like batch
+/// payloads and signed with the sequencer's identity key. Sent over
+/// WebSocket as base64 of the bincode frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockFrame {
+    pub version: u32,
+    pub genesis_hash: [u8; 32],
+    pub slot: u64,
+    pub payload: Vec<u8>,
+    /// Over `frame_digest`
+    pub signature: Signature,
+}
+
+impl BlockFrame {
+    pub fn seal(block: &StreamedBlock, genesis_hash: [u8; 32], attestor: &ResponseAttestor) -> anyhow::Result<Self> {
+        let payload = BatchCompression::default().compress(&bincode::serialize(block)?)?;
+        let slot = block.header.slot;
+        let signature = attestor.sign(&frame_digest(BLOCK_STREAM_VERSION, &genesis_hash, slot, &payload));
+
+        Ok(Self { version: BLOCK_STREAM_VERSION, genesis_hash, slot, payload, signature })
+    }
+
+    /// Decodes the block after checking the frame is of this protocol
+    /// version and signed by `identity`.
+    pub fn open(&self, identity: &Pubkey) -> anyhow::Result<StreamedBlock> {
+        if self.version != BLOCK_STREAM_VERSION {
+            anyhow::bail!("Unsupported block stream version {}", self.version);
+        }
+        let digest = frame_digest(self.version, &self.genesis_hash, self.slot, &self.payload);
+        if !self.signature.verify(identity.as_ref(), &digest) {
+            anyhow::bail!("Block {} is not signed by sequencer identity {}", self.slot, identity);
+        }
+
+        let block: StreamedBlock = bincode::deserialize(&decompress(&self.payload)?)?;
+        if block.header.slot != self.slot {
+            anyhow::bail!("Frame of block {} carries block {}", self.slot, block.header.slot);
+        }
+
+        Ok(block)
+    }
+
+    pub fn encode(&self) -> anyhow::Result<String> {
+        Ok(BASE64_STANDARD.encode(bincode::serialize(self)?))
+    }
+
+    pub fn decode(encoded: &str) -> anyhow::Result<Self> {
+        Ok(bincode::deserialize(&BASE64_STANDARD.decode(encoded)?)?)
+    }
+}
+
+/// What a frame's signature signs: sha256 of the domain, the version, the
+/// genesis hash, the slot and the payload.
+fn frame_digest(version: u32, genesis_hash: &[u8; 32], slot: u64, payload: &[u8]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(FRAME_DOMAIN)
+        .chain_update(version.to_le_bytes())
+        .chain_update(genesis_hash)
+        .chain_update(slot.to_le_bytes())
+        .chain_update(payload)
+        .finalize()
+        .into()
+}
@@ -0,0 +1,2 @@
+pub mod block_stream;
+pub mod replica;
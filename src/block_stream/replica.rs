@@ -0,0 +1,231 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use solana_sdk::{hash::Hash, pubkey::Pubkey};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{
+    batch_processor::batch_processor::StoredBatch,
+    block_stream::block_stream::BlockFrame,
+    metrics::metrics,
+    notifications::notifications::{NotificationEvent, Notifier},
+    sequencer::block::BlockHeader,
+    state_manager::state_manager::StateManager,
+};
+
+/// Wait before reconnecting after the stream closes or fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Keeps a database in sync with a sequencer by following its block stream
+/// (blockStreamSubscribe). Each block's state diff is staged as is and
+/// checked against the block's state root, and only then stored with the
+/// block and its transactions, so nothing is executed and a diff that
+/// doesn't match is never written. The database must hold the
+/// state the sequencer's chain started from: it is either empty, in which
+/// case the chain's genesis hash is adopted, or a copy of the sequencer's
+/// database. After a disconnect the stream resumes from the next block.
+pub struct Replica {
+    url: String,
+    sequencer_identity: Pubkey,
+    state_manager: Arc<StateManager>,
+    notifier: Option<Arc<Notifier>>,
+    /// Last block the operator was notified diverged; the stream retries it
+    /// after every reconnect
+    notified_divergence: Mutex<Option<u64>>,
+}
+
+impl Replica {
+    pub fn new(url: String, sequencer_identity: Pubkey, state_manager: Arc<StateManager>) -> Self {
+        Self { url, sequencer_identity, state_manager, notifier: None, notified_divergence: Mutex::new(None) }
+    }
+
+    /// Notifies the operator through `notifier` when a block's diff doesn't
+    /// give its state root.
+    pub fn with_notifier(mut self, notifier: Arc<Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    pub async fn run(&self) {
+        loop {
+            match self.follow().await {
+                Ok(()) => eprintln!("⚠️  Block stream from {} closed, reconnecting", self.url),
+                Err(e) => {
+                    eprintln!("⚠️  Block stream from {} failed: {}", self.url, e);
+                    metrics::increment("replica_stream_failures");
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    /// Subscribes from the next block this database lacks and applies
+    /// blocks until the stream ends.
+    async fn follow(&self) -> anyhow::Result<()> {
+        let next_slot = self.state_manager.batch_count()?;
+        let (mut socket, _) = connect_async(self.url.as_str()).await?;
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "blockStreamSubscribe",
+            "params": [next_slot],
+        });
+        socket.send(Message::Text(request.to_string())).await?;
+        println!("🔁 Replicating {} from block {}", self.url, next_slot);
+
+        while let Some(message) = socket.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => return Ok(()),
+                _ => continue,
+            };
+            let message: Value = serde_json::from_str(&text)?;
+            if let Some(error) = message.get("error").or_else(|| message.pointer("/params/error")) {
+                anyhow::bail!("{}", error);
+            }
+            let Some(frame) = message.pointer("/params/result/frame").and_then(Value::as_str) else {
+                continue;
+            };
+            self.apply(&BlockFrame::decode(frame)?).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores the block in `frame` if it is the next one this database
+    /// lacks. Blocks already stored are skipped, so a stream resumed after
+    /// a partial apply picks up where it left off.
+    pub async fn apply(&self, frame: &BlockFrame) -> anyhow::Result<()> {
+        let next_slot = self.state_manager.batch_count()?;
+        if frame.slot < next_slot {
+            return Ok(());
+        }
+        if frame.slot > next_slot {
+            anyhow::bail!("Block stream skipped from block {} to {}", next_slot, frame.slot);
+        }
+        let block = frame.open(&self.sequencer_identity)?;
+        let genesis_hash = self.adopt_genesis_hash(frame.genesis_hash)?;
+
+        let header = block.header;
+        let parent_blockhash = match self.state_manager.latest_block()? {
+            Some(parent) => parent.blockhash,
+            None => Hash::new_from_array(genesis_hash),
+        };
+        let rederived = BlockHeader::new(header.slot, parent_blockhash, header.state_root, &block.transactions, header.block_time);
+        if rederived != header {
+            anyhow::bail!("Block {} does not chain onto block {} or does not match its transactions", header.slot, next_slot.saturating_sub(1));
+        }
+
+        let _blocks = self.state_manager.lock_blocks().await;
+        self.state_manager.begin_block()?;
+        let staged = async {
+            for record in &block.records {
+                let (Some(signature), Some(fee_payer)) = (record.transaction.signatures.first(), record.transaction.message.account_keys.first()) else {
+                    continue;
+                };
+                if !self.state_manager.is_signature_processed(signature)? {
+                    self.state_manager.record_processed_transaction(signature, fee_payer, record)?;
+                }
+            }
+            self.state_manager.commit_accounts(block.diff).await?;
+            self.state_manager.seal_state()
+        }.await;
+        let (state_root, diff) = match staged {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                self.state_manager.discard_block();
+                return Err(e);
+            }
+        };
+        if state_root != header.state_root {
+            self.state_manager.discard_block();
+            let detail = format!(
+                "State diverged at block {}: its diff gives root {} instead of {}; resync the database from the sequencer's",
+                header.slot, hex::encode(state_root), hex::encode(header.state_root)
+            );
+            if let Some(notifier) = &self.notifier
+                && self.notified_divergence.lock().unwrap().replace(header.slot) != Some(header.slot)
+            {
+                notifier.notify(NotificationEvent::StateRootDivergence { slot: header.slot, detail: detail.clone() });
+            }
+            anyhow::bail!(detail);
+        }
+
+        let batch = StoredBatch { transactions: block.transactions, state_root };
+        self.state_manager.put_block(&header, &batch, &diff)?;
+        self.state_manager.seal_snapshot(header.slot);
+        metrics::increment("replica_blocks_applied");
+
+        Ok(())
+    }
+
+    /// The chain's genesis hash, adopting `streamed` if this database hasn't
+    /// started a chain of its own: no blocks and no accounts.
+    fn adopt_genesis_hash(&self, streamed: [u8; 32]) -> anyhow::Result<[u8; 32]> {
+        let local = self.state_manager.genesis_hash()?;
+        if local == Some(streamed) {
+            return Ok(streamed);
+        }
+        if self.state_manager.batch_count()? > 0 || !self.state_manager.account_page(None, 1)?.is_empty() {
+            anyhow::bail!(
+                "The sequencer streams chain {}, but this database holds chain {}",
+                bs58::encode(streamed).into_string(),
+                local.map(|hash| bs58::encode(hash).into_string()).unwrap_or_default()
+            );
+        }
+
+        self.state_manager.restore_chain_metadata(Some(streamed), None)?;
+        println!("Adopted genesis hash {} of the sequencer's chain", bs58::encode(streamed).into_string());
+
+        Ok(streamed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk_ids::system_program;
+
+    use super::*;
+    use crate::{
+        block_stream::block_stream::StreamedBlock,
+        rpc_server::attestation::ResponseAttestor,
+        state_manager::state_manager::L2Account,
+    };
+
+    #[tokio::test]
+    async fn keeps_nothing_of_a_block_whose_diff_misses_its_root() {
+        let sequencer = StateManager::temporary();
+        let genesis_hash = sequencer.genesis_hash().unwrap().unwrap();
+        let pubkey = Pubkey::new_unique();
+        let diff = vec![(pubkey, Some(L2Account {
+            lamports: 1_000_000,
+            data: vec![],
+            owner: system_program::id(),
+            executable: false,
+            rent_epoch: 0,
+        }))];
+        sequencer.commit_accounts(diff.clone()).await.unwrap();
+        let (state_root, _) = sequencer.seal_state().unwrap();
+
+        let attestor = ResponseAttestor::new(Keypair::new());
+        let frame = |state_root| {
+            let header = BlockHeader::new(0, Hash::new_from_array(genesis_hash), state_root, &[], 0);
+            let block = StreamedBlock { header, transactions: vec![], records: vec![], diff: diff.clone() };
+            BlockFrame::seal(&block, genesis_hash, &attestor).unwrap()
+        };
+        let state_manager = StateManager::temporary();
+        let replica = Replica::new(String::new(), attestor.identity(), state_manager.clone());
+
+        assert!(replica.apply(&frame([7; 32])).await.is_err());
+        assert!(state_manager.get_account(&pubkey).await.is_none());
+        assert_eq!(state_manager.batch_count().unwrap(), 0);
+
+        replica.apply(&frame(state_root)).await.unwrap();
+        assert_eq!(state_manager.get_account(&pubkey).await.unwrap().lamports, 1_000_000);
+        assert_eq!(state_manager.get_state_root(), state_root);
+        assert_eq!(state_manager.batch_count().unwrap(), 1);
+    }
+}
@@ -0,0 +1,86 @@
+//! Fault injection for resilience testing. Hooks are compiled into the
+//! storage, sequencing and L1 submission paths, but are no-ops unless the
+//! crate is built with the `chaos` feature and an injector is installed.
+
+/// Places where faults can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum FaultPoint {
+    DbWrite,
+    L1Submit,
+    ChannelSend,
+}
+
+#[cfg(feature = "chaos")]
+mod injector {
+    use std::{collections::HashSet, sync::OnceLock, time::Duration};
+
+    use rand::Rng;
+
+    use super::FaultPoint;
+
+    #[derive(Debug, Clone)]
+    pub struct ChaosConfig {
+        /// Probability in [0, 1] that a hooked operation fails
+        pub failure_rate: f64,
+        /// Upper bound of the random delay added before a hooked operation
+        pub max_delay: Duration,
+        pub points: HashSet<FaultPoint>,
+    }
+
+    static INJECTOR: OnceLock<ChaosConfig> = OnceLock::new();
+
+    /// Enables fault injection for the rest of the process.
+    pub fn install(config: ChaosConfig) -> anyhow::Result<()> {
+        println!("⚠️  Chaos enabled: {:?}", config);
+        INJECTOR.set(config)
+            .map_err(|_| anyhow::anyhow!("Chaos injector is already installed"))
+    }
+
+    /// Returns the delay to apply and whether to fail, if `point` is armed.
+    pub(super) fn roll(point: FaultPoint) -> Option<(Duration, bool)> {
+        let config = INJECTOR.get().filter(|c| c.points.contains(&point))?;
+        let mut rng = rand::thread_rng();
+
+        let delay = if config.max_delay.is_zero() {
+            Duration::ZERO
+        } else {
+            rng.gen_range(Duration::ZERO..=config.max_delay)
+        };
+        Some((delay, rng.gen_bool(config.failure_rate.clamp(0.0, 1.0))))
+    }
+}
+
+#[cfg(feature = "chaos")]
+pub use injector::{ChaosConfig, install};
+
+/// Hook for async call sites.
+pub async fn inject(point: FaultPoint) -> anyhow::Result<()> {
+    #[cfg(feature = "chaos")]
+    if let Some((delay, fail)) = injector::roll(point) {
+        tokio::time::sleep(delay).await;
+        if fail {
+            anyhow::bail!("Injected fault at {:?}", point);
+        }
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    let _ = point;
+
+    Ok(())
+}
+
+/// Hook for blocking call sites, such as RocksDB writes.
+pub fn inject_blocking(point: FaultPoint) -> anyhow::Result<()> {
+    #[cfg(feature = "chaos")]
+    if let Some((delay, fail)) = injector::roll(point) {
+        std::thread::sleep(delay);
+        if fail {
+            anyhow::bail!("Injected fault at {:?}", point);
+        }
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    let _ = point;
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_util::future::BoxFuture;
+use tokio::sync::watch;
+
+/// Where time-driven components read the time and wait: batch sealing,
+/// mempool TTLs and aging, spam score decay, preflight expiry, degraded mode
+/// and aggregate waits. Nodes run on `SystemClock`; tests hand components a
+/// `ManualClock` and advance it instead of sleeping.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Monotonic time, for intervals and expiry
+    fn now(&self) -> Instant;
+
+    /// Wall clock time, for timestamps such as block times
+    fn system_time(&self) -> SystemTime;
+
+    /// Resolves once `now()` reaches `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'_, ()>;
+}
+
+/// The operating system's clocks and tokio's timers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'_, ()> {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
+
+/// A clock that stands still until `advance` moves it, waking every sleep
+/// whose deadline it passes. Both its monotonic and wall clock time start
+/// at the real time it was created.
+#[derive(Debug)]
+pub struct ManualClock {
+    started: Instant,
+    started_at: SystemTime,
+    elapsed: watch::Sender<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            elapsed: watch::Sender::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += by);
+    }
+
+    /// How far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.borrow()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.started + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.started_at + self.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'_, ()> {
+        let mut elapsed = self.elapsed.subscribe();
+        Box::pin(async move {
+            // The sender lives as long as the clock, which outlives the borrow
+            let _ = elapsed.wait_for(|elapsed| self.started + *elapsed >= deadline).await;
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use http::HeaderValue;
+use serde::{Deserialize, Deserializer};
+use solana_sdk::pubkey::Pubkey;
+
+/// Read from the working directory when no --config is given, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "rollup.toml";
+
+/// Node settings from a TOML file. Keys are the CLI flag names, and a flag
+/// given on the command line overrides the file.
+///
+/// ```toml
+/// port = 8899
+/// db-path = "./rollup_db"
+/// solana-rpc = "http://localhost:8899"
+/// batch-interval-ms = 2000
+/// authority-keypairs = ["authority.json", "next-authority.json@50000"]
+/// cors-origins = ["https://app.example.com"]
+///
+/// [fees]
+/// lamports-per-signature = 5000
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RollupConfig {
+    pub port: Option<u16>,
+    pub db_path: Option<String>,
+    pub solana_rpc: Option<String>,
+    pub batch_interval_ms: Option<u64>,
+    pub max_batch_bytes: Option<usize>,
+    /// PATH[@BATCH] specs, as for --authority-keypair
+    pub authority_keypairs: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_pubkey")]
+    pub rollup_program_id: Option<Pubkey>,
+    #[serde(default, deserialize_with = "deserialize_pubkey")]
+    pub bridge_program_id: Option<Pubkey>,
+    #[serde(default)]
+    pub fees: FeesConfig,
+    /// Origins browsers may call the RPC from; any when unset
+    pub cors_origins: Option<Vec<String>>,
+}
+
+/// The `[fees]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FeesConfig {
+    pub lamports_per_signature: Option<u64>,
+    pub base_fee_lamports: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_pubkey")]
+    pub fee_collector: Option<Pubkey>,
+}
+
+impl RollupConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))
+    }
+
+    /// Parses and validates a config file's contents.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks what the CLI's value parsers would check for the same flags.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.port == Some(0) {
+            anyhow::bail!("port must not be 0");
+        }
+        if self.db_path.as_deref().is_some_and(|db_path| db_path.trim().is_empty()) {
+            anyhow::bail!("db-path must not be empty");
+        }
+        if let Some(url) = &self.solana_rpc
+            && !(url.starts_with("http://") || url.starts_with("https://"))
+        {
+            anyhow::bail!("solana-rpc must be an http(s) URL, got {}", url);
+        }
+        if self.batch_interval_ms == Some(0) {
+            anyhow::bail!("batch-interval-ms must be at least 1");
+        }
+        if self.max_batch_bytes == Some(0) {
+            anyhow::bail!("max-batch-bytes must be at least 1");
+        }
+        if self.authority_keypairs.iter().flatten().any(|spec| spec.trim().is_empty()) {
+            anyhow::bail!("authority-keypairs must not contain empty entries");
+        }
+        for origin in self.cors_origins.iter().flatten() {
+            cors_origin(origin)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An allowed CORS origin, scheme and host with an optional port, as
+/// browsers send it in the Origin header.
+pub fn cors_origin(origin: &str) -> anyhow::Result<HeaderValue> {
+    let Some((_, host)) = origin.split_once("://").filter(|(scheme, _)| *scheme == "http" || *scheme == "https") else {
+        anyhow::bail!("Invalid CORS origin {}: expected http(s)://HOST[:PORT] (leave origins unset to allow any)", origin);
+    };
+    if host.is_empty() || host.contains('/') {
+        anyhow::bail!("Invalid CORS origin {}: expected http(s)://HOST[:PORT] without a path", origin);
+    }
+
+    HeaderValue::from_str(origin).map_err(|e| anyhow::anyhow!("Invalid CORS origin {}: {}", origin, e))
+}
+
+fn deserialize_pubkey<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Pubkey>, D::Error> {
+    let pubkey = String::deserialize(deserializer)?;
+    Pubkey::from_str(&pubkey).map(Some).map_err(serde::de::Error::custom)
+}
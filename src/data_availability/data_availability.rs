@@ -0,0 +1,295 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{ConfirmedBlock, TransactionWithStatusMeta, UiTransactionEncoding};
+
+use crate::{
+    batch_processor::batch_processor::{BatchCommitment, BatchReport, RollupInstruction, StoredBatch},
+    metrics::metrics,
+    sequencer::block::BlockHeader,
+    state_manager::state_manager::StateManager,
+};
+
+const OBJECT_STORE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a batch served by `DataAvailability::get_batch` came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BatchSource {
+    Local,
+    L1,
+    ObjectStore,
+}
+
+/// A place posted batch commitments can be fetched back from.
+enum DaSource {
+    /// The rollup instruction of the L1 transaction the batch landed in
+    L1(RpcClient),
+    /// `<url>/<batch_id>` serving the serialized commitment, e.g. an archive
+    /// mirroring L1
+    ObjectStore { url: String, client: reqwest::Client },
+}
+
+impl DaSource {
+    fn kind(&self) -> BatchSource {
+        match self {
+            DaSource::L1(_) => BatchSource::L1,
+            DaSource::ObjectStore { .. } => BatchSource::ObjectStore,
+        }
+    }
+
+    async fn fetch(&self, report: &BatchReport) -> anyhow::Result<Vec<u8>> {
+        match self {
+            DaSource::L1(client) => {
+                // Earlier attempts never landed; the last one confirmed
+                let signature = report.final_slot
+                    .and(report.signatures.last())
+                    .ok_or_else(|| anyhow::anyhow!("Batch {} was never confirmed on L1", report.batch_id))?;
+                let data = rollup_instruction(client, signature).await?
+                    .ok_or_else(|| anyhow::anyhow!("L1 transaction {} failed", signature))?;
+
+                match bincode::deserialize(&data) {
+                    Ok(RollupInstruction::Commit { commitment }) => Ok(commitment),
+                    Ok(RollupInstruction::CommitAggregate { .. }) => anyhow::bail!(
+                        "Batch {} was committed in an aggregate; its data is on the object store it was published to",
+                        report.batch_id
+                    ),
+                    Ok(RollupInstruction::CommitChunks { batch_id, count, len, .. }) if batch_id == report.batch_id => {
+                        join_chunks(client, report, count, len as usize).await
+                    }
+                    Ok(_) => anyhow::bail!("L1 transaction {} does not commit batch {}", signature, report.batch_id),
+                    // Posted before commitments were wrapped in an instruction
+                    Err(_) => Ok(data),
+                }
+            }
+            DaSource::ObjectStore { url, client } => {
+                let response = client.get(format!("{}/{}", url.trim_end_matches('/'), report.batch_id))
+                    .send().await?
+                    .error_for_status()?;
+                Ok(response.bytes().await?.to_vec())
+            }
+        }
+    }
+}
+
+/// Data of the rollup instruction (always the last one) of an L1
+/// transaction, or `None` if the transaction failed.
+async fn rollup_instruction(client: &RpcClient, signature: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let confirmed = client.get_transaction_with_config(&Signature::from_str(signature)?, config).await?;
+    if confirmed.transaction.meta.is_some_and(|meta| meta.err.is_some()) {
+        return Ok(None);
+    }
+    let transaction = confirmed.transaction.transaction.decode()
+        .ok_or_else(|| anyhow::anyhow!("Undecodable L1 transaction {}", signature))?;
+
+    let instruction = transaction.message.instructions().last()
+        .ok_or_else(|| anyhow::anyhow!("L1 transaction {} has no instructions", signature))?;
+    Ok(Some(instruction.data.clone()))
+}
+
+/// Reassembles a chunked commitment from the chunks among the batch's L1
+/// transactions. Chunks sent again after a failed attempt may have landed
+/// twice; they carry the same bytes, so either copy will do.
+async fn join_chunks(client: &RpcClient, report: &BatchReport, count: u16, len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut chunks = vec![None; count as usize];
+    // The last transaction is the commit itself
+    let sent = &report.signatures[..report.signatures.len().saturating_sub(1)];
+    for signature in sent {
+        // Attempts that never landed can't be fetched
+        let Ok(Some(data)) = rollup_instruction(client, signature).await else {
+            continue;
+        };
+        if let Ok(RollupInstruction::WriteChunk { batch_id, index, data, .. }) = bincode::deserialize(&data)
+            && batch_id == report.batch_id
+            && let Some(chunk) = chunks.get_mut(index as usize)
+        {
+            *chunk = Some(data);
+        }
+    }
+
+    let mut commitment = Vec::with_capacity(len);
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let chunk = chunk.ok_or_else(|| anyhow::anyhow!("Chunk {}/{} of batch {} is not on L1", index + 1, count, report.batch_id))?;
+        commitment.extend_from_slice(&chunk);
+    }
+    if commitment.len() != len {
+        anyhow::bail!("Chunks of batch {} join into {} bytes, expected {}", report.batch_id, commitment.len(), len);
+    }
+
+    Ok(commitment)
+}
+
+/// Publishes serialized batch commitments to an object store as
+/// `PUT <url>/<batch_id>`, the layout the object store DA source reads.
+pub struct DaPublisher {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl DaPublisher {
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            anyhow::bail!("Invalid DA publish URL {}: expected an http(s) URL", url);
+        }
+
+        Ok(Self {
+            url: url.to_string(),
+            client: reqwest::Client::builder().timeout(OBJECT_STORE_TIMEOUT).build()?,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub async fn publish(&self, batch_id: u64, commitment: &[u8]) -> anyhow::Result<()> {
+        self.client.put(format!("{}/{}", self.url.trim_end_matches('/'), batch_id))
+            .body(commitment.to_vec())
+            .send().await?
+            .error_for_status()?;
+        metrics::increment("da_batches_published");
+
+        Ok(())
+    }
+}
+
+/// Serves stored batches, falling back to DA sources for batches pruned
+/// locally. Fetched data is only used if it hashes to the commitment
+/// recorded when the batch was posted, so a DA source can't substitute
+/// different transactions. With no sources, only local batches are served.
+#[derive(Default)]
+pub struct DataAvailability {
+    sources: Vec<DaSource>,
+}
+
+impl DataAvailability {
+    /// `specs` are tried in order: `l1` for the L1 transaction history (via
+    /// `solana_rpc_url`), or an http(s) URL of an object store.
+    pub fn new(specs: &[String], solana_rpc_url: Option<&str>) -> anyhow::Result<Self> {
+        let sources = specs.iter()
+            .map(|spec| match spec.as_str() {
+                "l1" => {
+                    let url = solana_rpc_url
+                        .ok_or_else(|| anyhow::anyhow!("The l1 DA source needs --solana-rpc"))?;
+                    Ok(DaSource::L1(RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed())))
+                }
+                url if url.starts_with("http://") || url.starts_with("https://") => Ok(DaSource::ObjectStore {
+                    url: url.to_string(),
+                    client: reqwest::Client::builder().timeout(OBJECT_STORE_TIMEOUT).build()?,
+                }),
+                _ => anyhow::bail!("Invalid DA source {}: expected l1 or an http(s) URL", spec),
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { sources })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// The batch from local storage, or else rebuilt from the first DA
+    /// source that returns the posted commitment. `None` if the batch isn't
+    /// stored locally and was never posted.
+    pub async fn get_batch(
+        &self,
+        state_manager: &StateManager,
+        batch_id: u64
+    ) -> anyhow::Result<Option<(StoredBatch, BatchSource)>> {
+        if let Some(batch) = state_manager.get_batch(batch_id)? {
+            return Ok(Some((batch, BatchSource::Local)));
+        }
+
+        let (Some(report), Some(posted)) = (
+            state_manager.get_batch_report(batch_id)?,
+            state_manager.get_posted_commitment(batch_id)?
+        ) else {
+            return Ok(None);
+        };
+        if posted.netted_transactions > 0 {
+            anyhow::bail!(
+                "Batch {} is not stored locally, and {} of its transactions were netted out of the posted commitment",
+                batch_id, posted.netted_transactions
+            );
+        }
+
+        let mut failures = Vec::new();
+        for source in &self.sources {
+            let data = match source.fetch(&report).await {
+                Ok(data) => data,
+                Err(e) => {
+                    failures.push(format!("{:?}: {}", source.kind(), e));
+                    continue;
+                }
+            };
+
+            let digest: [u8; 32] = Sha256::digest(&data).into();
+            if digest != posted.digest {
+                metrics::increment("da_commitment_mismatches");
+                failures.push(format!("{:?}: data does not match the posted commitment", source.kind()));
+                continue;
+            }
+
+            let commitment: BatchCommitment = bincode::deserialize(&data)?;
+            metrics::increment("da_batches_fetched");
+            return Ok(Some((
+                StoredBatch {
+                    transactions: commitment.decode_transactions()?,
+                    state_root: posted.state_root,
+                },
+                source.kind()
+            )));
+        }
+
+        anyhow::bail!("Batch {} is not stored locally or retrievable from DA: {}", batch_id, failures.join("; "))
+    }
+
+    /// The block at `slot` as Solana's `ConfirmedBlock`, its transactions
+    /// from `get_batch` with their status metadata. `None` if there is no
+    /// such block or its batch isn't available.
+    pub async fn get_block(
+        &self,
+        state_manager: &StateManager,
+        slot: u64
+    ) -> anyhow::Result<Option<(BlockHeader, ConfirmedBlock)>> {
+        let Some(header) = state_manager.get_block(slot)? else {
+            return Ok(None);
+        };
+        let Some((batch, _)) = self.get_batch(state_manager, slot).await? else {
+            return Ok(None);
+        };
+
+        let mut transactions = Vec::with_capacity(batch.transactions.len());
+        for tx in &batch.transactions {
+            let record = match tx.signatures.first() {
+                Some(signature) => state_manager.get_transaction(signature)?,
+                None => None,
+            };
+            transactions.push(match record {
+                Some(record) => record.with_status_meta(),
+                None => TransactionWithStatusMeta::MissingMetadata((**tx).clone()),
+            });
+        }
+
+        Ok(Some((header, ConfirmedBlock {
+            previous_blockhash: header.parent_blockhash.to_string(),
+            blockhash: header.blockhash.to_string(),
+            parent_slot: slot.saturating_sub(1),
+            transactions,
+            rewards: vec![],
+            num_partitions: None,
+            block_time: Some(header.block_time),
+            block_height: Some(slot),
+        })))
+    }
+}
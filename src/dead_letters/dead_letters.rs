@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sequencer::sequencer::DropReason,
+    transaction_processor::wire_transaction::WireTransaction,
+};
+
+/// Why an accepted transaction will never be finalized. Externally tagged,
+/// since records are stored with bincode.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeadLetterReason {
+    /// Left the mempool without being sealed into a batch
+    #[serde(rename_all = "camelCase")]
+    Dropped { drop_reason: DropReason },
+    /// Executed and sealed on L2 into a batch that could not be posted to
+    /// L1. Its effects stand; it is the batch that needs posting again
+    #[serde(rename_all = "camelCase")]
+    BatchNotPosted { batch_id: u64, error: String },
+    /// Failed when its block was being built, so it was never sealed
+    #[serde(rename_all = "camelCase")]
+    ExecutionFailed { error: String },
+}
+
+impl DeadLetterReason {
+    /// Whether the transaction executed, so resubmitting it would be refused
+    /// as already processed. The others changed no state and can be
+    /// resubmitted once whatever failed them is fixed.
+    pub fn executed(&self) -> bool {
+        matches!(self, Self::BatchNotPosted { .. })
+    }
+}
+
+/// An accepted transaction that failed permanently, kept with the reason so
+/// operators can inspect it (and resubmit it, unless it executed) instead of
+/// digging through logs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: u64,
+    pub reason: DeadLetterReason,
+    /// Unix seconds
+    pub recorded_at: u64,
+    pub transaction: WireTransaction,
+}
@@ -0,0 +1,114 @@
+use std::sync::LazyLock;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    transaction::Transaction,
+};
+use solana_sdk_ids::system_program;
+
+/// Seed, followed by the L2 recipient, of the bridge program account that
+/// holds deposits for that recipient
+pub const DEPOSIT_SEED: &[u8] = b"deposit";
+
+/// Seed of the bridge program account every deposit is swept into
+pub const VAULT_SEED: &[u8] = b"vault";
+
+/// Prefix of the bridge program's deposit instruction: the first 8 bytes of
+/// sha256("global:deposit"), as Anchor derives instruction discriminators.
+static DEPOSIT_INSTRUCTION_DISCRIMINATOR: LazyLock<[u8; 8]> = LazyLock::new(|| {
+    Sha256::digest(b"global:deposit")[..8]
+        .try_into()
+        .expect("sha256 is 32 bytes")
+});
+
+/// Arguments of the deposit instruction, laid out as the bridge program
+/// reads them (fixed-size little-endian fields, as in its events).
+#[derive(Serialize)]
+struct DepositArgs {
+    l2_recipient: Pubkey,
+    lamports: u64,
+}
+
+/// The L1 deposit address of `l2_recipient`: the bridge program account,
+/// with its bump seed, that lamports for that recipient are deposited
+/// through. Integrators can show it to users without talking to L1.
+pub fn deposit_address(bridge_program_id: &Pubkey, l2_recipient: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DEPOSIT_SEED, l2_recipient.as_ref()], bridge_program_id)
+}
+
+/// The bridge program's vault, with its bump seed.
+pub fn vault_address(bridge_program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED], bridge_program_id)
+}
+
+/// Deposits `lamports` from `l1_sender` for `l2_recipient`, who is credited
+/// on L2 once the bridge program's `Deposit` event for it is finalized.
+pub fn deposit_instruction(bridge_program_id: &Pubkey, l1_sender: &Pubkey, l2_recipient: &Pubkey, lamports: u64) -> Instruction {
+    let mut data = DEPOSIT_INSTRUCTION_DISCRIMINATOR.to_vec();
+    data.extend(bincode::serialize(&DepositArgs { l2_recipient: *l2_recipient, lamports }).expect("deposit arguments serialize"));
+
+    Instruction::new_with_bytes(*bridge_program_id, &data, vec![
+        AccountMeta::new(*l1_sender, true),
+        AccountMeta::new(deposit_address(bridge_program_id, l2_recipient).0, false),
+        AccountMeta::new(vault_address(bridge_program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ])
+}
+
+/// A signed L1 transaction depositing `lamports` from `l1_sender`, who
+/// also pays its fee, for `l2_recipient`.
+pub fn deposit_transaction(
+    bridge_program_id: &Pubkey,
+    l1_sender: &Keypair,
+    l2_recipient: &Pubkey,
+    lamports: u64,
+    recent_blockhash: Hash
+) -> Transaction {
+    let instruction = deposit_instruction(bridge_program_id, &l1_sender.pubkey(), l2_recipient, lamports);
+    Transaction::new_signed_with_payer(&[instruction], Some(&l1_sender.pubkey()), &[l1_sender], recent_blockhash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deposit_address_is_the_recipients_program_address() {
+        let bridge_program_id = Pubkey::new_unique();
+        let l2_recipient = Pubkey::new_unique();
+
+        let (address, bump) = deposit_address(&bridge_program_id, &l2_recipient);
+        let derived = Pubkey::create_program_address(&[DEPOSIT_SEED, l2_recipient.as_ref(), &[bump]], &bridge_program_id).unwrap();
+        assert_eq!(address, derived);
+        assert!(!address.is_on_curve());
+        assert_ne!(address, deposit_address(&bridge_program_id, &Pubkey::new_unique()).0);
+    }
+
+    #[test]
+    fn deposit_instruction_encodes_recipient_and_lamports() {
+        let bridge_program_id = Pubkey::new_unique();
+        let l1_sender = Keypair::new();
+        let l2_recipient = Pubkey::new_unique();
+
+        let tx = deposit_transaction(&bridge_program_id, &l1_sender, &l2_recipient, 5_000, Hash::default());
+        tx.verify().unwrap();
+        let instruction = &tx.message.instructions[0];
+        assert_eq!(&instruction.data[..8], DEPOSIT_INSTRUCTION_DISCRIMINATOR.as_slice());
+        assert_eq!(&instruction.data[8..40], l2_recipient.as_ref());
+        assert_eq!(&instruction.data[40..], 5_000u64.to_le_bytes().as_slice());
+
+        let accounts: Vec<Pubkey> = instruction.accounts.iter().map(|&index| tx.message.account_keys[index as usize]).collect();
+        assert_eq!(accounts, vec![
+            l1_sender.pubkey(),
+            deposit_address(&bridge_program_id, &l2_recipient).0,
+            vault_address(&bridge_program_id).0,
+            system_program::id(),
+        ]);
+    }
+}
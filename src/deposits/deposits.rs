@@ -0,0 +1,90 @@
+use std::sync::LazyLock;
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+
+/// Prefix of the bridge program's deposit event: the first 8 bytes of
+/// sha256("event:Deposit"), as Anchor derives event discriminators.
+static DEPOSIT_EVENT_DISCRIMINATOR: LazyLock<[u8; 8]> = LazyLock::new(|| {
+    Sha256::digest(b"event:Deposit")[..8]
+        .try_into()
+        .expect("sha256 is 32 bytes")
+});
+
+/// Event the bridge program logs (with `sol_log_data`) for every deposit
+/// into its vault. Ids are assigned by the program and never reused, so they
+/// are what keeps a deposit from being credited twice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositEvent {
+    pub deposit_id: u64,
+    pub l1_sender: Pubkey,
+    pub l2_recipient: Pubkey,
+    pub lamports: u64,
+}
+
+/// A deposit seen on L1, credited to its L2 recipient when the next block is
+/// sealed. `batch_id` is that block once it has been credited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositRecord {
+    pub id: u64,
+    pub l1_signature: String,
+    pub l1_slot: u64,
+    pub l1_sender: Pubkey,
+    pub l2_recipient: Pubkey,
+    pub lamports: u64,
+    pub batch_id: Option<u64>,
+}
+
+/// Deposit events the bridge program itself logged in a transaction's logs.
+/// Data logged by any other program, including ones the bridge invokes or
+/// that invoke it, is ignored, so no other program can forge a deposit.
+pub fn parse_deposit_events(bridge_program_id: &Pubkey, logs: &[String]) -> Vec<DepositEvent> {
+    program_data(bridge_program_id, logs)
+        .into_iter()
+        .filter_map(decode_deposit_event)
+        .collect()
+}
+
+/// First base64 field of every `Program data:` line `program_id` logged
+/// while it was the innermost program running, in log order.
+pub fn program_data<'a>(program_id: &Pubkey, logs: &'a [String]) -> Vec<&'a str> {
+    let program_id = program_id.to_string();
+    let mut invoked: Vec<&str> = Vec::new();
+    let mut data_logs = Vec::new();
+
+    for log in logs {
+        let Some(rest) = log.strip_prefix("Program ") else {
+            continue;
+        };
+        if let Some(data) = rest.strip_prefix("data: ") {
+            if invoked.last() == Some(&program_id.as_str())
+                && let Some(first) = data.split(' ').next()
+            {
+                data_logs.push(first);
+            }
+            continue;
+        }
+
+        let mut words = rest.split(' ');
+        match (words.next(), words.next()) {
+            (Some(program_id), Some("invoke")) => invoked.push(program_id),
+            (Some(program_id), Some("success" | "failed:"))
+                if invoked.last() == Some(&program_id) =>
+            {
+                invoked.pop();
+            }
+            _ => {}
+        }
+    }
+
+    data_logs
+}
+
+fn decode_deposit_event(data: &str) -> Option<DepositEvent> {
+    let bytes = BASE64_STANDARD.decode(data).ok()?;
+    let payload = bytes.strip_prefix(DEPOSIT_EVENT_DISCRIMINATOR.as_slice())?;
+    bincode::deserialize(payload).ok()
+}
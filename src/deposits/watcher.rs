@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+use crate::{
+    deposits::deposits::{DepositRecord, parse_deposit_events},
+    l1_watcher::program_logs::ProgramLogHandler,
+    metrics::metrics,
+    state_manager::state_manager::StateManager,
+};
+
+/// Queues the deposits logged by the bridge program to be credited on L2,
+/// for a `ProgramLogWatcher` of the bridge program's finalized history, so a
+/// deposit is never credited from a fork L1 later abandons.
+pub struct DepositHandler {
+    state_manager: Arc<StateManager>,
+}
+
+impl DepositHandler {
+    pub fn new(state_manager: Arc<StateManager>) -> Self {
+        Self { state_manager }
+    }
+}
+
+impl ProgramLogHandler for DepositHandler {
+    fn name(&self) -> &'static str {
+        "deposits"
+    }
+
+    fn handle(&self, bridge_program_id: &Pubkey, signature: &Signature, slot: u64, logs: &[String]) -> anyhow::Result<()> {
+        for event in parse_deposit_events(bridge_program_id, logs) {
+            let record = DepositRecord {
+                id: event.deposit_id,
+                l1_signature: signature.to_string(),
+                l1_slot: slot,
+                l1_sender: event.l1_sender,
+                l2_recipient: event.l2_recipient,
+                lamports: event.lamports,
+                batch_id: None,
+            };
+            if self.state_manager.queue_deposit(&record)? {
+                metrics::increment("deposits_queued");
+                println!(
+                    "Deposit {}: {} lamports from {} to {} (L1 {})",
+                    record.id,
+                    record.lamports,
+                    record.l1_sender,
+                    record.l2_recipient,
+                    record.l1_signature
+                );
+            } else {
+                metrics::increment("deposits_duplicate");
+                eprintln!(
+                    "Ignoring deposit {} from L1 {}: already seen",
+                    record.id, record.l1_signature
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
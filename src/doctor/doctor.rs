@@ -0,0 +1,338 @@
+use std::fmt;
+use std::net::{SocketAddr, TcpListener};
+use std::str::FromStr;
+use std::time::Duration;
+
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{hash::Hash, pubkey::Pubkey};
+
+use crate::batch_processor::authority::{AuthorityKey, AuthoritySchedule};
+use crate::state_manager::state_manager::{StateManager, SCHEMA_VERSION};
+
+/// How long each L1 request may take before the RPC counts as unreachable
+const L1_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The L1 cluster a node expects to post to, identified by its genesis hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L1Cluster {
+    MainnetBeta,
+    Testnet,
+    Devnet,
+    /// Any other cluster, such as a local test validator
+    Custom(Hash),
+}
+
+impl L1Cluster {
+    const MAINNET_BETA_GENESIS: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+    const TESTNET_GENESIS: &str = "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY";
+    const DEVNET_GENESIS: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+
+    pub fn genesis_hash(&self) -> Hash {
+        match self {
+            L1Cluster::MainnetBeta => Hash::from_str(Self::MAINNET_BETA_GENESIS).unwrap(),
+            L1Cluster::Testnet => Hash::from_str(Self::TESTNET_GENESIS).unwrap(),
+            L1Cluster::Devnet => Hash::from_str(Self::DEVNET_GENESIS).unwrap(),
+            L1Cluster::Custom(hash) => *hash,
+        }
+    }
+
+    pub fn from_genesis_hash(hash: Hash) -> Self {
+        [L1Cluster::MainnetBeta, L1Cluster::Testnet, L1Cluster::Devnet]
+            .into_iter()
+            .find(|cluster| cluster.genesis_hash() == hash)
+            .unwrap_or(L1Cluster::Custom(hash))
+    }
+}
+
+impl FromStr for L1Cluster {
+    type Err = String;
+
+    /// `mainnet-beta`, `testnet`, `devnet`, or a cluster's genesis hash.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet-beta" => Ok(L1Cluster::MainnetBeta),
+            "testnet" => Ok(L1Cluster::Testnet),
+            "devnet" => Ok(L1Cluster::Devnet),
+            _ => Hash::from_str(s)
+                .map(L1Cluster::from_genesis_hash)
+                .map_err(|_| format!("expected mainnet-beta, testnet, devnet or a genesis hash, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for L1Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            L1Cluster::MainnetBeta => write!(f, "mainnet-beta"),
+            L1Cluster::Testnet => write!(f, "testnet"),
+            L1Cluster::Devnet => write!(f, "devnet"),
+            L1Cluster::Custom(hash) => write!(f, "cluster {}", hash),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    /// Works, but probably not what a production node wants
+    Warning,
+    /// The node can't run correctly until this is fixed
+    Failed,
+    /// Not configured, so nothing to check
+    Skipped,
+}
+
+/// Outcome of one check; `detail` says what to do about anything but `Ok`.
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self { name, status, detail: detail.into() }
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let icon = match self.status {
+            CheckStatus::Ok => "✅",
+            CheckStatus::Warning => "⚠️ ",
+            CheckStatus::Failed => "❌",
+            CheckStatus::Skipped => "➖",
+        };
+        write!(f, "{} {}: {}", icon, self.name, self.detail)
+    }
+}
+
+/// The state database as the checks see it.
+pub enum Database<'a> {
+    /// Nothing at the path yet; starting creates a new chain there
+    Missing,
+    Open(&'a StateManager),
+    Failed(String),
+}
+
+/// What a node is about to run with.
+pub struct DoctorConfig {
+    pub db_path: String,
+    /// Genesis hash of the chain the database must belong to
+    pub genesis_hash: Option<Hash>,
+    pub authority_keypairs: Vec<String>,
+    /// Program on L1 that batches are posted to
+    pub rollup_program_id: Option<Pubkey>,
+    pub solana_rpc: Option<String>,
+    pub l1_cluster: Option<L1Cluster>,
+    /// Addresses the RPC servers will listen on
+    pub listen_addresses: Vec<SocketAddr>,
+}
+
+/// Self-checks run by `tiny-rollup doctor` and before a node serves
+/// traffic, so misconfiguration surfaces as one actionable message at
+/// startup rather than as failed batch submissions later.
+pub struct Doctor {
+    config: DoctorConfig,
+}
+
+impl Doctor {
+    pub fn new(config: DoctorConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn run(&self, database: &Database<'_>) -> Vec<CheckResult> {
+        let client = self.config.solana_rpc.as_ref()
+            .map(|url| RpcClient::new_with_timeout(url.clone(), L1_TIMEOUT));
+
+        vec![
+            self.check_database(database),
+            self.check_genesis(database),
+            self.check_l1(client.as_ref()).await,
+            self.check_authority(database, client.as_ref()).await,
+            self.check_rollup_program(client.as_ref()).await,
+            self.check_ports(),
+        ]
+    }
+
+    fn check_database(&self, database: &Database<'_>) -> CheckResult {
+        const NAME: &str = "database";
+        let db_path = &self.config.db_path;
+
+        match database {
+            Database::Missing => CheckResult::new(NAME, CheckStatus::Ok, format!("none at {} yet; a new chain will be created", db_path)),
+            Database::Failed(e) => CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                format!("can't open {}: {}; check --db-path points at a tiny-rollup database this user can write", db_path, e)
+            ),
+            Database::Open(state_manager) => match state_manager.schema_version() {
+                Ok(Some(version)) => CheckResult::new(NAME, CheckStatus::Ok, format!("{} at schema version {}", db_path, version)),
+                Ok(None) => CheckResult::new(
+                    NAME,
+                    CheckStatus::Ok,
+                    format!("{} predates schema versioning; it is migrated to version {} on start", db_path, SCHEMA_VERSION)
+                ),
+                Err(e) => CheckResult::new(NAME, CheckStatus::Failed, format!("can't read the schema version of {}: {}", db_path, e)),
+            },
+        }
+    }
+
+    fn check_genesis(&self, database: &Database<'_>) -> CheckResult {
+        const NAME: &str = "genesis";
+        let Some(expected) = self.config.genesis_hash else {
+            return CheckResult::new(NAME, CheckStatus::Skipped, "no --genesis-hash to compare against");
+        };
+
+        match database {
+            Database::Missing => CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                format!("no database at {}; import an export of chain {} (tiny-rollup import) before starting", self.config.db_path, expected)
+            ),
+            Database::Failed(_) => CheckResult::new(NAME, CheckStatus::Failed, "database didn't open"),
+            Database::Open(state_manager) => match state_manager.genesis_hash() {
+                Ok(Some(genesis_hash)) if Hash::new_from_array(genesis_hash) == expected => {
+                    CheckResult::new(NAME, CheckStatus::Ok, format!("chain {}", expected))
+                }
+                Ok(Some(genesis_hash)) => CheckResult::new(
+                    NAME,
+                    CheckStatus::Failed,
+                    format!(
+                        "{} belongs to chain {}, but --genesis-hash is {}; point --db-path at this chain's database",
+                        self.config.db_path, Hash::new_from_array(genesis_hash), expected
+                    )
+                ),
+                Ok(None) => CheckResult::new(
+                    NAME,
+                    CheckStatus::Warning,
+                    format!("{} has no genesis hash yet; one is created on start and won't match {}", self.config.db_path, expected)
+                ),
+                Err(e) => CheckResult::new(NAME, CheckStatus::Failed, format!("can't read the genesis hash: {}", e)),
+            },
+        }
+    }
+
+    async fn check_l1(&self, client: Option<&RpcClient>) -> CheckResult {
+        const NAME: &str = "l1";
+        let Some(client) = client else {
+            return CheckResult::new(NAME, CheckStatus::Failed, "no --solana-rpc given");
+        };
+
+        let genesis_hash = match client.get_genesis_hash().await {
+            Ok(genesis_hash) => genesis_hash,
+            Err(e) => return CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                format!("{} is unreachable: {}; check --solana-rpc and the network", client.url(), e)
+            ),
+        };
+        let cluster = L1Cluster::from_genesis_hash(genesis_hash);
+
+        match self.config.l1_cluster {
+            Some(expected) if expected != cluster => CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                format!("{} serves {}, but --l1-cluster is {}; point --solana-rpc at a {} RPC", client.url(), cluster, expected, expected)
+            ),
+            Some(_) => CheckResult::new(NAME, CheckStatus::Ok, format!("{} serves {}", client.url(), cluster)),
+            None => CheckResult::new(NAME, CheckStatus::Ok, format!("{} serves {} (pin it with --l1-cluster)", client.url(), cluster)),
+        }
+    }
+
+    async fn check_authority(&self, database: &Database<'_>, client: Option<&RpcClient>) -> CheckResult {
+        const NAME: &str = "authority";
+        if self.config.authority_keypairs.is_empty() {
+            return CheckResult::new(
+                NAME,
+                CheckStatus::Warning,
+                "no --authority-keypair or TINY_ROLLUP_AUTHORITY_KEYPAIR; batches are signed by a throwaway key without L1 funds"
+            );
+        }
+
+        let schedule = match self.config.authority_keypairs.iter()
+            .map(|spec| AuthorityKey::from_spec(spec))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .and_then(AuthoritySchedule::new)
+        {
+            Ok(schedule) => schedule,
+            Err(e) => return CheckResult::new(NAME, CheckStatus::Failed, e.to_string()),
+        };
+        let next_batch = match database {
+            Database::Open(state_manager) => match state_manager.batch_count() {
+                Ok(next_batch) => next_batch,
+                Err(e) => return CheckResult::new(NAME, CheckStatus::Failed, format!("can't read the next batch id: {}", e)),
+            },
+            Database::Missing | Database::Failed(_) => 0,
+        };
+        let authority = schedule.scheduled(next_batch);
+
+        let Some(client) = client else {
+            return CheckResult::new(NAME, CheckStatus::Skipped, format!("{} loaded; no --solana-rpc to check its balance", authority));
+        };
+        match client.get_balance(&authority).await {
+            Ok(0) => CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                format!("{} has no lamports on L1 and can't pay for batch submissions; fund it first", authority)
+            ),
+            Ok(balance) => CheckResult::new(NAME, CheckStatus::Ok, format!("{} signs batch {} with {} lamports", authority, next_batch, balance)),
+            Err(e) => CheckResult::new(NAME, CheckStatus::Failed, format!("can't fetch the balance of {}: {}", authority, e)),
+        }
+    }
+
+    async fn check_rollup_program(&self, client: Option<&RpcClient>) -> CheckResult {
+        const NAME: &str = "rollup program";
+        let Some(program_id) = self.config.rollup_program_id else {
+            if self.config.solana_rpc.is_none() {
+                return CheckResult::new(NAME, CheckStatus::Skipped, "no --rollup-program-id or --solana-rpc; batches aren't posted");
+            }
+            return CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                "no --rollup-program-id; batches have no program to be posted to on L1"
+            );
+        };
+        let Some(client) = client else {
+            return CheckResult::new(NAME, CheckStatus::Skipped, format!("no --solana-rpc to look up {}", program_id));
+        };
+
+        match client.get_account_with_commitment(&program_id, client.commitment()).await {
+            Ok(response) => match response.value {
+                Some(account) if account.executable => CheckResult::new(
+                    NAME,
+                    CheckStatus::Ok,
+                    format!("{} is deployed (owner {})", program_id, account.owner)
+                ),
+                Some(_) => CheckResult::new(
+                    NAME,
+                    CheckStatus::Failed,
+                    format!("{} exists on L1 but isn't a program; check --rollup-program-id", program_id)
+                ),
+                None => CheckResult::new(
+                    NAME,
+                    CheckStatus::Failed,
+                    format!("{} doesn't exist on L1; deploy the rollup program or check --rollup-program-id", program_id)
+                ),
+            },
+            Err(e) => CheckResult::new(NAME, CheckStatus::Failed, format!("can't fetch {}: {}", program_id, e)),
+        }
+    }
+
+    fn check_ports(&self) -> CheckResult {
+        const NAME: &str = "ports";
+        for address in &self.config.listen_addresses {
+            if let Err(e) = TcpListener::bind(address) {
+                return CheckResult::new(
+                    NAME,
+                    CheckStatus::Failed,
+                    format!("can't listen on {}: {}; stop whatever holds it or choose another address", address, e)
+                );
+            }
+        }
+
+        let addresses: Vec<_> = self.config.listen_addresses.iter().map(|a| a.to_string()).collect();
+        CheckResult::new(NAME, CheckStatus::Ok, format!("{} free", addresses.join(", ")))
+    }
+}
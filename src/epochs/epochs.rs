@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, rent::Rent};
+
+use crate::state_manager::state_manager::{L2Account, StateManager};
+
+/// Account metadata bytes rent is charged for on top of the data, as on L1.
+const ACCOUNT_STORAGE_OVERHEAD: u64 = 128;
+
+/// Batches are the rollup's blocks; an epoch is a fixed run of them.
+#[derive(Debug, Clone, Copy)]
+pub struct EpochConfig {
+    pub batches_per_epoch: u64,
+    /// Charged every epoch, per byte, to accounts that aren't rent exempt
+    pub rent_lamports_per_byte: u64,
+    /// Receives the rent collected each epoch. Collected rent is burned when unset.
+    pub fee_collector: Option<Pubkey>,
+    /// Epochs of batches and reports kept in the hot database when cold
+    /// storage is attached; older ones migrate at each epoch boundary
+    pub hot_epochs: u64,
+    /// First batch height at which epoch boundaries reap accounts with no
+    /// lamports and no data; reaping is off when unset
+    pub reap_empty_accounts_from: Option<u64>,
+    /// Epochs of batch data kept locally; older batches that can be rebuilt
+    /// from DA are deleted at each epoch boundary. Nothing is pruned when unset
+    pub prune_batches_after_epochs: Option<u64>,
+}
+
+impl EpochConfig {
+    pub fn epoch(&self, batch_id: u64) -> u64 {
+        batch_id / self.batches_per_epoch
+    }
+
+    pub fn first_batch(&self, epoch: u64) -> u64 {
+        epoch.saturating_mul(self.batches_per_epoch)
+    }
+
+    pub fn is_epoch_end(&self, batch_id: u64) -> bool {
+        (batch_id + 1).is_multiple_of(self.batches_per_epoch)
+    }
+
+    pub fn last_batch(&self, epoch: u64) -> u64 {
+        self.first_batch(epoch) + self.batches_per_epoch - 1
+    }
+
+    /// Whether reaping had activated by the end of `epoch`.
+    pub fn reaps_at(&self, epoch: u64) -> bool {
+        self.reap_empty_accounts_from.is_some_and(|from| self.last_batch(epoch) >= from)
+    }
+}
+
+/// What happened at the end of an epoch, including the state root it was
+/// checkpointed at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpochRecord {
+    pub epoch: u64,
+    pub first_batch: u64,
+    pub last_batch: u64,
+    pub rent_lamports_per_byte: u64,
+    pub rent_collected: u64,
+    pub accounts_charged: usize,
+    pub fee_collector: Option<Pubkey>,
+    pub fees_distributed: u64,
+    /// Empty accounts removed, or None if reaping wasn't active yet
+    pub accounts_reaped: Option<usize>,
+    pub checkpoint_root: [u8; 32],
+    /// L1 transaction of the batch that closed the epoch, anchoring the checkpoint
+    pub anchor_signature: Option<String>,
+}
+
+/// Runs the boundary tasks for `epoch` once its last batch has been posted,
+/// then persists the epoch record.
+pub async fn close_epoch(
+    state_manager: &StateManager,
+    config: &EpochConfig,
+    epoch: u64,
+    anchor_signature: Option<String>
+) -> anyhow::Result<EpochRecord> {
+    // Between blocks, never into one being built
+    let _blocks = state_manager.lock_blocks().await;
+    let (rent_collected, accounts_charged) =
+        collect_rent(state_manager, epoch, config.rent_lamports_per_byte).await?;
+    let fees_distributed = distribute_fees(state_manager, config.fee_collector, rent_collected).await?;
+    let accounts_reaped = match config.reaps_at(epoch) {
+        true => Some(reap_empty_accounts(state_manager).await?),
+        false => None,
+    };
+
+    let record = EpochRecord {
+        epoch,
+        first_batch: config.first_batch(epoch),
+        last_batch: config.last_batch(epoch),
+        rent_lamports_per_byte: config.rent_lamports_per_byte,
+        rent_collected,
+        accounts_charged,
+        fee_collector: config.fee_collector,
+        fees_distributed,
+        accounts_reaped,
+        checkpoint_root: state_manager.get_state_root(),
+        anchor_signature,
+    };
+    state_manager.put_epoch_record(&record)?;
+
+    Ok(record)
+}
+
+/// Applies the boundary tasks of a recorded epoch again, with the parameters
+/// it was closed with. Used when replaying.
+pub async fn reapply_epoch(state_manager: &StateManager, record: &EpochRecord) -> anyhow::Result<()> {
+    let (rent_collected, _) =
+        collect_rent(state_manager, record.epoch, record.rent_lamports_per_byte).await?;
+    distribute_fees(state_manager, record.fee_collector, rent_collected).await?;
+    if record.accounts_reaped.is_some() {
+        reap_empty_accounts(state_manager).await?;
+    }
+
+    Ok(())
+}
+
+/// Charges every account below the rent-exempt minimum. Accounts drained to
+/// zero are closed. Returns the lamports collected and accounts charged.
+async fn collect_rent(
+    state_manager: &StateManager,
+    epoch: u64,
+    lamports_per_byte: u64
+) -> anyhow::Result<(u64, usize)> {
+    if lamports_per_byte == 0 {
+        return Ok((0, 0));
+    }
+
+    let rent = Rent::default();
+    let mut collected = 0u64;
+    let mut charged = 0;
+    for (pubkey, mut account) in state_manager.all_accounts()? {
+        if account.executable || rent.is_exempt(account.lamports, account.data.len()) {
+            continue;
+        }
+
+        let due = lamports_per_byte
+            .saturating_mul(account.data.len() as u64 + ACCOUNT_STORAGE_OVERHEAD)
+            .min(account.lamports);
+        account.lamports -= due;
+        account.rent_epoch = epoch + 1;
+        collected = collected.saturating_add(due);
+        charged += 1;
+
+        if account.lamports == 0 {
+            state_manager.close_account(&pubkey).await?;
+        } else {
+            state_manager.update_account(&pubkey, account).await?;
+        }
+    }
+
+    Ok((collected, charged))
+}
+
+/// Removes accounts left with no lamports and no data, e.g. by dust
+/// transfers of zero lamports, from state. Returns how many were removed.
+async fn reap_empty_accounts(state_manager: &StateManager) -> anyhow::Result<usize> {
+    let mut reaped = 0;
+    for (pubkey, account) in state_manager.all_accounts()? {
+        if account.lamports == 0 && account.data.is_empty() && !account.executable {
+            state_manager.close_account(&pubkey).await?;
+            reaped += 1;
+        }
+    }
+
+    Ok(reaped)
+}
+
+/// Credits `lamports` to the fee collector. Returns what was distributed.
+async fn distribute_fees(
+    state_manager: &StateManager,
+    fee_collector: Option<Pubkey>,
+    lamports: u64
+) -> anyhow::Result<u64> {
+    let Some(fee_collector) = fee_collector else {
+        return Ok(0);
+    };
+    if lamports == 0 {
+        return Ok(0);
+    }
+
+    let mut account = state_manager.get_account(&fee_collector).await
+        .unwrap_or_else(|| L2Account {
+            lamports: 0,
+            data: vec![],
+            owner: Pubkey::default(),
+            executable: false,
+            rent_epoch: 0,
+        });
+    account.lamports = account.lamports.saturating_add(lamports);
+    state_manager.update_account(&fee_collector, account).await?;
+
+    Ok(lamports)
+}
@@ -0,0 +1,191 @@
+use serde_json::Value;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use tokio::{sync::broadcast::{self, error::RecvError}, task::JoinHandle};
+
+use crate::{
+    batch_processor::batch_processor::BatchReport,
+    metrics::metrics,
+    sequencer::{block::BlockHeader, sequencer::DropReason},
+    state_manager::state_manager::L2Account,
+    transaction_processor::transaction_record::TransactionStage,
+};
+
+/// Events buffered per subscriber before the slowest one starts missing them
+const CAPACITY: usize = 1024;
+
+/// Something that happened to rollup state, as pubsub and SSE clients see it.
+#[derive(Debug, Clone)]
+pub enum RollupEvent {
+    /// A block was sealed and stored
+    Block { header: BlockHeader, signatures: Vec<Signature> },
+    /// A transaction reached `stage`; `accounts` are its account keys
+    Transaction { signature: Signature, stage: TransactionStage, accounts: Vec<Pubkey> },
+    /// A transaction left the mempool without being sealed
+    TransactionDropped { signature: Signature, reason: DropReason },
+    /// An account was written; `account` is `None` once it is closed
+    Account { pubkey: Pubkey, account: Option<L2Account> },
+    /// Posting a batch to L1 finished, successfully or with `report.error`
+    BatchSubmitted { report: BatchReport },
+    /// A batch entered the finalized view
+    BatchFinalized { batch_id: u64 },
+}
+
+/// The stream a `RollupEvent` belongs to, for filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Block,
+    /// Transactions reaching a stage, or dropped
+    Transaction,
+    Account,
+    /// Batches submitted to or finalized on L1
+    Batch,
+}
+
+impl std::str::FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(EventKind::Block),
+            "transaction" => Ok(EventKind::Transaction),
+            "account" => Ok(EventKind::Account),
+            "batch" => Ok(EventKind::Batch),
+            _ => Err(format!("expected block, transaction, account or batch, got {}", s)),
+        }
+    }
+}
+
+impl RollupEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            RollupEvent::Block { .. } => EventKind::Block,
+            RollupEvent::Transaction { .. } | RollupEvent::TransactionDropped { .. } => EventKind::Transaction,
+            RollupEvent::Account { .. } => EventKind::Account,
+            RollupEvent::BatchSubmitted { .. } | RollupEvent::BatchFinalized { .. } => EventKind::Batch,
+        }
+    }
+
+    /// The event's name on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RollupEvent::Block { .. } => "block",
+            RollupEvent::Transaction { .. } => "transaction",
+            RollupEvent::TransactionDropped { .. } => "transactionDropped",
+            RollupEvent::Account { .. } => "account",
+            RollupEvent::BatchSubmitted { .. } => "batchSubmitted",
+            RollupEvent::BatchFinalized { .. } => "batchFinalized",
+        }
+    }
+
+    /// The transaction the event is about, if any.
+    pub fn signature(&self) -> Option<&Signature> {
+        match self {
+            RollupEvent::Transaction { signature, .. } | RollupEvent::TransactionDropped { signature, .. } => Some(signature),
+            _ => None,
+        }
+    }
+
+    /// Whether the event concerns `address`: writes to it, or transactions
+    /// referencing it. Drops carry no accounts, so never match.
+    pub fn involves(&self, address: &Pubkey) -> bool {
+        match self {
+            RollupEvent::Transaction { accounts, .. } => accounts.contains(address),
+            RollupEvent::Account { pubkey, .. } => pubkey == address,
+            _ => false,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            RollupEvent::Block { header, signatures } => serde_json::json!({
+                "slot": header.slot,
+                "blockhash": header.blockhash.to_string(),
+                "parentBlockhash": header.parent_blockhash.to_string(),
+                "stateRoot": hex::encode(header.state_root),
+                "blockTime": header.block_time,
+                "seed": header.seed.to_string(),
+                "signatures": signatures.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            }),
+            RollupEvent::Transaction { signature, stage, accounts } => serde_json::json!({
+                "signature": signature.to_string(),
+                "stage": stage,
+                "confirmationStatus": stage.confirmation_status(),
+                "accounts": accounts.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            }),
+            RollupEvent::TransactionDropped { signature, reason } => serde_json::json!({
+                "signature": signature.to_string(),
+                "dropReason": reason,
+            }),
+            RollupEvent::Account { pubkey, account } => serde_json::json!({
+                "pubkey": pubkey.to_string(),
+                "closed": account.is_none(),
+                "account": account.as_ref().map(|account| serde_json::json!({
+                    "lamports": account.lamports,
+                    "owner": account.owner.to_string(),
+                    "dataLen": account.data.len(),
+                    "executable": account.executable,
+                    "rentEpoch": account.rent_epoch,
+                })),
+            }),
+            RollupEvent::BatchSubmitted { report } => serde_json::json!(report),
+            RollupEvent::BatchFinalized { batch_id } => serde_json::json!({ "batchId": batch_id }),
+        }
+    }
+}
+
+/// Fans rollup events out to every subscriber. Publishing never blocks: a
+/// subscriber that falls `CAPACITY` events behind skips ahead and is told
+/// how many it missed.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<RollupEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self { sender: broadcast::channel(CAPACITY).0 }
+    }
+}
+
+impl EventBus {
+    /// Publishes the event `event` builds, only building it when someone is
+    /// listening, since account events copy account data.
+    pub fn publish(&self, event: impl FnOnce() -> RollupEvent) {
+        if self.sender.receiver_count() > 0 {
+            // Everyone unsubscribing in between is not an error
+            let _ = self.sender.send(event());
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RollupEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Runs `consumer` on its own task for every event published from now
+    /// on, until the bus goes away.
+    pub fn attach(&self, mut consumer: impl EventConsumer) -> JoinHandle<()> {
+        let mut events = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => consumer.handle(&event),
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("⚠️  Event consumer {} fell behind and missed {} events", consumer.name(), skipped);
+                        metrics::add("event_consumer_missed", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+/// Reacts to rollup events off the execution path. Components that publish
+/// events don't know their consumers, so adding one means implementing this
+/// and attaching it to the bus.
+pub trait EventConsumer: Send + 'static {
+    /// Identifies the consumer in logs
+    fn name(&self) -> &'static str;
+
+    fn handle(&mut self, event: &RollupEvent);
+}
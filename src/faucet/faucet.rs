@@ -0,0 +1,194 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use solana_sdk_ids::system_program;
+
+use crate::{
+    clock::clock::{Clock, SystemClock},
+    transaction_processor::wire_transaction::WireTransaction,
+};
+
+/// SystemInstruction::Transfer
+const SYSTEM_TRANSFER: u32 = 2;
+
+/// Limits on what requestAirdrop hands out.
+#[derive(Debug, Clone, Copy)]
+pub struct FaucetConfig {
+    /// Most lamports a single airdrop may mint
+    pub max_lamports: u64,
+    /// Airdrops an address may receive within `window`
+    pub requests_per_address: usize,
+    pub window: Duration,
+}
+
+/// Why an airdrop was refused before anything was minted.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AirdropError {
+    #[error("Airdrop amount must be at least 1 lamport")]
+    ZeroAmount,
+    #[error("Airdrop of {lamports} lamports exceeds the {max} lamport maximum")]
+    TooLarge {
+        lamports: u64,
+        max: u64,
+    },
+    /// `recipient` has had its share of airdrops; the oldest counted one
+    /// expires after `retry_after`
+    #[error("Too many airdrops to {recipient}, retry in {}s", retry_after.as_secs().max(1))]
+    RateLimited {
+        recipient: Pubkey,
+        retry_after: Duration,
+    },
+}
+
+/// Lamports minted into an airdrop's fresh payer by the block that carries
+/// its transfer, ahead of the block's transactions. Recorded with the block
+/// so replaying it mints the same.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AirdropMint {
+    pub id: u64,
+    pub payer: Pubkey,
+    pub lamports: u64,
+}
+
+/// An airdrop waiting for the next block: its mint and the transfer the
+/// minted lamports pay for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingAirdrop {
+    pub mint: AirdropMint,
+    pub transaction: WireTransaction,
+}
+
+/// Development faucet behind requestAirdrop. Every airdrop is a system
+/// transfer to the recipient from a fresh account minted exactly the
+/// lamports and fee it needs. The next block mints them and runs the
+/// transfer first thing, so it is sealed and shows up in history like any
+/// other L2 transaction, and nothing else can touch the minted lamports
+/// before it runs.
+#[derive(Debug)]
+pub struct Faucet {
+    config: FaucetConfig,
+    /// When each address's airdrops within the window were granted, oldest first
+    granted: Mutex<HashMap<Pubkey, VecDeque<Instant>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Faucet {
+    pub fn new(config: FaucetConfig) -> Self {
+        Self {
+            config,
+            granted: Mutex::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Reads the time from `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn config(&self) -> &FaucetConfig {
+        &self.config
+    }
+
+    /// Counts an airdrop of `lamports` to `recipient` against its limit, or
+    /// refuses it without counting it.
+    pub fn admit(&self, recipient: &Pubkey, lamports: u64) -> Result<(), AirdropError> {
+        if lamports == 0 {
+            return Err(AirdropError::ZeroAmount);
+        }
+        if lamports > self.config.max_lamports {
+            return Err(AirdropError::TooLarge { lamports, max: self.config.max_lamports });
+        }
+
+        let now = self.clock.now();
+        let mut granted = self.granted.lock().unwrap();
+        // Forget addresses whose airdrops have all aged out of the window
+        granted.retain(|_, times| {
+            while times.front().is_some_and(|granted_at| now.saturating_duration_since(*granted_at) >= self.config.window) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = granted.entry(*recipient).or_default();
+        if times.len() >= self.config.requests_per_address {
+            let retry_after = times.front()
+                .map(|oldest| (*oldest + self.config.window).saturating_duration_since(now))
+                .unwrap_or(self.config.window);
+            return Err(AirdropError::RateLimited { recipient: *recipient, retry_after });
+        }
+        times.push_back(now);
+
+        Ok(())
+    }
+
+    /// A transfer of `lamports` to `recipient` from `payer`, a fresh keypair
+    /// the caller mints the lamports and fee into.
+    pub fn airdrop_transaction(payer: &Keypair, recipient: &Pubkey, lamports: u64, blockhash: Hash) -> Transaction {
+        let instruction = Instruction::new_with_bincode(
+            system_program::id(),
+            &(SYSTEM_TRANSFER, lamports),
+            vec![AccountMeta::new(payer.pubkey(), true), AccountMeta::new(*recipient, false)],
+        );
+        Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], blockhash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::clock::ManualClock;
+
+    fn faucet(clock: &Arc<ManualClock>) -> Faucet {
+        Faucet::new(FaucetConfig {
+            max_lamports: 1_000,
+            requests_per_address: 2,
+            window: Duration::from_secs(60),
+        }).with_clock(clock.clone())
+    }
+
+    #[test]
+    fn refuses_empty_and_oversized_airdrops_without_counting_them() {
+        let clock = Arc::new(ManualClock::new());
+        let faucet = faucet(&clock);
+        let recipient = Pubkey::new_unique();
+
+        assert_eq!(faucet.admit(&recipient, 0), Err(AirdropError::ZeroAmount));
+        assert_eq!(faucet.admit(&recipient, 1_001), Err(AirdropError::TooLarge { lamports: 1_001, max: 1_000 }));
+        assert_eq!(faucet.admit(&recipient, 1_000), Ok(()));
+        assert_eq!(faucet.admit(&recipient, 1), Ok(()));
+    }
+
+    #[test]
+    fn rate_limits_each_address_until_its_oldest_airdrop_leaves_the_window() {
+        let clock = Arc::new(ManualClock::new());
+        let faucet = faucet(&clock);
+        let recipient = Pubkey::new_unique();
+
+        faucet.admit(&recipient, 10).unwrap();
+        clock.advance(Duration::from_secs(20));
+        faucet.admit(&recipient, 10).unwrap();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(
+            faucet.admit(&recipient, 10),
+            Err(AirdropError::RateLimited { recipient, retry_after: Duration::from_secs(30) })
+        );
+        // Other addresses have their own allowance
+        faucet.admit(&Pubkey::new_unique(), 10).unwrap();
+
+        clock.advance(Duration::from_secs(30));
+        faucet.admit(&recipient, 10).unwrap();
+        assert!(matches!(faucet.admit(&recipient, 10), Err(AirdropError::RateLimited { .. })));
+    }
+}
@@ -0,0 +1,57 @@
+use std::slice;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::index_sink::index_sink::{BlockRow, IndexStore, ReceiptRow};
+
+/// How long one insert may take
+const INSERT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Writes the index to ClickHouse over its HTTP interface, as `JSONEachRow`
+/// inserts into `<prefix>blocks` and `<prefix>receipts`. Columns are named
+/// after the row fields; a `ReplacingMergeTree` ordered by slot (blocks)
+/// or signature (receipts) absorbs rows written again after a failure.
+pub struct ClickHouseStore {
+    client: reqwest::Client,
+    url: String,
+    table_prefix: String,
+}
+
+impl ClickHouseStore {
+    pub fn new(url: String, table_prefix: String) -> Self {
+        Self { client: reqwest::Client::new(), url, table_prefix }
+    }
+
+    async fn insert<T: Serialize>(&self, table: &str, rows: &[T]) -> anyhow::Result<()> {
+        let mut body = Vec::new();
+        for row in rows {
+            serde_json::to_writer(&mut body, row)?;
+            body.push(b'\n');
+        }
+
+        let query = format!("INSERT INTO {}{} FORMAT JSONEachRow", self.table_prefix, table);
+        self.client.post(&self.url)
+            .query(&[("query", query)])
+            .body(body)
+            .timeout(INSERT_TIMEOUT)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+impl IndexStore for ClickHouseStore {
+    fn name(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    /// Receipts go first, so a block in the index always has its receipts.
+    async fn write(&self, block: &BlockRow, receipts: &[ReceiptRow]) -> anyhow::Result<()> {
+        if !receipts.is_empty() {
+            self.insert("receipts", receipts).await?;
+        }
+        self.insert("blocks", slice::from_ref(block)).await
+    }
+}
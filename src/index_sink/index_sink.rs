@@ -0,0 +1,166 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use solana_sdk::signature::Signature;
+use tokio::sync::mpsc;
+
+use crate::{
+    events::events::{EventConsumer, RollupEvent},
+    metrics::metrics,
+    sequencer::block::BlockHeader,
+    state_manager::state_manager::StateManager,
+};
+
+/// Blocks waiting to be written before new ones are dropped
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Attempts at writing a block before it is given up on
+const WRITE_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubled for each one after
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// A sealed block, as mirrored to the external index.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockRow {
+    pub slot: u64,
+    pub blockhash: String,
+    pub parent_blockhash: String,
+    pub state_root: String,
+    pub transactions_hash: String,
+    pub transaction_count: u64,
+    pub block_time: i64,
+}
+
+/// One transaction of a sealed block, as mirrored to the external index.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiptRow {
+    pub signature: String,
+    pub slot: u64,
+    /// Position of the transaction in its block
+    pub position: u64,
+    pub block_time: i64,
+    pub fee_payer: String,
+    pub fee: u64,
+    pub account_keys: Vec<String>,
+    pub pre_balances: Vec<u64>,
+    pub post_balances: Vec<u64>,
+}
+
+/// An external store the index sink writes to, e.g. an analytical database.
+/// A block is written again when a write fails, so stores should treat rows
+/// they already have (by slot, or by signature) as duplicates.
+pub trait IndexStore: Send + Sync + 'static {
+    /// Identifies the store in logs
+    fn name(&self) -> &'static str;
+
+    /// Writes a block together with its receipts.
+    fn write(&self, block: &BlockRow, receipts: &[ReceiptRow]) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Mirrors every sealed block and its transaction receipts to an
+/// `IndexStore` as they are produced, so heavy queries can run there rather
+/// than against the node's RocksDB. Writes happen on their own task; if the
+/// store falls `QUEUE_CAPACITY` blocks behind, further blocks are skipped
+/// and counted rather than slowing the node down.
+pub struct IndexSink {
+    blocks: mpsc::Sender<(BlockHeader, Vec<Signature>)>,
+}
+
+impl IndexSink {
+    /// Starts the task writing blocks handed to the sink to `store`.
+    pub fn start(store: impl IndexStore, state_manager: Arc<StateManager>) -> Self {
+        let (blocks, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(write_blocks(store, state_manager, receiver));
+
+        Self { blocks }
+    }
+}
+
+impl EventConsumer for IndexSink {
+    fn name(&self) -> &'static str {
+        "index-sink"
+    }
+
+    fn handle(&mut self, event: &RollupEvent) {
+        let RollupEvent::Block { header, signatures } = event else {
+            return;
+        };
+        if self.blocks.try_send((*header, signatures.clone())).is_err() {
+            eprintln!("⚠️  Index sink is behind, block {} won't be indexed", header.slot);
+            metrics::increment("index_sink_blocks_skipped");
+        }
+    }
+}
+
+async fn write_blocks(
+    store: impl IndexStore,
+    state_manager: Arc<StateManager>,
+    mut blocks: mpsc::Receiver<(BlockHeader, Vec<Signature>)>
+) {
+    while let Some((header, signatures)) = blocks.recv().await {
+        let receipts = match receipts(&state_manager, &header, &signatures) {
+            Ok(receipts) => receipts,
+            Err(e) => {
+                eprintln!("Failed to load receipts of block {} for the index: {}", header.slot, e);
+                metrics::increment("index_sink_blocks_failed");
+                continue;
+            }
+        };
+        let block = BlockRow {
+            slot: header.slot,
+            blockhash: header.blockhash.to_string(),
+            parent_blockhash: header.parent_blockhash.to_string(),
+            state_root: hex::encode(header.state_root),
+            transactions_hash: header.transactions_hash.to_string(),
+            transaction_count: header.transaction_count,
+            block_time: header.block_time,
+        };
+
+        let mut attempt = 1;
+        loop {
+            match store.write(&block, &receipts).await {
+                Ok(()) => {
+                    metrics::increment("index_sink_blocks_written");
+                    break;
+                }
+                Err(e) if attempt < WRITE_ATTEMPTS => {
+                    tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempt - 1)).await;
+                    attempt += 1;
+                    eprintln!("Retrying block {} for {} index: {}", header.slot, store.name(), e);
+                }
+                Err(e) => {
+                    eprintln!("❌ Failed to index block {} in {}: {}", header.slot, store.name(), e);
+                    metrics::increment("index_sink_blocks_failed");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn receipts(state_manager: &StateManager, header: &BlockHeader, signatures: &[Signature]) -> anyhow::Result<Vec<ReceiptRow>> {
+    let mut receipts = Vec::with_capacity(signatures.len());
+    for (position, signature) in signatures.iter().enumerate() {
+        let Some(record) = state_manager.get_transaction(signature)? else {
+            continue;
+        };
+        let account_keys = &record.transaction.message.account_keys;
+
+        receipts.push(ReceiptRow {
+            signature: signature.to_string(),
+            slot: header.slot,
+            position: position as u64,
+            block_time: header.block_time,
+            fee_payer: account_keys.first().map(|key| key.to_string()).unwrap_or_default(),
+            fee: record.fee,
+            account_keys: account_keys.iter().map(|key| key.to_string()).collect(),
+            pre_balances: record.pre_balances,
+            post_balances: record.post_balances,
+        });
+    }
+
+    Ok(receipts)
+}
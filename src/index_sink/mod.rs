@@ -0,0 +1,2 @@
+pub mod clickhouse;
+pub mod index_sink;
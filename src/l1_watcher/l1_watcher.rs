@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_request::RpcRequest,
+    rpc_response::{Response, RpcBlockhash},
+};
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use tokio::sync::watch;
+
+use crate::metrics::metrics;
+
+/// The newest finalized L1 block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinalizedL1 {
+    pub slot: u64,
+    pub blockhash: Hash,
+}
+
+/// Tracks L1 finality for every component that depends on it, so they share
+/// one poll instead of each querying the RPC. The published slot only moves
+/// forward: a lagging RPC node (e.g. after a failover) reporting an older
+/// finalized block is ignored, so consumers never see finality roll back.
+pub struct L1Watcher {
+    client: RpcClient,
+    poll_interval: Duration,
+    finalized: watch::Sender<Option<FinalizedL1>>,
+}
+
+impl L1Watcher {
+    pub fn new(solana_rpc_url: String, poll_interval: Duration) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(solana_rpc_url, CommitmentConfig::finalized()),
+            poll_interval,
+            finalized: watch::Sender::new(None),
+        }
+    }
+
+    /// Latest finalized block seen, or `None` before the first successful poll.
+    pub fn finalized(&self) -> Option<FinalizedL1> {
+        *self.finalized.borrow()
+    }
+
+    /// Notified every time the finalized block advances.
+    pub fn subscribe(&self) -> watch::Receiver<Option<FinalizedL1>> {
+        self.finalized.subscribe()
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            match self.fetch_finalized().await {
+                Ok(latest) => self.advance(latest),
+                Err(e) => eprintln!("Failed to fetch finalized L1 block: {}", e),
+            }
+        }
+    }
+
+    /// The slot and blockhash come from one response, so they always
+    /// describe the same block.
+    async fn fetch_finalized(&self) -> anyhow::Result<FinalizedL1> {
+        let response = self.client
+            .send::<Response<RpcBlockhash>>(
+                RpcRequest::GetLatestBlockhash,
+                serde_json::json!([CommitmentConfig::finalized()])
+            )
+            .await?;
+
+        Ok(FinalizedL1 {
+            slot: response.context.slot,
+            blockhash: response.value.blockhash.parse()?,
+        })
+    }
+
+    fn advance(&self, latest: FinalizedL1) {
+        self.finalized.send_if_modified(|finalized| match finalized {
+            Some(current) if latest.slot < current.slot => {
+                metrics::increment("l1_finalized_slot_regressions");
+                eprintln!(
+                    "Ignoring finalized L1 slot {} behind the already finalized {}",
+                    latest.slot, current.slot
+                );
+                false
+            }
+            Some(current) if latest.slot == current.slot => false,
+            _ => {
+                metrics::set_gauge("l1_finalized_slot", latest.slot);
+                *finalized = Some(latest);
+                true
+            }
+        });
+    }
+}
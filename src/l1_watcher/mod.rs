@@ -0,0 +1,2 @@
+pub mod l1_watcher;
+pub mod program_logs;
@@ -0,0 +1,170 @@
+use std::{str::FromStr, sync::Arc};
+
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig,
+};
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{UiTransactionEncoding, option_serializer::OptionSerializer};
+
+use crate::{
+    l1_watcher::l1_watcher::L1Watcher,
+    state_manager::state_manager::{L1Cursor, StateManager},
+};
+
+/// Signatures requested per getSignaturesForAddress page, Solana's maximum
+const SIGNATURES_PAGE_LEN: usize = 1000;
+
+/// What a `ProgramLogWatcher` does with the watched program's transactions.
+pub trait ProgramLogHandler: Send + Sync {
+    /// What the handler reads, for error messages (e.g. "deposits").
+    fn name(&self) -> &'static str;
+
+    /// Handles the logs of a successful finalized transaction of
+    /// `program_id`, which landed in `slot`.
+    fn handle(&self, program_id: &Pubkey, signature: &Signature, slot: u64, logs: &[String]) -> anyhow::Result<()>;
+}
+
+/// Where a watcher without a cursor starts reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartFrom {
+    /// The program's first transaction
+    Oldest,
+    /// The program's latest transaction, so only what comes after it is read
+    Latest,
+}
+
+/// Reads a program's finalized L1 history, oldest first, every time the
+/// `L1Watcher` reports a newly finalized slot, and hands the logs of each
+/// successful transaction to its handler. Only finalized transactions are
+/// read, so nothing is handled from a fork L1 later abandons. The last
+/// transaction read is kept in the state database as the watcher's
+/// `L1Cursor`, so a restart resumes where it left off.
+pub struct ProgramLogWatcher<H> {
+    client: RpcClient,
+    l1_watcher: Arc<L1Watcher>,
+    program_id: Pubkey,
+    state_manager: Arc<StateManager>,
+    cursor: L1Cursor,
+    start: StartFrom,
+    handler: H,
+}
+
+impl<H: ProgramLogHandler> ProgramLogWatcher<H> {
+    pub fn new(
+        solana_rpc_url: String,
+        l1_watcher: Arc<L1Watcher>,
+        program_id: Pubkey,
+        state_manager: Arc<StateManager>,
+        cursor: L1Cursor,
+        handler: H,
+    ) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(solana_rpc_url, CommitmentConfig::finalized()),
+            l1_watcher,
+            program_id,
+            state_manager,
+            cursor,
+            start: StartFrom::Oldest,
+            handler,
+        }
+    }
+
+    /// Starts at `start` when there is no cursor yet, instead of the
+    /// program's first transaction.
+    pub fn with_start(mut self, start: StartFrom) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub async fn run(&self) {
+        let mut finalized = self.l1_watcher.subscribe();
+        loop {
+            if let Err(e) = self.poll().await {
+                eprintln!("Failed to poll L1 for {}: {}", self.handler.name(), e);
+            }
+            if finalized.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Reads every transaction of the program since the cursor, oldest first.
+    async fn poll(&self) -> anyhow::Result<()> {
+        let until = self.state_manager.l1_cursor(self.cursor)?;
+        if until.is_none() && self.start == StartFrom::Latest {
+            if let Some((signature, _, _)) = self.signatures(None, None, 1).await?.first() {
+                self.state_manager.set_l1_cursor(self.cursor, signature)?;
+            }
+            return Ok(());
+        }
+
+        // Pages come newest first; walk back to the cursor, then replay forward
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self.signatures(before, until, SIGNATURES_PAGE_LEN).await?;
+            let full = page.len() == SIGNATURES_PAGE_LEN;
+            before = page.last().map(|(signature, _, _)| *signature);
+            signatures.extend(page);
+            if !full {
+                break;
+            }
+        }
+
+        for (signature, slot, succeeded) in signatures.into_iter().rev() {
+            if succeeded {
+                self.read(&signature, slot).await?;
+            }
+            self.state_manager.set_l1_cursor(self.cursor, &signature)?;
+        }
+
+        Ok(())
+    }
+
+    /// Signatures of the program's finalized transactions, newest first,
+    /// with the slot each landed in and whether it succeeded.
+    async fn signatures(
+        &self,
+        before: Option<Signature>,
+        until: Option<Signature>,
+        limit: usize
+    ) -> anyhow::Result<Vec<(Signature, u64, bool)>> {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before,
+            until,
+            limit: Some(limit),
+            commitment: Some(CommitmentConfig::finalized()),
+        };
+        self.client
+            .get_signatures_for_address_with_config(&self.program_id, config)
+            .await?
+            .into_iter()
+            .map(|status| Ok((Signature::from_str(&status.signature)?, status.slot, status.err.is_none())))
+            .collect()
+    }
+
+    async fn read(&self, signature: &Signature, slot: u64) -> anyhow::Result<()> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment: Some(CommitmentConfig::finalized()),
+            max_supported_transaction_version: Some(0),
+        };
+        let confirmed = self
+            .client
+            .get_transaction_with_config(signature, config)
+            .await?;
+        let Some(meta) = confirmed.transaction.meta else {
+            anyhow::bail!("L1 transaction {} has no status metadata", signature);
+        };
+        if meta.err.is_some() {
+            return Ok(());
+        }
+        let OptionSerializer::Some(logs) = meta.log_messages else {
+            anyhow::bail!("L1 transaction {} has no logs", signature);
+        };
+
+        self.handler.handle(&self.program_id, signature, slot, &logs)
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use solana_transaction_status::{BlockEncodingOptions, TransactionDetails, UiTransactionEncoding};
+
+use crate::{
+    data_availability::data_availability::DataAvailability,
+    metrics::metrics,
+    state_manager::state_manager::StateManager,
+};
+
+pub const FORMAT_VERSION: u32 = 1;
+const INDEX_FILE: &str = "index.json";
+
+/// How often the exporter looks for newly sealed blocks
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Index of a ledger export directory. Rewritten after every append, and
+/// only then, so it never lists a block whose line isn't fully on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerIndex {
+    pub format_version: u32,
+    /// Base58, as in getVersion
+    pub genesis_hash: Option<String>,
+    /// Blocks per segment file before the next one is started
+    pub segment_blocks: u64,
+    pub segments: Vec<SegmentInfo>,
+}
+
+/// A segment file: one block per line, in slot order, with no gaps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentInfo {
+    pub file: String,
+    pub first_slot: u64,
+    pub last_slot: u64,
+    /// Length of the file's complete lines; anything after is a partial
+    /// write and dropped when the export resumes
+    pub bytes: u64,
+}
+
+impl LedgerIndex {
+    fn next_slot(&self) -> u64 {
+        self.segments.last().map_or(0, |segment| segment.last_slot + 1)
+    }
+}
+
+pub fn read_index(dir: &Path) -> anyhow::Result<Option<LedgerIndex>> {
+    let path = dir.join(INDEX_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let index: LedgerIndex = serde_json::from_slice(&fs::read(&path)?)?;
+    if index.format_version != FORMAT_VERSION {
+        anyhow::bail!("Unsupported ledger export format version {}", index.format_version);
+    }
+
+    Ok(Some(index))
+}
+
+/// Appends every sealed block to rolling JSON Lines files in a directory,
+/// so history can be processed offline instead of through the RPC. Each
+/// line is the block as getBlock returns it with `json` encoding and full
+/// transaction details, plus its `slot`, `blockSeed` and hex `stateRoot`. Runs behind
+/// the sequencer on its own task and resumes from its index after a
+/// restart, fetching blocks pruned in the meantime from DA.
+pub struct LedgerExporter {
+    dir: PathBuf,
+    state_manager: Arc<StateManager>,
+    data_availability: Arc<DataAvailability>,
+    index: LedgerIndex,
+}
+
+impl LedgerExporter {
+    /// Opens the export in `dir`, creating it if needed. An existing export
+    /// must be of the same chain; it keeps its segment size.
+    pub fn open(
+        dir: &Path,
+        segment_blocks: u64,
+        state_manager: Arc<StateManager>,
+        data_availability: Arc<DataAvailability>
+    ) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let genesis_hash = state_manager.genesis_hash()?.map(|hash| bs58::encode(hash).into_string());
+        let index = match read_index(dir)? {
+            Some(index) => {
+                if index.genesis_hash != genesis_hash {
+                    anyhow::bail!("{} holds a ledger export of a different chain", dir.display());
+                }
+                if let Some(segment) = index.segments.last() {
+                    // Drop whatever was written after the index last was
+                    OpenOptions::new().write(true).open(dir.join(&segment.file))?.set_len(segment.bytes)?;
+                }
+                index
+            }
+            None => LedgerIndex {
+                format_version: FORMAT_VERSION,
+                genesis_hash,
+                segment_blocks,
+                segments: Vec::new(),
+            },
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            state_manager,
+            data_availability,
+            index,
+        })
+    }
+
+    pub fn next_slot(&self) -> u64 {
+        self.index.next_slot()
+    }
+
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.export_new_blocks().await {
+                eprintln!("⚠️  Ledger export stopped at block {}: {}", self.next_slot(), e);
+                metrics::increment("ledger_export_failures");
+            }
+        }
+    }
+
+    /// Appends every block sealed since the last call.
+    async fn export_new_blocks(&mut self) -> anyhow::Result<()> {
+        loop {
+            let slot = self.next_slot();
+            let Some((header, block)) = self.data_availability.get_block(&self.state_manager, slot).await? else {
+                return Ok(());
+            };
+            let options = BlockEncodingOptions {
+                transaction_details: TransactionDetails::Full,
+                show_rewards: true,
+                max_supported_transaction_version: Some(0),
+            };
+            let mut line = serde_json::json!(block.encode_with_options(UiTransactionEncoding::Json, options)?);
+            line["slot"] = serde_json::json!(slot);
+            line["blockSeed"] = serde_json::json!(header.seed.to_string());
+            line["stateRoot"] = serde_json::json!(hex::encode(header.state_root));
+            let mut line = serde_json::to_vec(&line)?;
+            line.push(b'\n');
+
+            self.append(slot, &line)?;
+            metrics::increment("ledger_export_blocks");
+        }
+    }
+
+    /// Writes `line` for `slot` to the open segment, or a new one when it's
+    /// full, then records it in the index.
+    fn append(&mut self, slot: u64, line: &[u8]) -> anyhow::Result<()> {
+        let open_segment = self.index.segments.last()
+            .filter(|segment| segment.last_slot + 1 - segment.first_slot < self.index.segment_blocks);
+        match open_segment {
+            Some(segment) => {
+                // Past a line an earlier failed append may have left half written
+                let mut file = OpenOptions::new().write(true).open(self.dir.join(&segment.file))?;
+                file.set_len(segment.bytes)?;
+                file.seek(SeekFrom::End(0))?;
+                file.write_all(line)?;
+                file.sync_data()?;
+            }
+            None => {
+                let file = format!("ledger-{:020}.jsonl", slot);
+                let mut created = File::create(self.dir.join(&file))?;
+                created.write_all(line)?;
+                created.sync_data()?;
+                self.index.segments.push(SegmentInfo { file, first_slot: slot, last_slot: slot, bytes: 0 });
+            }
+        }
+
+        // Only once the line is on disk
+        let segment = self.index.segments.last_mut()
+            .ok_or_else(|| anyhow::anyhow!("Ledger export has no open segment"))?;
+        segment.last_slot = slot;
+        segment.bytes += line.len() as u64;
+
+        self.write_index()
+    }
+
+    fn write_index(&self) -> anyhow::Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp", INDEX_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.index)?)?;
+        fs::rename(&tmp, self.dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+}
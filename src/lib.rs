@@ -0,0 +1,31 @@
+#![allow(clippy::module_inception)]
+
+pub mod batch_processor;
+pub mod block_stream;
+pub mod chaos;
+pub mod clock;
+pub mod config;
+pub mod data_availability;
+pub mod dead_letters;
+pub mod deposits;
+pub mod doctor;
+pub mod epochs;
+pub mod events;
+pub mod faucet;
+pub mod index_sink;
+pub mod l1_watcher;
+pub mod ledger_export;
+pub mod load_shedding;
+pub mod metrics;
+pub mod notifications;
+pub mod pipeline;
+pub mod program_stats;
+pub mod replay;
+pub mod rpc_server;
+pub mod sequencer;
+pub mod state_export;
+pub mod state_manager;
+pub mod telemetry;
+pub mod token_registry;
+pub mod transaction_processor;
+pub mod withdrawals;
@@ -0,0 +1,156 @@
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{metrics::metrics, state_manager::state_manager::StateManager};
+
+/// How often resource usage is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Percent of a threshold usage must fall back under before shedding stops,
+/// so a node hovering at a limit doesn't flap in and out of it
+const RECOVERY_PERCENT: u64 = 90;
+
+/// Limits past which the node sheds load. Unset limits aren't checked.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PressureThresholds {
+    /// Resident memory of the process
+    pub max_memory_bytes: Option<u64>,
+    pub max_open_files: Option<u64>,
+    /// Shed while RocksDB stops or delays writes
+    pub write_stalls: bool,
+}
+
+impl PressureThresholds {
+    pub fn is_enabled(&self) -> bool {
+        self.max_memory_bytes.is_some() || self.max_open_files.is_some() || self.write_stalls
+    }
+}
+
+/// Resource usage at one sample. Readings the platform can't provide are
+/// `None`.
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    memory_bytes: Option<u64>,
+    open_files: Option<u64>,
+    write_stalled: bool,
+}
+
+/// Watches memory, open file descriptors and RocksDB write stalls, and
+/// reports the node under pressure while any is past its threshold. Heavy
+/// RPCs are refused and batches sealed less often until it recovers.
+#[derive(Debug)]
+pub struct LoadShedder {
+    thresholds: PressureThresholds,
+    state_manager: Arc<StateManager>,
+    /// Why the node is shedding load, while it is
+    pressure: Mutex<Option<String>>,
+}
+
+impl LoadShedder {
+    pub fn new(thresholds: PressureThresholds, state_manager: Arc<StateManager>) -> Self {
+        Self {
+            thresholds,
+            state_manager,
+            pressure: Mutex::new(None),
+        }
+    }
+
+    /// Why the node is shedding load, or None while it isn't.
+    pub fn pressure_reason(&self) -> Option<String> {
+        self.pressure.lock().unwrap().clone()
+    }
+
+    pub fn is_shedding(&self) -> bool {
+        self.pressure.lock().unwrap().is_some()
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.sample();
+        }
+    }
+
+    fn sample(&self) {
+        let usage = Usage {
+            memory_bytes: resident_memory_bytes(),
+            open_files: open_file_count(),
+            write_stalled: self.thresholds.write_stalls && self.state_manager.write_stalled().unwrap_or_else(|e| {
+                eprintln!("⚠️  Failed to read RocksDB write stall state: {}", e);
+                false
+            }),
+        };
+        if let Some(memory_bytes) = usage.memory_bytes {
+            metrics::set_gauge("resource_memory_bytes", memory_bytes);
+        }
+        if let Some(open_files) = usage.open_files {
+            metrics::set_gauge("resource_open_files", open_files);
+        }
+        metrics::set_gauge("rocksdb_write_stalled", usage.write_stalled as u64);
+
+        let mut pressure = self.pressure.lock().unwrap();
+        let reason = self.pressure_from(&usage, pressure.is_some());
+        match (pressure.is_some(), &reason) {
+            (false, Some(reason)) => {
+                eprintln!("⚠️  Shedding load: {}", reason);
+                metrics::increment("load_shedding_episodes");
+            }
+            (true, None) => println!("Resource pressure cleared, no longer shedding load"),
+            _ => {}
+        }
+        metrics::set_gauge("load_shedding", reason.is_some() as u64);
+        *pressure = reason;
+    }
+
+    /// What is past its threshold in `usage`. While already shedding,
+    /// usage must fall under the recovery level instead.
+    fn pressure_from(&self, usage: &Usage, shedding: bool) -> Option<String> {
+        let over = |value: Option<u64>, limit: Option<u64>| match (value, limit) {
+            (Some(value), Some(limit)) if shedding => value >= limit * RECOVERY_PERCENT / 100,
+            (Some(value), Some(limit)) => value >= limit,
+            _ => false,
+        };
+
+        let mut reasons = Vec::new();
+        if over(usage.memory_bytes, self.thresholds.max_memory_bytes) {
+            reasons.push(format!(
+                "resident memory {} MB of {} MB",
+                usage.memory_bytes.unwrap_or(0) / (1024 * 1024),
+                self.thresholds.max_memory_bytes.unwrap_or(0) / (1024 * 1024)
+            ));
+        }
+        if over(usage.open_files, self.thresholds.max_open_files) {
+            reasons.push(format!(
+                "{} open files of {}",
+                usage.open_files.unwrap_or(0),
+                self.thresholds.max_open_files.unwrap_or(0)
+            ));
+        }
+        if usage.write_stalled {
+            reasons.push("RocksDB is stalling writes".to_string());
+        }
+
+        (!reasons.is_empty()).then(|| format!("Node is under resource pressure: {}", reasons.join(", ")))
+    }
+}
+
+/// Resident set size of this process, from /proc (Linux only).
+fn resident_memory_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status.lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// File descriptors this process has open, from /proc (Linux only).
+fn open_file_count() -> Option<u64> {
+    Some(fs::read_dir("/proc/self/fd").ok()?.count() as u64)
+}
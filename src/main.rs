@@ -1,82 +1,1454 @@
-use clap::Parser;
-use jsonrpsee::server::{ServerBuilder, Server};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use jsonrpsee::server::{middleware::rpc::RpcServiceBuilder, BatchRequestConfig, ServerBuilder, ServerConfig};
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::{read_keypair_file, Signer}};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tower_http::cors::{CorsLayer, Any};
-
-use crate::{
-    batch_processor::batch_processor::BatchProcessor, 
-    rpc_server::server::{RollupRpcImpl, RollupRpcServer},
-    sequencer::sequencer::Sequencer, 
-    state_manager::state_manager::StateManager,
-    transaction_processor::transaction_processor::TransactionProcessor,
-};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
-mod batch_processor;
-mod rpc_server;
-mod sequencer;
-mod state_manager;
-mod transaction_processor;
+use tiny_rollup::{
+    batch_processor::{
+        aggregation::{Aggregation, CommitmentStrategy},
+        authority::{AuthorityKey, AuthoritySchedule, AUTHORITY_KEYPAIR_ENV},
+        batch_processor::BatchProcessor,
+        compression::{BatchCompression, CompressionAlgorithm},
+        cosigning::{Cosigner, CosignerSet},
+        health::{DegradedMode, L1Health},
+    },
+    block_stream::replica::Replica,
+    config::config::{cors_origin, RollupConfig, DEFAULT_CONFIG_FILE},
+    data_availability::data_availability::{DaPublisher, DataAvailability},
+    deposits::watcher::DepositHandler,
+    doctor::doctor::{CheckStatus, Database, Doctor, DoctorConfig, L1Cluster},
+    epochs::epochs::EpochConfig,
+    faucet::faucet::{Faucet, FaucetConfig},
+    index_sink::{clickhouse::ClickHouseStore, index_sink::IndexSink},
+    l1_watcher::{l1_watcher::L1Watcher, program_logs::{ProgramLogWatcher, StartFrom}},
+    ledger_export::ledger_export::LedgerExporter,
+    load_shedding::load_shedding::{LoadShedder, PressureThresholds},
+    metrics::{event_metrics::EventMetrics, latency::SloTargets},
+    notifications::{
+        challenges::ChallengeHandler,
+        notifications::{NotificationSink, NotificationThresholds, Notifier},
+    },
+    pipeline::pipeline::{Pipeline, PipelineConfig},
+    replay::{audit::{AuditMode, DeterminismAudit}, replay::{ExecutionConfig, Replayer}},
+    rpc_server::{
+        admin::{AdminRpcImpl, AdminRpcServer},
+        attestation::ResponseAttestor,
+        batch_limits::{BatchLimitLayer, MethodCosts},
+        cosigner::{CosignerRpcImpl, CosignerRpcServer},
+        routing::{split_admin_methods, split_write_methods},
+        server::{RollupRpcImpl, RollupRpcServer},
+        shedding::SheddingLayer,
+        slo::SloLayer,
+        sse::{SseLayer, EVENTS_PATH},
+        snapshot_files::{SnapshotFilesLayer, SNAPSHOTS_PATH},
+        token_list::{TokenListLayer, TOKENS_PATH},
+        unsupported::unsupported_methods,
+        verifier::{VerifierRpcImpl, VerifierRpcServer},
+    },
+    sequencer::{
+        sequencer::{EvictionPolicy, MempoolLimits, Sequencer},
+        snapshot::MempoolSnapshot,
+        spam::SpamScorer,
+    }, 
+    state_export::state_export::{export_state, import_state, read_manifest},
+    state_manager::{cold_storage::ColdStorage, state_hasher::HashFunction, state_manager::{L1Cursor, StateManager}},
+    telemetry::telemetry::Telemetry,
+    token_registry::token_registry::TokenRegistry,
+    transaction_processor::{
+        access_policy::AccessPolicy,
+        dust_policy::{DustAction, DustPolicy},
+        fee_schedule::FeeSchedule,
+        program_registry::ProgramRegistry,
+        transaction_processor::{TransactionProcessor, UnknownProgramPolicy},
+    },
+};
 
 #[derive(Parser)]
-#[command(author, version, about)]
+#[command(author, version, about, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TOML file of settings (see RollupConfig); flags given on the command
+    /// line override it. Defaults to ./rollup.toml when that exists
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Probability (0-1) that a hooked operation fails; enables fault injection
+    #[cfg(feature = "chaos")]
+    #[arg(long)]
+    chaos_failure_rate: Option<f64>,
+
+    /// Maximum random delay added to hooked operations
+    #[cfg(feature = "chaos")]
+    #[arg(long, default_value = "0")]
+    chaos_max_delay_ms: u64,
+
+    /// Operations to inject faults into (default: all)
+    #[cfg(feature = "chaos")]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    chaos_points: Vec<tiny_rollup::chaos::chaos::FaultPoint>,
+
     #[arg(short, long, default_value = "8899")]
     port: u16,
 
     #[arg(short, long, default_value = "./rollup_db")]
     db_path: String,
 
+    /// fsync the RocksDB WAL on every write, so no acknowledged write is lost
+    /// on power failure (slower)
+    #[arg(long)]
+    sync_writes: bool,
+
+    /// Solana RPC URL of L1; required to run the node
     #[arg(short, long)]
-    solana_rpc: String,
+    solana_rpc: Option<String>,
+
+    /// L1 cluster --solana-rpc must serve: mainnet-beta, testnet, devnet or a
+    /// genesis hash
+    #[arg(long)]
+    l1_cluster: Option<L1Cluster>,
+
+    /// Genesis hash (as reported by getRollupStatus) of the chain --db-path
+    /// must hold; co-signers only sign batches of this chain
+    #[arg(long)]
+    genesis_hash: Option<Hash>,
+
+    /// Start even if the startup self-checks (see `doctor`) fail
+    #[arg(long)]
+    skip_startup_checks: bool,
+
+    /// Hash function for state roots; only applied when creating a new database
+    #[arg(long, value_enum)]
+    state_hash: Option<HashFunction>,
+
+    /// Preload this many of the most recently written accounts into memory on startup
+    #[arg(long, default_value = "0")]
+    warmup_accounts: usize,
+
+    /// Priority (micro-lamports per CU) a pending transaction gains per second of waiting
+    #[arg(long, default_value = "1000")]
+    priority_aging_rate: u64,
+
+    /// How often a batch is sealed
+    #[arg(long, default_value = "2000", value_parser = clap::value_parser!(u64).range(1..))]
+    batch_interval_ms: u64,
+
+    /// Byte budget for a sealed batch as posted to L1; batches too large
+    /// for one L1 transaction are posted in chunks. Transactions that
+    /// can't fit a batch on their own are rejected
+    #[arg(long, default_value = "1000")]
+    max_batch_bytes: usize,
+
+    /// Seconds a transaction may wait in the mempool before it is dropped
+    #[arg(long, default_value = "120")]
+    mempool_ttl_secs: u64,
+
+    /// Pending transactions kept before --eviction-policy kicks in
+    #[arg(long, default_value = "10000")]
+    max_pending_transactions: usize,
+
+    /// What to do with a new transaction when the mempool is full
+    #[arg(long, value_enum, default_value = "evict-lowest-fee")]
+    eviction_policy: EvictionPolicy,
+
+    /// Pending transactions a single fee payer may have in the mempool
+    #[arg(long, default_value = "64")]
+    max_pending_per_sender: usize,
+
+    /// System transfers below this many lamports count towards a sender's spam score
+    #[arg(long, default_value = "1000")]
+    dust_threshold_lamports: u64,
+
+    /// Seconds for a sender's failed and dust transaction counts to decay by half
+    #[arg(long, default_value = "300")]
+    spam_half_life_secs: u64,
+
+    /// Batch authority keypair file, optionally active from a batch height: PATH[@BATCH]
+    /// (repeatable). Without one, TINY_ROLLUP_AUTHORITY_KEYPAIR holds the spec, with a
+    /// path or the keypair's JSON byte array; with neither, a throwaway key is generated.
+    #[arg(long = "authority-keypair", value_name = "PATH[@BATCH]")]
+    authority_keypairs: Vec<String>,
+
+    /// Rollup program on L1 that batches are posted to; required with
+    /// --solana-rpc, and startup checks that it is deployed
+    #[arg(long)]
+    rollup_program_id: Option<Pubkey>,
+
+    /// Co-signer whose signature batch submissions need: PUBKEY@URL (repeatable)
+    #[arg(long = "cosigner", value_name = "PUBKEY@URL")]
+    cosigners: Vec<String>,
+
+    /// Co-signatures required per batch; defaults to all configured co-signers
+    #[arg(long)]
+    cosigner_threshold: Option<usize>,
+
+    /// Serve operator methods such as rotateAuthority on --admin-rpc-address
+    #[arg(long)]
+    admin_rpc: bool,
+
+    /// Address the operator methods are served on with --admin-rpc. They are
+    /// unauthenticated, so it is loopback unless set, and they are never
+    /// served on --port or --write-rpc-address
+    #[arg(long, value_name = "ADDR:PORT", default_value = "127.0.0.1:8900")]
+    admin_rpc_address: std::net::SocketAddr,
+
+    /// Batches simulateTransactionAtBatch may replay from genesis to
+    /// rebuild a past state, so it only reaches batches below this
+    #[arg(long, default_value = "10000")]
+    historical_simulation_max_batches: u64,
+
+    /// Serve write methods (sendTransaction, requestAirdrop, getDisputeData) only on this
+    /// address, e.g. a private interface, instead of alongside reads on --port
+    #[arg(long, value_name = "ADDR:PORT")]
+    write_rpc_address: Option<std::net::SocketAddr>,
+
+    /// Origin browsers may call the RPC from, e.g. https://app.example.com
+    /// (repeatable; any origin when none is given)
+    #[arg(long = "cors-origin", value_name = "ORIGIN")]
+    cors_origins: Vec<String>,
+
+    /// Priority fee (micro-lamports per compute unit) paid on L1 batch submissions
+    #[arg(long, default_value = "0")]
+    compute_unit_price: u64,
+
+    /// Periodically POST anonymized node stats (version, chain tip, batch lag) here
+    #[arg(long, value_name = "URL")]
+    telemetry_endpoint: Option<String>,
+
+    /// Seconds between telemetry reports
+    #[arg(long, default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    telemetry_interval_secs: u64,
+
+    /// Mirror sealed blocks and their transaction receipts to ClickHouse at
+    /// this HTTP URL, for analytical queries off the node
+    #[arg(long, value_name = "URL")]
+    index_clickhouse_url: Option<String>,
+
+    /// Prefix of the tables the index sink writes (<prefix>blocks, <prefix>receipts)
+    #[arg(long, default_value = "rollup_")]
+    index_table_prefix: String,
+
+    /// Append every sealed block to JSON Lines files in this directory, with
+    /// an index.json listing the files and their slot ranges
+    #[arg(long, value_name = "DIR")]
+    ledger_export_dir: Option<PathBuf>,
+
+    /// Serve requestAirdrop, minting lamports to any address (local development only)
+    #[arg(long)]
+    faucet_enabled: bool,
+
+    /// Most lamports a single airdrop may mint
+    #[arg(long, default_value = "10000000000", value_parser = clap::value_parser!(u64).range(1..))]
+    faucet_max_lamports: u64,
+
+    /// Airdrops an address may receive per --faucet-window-secs
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+    faucet_requests_per_address: u64,
+
+    /// Window the per-address airdrop limit is counted over
+    #[arg(long, default_value = "3600", value_parser = clap::value_parser!(u64).range(1..))]
+    faucet_window_secs: u64,
+
+    /// Directory of state exports (`export --out <DIR>/<NAME>`) reported by
+    /// getHighestSnapshotSlot and rollup_getSnapshots
+    #[arg(long, value_name = "DIR")]
+    snapshot_dir: Option<PathBuf>,
+
+    /// Also serve the snapshot directory's exports on GET /snapshots/<NAME>/<FILE>
+    #[arg(long, requires = "snapshot_dir")]
+    serve_snapshots: bool,
+
+    /// Where clients download snapshots from, advertised by rollup_getSnapshots
+    /// (default: this node's /snapshots when serving them)
+    #[arg(long, value_name = "URL", requires = "snapshot_dir")]
+    snapshot_base_url: Option<String>,
+
+    /// Blocks per ledger export file before the next one is started
+    #[arg(long, default_value = "10000", value_parser = clap::value_parser!(u64).range(1..))]
+    ledger_export_segment_blocks: u64,
+
+    /// Seconds L1 batch posting may keep failing (RPC down, authority unfunded)
+    /// before the node enters degraded mode
+    #[arg(long, default_value = "120")]
+    degraded_after_secs: u64,
+
+    /// Execute every block a second time on a shadow executor and compare
+    /// state roots before storing it: warn and keep sealing, or halt sealing
+    /// on divergence. The shadow first replays the whole chain
+    #[arg(long, value_enum, default_value = "off")]
+    determinism_audit: AuditMode,
+
+    /// POST critical event notifications as JSON to this URL (repeatable)
+    #[arg(long = "notify-webhook", value_name = "URL")]
+    notify_webhooks: Vec<String>,
+
+    /// Post critical event notifications to this Slack-compatible incoming
+    /// webhook (repeatable)
+    #[arg(long = "notify-slack-webhook", value_name = "URL")]
+    notify_slack_webhooks: Vec<String>,
+
+    /// Mail critical event notifications to this address through
+    /// --notify-sendmail (repeatable)
+    #[arg(long = "notify-email", value_name = "ADDRESS")]
+    notify_emails: Vec<String>,
+
+    /// From address of notification mails (default: sendmail's)
+    #[arg(long, value_name = "ADDRESS")]
+    notify_email_from: Option<String>,
+
+    /// sendmail-compatible binary notification mails are piped to, with -t
+    #[arg(long, value_name = "PATH", default_value = "sendmail")]
+    notify_sendmail: PathBuf,
+
+    /// Name of this node in notifications
+    #[arg(long, value_name = "NAME")]
+    notify_node_name: Option<String>,
+
+    /// Notify once this many batch submissions in a row failed (0 never notifies)
+    #[arg(long, default_value = "3")]
+    notify_batch_failures: u32,
+
+    /// Notify when the authority's L1 balance drops below this many lamports
+    /// (0 never notifies)
+    #[arg(long, default_value = "100000000")]
+    notify_authority_balance_lamports: u64,
+
+    /// L1 challenge program whose finalized transactions are polled every
+    /// --l1-poll-interval-secs; every challenge it opens against a batch is
+    /// notified (needs a --notify-* sink)
+    #[arg(long, value_name = "PUBKEY")]
+    challenge_program_id: Option<Pubkey>,
+
+    /// What sendTransaction does while degraded
+    #[arg(long, value_enum, default_value = "reject")]
+    degraded_mode: DegradedMode,
+
+    /// Seconds between polls of the finalized L1 slot; batches only become
+    /// finalized once the slot they landed in is
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u64).range(1..))]
+    l1_poll_interval_secs: u64,
+
+    /// L1 bridge program whose deposit events credit L2 accounts; its
+    /// finalized transactions are polled every --l1-poll-interval-secs.
+    /// Without it, no deposits are taken
+    #[arg(long)]
+    bridge_program_id: Option<Pubkey>,
+
+    /// L1 slots the rollup program makes withdrawals wait after their batch
+    /// lands before they can be claimed; only used to report claimability
+    #[arg(long, default_value = "0")]
+    withdrawal_challenge_slots: u64,
+
+    /// Net out opposing transfers between the same accounts before posting a batch
+    #[arg(long)]
+    compact_transfers: bool,
+
+    /// How batches are compressed before posting to L1
+    #[arg(long, value_enum, default_value = "zstd")]
+    batch_compression: CompressionAlgorithm,
+
+    /// zstd level for --batch-compression zstd
+    #[arg(long, default_value = "3", value_parser = clap::value_parser!(i32).range(1..=22))]
+    zstd_level: i32,
+
+    /// How batches are committed to L1: `every-batch` posts each batch's data,
+    /// `aggregated` publishes it to --da-publish-url and posts one root for
+    /// several batches, trading finality latency for L1 cost
+    #[arg(long, value_enum, default_value = "every-batch")]
+    commitment_strategy: CommitmentStrategy,
+
+    /// Batches covered by each aggregate with --commitment-strategy aggregated
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..=1000))]
+    aggregate_batches: u64,
+
+    /// Longest a batch waits for the rest of its aggregate before a partial
+    /// one is posted
+    #[arg(long, default_value = "30")]
+    aggregate_max_wait_secs: u64,
+
+    /// Object store URL batch commitments are PUT to at <URL>/<BATCH_ID> with
+    /// --commitment-strategy aggregated; also used as a --da-source
+    #[arg(long, value_name = "URL")]
+    da_publish_url: Option<String>,
+
+    /// Seconds a passed simulateTransaction spares the following sendTransaction
+    /// from verifying signatures and precompiles again (0 disables)
+    #[arg(long, default_value = "30")]
+    preflight_cache_ttl_secs: u64,
+
+    /// Workers verifying signatures and precompiles of submitted transactions
+    #[arg(long, default_value = "4", value_parser = clap::value_parser!(u64).range(1..))]
+    verify_workers: u64,
+
+    /// Workers simulating verified transactions against current state
+    #[arg(long, default_value = "4", value_parser = clap::value_parser!(u64).range(1..))]
+    preflight_workers: u64,
+
+    /// Workers moving preflighted transactions into the mempool
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+    sequence_workers: u64,
+
+    /// Capacity of each transaction pipeline stage's queue; submissions are
+    /// refused while the first one is full
+    #[arg(long, default_value = "1024", value_parser = clap::value_parser!(u64).range(1..))]
+    pipeline_queue_capacity: u64,
+
+    /// Cap on account data bytes returned by a single account read
+    #[arg(long, default_value = "10485760")]
+    max_response_bytes: usize,
+
+    /// Node identity keypair; getLatestBlockhash, getBatch, getTransaction
+    /// and getSignatureStatuses responses carry its signature in an
+    /// `attestation` field
+    #[arg(long, value_name = "PATH")]
+    identity_keypair: Option<PathBuf>,
+
+    /// Run as a read replica of the sequencer at this WebSocket URL: blocks
+    /// are taken from its blockStreamSubscribe stream instead of sealed
+    /// locally, and write methods are not served
+    #[arg(long, value_name = "WS_URL", requires = "sequencer_identity")]
+    replicate_from: Option<String>,
+
+    /// Identity (--identity-keypair) of the sequencer a replica follows or a
+    /// co-signer signs for; frames and co-signing requests not signed by it
+    /// are rejected
+    #[arg(long, value_name = "PUBKEY")]
+    sequencer_identity: Option<Pubkey>,
+
+    /// Batches per epoch; rent is collected and state checkpointed at every epoch boundary
+    #[arg(long, default_value = "1800", value_parser = clap::value_parser!(u64).range(1..))]
+    batches_per_epoch: u64,
+
+    /// Rent charged per byte each epoch to accounts below the rent-exempt minimum
+    #[arg(long, default_value = "0")]
+    rent_lamports_per_byte: u64,
+
+    /// Batch height from which epoch boundaries remove accounts with no
+    /// lamports and no data from state
+    #[arg(long, value_name = "BATCH")]
+    reap_empty_accounts_from: Option<u64>,
+
+    /// Separate database that stored batches and reports older than
+    /// --hot-epochs are moved to; reads fall through to it transparently
+    #[arg(long)]
+    cold_db_path: Option<String>,
+
+    /// Epochs of batches and reports kept in the main database with --cold-db-path
+    #[arg(long, default_value = "4", value_parser = clap::value_parser!(u64).range(1..))]
+    hot_epochs: u64,
+
+    /// Where batches pruned locally are fetched back from, tried in order:
+    /// `l1` (the L1 transaction history) or an object store URL serving
+    /// posted commitments at <URL>/<BATCH_ID> (repeatable)
+    #[arg(long = "da-source", value_name = "l1|URL")]
+    da_sources: Vec<String>,
+
+    /// Epochs of batch data kept locally; older batches finalized on L1 are
+    /// deleted at each epoch boundary with their state diffs (needs
+    /// --da-source). Replicas of a pruning node start from a copy of its
+    /// database
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    prune_batches_after_epochs: Option<u64>,
+
+    /// Account credited with transaction fees and the rent collected each
+    /// epoch (both are burned when unset)
+    #[arg(long)]
+    fee_collector: Option<Pubkey>,
+
+    /// Also serve dispute data (pre-state, witnesses, expected post-state) for challenges
+    #[arg(long)]
+    verifier: bool,
+
+    /// Batches getDisputeData may replay from genesis to rebuild a batch's
+    /// pre-state, so it only reaches batches below this
+    #[arg(long, default_value = "10000")]
+    dispute_max_batches: u64,
+
+    /// Also stream block, transaction and account events as server-sent
+    /// events on GET /events
+    #[arg(long)]
+    sse: bool,
+
+    /// How to handle instructions for programs that aren't registered
+    #[arg(long, value_enum, default_value = "reject")]
+    unknown_programs: UnknownProgramPolicy,
+
+    /// Token list JSON (a Solana token list, or an array of its tokens)
+    /// seeding the symbols, names, decimals and logos served for L2 mints
+    #[arg(long, value_name = "FILE")]
+    token_list: Option<String>,
+
+    /// Register a program with a handler: system, memo, spl-token or svm
+    /// (repeatable; the system, memo and spl-token programs are registered by default)
+    #[arg(long = "program", value_name = "PROGRAM_ID=HANDLER")]
+    programs: Vec<String>,
+
+    /// Only admit transactions signed by one of these keys (repeatable)
+    #[arg(long = "allowed-signer")]
+    allowed_signers: Vec<Pubkey>,
+
+    /// Admit transactions that only invoke these programs (repeatable)
+    #[arg(long = "allowed-program")]
+    allowed_programs: Vec<Pubkey>,
+
+    /// System transfers below this many lamports are rejected or surcharged
+    /// per --dust-policy (0 allows any amount)
+    #[arg(long, default_value = "0")]
+    min_transfer_lamports: u64,
+
+    /// Whether dust transfers are rejected or pay --dust-surcharge-lamports
+    /// for each account they create
+    #[arg(long, value_enum, default_value = "reject")]
+    dust_policy: DustAction,
+
+    /// Burned from the fee payer for each account a dust transfer creates
+    #[arg(long, default_value = "100000")]
+    dust_surcharge_lamports: u64,
+
+    /// Flat fee charged to every transaction, on top of the signature fee
+    #[arg(long, default_value = "0")]
+    base_fee_lamports: u64,
+
+    /// Fee charged per signature a transaction requires, as on L1
+    #[arg(long, default_value = "5000")]
+    lamports_per_signature: u64,
+
+    /// Latency target for RPC calls in milliseconds; slower calls are
+    /// logged and counted as SLO violations in getMetrics
+    #[arg(long, default_value = "500")]
+    rpc_slo_ms: u64,
+
+    /// Latency target for one RPC method, overriding --rpc-slo-ms (repeatable)
+    #[arg(long = "rpc-slo", value_name = "METHOD=MS")]
+    rpc_slos: Vec<String>,
+
+    /// Calls a JSON-RPC batch may contain; longer batches are rejected whole
+    #[arg(long, default_value = "100", value_parser = clap::value_parser!(u32).range(1..))]
+    rpc_max_batch_len: u32,
+
+    /// Total cost of the calls in a JSON-RPC batch; calls past it fail
+    /// without running
+    #[arg(long, default_value = "200")]
+    rpc_max_batch_cost: u64,
+
+    /// Cost of one RPC method against --rpc-max-batch-cost, overriding the
+    /// default of 1 (or more for methods such as getProgramAccounts) (repeatable)
+    #[arg(long = "rpc-method-cost", value_name = "METHOD=COST")]
+    rpc_method_costs: Vec<String>,
+
+    /// Batch calls executing at once across all connections; single calls
+    /// aren't limited
+    #[arg(long, default_value = "4", value_parser = clap::value_parser!(u64).range(1..))]
+    rpc_batch_concurrency: u64,
+
+    /// Shed load while the node's resident memory is at least this many MB
+    #[arg(long)]
+    shed_memory_mb: Option<u64>,
+
+    /// Shed load while the node has at least this many file descriptors open
+    #[arg(long)]
+    shed_open_files: Option<u64>,
+
+    /// Shed load while RocksDB is stopping or delaying writes
+    #[arg(long)]
+    shed_on_write_stalls: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Re-execute stored batches from genesis and compare their state roots
+    Replay {
+        /// First batch to report on
+        #[arg(long)]
+        from: u64,
+
+        /// Last batch to replay (inclusive)
+        #[arg(long)]
+        to: u64,
+    },
+    /// Write all accounts to a directory of hashed chunks; rerun to resume
+    Export {
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Accounts per chunk file
+        #[arg(long, default_value = "10000", value_parser = clap::value_parser!(u64).range(1..))]
+        chunk_accounts: u64,
+    },
+    /// Load an export into an empty database; rerun to resume
+    Import {
+        #[arg(long)]
+        from: PathBuf,
+    },
+    /// Run as a batch co-signer on --port for --sequencer-identity, recording
+    /// signed batches in --db-path (only batches of --genesis-hash, when given)
+    Cosign {
+        /// Keypair file co-signatures are made with
+        #[arg(long)]
+        keypair: PathBuf,
+    },
+    /// Check the database, chain, authority keys, L1 RPC and ports a node
+    /// started with the same flags would use, without starting it
+    Doctor,
+    /// Save the mempool of the node whose admin RPC is at --url to a file
+    DumpMempool {
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Defaults to the local node's --admin-rpc-address
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Load a saved mempool into the node whose admin RPC is at --url
+    LoadMempool {
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Defaults to the local node's --admin-rpc-address
+        #[arg(long)]
+        url: Option<String>,
+    },
+}
+
+impl Args {
+    /// Takes every setting of `config` that wasn't given on the command line.
+    fn apply_config(&mut self, config: RollupConfig, matches: &ArgMatches) {
+        let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if let Some(port) = config.port && unset("port") {
+            self.port = port;
+        }
+        if let Some(db_path) = config.db_path && unset("db_path") {
+            self.db_path = db_path;
+        }
+        if let Some(solana_rpc) = config.solana_rpc && unset("solana_rpc") {
+            self.solana_rpc = Some(solana_rpc);
+        }
+        if let Some(batch_interval_ms) = config.batch_interval_ms && unset("batch_interval_ms") {
+            self.batch_interval_ms = batch_interval_ms;
+        }
+        if let Some(max_batch_bytes) = config.max_batch_bytes && unset("max_batch_bytes") {
+            self.max_batch_bytes = max_batch_bytes;
+        }
+        if let Some(authority_keypairs) = config.authority_keypairs && unset("authority_keypairs") {
+            self.authority_keypairs = authority_keypairs;
+        }
+        if let Some(rollup_program_id) = config.rollup_program_id && unset("rollup_program_id") {
+            self.rollup_program_id = Some(rollup_program_id);
+        }
+        if let Some(bridge_program_id) = config.bridge_program_id && unset("bridge_program_id") {
+            self.bridge_program_id = Some(bridge_program_id);
+        }
+        if let Some(lamports_per_signature) = config.fees.lamports_per_signature && unset("lamports_per_signature") {
+            self.lamports_per_signature = lamports_per_signature;
+        }
+        if let Some(base_fee_lamports) = config.fees.base_fee_lamports && unset("base_fee_lamports") {
+            self.base_fee_lamports = base_fee_lamports;
+        }
+        if let Some(fee_collector) = config.fees.fee_collector && unset("fee_collector") {
+            self.fee_collector = Some(fee_collector);
+        }
+        if let Some(cors_origins) = config.cors_origins && unset("cors_origins") {
+            self.cors_origins = cors_origins;
+        }
+    }
+
+    fn fees(&self) -> FeeSchedule {
+        FeeSchedule {
+            base_fee_lamports: self.base_fee_lamports,
+            lamports_per_signature: self.lamports_per_signature,
+            fee_collector: self.fee_collector,
+        }
+    }
+
+    /// How blocks execute on this chain, for the node and every replay of it.
+    fn execution(&self) -> anyhow::Result<ExecutionConfig> {
+        Ok(ExecutionConfig {
+            programs: self.programs.iter()
+                .map(|spec| ProgramRegistry::parse_spec(spec))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            unknown_programs: self.unknown_programs,
+            dust_policy: DustPolicy {
+                min_transfer_lamports: self.min_transfer_lamports,
+                action: self.dust_policy,
+                surcharge_lamports: self.dust_surcharge_lamports,
+            },
+            fees: self.fees(),
+        })
+    }
+
+    /// Notifier for the configured --notify-* sinks, if there are any.
+    fn notifier(&self) -> Option<Notifier> {
+        let mut sinks: Vec<NotificationSink> = self.notify_webhooks.iter().cloned().map(NotificationSink::Webhook)
+            .chain(self.notify_slack_webhooks.iter().cloned().map(NotificationSink::Slack))
+            .collect();
+        if !self.notify_emails.is_empty() {
+            sinks.push(NotificationSink::Email {
+                sendmail: self.notify_sendmail.clone(),
+                from: self.notify_email_from.clone(),
+                to: self.notify_emails.clone(),
+            });
+        }
+        if sinks.is_empty() {
+            return None;
+        }
+
+        let notifier = Notifier::new(sinks, NotificationThresholds {
+            batch_failures: self.notify_batch_failures,
+            authority_balance_lamports: self.notify_authority_balance_lamports,
+        });
+        Some(match &self.notify_node_name {
+            Some(name) => notifier.with_node_name(name.clone()),
+            None => notifier,
+        })
+    }
+
+    fn doctor(&self) -> Doctor {
+        let mut listen_addresses = vec![std::net::SocketAddr::from(([0, 0, 0, 0], self.port))];
+        listen_addresses.extend(self.write_rpc_address);
+        if self.admin_rpc {
+            listen_addresses.push(self.admin_rpc_address);
+        }
+
+        Doctor::new(DoctorConfig {
+            db_path: self.db_path.clone(),
+            genesis_hash: self.genesis_hash,
+            authority_keypairs: self.authority_keypairs.clone(),
+            rollup_program_id: self.rollup_program_id,
+            solana_rpc: self.solana_rpc.clone(),
+            l1_cluster: self.l1_cluster,
+            listen_addresses,
+        })
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let config_path = args.config.clone()
+        .or_else(|| Path::new(DEFAULT_CONFIG_FILE).exists().then(|| PathBuf::from(DEFAULT_CONFIG_FILE)));
+    if let Some(path) = config_path {
+        args.apply_config(RollupConfig::load(&path)?, &matches);
+        println!("⚙️  Loaded configuration from {}", path.display());
+    }
+    if args.authority_keypairs.is_empty()
+        && let Ok(spec) = std::env::var(AUTHORITY_KEYPAIR_ENV)
+        && !spec.trim().is_empty()
+    {
+        args.authority_keypairs.push(spec);
+    }
+
+    match &args.command {
+        Some(Command::Replay { from, to }) => {
+            let data_availability = DataAvailability::new(&args.da_sources, args.solana_rpc.as_deref())?;
+            return replay(&args.db_path, args.cold_db_path.as_deref(), data_availability, &args.execution()?, *from, *to).await;
+        }
+        Some(Command::Export { out, chunk_accounts }) => return export(&args.db_path, out, *chunk_accounts as usize),
+        Some(Command::Import { from }) => return import(&args.db_path, from),
+        Some(Command::Cosign { keypair }) => {
+            let Some(sequencer) = args.sequencer_identity else {
+                anyhow::bail!("cosign needs the --sequencer-identity whose requests it signs");
+            };
+            return cosign(&args.db_path, keypair, sequencer, args.genesis_hash, args.port).await;
+        }
+        Some(Command::Doctor) => return doctor(&args).await,
+        Some(Command::DumpMempool { out, url }) => return dump_mempool(&admin_url(url, args.admin_rpc_address), out).await,
+        Some(Command::LoadMempool { from, url }) => return load_mempool(&admin_url(url, args.admin_rpc_address), from).await,
+        None => {}
+    }
+    let Some(solana_rpc) = args.solana_rpc.clone() else {
+        anyhow::bail!("--solana-rpc is required, on the command line or as solana-rpc in the config file");
+    };
+    let Some(rollup_program_id) = args.rollup_program_id else {
+        anyhow::bail!("--rollup-program-id is required to post batches, on the command line or as rollup-program-id in the config file");
+    };
+
+    #[cfg(feature = "chaos")]
+    if let Some(failure_rate) = args.chaos_failure_rate {
+        use clap::ValueEnum;
+        use tiny_rollup::chaos::chaos::{install, ChaosConfig, FaultPoint};
+
+        let points = if args.chaos_points.is_empty() {
+            FaultPoint::value_variants().to_vec()
+        } else {
+            args.chaos_points.clone()
+        };
+        install(ChaosConfig {
+            failure_rate,
+            max_delay: Duration::from_millis(args.chaos_max_delay_ms),
+            points: points.into_iter().collect(),
+        })?;
+    }
 
     // Initialize components
-    let state_manager = Arc::new(StateManager::new(&args.db_path)?);
-    let transaction_processor = Arc::new(TransactionProcessor::new(state_manager.clone()));
-    let (sequencer, batch_receiver) = Sequencer::new(state_manager.clone());
+    let mut state_manager = StateManager::new(&args.db_path, args.state_hash, args.sync_writes)?;
+    if state_manager.unverified_import()? {
+        anyhow::bail!("{} holds an import whose state root wasn't verified; rerun the import to finish it", args.db_path);
+    }
+    if let Some(cold_db_path) = &args.cold_db_path {
+        state_manager = state_manager.with_cold_storage(ColdStorage::open(cold_db_path)?);
+    }
+    let state_manager = Arc::new(state_manager);
+    state_manager.events().attach(EventMetrics);
+    if let Some(url) = args.index_clickhouse_url.clone() {
+        println!("🗄️  Indexing blocks and receipts into ClickHouse at {}", url);
+        let store = ClickHouseStore::new(url, args.index_table_prefix.clone());
+        state_manager.events().attach(IndexSink::start(store, state_manager.clone()));
+    }
+    if state_manager.unclean_shutdown() {
+        eprintln!(
+            "⚠️  {} was not closed cleanly; {}",
+            args.db_path,
+            if args.sync_writes { "no synced writes were lost" } else { "the last unsynced writes may be lost" }
+        );
+    }
+    if !args.skip_startup_checks {
+        let results = args.doctor().run(&Database::Open(&state_manager)).await;
+        let mut failed = 0;
+        for result in &results {
+            match result.status {
+                CheckStatus::Failed => {
+                    failed += 1;
+                    eprintln!("{}", result);
+                }
+                CheckStatus::Warning => eprintln!("{}", result),
+                CheckStatus::Ok | CheckStatus::Skipped => {}
+            }
+        }
+        if failed > 0 {
+            state_manager.close()?;
+            anyhow::bail!("{} startup checks failed; fix them or pass --skip-startup-checks", failed);
+        }
+    }
+    if args.warmup_accounts > 0 {
+        let stats = state_manager.warm_cache(args.warmup_accounts)?;
+        println!(
+            "Warmed {} accounts ({} bytes of data) in {:?}",
+            stats.accounts, stats.bytes, stats.elapsed
+        );
+    }
+    let notifier = args.notifier().map(Arc::new);
+    if let Some(notifier) = &notifier {
+        println!(
+            "🔔 Notifying {} of critical events",
+            notifier.sinks().iter().map(|sink| match sink {
+                NotificationSink::Webhook(url) | NotificationSink::Slack(url) => url.clone(),
+                NotificationSink::Email { to, .. } => to.join(", "),
+            }).collect::<Vec<_>>().join(", ")
+        );
+    }
+    let execution = args.execution()?;
+    let fees = execution.fees;
+    let access_policy = AccessPolicy::new(args.allowed_signers, args.allowed_programs);
+    if access_policy.is_permissioned() {
+        println!("Permissioned mode: admission restricted by signer/program allowlist");
+    }
+    let programs = Arc::new(ProgramRegistry::new(execution.programs.clone())?);
+    // Registrations made through the admin RPC are recorded with blocks
+    for change in state_manager.program_changes()? {
+        programs.apply(&change)?;
+    }
+    let mut transaction_processor = TransactionProcessor::new(
+        state_manager.clone(),
+        access_policy,
+        execution.unknown_programs
+    ).with_programs(programs.clone())
+        .with_fees(fees);
+    if fees.is_enabled() {
+        println!(
+            "Fees: {} lamports per signature plus {} base, {}",
+            fees.lamports_per_signature,
+            fees.base_fee_lamports,
+            match fees.fee_collector {
+                Some(fee_collector) => format!("credited to {}", fee_collector),
+                None => "burned".to_string(),
+            }
+        );
+    }
+    let dust_policy = execution.dust_policy;
+    if dust_policy.is_enabled() {
+        println!(
+            "Dust policy: transfers below {} lamports are {}",
+            dust_policy.min_transfer_lamports,
+            match dust_policy.action {
+                DustAction::Reject => "rejected".to_string(),
+                DustAction::Surcharge => format!("charged {} lamports per account created", dust_policy.surcharge_lamports),
+            }
+        );
+        transaction_processor = transaction_processor.with_dust_policy(dust_policy);
+    }
+    if args.preflight_cache_ttl_secs > 0 {
+        transaction_processor = transaction_processor
+            .with_preflight_cache(Duration::from_secs(args.preflight_cache_ttl_secs));
+    }
+    let transaction_processor = Arc::new(transaction_processor);
+    let (sequencer, batch_receiver) = Sequencer::new(
+        state_manager.clone(),
+        transaction_processor.clone(),
+        args.priority_aging_rate,
+        args.max_batch_bytes,
+        Duration::from_secs(args.mempool_ttl_secs),
+        MempoolLimits {
+            max_pending: args.max_pending_transactions,
+            max_pending_per_sender: args.max_pending_per_sender,
+            eviction: args.eviction_policy,
+        },
+        SpamScorer::new(Duration::from_secs(args.spam_half_life_secs), args.dust_threshold_lamports)
+    );
+    let sequencer = sequencer.with_batch_interval(Duration::from_millis(args.batch_interval_ms))
+        .with_compression(BatchCompression { algorithm: args.batch_compression, zstd_level: args.zstd_level });
+    let pressure_thresholds = PressureThresholds {
+        max_memory_bytes: args.shed_memory_mb.map(|mb| mb * 1024 * 1024),
+        max_open_files: args.shed_open_files,
+        write_stalls: args.shed_on_write_stalls,
+    };
+    let load_shedder = pressure_thresholds.is_enabled().then(|| {
+        println!("Load shedding enabled: heavy RPCs are refused and batches sealed less often under resource pressure");
+        let load_shedder = Arc::new(LoadShedder::new(pressure_thresholds, state_manager.clone()));
+        let monitor = load_shedder.clone();
+        tokio::spawn(async move {
+            monitor.run().await;
+        });
+        load_shedder
+    });
+    let sequencer = match &load_shedder {
+        Some(load_shedder) => sequencer.with_load_shedder(load_shedder.clone()),
+        None => sequencer,
+    };
+    let sequencer = match args.determinism_audit {
+        AuditMode::Off => sequencer,
+        // Replicas don't seal blocks
+        _ if args.replicate_from.is_some() => sequencer,
+        mode => {
+            let shadow = Replayer::new(&state_manager, &execution)?
+                .with_data_availability(Arc::new(DataAvailability::new(&args.da_sources, Some(&solana_rpc))?));
+            let mut audit = DeterminismAudit::new(&state_manager, shadow, mode).await?;
+            if let Some(notifier) = &notifier {
+                audit = audit.with_notifier(notifier.clone());
+            }
+            println!("🔬 Determinism audit ({:?}): every block is executed again on a shadow executor before it is stored", mode);
+            sequencer.with_determinism_audit(Arc::new(audit))
+        }
+    };
     let sequencer = Arc::new(sequencer);
 
-    // Start sequencer
-    let sequencer_clone = sequencer.clone();
-    tokio::spawn(async move {
-        sequencer_clone.start_batching().await;
-    });
+    // Start sequencer, or follow one as a replica
+    if let (Some(url), Some(identity)) = (&args.replicate_from, args.sequencer_identity) {
+        println!("🔁 Replica of {} (identity {}); blocks are not sealed locally", url, identity);
+        let mut replica = Replica::new(url.clone(), identity, state_manager.clone());
+        if let Some(notifier) = &notifier {
+            replica = replica.with_notifier(notifier.clone());
+        }
+        tokio::spawn(async move {
+            replica.run().await;
+        });
+    } else {
+        let sequencer_clone = sequencer.clone();
+        tokio::spawn(async move {
+            sequencer_clone.start_batching().await;
+        });
+    }
 
     // Start batch processor
-    let batch_processor = BatchProcessor::new(args.solana_rpc);
+    let authorities = if args.authority_keypairs.is_empty() {
+        let authorities = AuthoritySchedule::ephemeral();
+        eprintln!(
+            "⚠️  No --authority-keypair or {}; batches are signed by throwaway key {}",
+            AUTHORITY_KEYPAIR_ENV, authorities.current()
+        );
+        authorities
+    } else {
+        let keys = args.authority_keypairs.iter()
+            .map(|spec| AuthorityKey::from_spec(spec))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        AuthoritySchedule::new(keys)?
+    };
+    let epochs = EpochConfig {
+        batches_per_epoch: args.batches_per_epoch,
+        rent_lamports_per_byte: args.rent_lamports_per_byte,
+        fee_collector: args.fee_collector,
+        hot_epochs: args.hot_epochs,
+        reap_empty_accounts_from: args.reap_empty_accounts_from,
+        prune_batches_after_epochs: args.prune_batches_after_epochs,
+    };
+    let aggregation = match args.commitment_strategy {
+        CommitmentStrategy::EveryBatch => None,
+        CommitmentStrategy::Aggregated => {
+            let Some(url) = &args.da_publish_url else {
+                anyhow::bail!("--commitment-strategy aggregated needs a --da-publish-url to publish batch data to");
+            };
+            if !args.cosigners.is_empty() {
+                anyhow::bail!("--cosigner is not supported with --commitment-strategy aggregated");
+            }
+            Some(Aggregation {
+                batches: args.aggregate_batches as usize,
+                max_wait: Duration::from_secs(args.aggregate_max_wait_secs),
+                publisher: DaPublisher::new(url)?,
+            })
+        }
+    };
+    let mut da_sources = args.da_sources.clone();
+    if let Some(aggregation) = &aggregation {
+        println!(
+            "Committing batches in aggregates of up to {} (waiting at most {}s), published to {}",
+            aggregation.batches, aggregation.max_wait.as_secs(), aggregation.publisher.url()
+        );
+        // Aggregated batches can only be fetched back from where they were published
+        if !da_sources.iter().any(|source| source == aggregation.publisher.url()) {
+            da_sources.push(aggregation.publisher.url().to_string());
+        }
+    }
+    let data_availability = Arc::new(DataAvailability::new(&da_sources, Some(&solana_rpc))?);
+    if args.prune_batches_after_epochs.is_some() && data_availability.is_empty() {
+        anyhow::bail!("--prune-batches-after-epochs needs at least one --da-source to fetch pruned batches from");
+    }
+    if let Some(dir) = &args.ledger_export_dir {
+        let exporter = LedgerExporter::open(
+            dir,
+            args.ledger_export_segment_blocks,
+            state_manager.clone(),
+            data_availability.clone()
+        )?;
+        println!("📒 Exporting the ledger to {} from block {}", dir.display(), exporter.next_slot());
+        tokio::spawn(exporter.run());
+    }
+    let l1_watcher = Arc::new(L1Watcher::new(solana_rpc.clone(), Duration::from_secs(args.l1_poll_interval_secs)));
+    let watcher = l1_watcher.clone();
     tokio::spawn(async move {
-        batch_processor.process_batches(batch_receiver).await;
+        watcher.run().await;
     });
+    if let Some(bridge_program_id) = args.bridge_program_id
+        && args.replicate_from.is_none()
+    {
+        println!("Crediting deposits made through bridge program {}", bridge_program_id);
+        let deposit_watcher = ProgramLogWatcher::new(
+            solana_rpc.clone(),
+            l1_watcher.clone(),
+            bridge_program_id,
+            state_manager.clone(),
+            L1Cursor::Deposits,
+            DepositHandler::new(state_manager.clone())
+        );
+        tokio::spawn(async move {
+            deposit_watcher.run().await;
+        });
+    }
+    if let Some(challenge_program_id) = args.challenge_program_id {
+        let Some(notifier) = &notifier else {
+            anyhow::bail!("--challenge-program-id needs a --notify-webhook, --notify-slack-webhook or --notify-email to report challenges to");
+        };
+        println!("Watching challenge program {} for challenges to report", challenge_program_id);
+        let challenge_watcher = ProgramLogWatcher::new(
+            solana_rpc.clone(),
+            l1_watcher.clone(),
+            challenge_program_id,
+            state_manager.clone(),
+            L1Cursor::Challenges,
+            ChallengeHandler::new(notifier.clone())
+        ).with_start(StartFrom::Latest);
+        tokio::spawn(async move {
+            challenge_watcher.run().await;
+        });
+    }
+    let mut batch_processor = BatchProcessor::new(
+        solana_rpc,
+        rollup_program_id,
+        state_manager.clone(),
+        authorities,
+        args.compute_unit_price,
+        epochs,
+        args.compact_transfers,
+        L1Health::new(Duration::from_secs(args.degraded_after_secs), args.degraded_mode)
+    ).with_l1_watcher(l1_watcher)
+        .with_compression(BatchCompression { algorithm: args.batch_compression, zstd_level: args.zstd_level });
+    if let Some(aggregation) = aggregation {
+        batch_processor = batch_processor.with_aggregation(aggregation);
+    }
+    if let Some(notifier) = &notifier {
+        batch_processor = batch_processor.with_notifier(notifier.clone());
+    }
+    if !args.cosigners.is_empty() {
+        let cosigners = args.cosigners.iter()
+            .map(|spec| Cosigner::from_spec(spec))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let threshold = args.cosigner_threshold.unwrap_or(cosigners.len());
+        let Some(path) = &args.identity_keypair else {
+            anyhow::bail!("--cosigner needs an --identity-keypair to sign co-signing requests with");
+        };
+        let identity = read_keypair_file(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read identity keypair {}: {}", path.display(), e))?;
+        println!("Batches need {} of {} co-signatures", threshold, cosigners.len());
+        batch_processor = batch_processor.with_cosigners(CosignerSet::new(cosigners, threshold, identity)?);
+    }
+    batch_processor.restore_finality()?;
+    let batch_processor = Arc::new(batch_processor);
+    let blockhash_refresher = batch_processor.clone();
+    tokio::spawn(async move {
+        blockhash_refresher.refresh_blockhash().await;
+    });
+    let health_monitor = batch_processor.clone();
+    tokio::spawn(async move {
+        health_monitor.monitor_health().await;
+    });
+    let finality_tracker = batch_processor.clone();
+    tokio::spawn(async move {
+        finality_tracker.track_finality().await;
+    });
+    let batch_processor_clone = batch_processor.clone();
+    tokio::spawn(async move {
+        batch_processor_clone.process_batches(batch_receiver).await;
+    });
+
+    if let Some(endpoint) = args.telemetry_endpoint {
+        println!("Telemetry enabled: reporting to {}", endpoint);
+        let telemetry = Telemetry::new(
+            endpoint,
+            Duration::from_secs(args.telemetry_interval_secs),
+            state_manager.clone(),
+            sequencer.clone()
+        );
+        tokio::spawn(async move {
+            telemetry.run().await;
+        });
+    }
+
+    let pipeline = Arc::new(Pipeline::start(
+        transaction_processor.clone(),
+        sequencer.clone(),
+        PipelineConfig {
+            verify_workers: args.verify_workers as usize,
+            preflight_workers: args.preflight_workers as usize,
+            sequence_workers: args.sequence_workers as usize,
+            queue_capacity: args.pipeline_queue_capacity as usize,
+        }
+    ));
+
+    let tokens = match &args.token_list {
+        Some(path) => {
+            let tokens = TokenRegistry::new(TokenRegistry::load(path)?)?;
+            println!("Loaded metadata for {} tokens from {}", tokens.tokens().len(), path);
+            tokens
+        }
+        None => TokenRegistry::default(),
+    };
+    let tokens = Arc::new(tokens);
 
     // Start RPC Server
-    let rpc_impl = RollupRpcImpl::new(state_manager, transaction_processor, sequencer);
+    let mut rpc = RollupRpcImpl::new(
+        state_manager.clone(),
+        transaction_processor,
+        pipeline.clone(),
+        sequencer.clone(),
+        batch_processor.clone(),
+        args.max_response_bytes,
+        epochs
+    ).with_execution(execution.clone())
+        .with_historical_simulation(args.historical_simulation_max_batches)
+        .with_data_availability(data_availability)
+        .with_token_registry(tokens.clone())
+        .with_withdrawal_challenge_slots(args.withdrawal_challenge_slots);
+    if let Some(path) = &args.identity_keypair {
+        let keypair = read_keypair_file(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read identity keypair {}: {}", path.display(), e))?;
+        println!("Signing RPC responses as node identity {}", keypair.pubkey());
+        rpc = rpc.with_attestor(Arc::new(ResponseAttestor::new(keypair)));
+    }
+    if let Some(load_shedder) = &load_shedder {
+        rpc = rpc.with_load_shedder(load_shedder.clone());
+    }
+    if args.faucet_enabled {
+        let config = FaucetConfig {
+            max_lamports: args.faucet_max_lamports,
+            requests_per_address: args.faucet_requests_per_address as usize,
+            window: Duration::from_secs(args.faucet_window_secs),
+        };
+        println!(
+            "🚰 Faucet enabled: up to {} lamports per airdrop, {} airdrops per address every {}s",
+            config.max_lamports, config.requests_per_address, args.faucet_window_secs
+        );
+        rpc = rpc.with_faucet(Arc::new(Faucet::new(config)));
+    }
+    if let Some(dir) = &args.snapshot_dir {
+        let base_url = args.snapshot_base_url.clone()
+            .or_else(|| args.serve_snapshots.then(|| format!("http://localhost:{}{}", args.port, SNAPSHOTS_PATH)));
+        rpc = rpc.with_snapshots(dir.clone(), base_url);
+    }
+    let mut rpc_module = rpc.into_rpc();
+    rpc_module.merge(unsupported_methods()?)?;
+    // Operator methods only ever go to the admin listener
+    let mut admin_methods = split_admin_methods(&mut rpc_module)?;
+    if args.verifier {
+        rpc_module.merge(VerifierRpcImpl::new(state_manager.clone(), execution, args.dispute_max_batches).into_rpc())?;
+    }
+    if args.admin_rpc {
+        admin_methods.merge(AdminRpcImpl::new(batch_processor, state_manager.clone(), programs, tokens.clone(), sequencer, pipeline).into_rpc())?;
+    }
+    let slo_targets = SloTargets::new(Duration::from_millis(args.rpc_slo_ms), args.rpc_slos.iter()
+        .map(|spec| SloTargets::parse_spec(spec))
+        .collect::<anyhow::Result<Vec<_>>>()?);
+    let slo = SloLayer::new(slo_targets, rpc_module.method_names());
+    let method_costs = MethodCosts::new(args.rpc_method_costs.iter()
+        .map(|spec| MethodCosts::parse_spec(spec))
+        .collect::<anyhow::Result<Vec<_>>>()?);
+    let shedding = load_shedder.map(|load_shedder| SheddingLayer::new(load_shedder, method_costs.clone()));
+    let batch_limits = BatchLimitLayer::new(method_costs, args.rpc_max_batch_cost, args.rpc_batch_concurrency as usize);
+    let server_config = ServerConfig::builder()
+        .set_batch_request_config(BatchRequestConfig::Limit(args.rpc_max_batch_len))
+        .build();
     
     // Configure CORS
     let cors = CorsLayer::new()
         .allow_methods(Any)
-        .allow_origin(Any)
         .allow_headers(Any);
+    let cors = if args.cors_origins.is_empty() {
+        cors.allow_origin(Any)
+    } else {
+        let origins = args.cors_origins.iter()
+            .map(|origin| cors_origin(origin))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        cors.allow_origin(AllowOrigin::list(origins))
+    };
 
     let middleware = tower::ServiceBuilder::new().layer(cors);
 
+    let write_server = match args.write_rpc_address {
+        // Replicas take state only from the block stream
+        _ if args.replicate_from.is_some() => {
+            split_write_methods(&mut rpc_module)?;
+            println!("✍️  Write methods are not served by a replica");
+            None
+        }
+        Some(address) => {
+            let write_methods = split_write_methods(&mut rpc_module)?;
+            let server = ServerBuilder::default()
+                .set_http_middleware(middleware.clone())
+                .set_config(server_config.clone())
+                .set_rpc_middleware(RpcServiceBuilder::new().layer(slo.clone()).layer(batch_limits.clone()).option_layer(shedding.clone()))
+                .build(address)
+                .await?;
+            println!("✍️  Write methods served on {}", address);
+            Some(server.start(write_methods))
+        }
+        None => None,
+    };
+
+    // No CORS, so web pages can't call it
+    let admin_server = if args.admin_rpc {
+        let server = ServerBuilder::default()
+            .set_config(server_config.clone())
+            .set_rpc_middleware(RpcServiceBuilder::new().layer(batch_limits.clone()))
+            .build(args.admin_rpc_address)
+            .await?;
+        println!("🔧 Admin methods served on {}", args.admin_rpc_address);
+        Some(server.start(admin_methods))
+    } else {
+        None
+    };
+
+    let sse = args.sse.then(|| SseLayer::new(state_manager.events().clone()));
+    let snapshot_files = args.snapshot_dir.clone()
+        .filter(|_| args.serve_snapshots)
+        .map(SnapshotFilesLayer::new);
     let server = ServerBuilder::default()
-        .set_http_middleware(middleware)
+        .set_http_middleware(middleware.layer(TokenListLayer::new(tokens)).option_layer(sse).option_layer(snapshot_files))
+        .set_config(server_config)
+        .set_rpc_middleware(RpcServiceBuilder::new().layer(slo).layer(batch_limits).option_layer(shedding))
         .build(format!("0.0.0.0:{}", args.port))
         .await?;
 
-    let handle = server.start(rpc_impl.into_rpc());
+    let handle = server.start(rpc_module);
 
     println!("🚀 Rollup validator started on port {}", args.port);
+    println!("🪙 Token list served on http://localhost:{}{}", args.port, TOKENS_PATH);
+    if args.sse {
+        println!("📡 Events streamed on http://localhost:{}{}", args.port, EVENTS_PATH);
+    }
+    if args.serve_snapshots {
+        println!("📸 Snapshots served on http://localhost:{}{}", args.port, SNAPSHOTS_PATH);
+    }
     println!("Users can connect with: http://localhost:{}", args.port);
 
     // Wait for shutdown signal
     tokio::signal::ctrl_c().await?;
     handle.stop()?;
+    if let Some(write_handle) = write_server {
+        write_handle.stop()?;
+    }
+    if let Some(admin_handle) = admin_server {
+        admin_handle.stop()?;
+    }
+    state_manager.close()?;
+    println!("State flushed to {}", args.db_path);
 
     Ok(())
 }
 
+fn export(db_path: &str, out: &Path, chunk_accounts: usize) -> anyhow::Result<()> {
+    let source = StateManager::open_read_only(db_path)?;
+    let manifest = export_state(&source, out, chunk_accounts)?;
+
+    let accounts: usize = manifest.chunks.iter().map(|chunk| chunk.accounts).sum();
+    println!("Exported {} accounts in {} chunks to {}", accounts, manifest.chunks.len(), out.display());
+
+    Ok(())
+}
+
+fn import(db_path: &str, from: &Path) -> anyhow::Result<()> {
+    let manifest = read_manifest(from)?
+        .ok_or_else(|| anyhow::anyhow!("No export manifest in {}", from.display()))?;
+    let target = StateManager::new(db_path, Some(manifest.hash_function), false)?;
+    let manifest = import_state(&target, from)?;
+    target.close()?;
+
+    let accounts: usize = manifest.chunks.iter().map(|chunk| chunk.accounts).sum();
+    println!("Imported {} accounts into {}", accounts, db_path);
+
+    Ok(())
+}
+
+async fn cosign(db_path: &str, keypair: &Path, sequencer: Pubkey, genesis_hash: Option<Hash>, port: u16) -> anyhow::Result<()> {
+    let keypair = read_keypair_file(keypair)
+        .map_err(|e| anyhow::anyhow!("Failed to read co-signer keypair {}: {}", keypair.display(), e))?;
+    let pubkey = keypair.pubkey();
+
+    let server = ServerBuilder::default()
+        .build(format!("0.0.0.0:{}", port))
+        .await?;
+    let handle = server.start(CosignerRpcImpl::new(keypair, sequencer, genesis_hash, db_path)?.into_rpc());
+    println!("Co-signing batches as {} on port {}", pubkey, port);
+
+    tokio::signal::ctrl_c().await?;
+    handle.stop()?;
+
+    Ok(())
+}
+
+async fn doctor(args: &Args) -> anyhow::Result<()> {
+    // Read-only, so it can also inspect the database of a running node
+    let state_manager = if !Path::new(&args.db_path).exists() {
+        None
+    } else {
+        Some(StateManager::open_read_only(&args.db_path).map_err(|e| e.to_string()))
+    };
+    let database = match &state_manager {
+        None => Database::Missing,
+        Some(Ok(state_manager)) => Database::Open(state_manager),
+        Some(Err(e)) => Database::Failed(e.clone()),
+    };
+
+    let results = args.doctor().run(&database).await;
+    for result in &results {
+        println!("{}", result);
+    }
+
+    let failed = results.iter().filter(|result| result.status == CheckStatus::Failed).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} checks failed", failed, results.len());
+    }
+    println!("All checks passed");
+
+    Ok(())
+}
+
+fn admin_url(url: &Option<String>, address: std::net::SocketAddr) -> String {
+    url.clone().unwrap_or_else(|| format!("http://{}", address))
+}
+
+async fn dump_mempool(url: &str, out: &Path) -> anyhow::Result<()> {
+    let snapshot: MempoolSnapshot = serde_json::from_value(admin_call(url, "getMempoolSnapshot", serde_json::json!([])).await?)?;
+    std::fs::write(out, serde_json::to_vec_pretty(&snapshot)?)?;
+
+    let next_batch = snapshot.transactions.iter().filter(|entry| entry.next_batch).count();
+    println!(
+        "Saved {} mempool transactions ({} slated for slot {}) to {}",
+        snapshot.transactions.len(), next_batch, snapshot.slot, out.display()
+    );
+
+    Ok(())
+}
+
+async fn load_mempool(url: &str, from: &Path) -> anyhow::Result<()> {
+    let snapshot: MempoolSnapshot = serde_json::from_slice(&std::fs::read(from)?)?;
+    let result = admin_call(url, "importMempool", serde_json::json!([snapshot])).await?;
+
+    println!(
+        "Loaded {} mempool transactions: {} submitted, {} already sealed",
+        snapshot.transactions.len(), result["submitted"], result["sealed"]
+    );
+    for failed in result["failed"].as_array().into_iter().flatten() {
+        eprintln!("⚠️  {} failed: {}", failed["signature"], failed["error"]);
+    }
+
+    Ok(())
+}
+
+/// Calls `method` on a node's admin RPC, returning its result.
+async fn admin_call(url: &str, method: &str, params: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    let mut response: serde_json::Value = reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+        .send().await?
+        .error_for_status()?
+        .json().await?;
+
+    if let Some(error) = response.get("error") {
+        anyhow::bail!("{} failed: {}", method, error);
+    }
+    Ok(response["result"].take())
+}
+
+async fn replay(
+    db_path: &str,
+    cold_db_path: Option<&str>,
+    data_availability: DataAvailability,
+    execution: &ExecutionConfig,
+    from: u64,
+    to: u64
+) -> anyhow::Result<()> {
+    if from > to {
+        anyhow::bail!("--from ({}) must not be after --to ({})", from, to);
+    }
+
+    let mut source = StateManager::open_read_only(db_path)?;
+    if let Some(cold_db_path) = cold_db_path {
+        source = source.with_cold_storage(ColdStorage::open_read_only(cold_db_path)?);
+    }
+    let mut replayer = Replayer::new(&source, execution)?.with_data_availability(Arc::new(data_availability));
+    let results = replayer.replay_until(&source, from, to).await?;
+
+    let mut divergent = 0;
+    for result in &results {
+        if result.matches() {
+            println!(
+                "Batch {}: ok ({} txs, {} failed) root {}",
+                result.batch_id,
+                result.transaction_count,
+                result.failed_transactions,
+                hex::encode(result.replayed_root)
+            );
+        } else {
+            divergent += 1;
+            println!(
+                "Batch {}: DIVERGED ({} txs, {} failed, {} with invalid signatures) expected {} got {}",
+                result.batch_id,
+                result.transaction_count,
+                result.failed_transactions,
+                result.invalid_signatures,
+                hex::encode(result.expected_root),
+                hex::encode(result.replayed_root)
+            );
+        }
+    }
+
+    if divergent > 0 {
+        anyhow::bail!("{} of {} batches diverged", divergent, results.len());
+    }
+    println!("Replayed batches {}..={} without divergence", from, to);
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use crate::{
+    events::events::{EventConsumer, RollupEvent},
+    metrics::metrics,
+    sequencer::sequencer::DropReason,
+    transaction_processor::transaction_record::TransactionStage,
+};
+
+/// Counts what happens to the rollup from the event bus.
+#[derive(Debug, Default)]
+pub struct EventMetrics;
+
+impl EventConsumer for EventMetrics {
+    fn name(&self) -> &'static str {
+        "metrics"
+    }
+
+    fn handle(&mut self, event: &RollupEvent) {
+        match event {
+            RollupEvent::Transaction { stage: TransactionStage::Processed, .. } => metrics::increment("transactions_processed"),
+            RollupEvent::Transaction { .. } => {}
+            RollupEvent::TransactionDropped { reason, .. } => metrics::increment(match reason {
+                DropReason::Expired => "mempool_dropped_expired",
+                DropReason::MempoolFull => "mempool_evicted",
+                DropReason::ExecutionFailed => "mempool_dropped_failed",
+            }),
+            RollupEvent::Block { .. } => metrics::increment("batches_sealed"),
+            RollupEvent::Account { account: Some(_), .. } => metrics::increment("accounts_updated"),
+            RollupEvent::Account { account: None, .. } => metrics::increment("accounts_closed"),
+            RollupEvent::BatchSubmitted { report } => metrics::increment(match report.error {
+                Some(_) => "l1_batches_failed",
+                None => "l1_batches_posted",
+            }),
+            RollupEvent::BatchFinalized { batch_id } => metrics::set_gauge("l1_finalized_batch", *batch_id),
+        }
+    }
+}
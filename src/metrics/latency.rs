@@ -0,0 +1,113 @@
+//! Per RPC method latency percentiles against SLO targets, served by the
+//! getMetrics RPC method.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Calls kept per method, so percentiles follow recent traffic
+const WINDOW: usize = 1024;
+
+static LATENCIES: LazyLock<Mutex<BTreeMap<&'static str, MethodLatency>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Default)]
+struct MethodLatency {
+    recent: VecDeque<Duration>,
+    calls: u64,
+    slo_violations: u64,
+    slo: Duration,
+}
+
+/// Latency of one method over its recent calls.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencySummary {
+    pub calls: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub slo_ms: f64,
+    /// Calls that took longer than the SLO, since startup
+    pub slo_violations: u64,
+}
+
+/// How long each RPC method may take: a default, with overrides per method.
+#[derive(Debug, Clone)]
+pub struct SloTargets {
+    default: Duration,
+    methods: HashMap<String, Duration>,
+}
+
+impl SloTargets {
+    pub fn new(default: Duration, methods: impl IntoIterator<Item = (String, Duration)>) -> Self {
+        Self { default, methods: methods.into_iter().collect() }
+    }
+
+    /// Parses `METHOD=MS`.
+    pub fn parse_spec(spec: &str) -> anyhow::Result<(String, Duration)> {
+        let (method, millis) = spec.split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid SLO {}: expected METHOD=MS", spec))?;
+        let millis = u64::from_str(millis)
+            .map_err(|e| anyhow::anyhow!("Invalid SLO milliseconds in {}: {}", spec, e))?;
+
+        Ok((method.to_string(), Duration::from_millis(millis)))
+    }
+
+    pub fn target(&self, method: &str) -> Duration {
+        self.methods.get(method).copied().unwrap_or(self.default)
+    }
+}
+
+/// Records a call of `method` that took `elapsed` against its `slo`.
+/// Returns whether it violated the SLO.
+pub fn record(method: &'static str, elapsed: Duration, slo: Duration) -> bool {
+    let mut latencies = LATENCIES.lock().unwrap();
+    let latency = latencies.entry(method).or_default();
+    if latency.recent.len() == WINDOW {
+        latency.recent.pop_front();
+    }
+    latency.recent.push_back(elapsed);
+    latency.calls += 1;
+    latency.slo = slo;
+
+    let violated = elapsed > slo;
+    if violated {
+        latency.slo_violations += 1;
+    }
+    violated
+}
+
+pub fn summaries() -> BTreeMap<&'static str, LatencySummary> {
+    LATENCIES.lock().unwrap()
+        .iter()
+        .map(|(method, latency)| {
+            let mut recent: Vec<Duration> = latency.recent.iter().copied().collect();
+            recent.sort_unstable();
+
+            (*method, LatencySummary {
+                calls: latency.calls,
+                p50_ms: millis(percentile(&recent, 50)),
+                p95_ms: millis(percentile(&recent, 95)),
+                p99_ms: millis(percentile(&recent, 99)),
+                slo_ms: millis(latency.slo),
+                slo_violations: latency.slo_violations,
+            })
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of `sorted`.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
@@ -0,0 +1,29 @@
+//! Process-wide counters and gauges, served by the getMetrics RPC method.
+
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+
+static COUNTERS: LazyLock<Mutex<BTreeMap<&'static str, u64>>> = LazyLock::new(Default::default);
+static GAUGES: LazyLock<Mutex<BTreeMap<&'static str, u64>>> = LazyLock::new(Default::default);
+
+pub fn increment(name: &'static str) {
+    add(name, 1);
+}
+
+pub fn add(name: &'static str, value: u64) {
+    let mut counters = COUNTERS.lock().unwrap();
+    let counter = counters.entry(name).or_default();
+    *counter = counter.saturating_add(value);
+}
+
+pub fn set_gauge(name: &'static str, value: u64) {
+    GAUGES.lock().unwrap().insert(name, value);
+}
+
+pub fn counters() -> BTreeMap<&'static str, u64> {
+    COUNTERS.lock().unwrap().clone()
+}
+
+pub fn gauges() -> BTreeMap<&'static str, u64> {
+    GAUGES.lock().unwrap().clone()
+}
@@ -0,0 +1,3 @@
+pub mod event_metrics;
+pub mod latency;
+pub mod metrics;
@@ -0,0 +1,79 @@
+use std::sync::{Arc, LazyLock};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+use crate::{
+    deposits::deposits::program_data,
+    l1_watcher::program_logs::ProgramLogHandler,
+    metrics::metrics,
+    notifications::notifications::{NotificationEvent, Notifier},
+};
+
+/// Prefix of the challenge program's event for a newly opened challenge:
+/// the first 8 bytes of sha256("event:ChallengeOpened"), as Anchor derives
+/// event discriminators.
+static CHALLENGE_EVENT_DISCRIMINATOR: LazyLock<[u8; 8]> = LazyLock::new(|| {
+    Sha256::digest(b"event:ChallengeOpened")[..8]
+        .try_into()
+        .expect("sha256 is 32 bytes")
+});
+
+/// Event the challenge program logs (with `sol_log_data`) when someone
+/// disputes a batch's state root.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ChallengeEvent {
+    pub batch_id: u64,
+    pub challenger: Pubkey,
+}
+
+/// Challenge events the challenge program itself logged in a transaction's
+/// logs; data logged by any other program is ignored.
+pub fn parse_challenge_events(challenge_program_id: &Pubkey, logs: &[String]) -> Vec<ChallengeEvent> {
+    program_data(challenge_program_id, logs)
+        .into_iter()
+        .filter_map(decode_challenge_event)
+        .collect()
+}
+
+fn decode_challenge_event(data: &str) -> Option<ChallengeEvent> {
+    let bytes = BASE64_STANDARD.decode(data).ok()?;
+    let payload = bytes.strip_prefix(CHALLENGE_EVENT_DISCRIMINATOR.as_slice())?;
+    bincode::deserialize(payload).ok()
+}
+
+/// Notifies the operator of every challenge the challenge program logs
+/// against a batch, for a `ProgramLogWatcher` of its finalized history.
+/// Challenges opened while the node was down are still reported once it
+/// restarts; a node watching for the first time starts at the program's
+/// latest transaction, and only reports challenges opened from then on.
+pub struct ChallengeHandler {
+    notifier: Arc<Notifier>,
+}
+
+impl ChallengeHandler {
+    pub fn new(notifier: Arc<Notifier>) -> Self {
+        Self { notifier }
+    }
+}
+
+impl ProgramLogHandler for ChallengeHandler {
+    fn name(&self) -> &'static str {
+        "challenges"
+    }
+
+    fn handle(&self, challenge_program_id: &Pubkey, signature: &Signature, _slot: u64, logs: &[String]) -> anyhow::Result<()> {
+        for event in parse_challenge_events(challenge_program_id, logs) {
+            metrics::increment("challenges_opened");
+            self.notifier.notify(NotificationEvent::ChallengeOpened {
+                batch_id: event.batch_id,
+                challenger: event.challenger,
+                l1_signature: signature.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
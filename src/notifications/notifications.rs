@@ -0,0 +1,281 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Serializer};
+use solana_sdk::pubkey::Pubkey;
+use tokio::io::AsyncWriteExt;
+
+use crate::metrics::metrics;
+
+/// Longest one sink gets to accept a notification
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A critical condition an operator should hear about without watching logs
+/// or metrics.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum NotificationEvent {
+    /// Batch submissions to L1 failed this many times in a row
+    BatchSubmissionFailing {
+        consecutive_failures: u32,
+        batch_id: u64,
+        error: String,
+    },
+    /// The authority paying for batch submissions is running out of lamports
+    AuthorityBalanceLow {
+        #[serde(serialize_with = "serialize_pubkey")]
+        authority: Pubkey,
+        lamports: u64,
+        threshold_lamports: u64,
+    },
+    /// A block's state root couldn't be reproduced, by the determinism
+    /// audit or by a replica applying its diff
+    StateRootDivergence {
+        slot: u64,
+        detail: String,
+    },
+    /// The L1 challenge program logged a challenge against a batch
+    ChallengeOpened {
+        batch_id: u64,
+        #[serde(serialize_with = "serialize_pubkey")]
+        challenger: Pubkey,
+        l1_signature: String,
+    },
+}
+
+impl NotificationEvent {
+    pub fn title(&self) -> &'static str {
+        match self {
+            NotificationEvent::BatchSubmissionFailing { .. } => "Batch submission failing",
+            NotificationEvent::AuthorityBalanceLow { .. } => "Authority balance low",
+            NotificationEvent::StateRootDivergence { .. } => "State root divergence",
+            NotificationEvent::ChallengeOpened { .. } => "Challenge opened on L1",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            NotificationEvent::BatchSubmissionFailing { consecutive_failures, batch_id, error } => format!(
+                "{} batch submissions to L1 failed in a row; batch {}: {}",
+                consecutive_failures, batch_id, error
+            ),
+            NotificationEvent::AuthorityBalanceLow { authority, lamports, threshold_lamports } => format!(
+                "Authority {} has {} lamports left, below the {} lamport threshold",
+                authority, lamports, threshold_lamports
+            ),
+            NotificationEvent::StateRootDivergence { detail, .. } => detail.clone(),
+            NotificationEvent::ChallengeOpened { batch_id, challenger, l1_signature } => format!(
+                "{} challenged batch {} (L1 {})",
+                challenger, batch_id, l1_signature
+            ),
+        }
+    }
+}
+
+/// What webhook sinks receive: the event's fields, plus where and when it
+/// happened.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    /// Unix seconds
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: NotificationEvent,
+}
+
+impl Notification {
+    /// One-line summary, prefixed with the node's name when it has one.
+    pub fn summary(&self) -> String {
+        match &self.node {
+            Some(node) => format!("[{}] {}", node, self.event.title()),
+            None => self.event.title().to_string(),
+        }
+    }
+}
+
+/// Where notifications are delivered.
+#[derive(Debug, Clone)]
+pub enum NotificationSink {
+    /// POSTs the notification as JSON
+    Webhook(String),
+    /// POSTs `{"text": ...}`, the payload of Slack incoming webhooks (also
+    /// accepted by Mattermost and Discord's /slack endpoints)
+    Slack(String),
+    /// Pipes a plain-text mail to `sendmail -t`
+    Email {
+        sendmail: PathBuf,
+        from: Option<String>,
+        to: Vec<String>,
+    },
+}
+
+impl NotificationSink {
+    fn describe(&self) -> String {
+        match self {
+            NotificationSink::Webhook(url) => format!("webhook {}", url),
+            NotificationSink::Slack(url) => format!("Slack webhook {}", url),
+            NotificationSink::Email { to, .. } => format!("email to {}", to.join(", ")),
+        }
+    }
+}
+
+/// When the conditions that are counted or measured become notifications.
+/// A threshold of 0 turns its notification off.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotificationThresholds {
+    /// Consecutive failed batch submissions
+    pub batch_failures: u32,
+    /// Authority balance, in lamports, below which it counts as low
+    pub authority_balance_lamports: u64,
+}
+
+#[derive(Debug, Default)]
+struct ConditionState {
+    consecutive_batch_failures: u32,
+    authority_balance_low: bool,
+}
+
+/// Delivers notifications of critical events to every configured sink.
+/// Conditions that persist (failing submissions, a low balance) notify once
+/// when they cross their threshold, and again only after they cleared.
+/// Delivery runs in the background, so callers never wait on a sink.
+#[derive(Debug)]
+pub struct Notifier {
+    client: reqwest::Client,
+    sinks: Arc<Vec<NotificationSink>>,
+    thresholds: NotificationThresholds,
+    node: Option<String>,
+    state: Mutex<ConditionState>,
+}
+
+impl Notifier {
+    pub fn new(sinks: Vec<NotificationSink>, thresholds: NotificationThresholds) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            sinks: Arc::new(sinks),
+            thresholds,
+            node: None,
+            state: Mutex::new(ConditionState::default()),
+        }
+    }
+
+    /// Names this node in every notification, for operators running several.
+    pub fn with_node_name(mut self, node: String) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    pub fn sinks(&self) -> &[NotificationSink] {
+        &self.sinks
+    }
+
+    pub fn thresholds(&self) -> NotificationThresholds {
+        self.thresholds
+    }
+
+    /// Counts a failed batch submission, notifying when the run of failures
+    /// reaches the threshold.
+    pub fn record_batch_failure(&self, batch_id: u64, error: &str) {
+        let consecutive_failures = {
+            let mut state = self.state.lock().unwrap();
+            state.consecutive_batch_failures += 1;
+            state.consecutive_batch_failures
+        };
+        if consecutive_failures == self.thresholds.batch_failures {
+            self.notify(NotificationEvent::BatchSubmissionFailing {
+                consecutive_failures,
+                batch_id,
+                error: error.to_string(),
+            });
+        }
+    }
+
+    pub fn record_batch_success(&self) {
+        self.state.lock().unwrap().consecutive_batch_failures = 0;
+    }
+
+    /// Notifies when the authority's balance drops below the threshold.
+    pub fn record_authority_balance(&self, authority: Pubkey, lamports: u64) {
+        let threshold_lamports = self.thresholds.authority_balance_lamports;
+        let low = lamports < threshold_lamports;
+        let was_low = std::mem::replace(&mut self.state.lock().unwrap().authority_balance_low, low);
+        if low && !was_low {
+            self.notify(NotificationEvent::AuthorityBalanceLow { authority, lamports, threshold_lamports });
+        }
+    }
+
+    /// Sends `event` to every sink in the background.
+    pub fn notify(&self, event: NotificationEvent) {
+        let notification = Notification {
+            node: self.node.clone(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+            event,
+        };
+        eprintln!("🔔 {}: {}", notification.summary(), notification.event.message());
+        metrics::increment("notifications");
+
+        let client = self.client.clone();
+        let sinks = self.sinks.clone();
+        tokio::spawn(async move {
+            for sink in sinks.iter() {
+                match tokio::time::timeout(SEND_TIMEOUT, deliver(&client, sink, &notification)).await {
+                    Ok(Ok(())) => metrics::increment("notifications_delivered"),
+                    Ok(Err(e)) => {
+                        metrics::increment("notifications_failed");
+                        eprintln!("Failed to deliver notification to {}: {}", sink.describe(), e);
+                    }
+                    Err(_) => {
+                        metrics::increment("notifications_failed");
+                        eprintln!("Failed to deliver notification to {}: timed out", sink.describe());
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn deliver(client: &reqwest::Client, sink: &NotificationSink, notification: &Notification) -> anyhow::Result<()> {
+    match sink {
+        NotificationSink::Webhook(url) => {
+            client.post(url).json(notification).send().await?.error_for_status()?;
+        }
+        NotificationSink::Slack(url) => {
+            let text = format!("🚨 *{}*\n{}", notification.summary(), notification.event.message());
+            client.post(url).json(&serde_json::json!({ "text": text })).send().await?.error_for_status()?;
+        }
+        NotificationSink::Email { sendmail, from, to } => {
+            let mut mail = format!("To: {}\nSubject: {}\n", to.join(", "), notification.summary());
+            if let Some(from) = from {
+                mail.push_str(&format!("From: {}\n", from));
+            }
+            mail.push_str(&format!(
+                "\n{}\n\n{}\n",
+                notification.event.message(),
+                serde_json::to_string_pretty(notification)?
+            ));
+
+            let mut child = tokio::process::Command::new(sendmail)
+                .arg("-t")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(mail.as_bytes()).await?;
+            }
+            let status = child.wait().await?;
+            if !status.success() {
+                anyhow::bail!("{} exited with {}", sendmail.display(), status);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn serialize_pubkey<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(pubkey)
+}
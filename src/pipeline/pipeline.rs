@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+use crate::{
+    metrics::metrics,
+    sequencer::sequencer::Sequencer,
+    transaction_processor::{transaction_processor::TransactionProcessor, wire_transaction::WireTransaction},
+};
+
+/// Sizes of the transaction pipeline's stages.
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineConfig {
+    /// Workers running signature, access and precompile checks in parallel
+    pub verify_workers: usize,
+    /// Workers simulating verified transactions against current state
+    pub preflight_workers: usize,
+    /// Workers moving preflighted transactions into the mempool
+    pub sequence_workers: usize,
+    /// Capacity of each stage's input queue
+    pub queue_capacity: usize,
+}
+
+/// A submitted transaction on its way through the stages, with the channel
+/// its submitter waits on.
+struct Job {
+    tx: WireTransaction,
+    /// Mempool wait carried over from a snapshot
+    waited: Duration,
+    reply: oneshot::Sender<anyhow::Result<String>>,
+}
+
+/// A job that passed preflight, waiting to enter the mempool.
+struct Preflighted {
+    job: Job,
+    signature: String,
+}
+
+/// Metric names of one stage.
+#[derive(Clone, Copy)]
+struct Stage {
+    queue_depth: &'static str,
+    processed: &'static str,
+}
+
+const VERIFY: Stage = Stage { queue_depth: "pipeline_verify_queue_depth", processed: "pipeline_verify_processed" };
+const PREFLIGHT: Stage = Stage { queue_depth: "pipeline_preflight_queue_depth", processed: "pipeline_preflight_processed" };
+const SEQUENCE: Stage = Stage { queue_depth: "pipeline_sequence_queue_depth", processed: "pipeline_sequence_processed" };
+
+/// Accept → verify → preflight → sequence, as separate stages connected by
+/// bounded queues, each drained by its own pool of workers. Transactions
+/// leave a stage in the order its workers finish them, not the order they
+/// were submitted in. Preflight simulates each transaction against current
+/// state without changing it, turning away those that would fail.
+///
+/// Execution and commit follow outside the pipeline, one at a time because
+/// both are ordered: the sequencer executes the mempool's transactions when
+/// it builds their block, in the order it seals them
+/// (`pipeline_execute_queue_depth` is the mempool it builds from), and sealed
+/// batches continue to the batch processor over the sequencer's own queue
+/// (`pipeline_commit_queue_depth`), which posts them to L1 in slot order.
+///
+/// Every queue publishes its depth as `pipeline_<stage>_queue_depth`, and
+/// every stage counts the jobs it handled as `pipeline_<stage>_processed`.
+pub struct Pipeline {
+    verify: mpsc::Sender<Job>,
+}
+
+impl Pipeline {
+    /// Spawns the workers of every stage.
+    pub fn start(
+        transaction_processor: Arc<TransactionProcessor>,
+        sequencer: Arc<Sequencer>,
+        config: PipelineConfig
+    ) -> Self {
+        let (verify, verify_queue) = mpsc::channel::<Job>(config.queue_capacity);
+        let (preflight, preflight_queue) = mpsc::channel::<Job>(config.queue_capacity);
+        let (sequence, sequence_queue) = mpsc::channel::<Preflighted>(config.queue_capacity);
+
+        spawn_stage(VERIFY, config.verify_workers, verify_queue, {
+            let transaction_processor = transaction_processor.clone();
+            let sequencer = sequencer.clone();
+            move |job: Job| {
+                let preflight = preflight.clone();
+                let transaction_processor = transaction_processor.clone();
+                let sequencer = sequencer.clone();
+                async move {
+                    // No batch could ever seal it, so it isn't worth checking
+                    if let Err(e) = sequencer.check_size(&job.tx) {
+                        let _ = job.reply.send(Err(e));
+                        return;
+                    }
+
+                    // Signature checks are CPU bound; keep them off the async workers
+                    let verified = tokio::task::spawn_blocking(move || {
+                        let result = transaction_processor.verify_transaction(&job.tx);
+                        (job, result)
+                    }).await;
+
+                    match verified {
+                        Ok((job, Ok(()))) => forward(&preflight, PREFLIGHT.queue_depth, job, |job| job).await,
+                        Ok((job, Err(e))) => {
+                            let _ = job.reply.send(Err(e));
+                        }
+                        Err(e) => eprintln!("Transaction verification worker failed: {}", e),
+                    }
+                }
+            }
+        });
+
+        spawn_stage(PREFLIGHT, config.preflight_workers, preflight_queue, move |job: Job| {
+            let sequence = sequence.clone();
+            let transaction_processor = transaction_processor.clone();
+            async move {
+                match transaction_processor.preflight(&job.tx).await {
+                    Ok(signature) => {
+                        let preflighted = Preflighted { job, signature };
+                        forward(&sequence, SEQUENCE.queue_depth, preflighted, |preflighted| preflighted.job).await;
+                    }
+                    Err(e) => {
+                        let _ = job.reply.send(Err(e));
+                    }
+                }
+            }
+        });
+
+        spawn_stage(SEQUENCE, config.sequence_workers, sequence_queue, move |Preflighted { job, signature }| {
+            let sequencer = sequencer.clone();
+            async move {
+                let added = sequencer.add_transaction(job.tx, job.waited).await;
+                let _ = job.reply.send(added.map(|()| signature));
+            }
+        });
+
+        Self { verify }
+    }
+
+    /// Runs `tx` through every stage up to the mempool and returns its
+    /// signature. Fails right away instead of queueing when the pipeline is
+    /// full.
+    pub async fn submit(&self, tx: WireTransaction) -> anyhow::Result<String> {
+        self.resubmit(tx, Duration::ZERO).await
+    }
+
+    /// Like `submit`, for a transaction from a mempool snapshot that had
+    /// been pending for `waited`; it enters the mempool with that age.
+    pub async fn resubmit(&self, tx: WireTransaction, waited: Duration) -> anyhow::Result<String> {
+        let (reply, response) = oneshot::channel();
+        self.verify.try_send(Job { tx, waited, reply }).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => anyhow::anyhow!("Transaction pipeline is full, retry later"),
+            mpsc::error::TrySendError::Closed(_) => anyhow::anyhow!("Transaction pipeline has stopped"),
+        })?;
+        publish_depth(&self.verify, VERIFY.queue_depth);
+
+        response.await
+            .map_err(|_| anyhow::anyhow!("Transaction pipeline dropped the transaction"))?
+    }
+}
+
+/// Spawns `workers` (at least one) tasks taking turns on `queue`, each
+/// running `handle` on the jobs it takes until the queue closes.
+fn spawn_stage<T, F, Fut>(stage: Stage, workers: usize, queue: mpsc::Receiver<T>, handle: F)
+where
+    T: Send + 'static,
+    F: Fn(T) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let queue = Arc::new(Mutex::new(queue));
+    for _ in 0..workers.max(1) {
+        let queue = queue.clone();
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            while let Some(job) = next_job(&queue, stage.queue_depth).await {
+                metrics::increment(stage.processed);
+                handle(job).await;
+            }
+        });
+    }
+}
+
+async fn next_job<T>(queue: &Mutex<mpsc::Receiver<T>>, depth_gauge: &'static str) -> Option<T> {
+    let mut queue = queue.lock().await;
+    let job = queue.recv().await;
+    metrics::set_gauge(depth_gauge, queue.len() as u64);
+    job
+}
+
+/// Hands `item` to the next stage, waiting while that stage is full. If the
+/// stage has stopped, the submitter is told instead of left waiting.
+async fn forward<T>(next: &mpsc::Sender<T>, depth_gauge: &'static str, item: T, job: impl FnOnce(T) -> Job) {
+    match next.send(item).await {
+        Ok(()) => publish_depth(next, depth_gauge),
+        Err(mpsc::error::SendError(item)) => {
+            let _ = job(item).reply.send(Err(anyhow::anyhow!("Transaction pipeline has stopped")));
+        }
+    }
+}
+
+fn publish_depth<T>(queue: &mpsc::Sender<T>, gauge: &'static str) {
+    metrics::set_gauge(gauge, (queue.max_capacity() - queue.capacity()) as u64);
+}
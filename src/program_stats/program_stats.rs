@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, transaction::{Transaction, TransactionError}};
+
+use crate::transaction_processor::svm::ExecutionTrace;
+
+/// How much a program was used over some run of blocks, counting the
+/// instructions that invoke it directly; programs it calls through CPI are
+/// charged to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgramStats {
+    pub invocations: u64,
+    /// Invocations whose instruction failed, failing the transaction
+    pub failures: u64,
+    pub compute_units: u64,
+}
+
+impl ProgramStats {
+    pub fn add(&mut self, other: &ProgramStats) {
+        self.invocations = self.invocations.saturating_add(other.invocations);
+        self.failures = self.failures.saturating_add(other.failures);
+        self.compute_units = self.compute_units.saturating_add(other.compute_units);
+    }
+
+    /// Share of invocations that failed, 0 for a program never invoked.
+    pub fn failure_rate(&self) -> f64 {
+        if self.invocations == 0 {
+            return 0.0;
+        }
+        self.failures as f64 / self.invocations as f64
+    }
+}
+
+/// What one execution of `tx` charges each program its instructions
+/// invoke: the instructions up to the one that failed, if `error` is an
+/// instruction error, or all of them. Nothing is charged for a transaction
+/// the SVM never ran.
+///
+/// Compute units come from the `consumed` lines of the top-level
+/// invocations in `trace`'s logs. Builtins don't log their consumption, so
+/// what isn't accounted for is split evenly between the instructions that
+/// didn't report any.
+pub fn program_usage(tx: &Transaction, trace: &ExecutionTrace, error: Option<&TransactionError>) -> Vec<(Pubkey, ProgramStats)> {
+    // Every executed instruction consumes compute units, if only a builtin's fixed cost
+    if trace.units_consumed == 0 {
+        return Vec::new();
+    }
+    let instructions = &tx.message.instructions;
+    let (invoked, failed) = match error {
+        Some(TransactionError::InstructionError(index, _)) => ((*index as usize + 1).min(instructions.len()), Some(*index as usize)),
+        _ => (instructions.len(), None),
+    };
+
+    let reported = top_level_units(&trace.logs, invoked);
+    let unaccounted = trace.units_consumed.saturating_sub(reported.iter().flatten().sum());
+    let unreported = reported.iter().filter(|units| units.is_none()).count() as u64;
+
+    let mut usage: HashMap<Pubkey, ProgramStats> = HashMap::new();
+    let mut first_unreported = true;
+    for (index, instruction) in instructions.iter().take(invoked).enumerate() {
+        let Some(program_id) = tx.message.account_keys.get(instruction.program_id_index as usize) else {
+            continue;
+        };
+        let compute_units = match reported[index] {
+            Some(units) => units,
+            None => {
+                // The first of them takes what doesn't divide evenly
+                let extra = if std::mem::take(&mut first_unreported) { unaccounted % unreported } else { 0 };
+                unaccounted / unreported + extra
+            }
+        };
+
+        usage.entry(*program_id).or_default().add(&ProgramStats {
+            invocations: 1,
+            failures: (failed == Some(index)) as u64,
+            compute_units,
+        });
+    }
+
+    usage.into_iter().collect()
+}
+
+/// Units each of the first `invoked` top-level instructions logged as
+/// consumed, None for those that logged none (builtins, or logs truncated
+/// before they ran).
+fn top_level_units(logs: &[String], invoked: usize) -> Vec<Option<u64>> {
+    let mut units = vec![None; invoked];
+    let mut instruction: Option<usize> = None;
+    let mut depth: usize = 0;
+    for line in logs {
+        let mut words = line.strip_prefix("Program ").unwrap_or_default().split_whitespace();
+        let (Some(program_id), Some(verb)) = (words.next(), words.next()) else {
+            continue;
+        };
+        // "Program log:", "Program data:" and "Program return:" lines
+        if Pubkey::from_str(program_id).is_err() {
+            continue;
+        }
+
+        match verb {
+            "invoke" => {
+                depth = words.next()
+                    .and_then(|level| level.trim_matches(['[', ']']).parse().ok())
+                    .unwrap_or(depth + 1);
+                if depth == 1 {
+                    instruction = Some(instruction.map_or(0, |index| index + 1));
+                }
+            }
+            "consumed" if depth == 1 => {
+                if let (Some(index), Some(consumed)) = (instruction, words.next().and_then(|n| n.parse().ok()))
+                    && let Some(slot) = units.get_mut(index)
+                {
+                    *slot = Some(consumed);
+                }
+            }
+            "success" | "failed:" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    units
+}
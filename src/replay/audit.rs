@@ -0,0 +1,141 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    batch_processor::batch_processor::StoredBatch,
+    metrics::metrics,
+    notifications::notifications::{NotificationEvent, Notifier},
+    replay::replay::Replayer,
+    sequencer::block::{BlockHeader, BlockOperations},
+    state_manager::state_manager::StateManager,
+};
+
+/// What the sequencer does when a block's state root differs between the
+/// primary and the shadow executor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AuditMode {
+    /// Execute every block once
+    #[default]
+    Off,
+    /// Seal the block anyway and log the divergence; later blocks aren't
+    /// audited, since the shadow state no longer matches
+    Warn,
+    /// Stop sealing blocks, so the diverged root is never stored or posted
+    Halt,
+}
+
+/// Executes every block a second time, on a `Replayer` kept in step with
+/// the chain, and compares its state root with the primary's before the
+/// block is stored. Catches nondeterministic execution (hash map iteration,
+/// time-dependent logic) before it reaches the commitments posted to L1.
+/// The shadow executes blocks as a verifier replaying them would, so any
+/// state the primary changes outside its blocks shows up as divergence too.
+pub struct DeterminismAudit {
+    mode: AuditMode,
+    /// None once it diverged
+    shadow: tokio::sync::Mutex<Option<Replayer>>,
+    halted: Mutex<Option<String>>,
+    notifier: Option<Arc<Notifier>>,
+}
+
+impl fmt::Debug for DeterminismAudit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeterminismAudit")
+            .field("mode", &self.mode)
+            .field("halted", &self.halted())
+            .finish_non_exhaustive()
+    }
+}
+
+impl DeterminismAudit {
+    /// Replays every block `source` has sealed on `shadow`, so it is in step
+    /// with the primary. A chain that doesn't replay fails to start in halt
+    /// mode, and goes unaudited otherwise.
+    pub async fn new(source: &StateManager, mut shadow: Replayer, mode: AuditMode) -> anyhow::Result<Self> {
+        let batch_count = source.batch_count()?;
+        let mut diverged = None;
+        if let Some(latest) = batch_count.checked_sub(1) {
+            println!("🔬 Replaying {} blocks on the shadow executor", batch_count);
+            diverged = shadow.replay_until(source, 0, latest).await?
+                .into_iter()
+                .find(|result| !result.matches());
+        }
+
+        let shadow = match diverged {
+            Some(result) if mode == AuditMode::Halt => anyhow::bail!(
+                "Shadow executor diverged replaying block {}: state root {}, expected {}",
+                result.batch_id, hex::encode(result.replayed_root), hex::encode(result.expected_root)
+            ),
+            Some(result) => {
+                metrics::increment("determinism_audit_mismatches");
+                eprintln!(
+                    "⚠️  Shadow executor diverged replaying block {}: state root {}, expected {}; blocks won't be audited",
+                    result.batch_id, hex::encode(result.replayed_root), hex::encode(result.expected_root)
+                );
+                None
+            }
+            None => Some(shadow),
+        };
+
+        Ok(Self {
+            mode,
+            shadow: tokio::sync::Mutex::new(shadow),
+            halted: Mutex::new(None),
+            notifier: None,
+        })
+    }
+
+    /// Notifies the operator through `notifier` when a block diverges.
+    pub fn with_notifier(mut self, notifier: Arc<Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Why sealing stopped, in halt mode once a block diverged.
+    pub fn halted(&self) -> Option<String> {
+        self.halted.lock().unwrap().clone()
+    }
+
+    /// Executes the block `header` seals, with `operations` after its
+    /// transactions, on the shadow and compares state roots. Fails, halting
+    /// sealing for good, if they differ in halt mode.
+    pub async fn check(
+        &self,
+        source: &StateManager,
+        header: &BlockHeader,
+        batch: &StoredBatch,
+        operations: &BlockOperations
+    ) -> anyhow::Result<()> {
+        let mut shadow = self.shadow.lock().await;
+        let Some(replayer) = shadow.as_mut() else {
+            return Ok(());
+        };
+
+        let reason = match replayer.replay_block(source, header, batch, operations).await {
+            Ok(result) if result.matches() => {
+                metrics::increment("determinism_audit_blocks");
+                return Ok(());
+            }
+            Ok(result) => format!(
+                "Block {} diverged: shadow state root {}, primary {}",
+                header.slot, hex::encode(result.replayed_root), hex::encode(result.expected_root)
+            ),
+            Err(e) => format!("Shadow executor failed on block {}: {}", header.slot, e),
+        };
+        metrics::increment("determinism_audit_mismatches");
+        *shadow = None;
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(NotificationEvent::StateRootDivergence { slot: header.slot, detail: reason.clone() });
+        }
+
+        if self.mode == AuditMode::Halt {
+            eprintln!("🛑 {}; no further blocks will be sealed", reason);
+            metrics::set_gauge("determinism_audit_halted", 1);
+            *self.halted.lock().unwrap() = Some(reason.clone());
+            anyhow::bail!(reason);
+        }
+        eprintln!("⚠️  {}; sealing it anyway, later blocks won't be audited", reason);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    batch_processor::batch_processor::StoredBatch,
+    replay::replay::{ExecutionConfig, Replayer},
+    sequencer::block::BlockOperations,
+    state_manager::state_manager::{L2Account, StateManager},
+};
+
+/// An account as it was before or after a disputed batch; `None` if it did not exist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountWitness {
+    pub pubkey: Pubkey,
+    pub account: Option<L2Account>,
+    /// Siblings from the account's leaf up to the root it is witnessed
+    /// against, see `sparse_merkle::prove`
+    pub proof: Vec<[u8; 32]>,
+}
+
+/// Everything the L1 challenge program needs to re-execute one batch: the
+/// state it started from, its transactions, and the state it should end in.
+/// Bincode-serialized, this is the challenge instruction data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisputeData {
+    pub batch_id: u64,
+    pub pre_state_root: [u8; 32],
+    pub pre_state: Vec<AccountWitness>,
+    /// Serialized transactions exactly as they were sealed into the batch
+    pub transactions: Vec<Vec<u8>>,
+    /// Root the sequencer recorded for the batch
+    pub claimed_post_state_root: [u8; 32],
+    /// Root obtained by re-executing the batch from the pre-state
+    pub expected_post_state_root: [u8; 32],
+    pub post_state: Vec<AccountWitness>,
+}
+
+impl DisputeData {
+    pub fn is_fraudulent(&self) -> bool {
+        self.claimed_post_state_root != self.expected_post_state_root
+    }
+
+    pub fn challenge_data(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+}
+
+/// Replays from genesis up to `batch_id`, executing as `execution` says, and
+/// captures the witness for it.
+pub async fn build_dispute_data(source: &StateManager, execution: &ExecutionConfig, batch_id: u64) -> anyhow::Result<DisputeData> {
+    let batch = source.get_batch(batch_id)?
+        .ok_or_else(|| anyhow::anyhow!("Batch {} is not stored locally", batch_id))?;
+    let touched = touched_accounts(&batch, &BlockOperations::stored(source, batch_id)?, execution);
+
+    let mut replayer = Replayer::new(source, execution)?;
+    if batch_id > 0 {
+        replayer.replay_until(source, batch_id, batch_id - 1).await?;
+    }
+
+    let pre_state_root = replayer.state_manager().get_state_root();
+    let pre_state = snapshot(&replayer, &touched).await?;
+
+    replayer.replay_until(source, batch_id, batch_id).await?;
+
+    let transactions = batch.transactions.iter()
+        .map(|tx| tx.wire_bytes().to_vec())
+        .collect();
+
+    Ok(DisputeData {
+        batch_id,
+        pre_state_root,
+        pre_state,
+        transactions,
+        claimed_post_state_root: batch.state_root,
+        expected_post_state_root: replayer.state_manager().get_state_root(),
+        post_state: snapshot(&replayer, &touched).await?,
+    })
+}
+
+/// Accounts the block's transactions name, those its airdrops, deposits,
+/// withdrawals and program registrations apply to, and the fee collector
+/// the transactions' fees credit.
+fn touched_accounts(batch: &StoredBatch, operations: &BlockOperations, execution: &ExecutionConfig) -> BTreeSet<Pubkey> {
+    batch.transactions.iter()
+        .flat_map(|tx| tx.message.account_keys.iter().copied())
+        .chain(operations.airdrops.iter().map(|mint| mint.payer))
+        .chain(operations.deposits.iter().map(|deposit| deposit.l2_recipient))
+        .chain(operations.withdrawals.iter().map(|withdrawal| withdrawal.l2_sender))
+        .chain(operations.programs.iter().map(|change| change.program_id))
+        .chain(execution.fees.fee_collector)
+        .collect()
+}
+
+async fn snapshot(replayer: &Replayer, pubkeys: &BTreeSet<Pubkey>) -> anyhow::Result<Vec<AccountWitness>> {
+    let mut witnesses = Vec::with_capacity(pubkeys.len());
+    for pubkey in pubkeys {
+        witnesses.push(AccountWitness {
+            pubkey: *pubkey,
+            account: replayer.state_manager().get_account(pubkey).await,
+            proof: replayer.state_manager().account_proof(pubkey)?,
+        });
+    }
+    Ok(witnesses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deposits::deposits::DepositRecord,
+        state_manager::{sparse_merkle::{account_leaf, proof_root}, state_hasher::StateHasher},
+    };
+
+    fn proves(witness: &AccountWitness, hasher: &dyn StateHasher, root: [u8; 32]) -> bool {
+        let leaf = account_leaf(hasher, &witness.pubkey, witness.account.as_ref()).unwrap();
+        proof_root(hasher, &witness.pubkey, leaf, &witness.proof).unwrap() == root
+    }
+
+    #[tokio::test]
+    async fn witnesses_deposit_recipients_with_proofs_against_both_roots() {
+        let source = StateManager::temporary();
+        source.put_empty_block();
+        let recipient = Pubkey::new_unique();
+        let deposit = DepositRecord {
+            id: 0,
+            l1_signature: String::new(),
+            l1_slot: 1,
+            l1_sender: Pubkey::new_unique(),
+            l2_recipient: recipient,
+            lamports: 1_000,
+            batch_id: None,
+        };
+        source.credit_deposits(1, vec![deposit]).await.unwrap();
+        source.put_empty_block();
+
+        let dispute = build_dispute_data(&source, &ExecutionConfig::default(), 1).await.unwrap();
+
+        assert!(!dispute.is_fraudulent());
+        let [pre] = dispute.pre_state.as_slice() else {
+            panic!("expected only the recipient in the pre-state, got {:?}", dispute.pre_state);
+        };
+        let [post] = dispute.post_state.as_slice() else {
+            panic!("expected only the recipient in the post-state, got {:?}", dispute.post_state);
+        };
+        assert_eq!((pre.pubkey, post.pubkey), (recipient, recipient));
+        assert!(pre.account.is_none());
+        assert_eq!(post.account.as_ref().map(|account| account.lamports), Some(1_000));
+        assert!(proves(pre, source.hasher(), dispute.pre_state_root));
+        assert!(proves(post, source.hasher(), dispute.expected_post_state_root));
+        assert!(!proves(post, source.hasher(), dispute.pre_state_root));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod audit;
+pub mod dispute;
+pub mod replay;
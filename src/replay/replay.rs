@@ -0,0 +1,246 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use solana_sdk::{hash::Hash, pubkey::Pubkey, transaction::Transaction};
+
+use crate::{
+    batch_processor::batch_processor::StoredBatch,
+    data_availability::data_availability::DataAvailability,
+    epochs::epochs::reapply_epoch,
+    sequencer::block::{BlockHeader, BlockOperations},
+    state_manager::state_manager::StateManager,
+    transaction_processor::{
+        access_policy::AccessPolicy,
+        dust_policy::DustPolicy,
+        fee_schedule::FeeSchedule,
+        program_registry::{ProgramHandler, ProgramRegistry},
+        transaction_processor::{Processed, Simulation, TransactionProcessor, UnknownProgramPolicy},
+    },
+};
+
+/// How the node executes transactions besides the transactions themselves:
+/// the programs it registers at startup (`--program`), what it does with
+/// unknown programs, its dust policy and its fees. Replayed state roots only
+/// match the chain's when batches are executed with the same.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionConfig {
+    pub programs: Vec<(Pubkey, ProgramHandler)>,
+    pub unknown_programs: UnknownProgramPolicy,
+    pub dust_policy: DustPolicy,
+    pub fees: FeeSchedule,
+}
+
+/// Outcome of re-executing one stored batch.
+#[derive(Debug, Clone)]
+pub struct BatchReplayResult {
+    pub batch_id: u64,
+    pub transaction_count: usize,
+    pub failed_transactions: usize,
+    /// Transactions whose signatures don't verify. The sequencer never
+    /// seals one, so a batch with any is rejected without executing it
+    pub invalid_signatures: usize,
+    pub expected_root: [u8; 32],
+    pub replayed_root: [u8; 32],
+}
+
+impl BatchReplayResult {
+    pub fn matches(&self) -> bool {
+        self.expected_root == self.replayed_root
+    }
+}
+
+/// Scratch state that batches are re-executed against. Its database lives in
+/// a temporary directory that is removed when the replayer is dropped.
+pub struct Replayer {
+    state_manager: Arc<StateManager>,
+    transaction_processor: TransactionProcessor,
+    data_availability: Arc<DataAvailability>,
+    path: PathBuf,
+    next_batch_id: u64,
+    next_epoch: u64,
+}
+
+impl Replayer {
+    /// Starts from an empty genesis state using the same hash function as
+    /// `source`, executing as `execution` says. Program changes recorded
+    /// with replayed blocks are applied to a registry of its own.
+    pub fn new(source: &StateManager, execution: &ExecutionConfig) -> anyhow::Result<Self> {
+        let programs = Arc::new(ProgramRegistry::new(execution.programs.clone())?);
+        let path = std::env::temp_dir().join(format!(
+            "tiny-rollup-replay-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_nanos()
+        ));
+        let path_str = path.to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid replay path: {}", path.display()))?;
+
+        let state_manager = Arc::new(StateManager::new(path_str, Some(source.hash_function()), false)?);
+        // The first block's parent is the genesis hash
+        state_manager.restore_chain_metadata(source.genesis_hash()?, None)?;
+        // Batches were already admitted when they were sequenced
+        let transaction_processor = TransactionProcessor::new(
+            state_manager.clone(),
+            AccessPolicy::default(),
+            execution.unknown_programs
+        ).with_programs(programs)
+            .with_dust_policy(execution.dust_policy)
+            .with_fees(execution.fees);
+
+        Ok(Self {
+            state_manager,
+            transaction_processor,
+            data_availability: Arc::new(DataAvailability::default()),
+            path,
+            next_batch_id: 0,
+            next_epoch: 0,
+        })
+    }
+
+    /// Fetches batches pruned from `source` from DA while replaying.
+    pub fn with_data_availability(mut self, data_availability: Arc<DataAvailability>) -> Self {
+        self.data_availability = data_availability;
+        self
+    }
+
+    /// Re-derives the block `batch_id` was sealed into, at `block_time`, on
+    /// top of the replayed chain.
+    fn put_block(&self, batch_id: u64, batch: &StoredBatch, block_time: i64) -> anyhow::Result<()> {
+        let parent_blockhash = match self.state_manager.latest_block()? {
+            Some(parent) => parent.blockhash,
+            None => Hash::new_from_array(self.state_manager.genesis_hash()?.unwrap_or_default()),
+        };
+        let header = BlockHeader::new(batch_id, parent_blockhash, batch.state_root, &batch.transactions, block_time);
+
+        let (_, diff) = self.state_manager.seal_state()?;
+        self.state_manager.put_block(&header, batch, &diff)
+    }
+
+    pub fn state_manager(&self) -> &Arc<StateManager> {
+        &self.state_manager
+    }
+
+    /// Simulates `tx` on top of the replayed state.
+    pub async fn simulate(&self, tx: &Transaction) -> Simulation {
+        self.transaction_processor.simulate(tx).await
+    }
+
+    /// Re-executes batches from wherever the replayer currently is up to and
+    /// including `to`, returning results for batches at or after `from`.
+    pub async fn replay_until(
+        &mut self,
+        source: &StateManager,
+        from: u64,
+        to: u64
+    ) -> anyhow::Result<Vec<BatchReplayResult>> {
+        let mut results = Vec::new();
+
+        while self.next_batch_id <= to {
+            let batch_id = self.next_batch_id;
+            let (batch, _) = self.data_availability.get_batch(source, batch_id).await?
+                .ok_or_else(|| anyhow::anyhow!("Batch {} is not stored locally", batch_id))?;
+
+            // The blockhash doesn't cover the block time, so it is taken from
+            // `source` where the block is still there
+            let block_time = source.get_block(batch_id)?.map(|header| header.block_time).unwrap_or_default();
+            let operations = BlockOperations::stored(source, batch_id)?;
+            let result = self.apply_batch(batch_id, &batch, &operations, block_time).await?;
+            if batch_id >= from {
+                results.push(result);
+            }
+
+            // Boundary tasks ran after the epoch's last batch was sealed, so
+            // they show up in the next batch's root
+            if let Some(record) = source.get_epoch_record(self.next_epoch)?
+                && record.last_batch == batch_id
+            {
+                reapply_epoch(&self.state_manager, &record).await?;
+                self.next_epoch += 1;
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Executes the block `header` seals, with `operations` after its
+    /// transactions, before `source` stores it, as the next block of the
+    /// replayed chain.
+    pub async fn replay_block(
+        &mut self,
+        source: &StateManager,
+        header: &BlockHeader,
+        batch: &StoredBatch,
+        operations: &BlockOperations
+    ) -> anyhow::Result<BatchReplayResult> {
+        if header.slot != self.next_batch_id {
+            anyhow::bail!("Replayer is at batch {}, not {}", self.next_batch_id, header.slot);
+        }
+
+        // Epochs close once their last batch is posted, so their boundary
+        // tasks land in whichever block is sealed next
+        while let Some(record) = source.get_epoch_record(self.next_epoch)?
+            && record.last_batch < header.slot
+        {
+            reapply_epoch(&self.state_manager, &record).await?;
+            self.next_epoch += 1;
+        }
+
+        self.apply_batch(header.slot, batch, operations, header.block_time).await
+    }
+
+    /// Executes `batch`, then applies `operations`, and seals it as block
+    /// `batch_id` at `block_time`.
+    async fn apply_batch(
+        &mut self,
+        batch_id: u64,
+        batch: &StoredBatch,
+        operations: &BlockOperations,
+        block_time: i64
+    ) -> anyhow::Result<BatchReplayResult> {
+        // Verify the whole batch up front, in parallel, instead of each
+        // transaction serially as it executes
+        let invalid_signatures = self.transaction_processor.verify_batch(&batch.transactions);
+        // The sequencer minted airdrops before the batch's transactions
+        self.state_manager.mint_airdrops(batch_id, operations.airdrops.clone()).await?;
+        let mut failed_transactions = 0;
+        if invalid_signatures.is_empty() {
+            for tx in &batch.transactions {
+                let processed = self.transaction_processor.process_verified_transaction(tx).await;
+                if !matches!(processed, Ok(Processed { error: None, .. })) {
+                    failed_transactions += 1;
+                }
+            }
+        } else {
+            eprintln!(
+                "❌ Batch {} rejected: transactions {:?} don't verify",
+                batch_id, invalid_signatures
+            );
+            failed_transactions = batch.transactions.len();
+        }
+        // The sequencer credited deposits and debited withdrawals after
+        // the batch's transactions
+        self.state_manager.credit_deposits(batch_id, operations.deposits.clone()).await?;
+        self.state_manager.apply_withdrawals(batch_id, &operations.withdrawals).await?;
+        self.put_block(batch_id, batch, block_time)?;
+        // Program changes took effect once the block was stored
+        for change in &operations.programs {
+            self.transaction_processor.programs().apply(change)?;
+        }
+        self.next_batch_id += 1;
+
+        Ok(BatchReplayResult {
+            batch_id,
+            transaction_count: batch.transactions.len(),
+            failed_transactions,
+            invalid_signatures: invalid_signatures.len(),
+            expected_root: batch.state_root,
+            replayed_root: self.state_manager.get_state_root(),
+        })
+    }
+}
+
+impl Drop for Replayer {
+    fn drop(&mut self) {
+        // Best effort; RocksDB may still hold files open on some platforms
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
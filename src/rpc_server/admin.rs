@@ -0,0 +1,289 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use jsonrpsee::{
+    core::{RpcResult, async_trait},
+    proc_macros::rpc,
+    types::ErrorObjectOwned,
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    batch_processor::batch_processor::BatchProcessor,
+    pipeline::pipeline::Pipeline,
+    sequencer::{
+        sequencer::Sequencer,
+        snapshot::{MempoolEntry, MempoolSnapshot, MEMPOOL_SNAPSHOT_VERSION},
+    },
+    state_manager::state_manager::StateManager,
+    token_registry::token_registry::{TokenMetadata, TokenRegistry},
+    transaction_processor::{
+        program_registry::{ProgramHandler, ProgramRegistry},
+        wire_transaction::WireTransaction,
+    },
+};
+
+const DEFAULT_DEAD_LETTER_LIMIT: usize = 100;
+const MAX_DEAD_LETTER_LIMIT: usize = 1000;
+
+/// Operator methods, only served when the node runs with `--admin-rpc`.
+#[rpc(server)]
+pub trait AdminRpc {
+    #[method(name = "rotateAuthority")]
+    async fn rotate_authority(&self) -> RpcResult<Value>;
+
+    /// Accepted transactions that failed permanently, newest first. Page
+    /// with `before`, the lowest id of the previous page.
+    #[method(name = "getDeadLetters")]
+    async fn get_dead_letters(&self, before: Option<u64>, limit: Option<usize>) -> RpcResult<Value>;
+
+    #[method(name = "getPrograms")]
+    async fn get_programs(&self) -> RpcResult<Value>;
+
+    /// Registers a program, or changes its handler, with the next block
+    /// sealed; it takes effect from the block after, and replays and
+    /// restarts apply it at the same point.
+    #[method(name = "registerProgram")]
+    async fn register_program(&self, program_id: String, handler: ProgramHandler) -> RpcResult<Value>;
+
+    /// Unregisters a program the same way `registerProgram` registers one.
+    #[method(name = "unregisterProgram")]
+    async fn unregister_program(&self, program_id: String) -> RpcResult<Value>;
+
+    /// Adds or replaces a mint's display metadata until the node restarts.
+    /// Decimals must match the mint's when it exists on L2.
+    #[method(name = "setTokenMetadata")]
+    async fn set_token_metadata(&self, token: TokenMetadata) -> RpcResult<Value>;
+
+    #[method(name = "removeTokenMetadata")]
+    async fn remove_token_metadata(&self, mint: String) -> RpcResult<Value>;
+
+    /// Every mempool transaction in sealing order with its metadata, and
+    /// the settings that ranked them.
+    #[method(name = "getMempoolSnapshot")]
+    async fn get_mempool_snapshot(&self) -> RpcResult<MempoolSnapshot>;
+
+    /// Loads a snapshot of the same chain into the mempool, oldest first,
+    /// each transaction keeping the time it had waited. Transactions this
+    /// node already executed go straight back into the mempool; others are
+    /// verified and executed first, and sealed ones are skipped.
+    #[method(name = "importMempool")]
+    async fn import_mempool(&self, snapshot: MempoolSnapshot) -> RpcResult<Value>;
+}
+
+/// What became of a mempool snapshot entry on import.
+enum Imported {
+    /// Verified and preflighted, then added to the mempool
+    Submitted,
+    /// Already sealed into a block
+    Sealed,
+}
+
+pub struct AdminRpcImpl {
+    batch_processor: Arc<BatchProcessor>,
+    state_manager: Arc<StateManager>,
+    programs: Arc<ProgramRegistry>,
+    tokens: Arc<TokenRegistry>,
+    sequencer: Arc<Sequencer>,
+    pipeline: Arc<Pipeline>,
+}
+
+impl AdminRpcImpl {
+    pub fn new(
+        batch_processor: Arc<BatchProcessor>,
+        state_manager: Arc<StateManager>,
+        programs: Arc<ProgramRegistry>,
+        tokens: Arc<TokenRegistry>,
+        sequencer: Arc<Sequencer>,
+        pipeline: Arc<Pipeline>
+    ) -> Self {
+        Self { batch_processor, state_manager, programs, tokens, sequencer, pipeline }
+    }
+
+    async fn import_entry(&self, entry: &MempoolEntry) -> anyhow::Result<Imported> {
+        let tx = WireTransaction::from_bytes(BASE64_STANDARD.decode(&entry.transaction)?)?;
+        let signature = tx.signatures.first().copied().unwrap_or_default();
+        if signature.to_string() != entry.signature {
+            anyhow::bail!("Transaction is signed {}", signature);
+        }
+        let waited = Duration::from_millis(entry.waited_ms);
+
+        // Transactions only execute when their block is sealed
+        if self.state_manager.is_signature_processed(&signature)? {
+            return Ok(Imported::Sealed);
+        }
+        self.pipeline.resubmit(tx, waited).await?;
+        Ok(Imported::Submitted)
+    }
+
+    /// Queues registering `program_id` with `handler`, or unregistering it,
+    /// for the next block, failing with `message` if it can't be made.
+    fn queue_program_change(&self, program_id: Pubkey, handler: Option<ProgramHandler>, message: &str) -> RpcResult<Value> {
+        ProgramRegistry::check(&program_id, handler)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, message, Some(e.to_string())))?;
+        let change = self.state_manager.queue_program_change(program_id, handler)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, message, Some(e.to_string())))?;
+
+        println!("Program change {} for {} queued for the next block", change.id, program_id);
+
+        Ok(serde_json::json!({
+            "programId": program_id.to_string(),
+            "handler": handler,
+            "current": self.programs.handler(&program_id),
+        }))
+    }
+}
+
+#[async_trait]
+impl AdminRpcServer for AdminRpcImpl {
+    async fn rotate_authority(&self) -> RpcResult<Value> {
+        let previous = self.batch_processor.authority();
+        let current = self.batch_processor.rotate_authority()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to rotate authority", Some(e.to_string())))?;
+
+        println!("Batch authority rotated from {} to {}", previous, current);
+
+        Ok(serde_json::json!({
+            "previous": previous.to_string(),
+            "current": current.to_string(),
+        }))
+    }
+
+    async fn get_dead_letters(&self, before: Option<u64>, limit: Option<usize>) -> RpcResult<Value> {
+        let limit = limit.unwrap_or(DEFAULT_DEAD_LETTER_LIMIT).min(MAX_DEAD_LETTER_LIMIT);
+        let dead_letters = self.state_manager.dead_letters(before, limit)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load dead letters", Some(e.to_string())))?;
+
+        Ok(dead_letters.iter()
+            .map(|d| serde_json::json!({
+                "id": d.id,
+                "signature": d.transaction.signatures.first().map(|s| s.to_string()),
+                "reason": d.reason,
+                "executed": d.reason.executed(),
+                "recordedAt": d.recorded_at,
+                "transaction": [bs58::encode(d.transaction.wire_bytes()).into_string(), "base58"],
+            }))
+            .collect())
+    }
+
+    async fn get_programs(&self) -> RpcResult<Value> {
+        Ok(self.programs.programs().iter()
+            .map(|(program_id, handler)| serde_json::json!({
+                "programId": program_id.to_string(),
+                "handler": handler,
+            }))
+            .collect())
+    }
+
+    async fn register_program(&self, program_id: String, handler: ProgramHandler) -> RpcResult<Value> {
+        let program_id = parse_pubkey(&program_id)?;
+        self.queue_program_change(program_id, Some(handler), "Failed to register program")
+    }
+
+    async fn unregister_program(&self, program_id: String) -> RpcResult<Value> {
+        let program_id = parse_pubkey(&program_id)?;
+        self.queue_program_change(program_id, None, "Failed to unregister program")
+    }
+
+    async fn set_token_metadata(&self, token: TokenMetadata) -> RpcResult<Value> {
+        let mint_decimals = self.state_manager.get_account(&token.address).await
+            .and_then(|mint| self.programs.mint_decimals(&mint.owner, &mint.data));
+        if let Some(decimals) = mint_decimals
+            && decimals != token.decimals
+        {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Failed to set token metadata",
+                Some(format!("Mint {} has {} decimals, not {}", token.address, decimals, token.decimals))
+            ));
+        }
+
+        let mint = token.address;
+        let previous = self.tokens.set(token.clone())
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Failed to set token metadata", Some(e.to_string())))?;
+
+        println!("Token metadata of {} set to {} ({})", mint, token.symbol, token.name);
+
+        Ok(serde_json::json!({
+            "token": token,
+            "previous": previous,
+        }))
+    }
+
+    async fn remove_token_metadata(&self, mint: String) -> RpcResult<Value> {
+        let mint = parse_pubkey(&mint)?;
+        let previous = self.tokens.remove(&mint);
+
+        if previous.is_some() {
+            println!("Token metadata of {} removed", mint);
+        }
+
+        Ok(serde_json::json!({
+            "mint": mint.to_string(),
+            "previous": previous,
+        }))
+    }
+
+    async fn get_mempool_snapshot(&self) -> RpcResult<MempoolSnapshot> {
+        self.sequencer.mempool_snapshot().await
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to snapshot mempool", Some(e.to_string())))
+    }
+
+    async fn import_mempool(&self, snapshot: MempoolSnapshot) -> RpcResult<Value> {
+        if snapshot.format_version != MEMPOOL_SNAPSHOT_VERSION {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Unsupported mempool snapshot",
+                Some(format!("Format version {}, expected {}", snapshot.format_version, MEMPOOL_SNAPSHOT_VERSION))
+            ));
+        }
+        let genesis_hash = self.state_manager.genesis_hash()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load genesis hash", Some(e.to_string())))?
+            .map(|hash| bs58::encode(hash).into_string());
+        if snapshot.genesis_hash != genesis_hash {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Mempool snapshot is of a different chain",
+                Some(format!(
+                    "Genesis hash {}, expected {}",
+                    snapshot.genesis_hash.as_deref().unwrap_or("none"),
+                    genesis_hash.as_deref().unwrap_or("none")
+                ))
+            ));
+        }
+
+        let mut entries: Vec<&MempoolEntry> = snapshot.transactions.iter().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.waited_ms));
+
+        let (mut submitted, mut sealed) = (0, 0);
+        let mut failed = Vec::new();
+        for entry in entries {
+            match self.import_entry(entry).await {
+                Ok(Imported::Submitted) => submitted += 1,
+                Ok(Imported::Sealed) => sealed += 1,
+                Err(e) => failed.push(serde_json::json!({
+                    "signature": entry.signature,
+                    "error": e.to_string(),
+                })),
+            }
+        }
+
+        println!(
+            "Imported mempool snapshot of slot {}: {} submitted, {} already sealed, {} failed",
+            snapshot.slot, submitted, sealed, failed.len()
+        );
+
+        Ok(serde_json::json!({
+            "submitted": submitted,
+            "sealed": sealed,
+            "failed": failed,
+        }))
+    }
+}
+
+fn parse_pubkey(pubkey: &str) -> RpcResult<Pubkey> {
+    pubkey.parse::<Pubkey>()
+        .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))
+}
@@ -0,0 +1,78 @@
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use solana_sdk::{pubkey::Pubkey, signature::{Keypair, Signature, Signer}};
+
+/// Prefix of every attested message, so an attestation can't be passed off
+/// as a signature over anything else made with the identity key
+const ATTESTATION_DOMAIN: &[u8] = b"tiny-rollup:rpc-attestation:v1";
+
+/// Signs RPC responses with the node's identity key, so a client pinning
+/// that key can tell a response really came from this node.
+pub struct ResponseAttestor {
+    keypair: Keypair,
+}
+
+impl ResponseAttestor {
+    pub fn new(keypair: Keypair) -> Self {
+        Self { keypair }
+    }
+
+    pub fn identity(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    /// Signs `message` with the identity key. For payloads other than RPC
+    /// responses, which must carry a domain of their own.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.keypair.sign_message(message)
+    }
+
+    /// Adds an `attestation` field to `result`, signing it as the response
+    /// to `method`. Anything but an object is returned as is.
+    pub fn attest(&self, method: &str, mut result: Value) -> Value {
+        let signature = self.keypair.sign_message(&attestation_digest(method, &result));
+        if let Value::Object(fields) = &mut result {
+            fields.insert("attestation".to_string(), serde_json::json!({
+                "identity": self.keypair.pubkey().to_string(),
+                "signature": signature.to_string(),
+            }));
+        }
+        result
+    }
+}
+
+/// What an attestation signs: sha256 of the domain, the method name, a zero
+/// byte and the response serialized as compact JSON with sorted keys, minus
+/// its `attestation` field.
+pub fn attestation_digest(method: &str, result: &Value) -> [u8; 32] {
+    let mut result = result.clone();
+    if let Value::Object(fields) = &mut result {
+        fields.remove("attestation");
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(ATTESTATION_DOMAIN);
+    hasher.update(method.as_bytes());
+    hasher.update([0]);
+    hasher.update(serde_json::to_vec(&result).unwrap_or_default());
+    hasher.finalize().into()
+}
+
+/// Whether `result` carries a valid attestation by `identity` as the
+/// response to `method`.
+pub fn verify_attestation(method: &str, result: &Value, identity: &Pubkey) -> bool {
+    let Some(attestation) = result.get("attestation") else {
+        return false;
+    };
+    if attestation.get("identity").and_then(Value::as_str) != Some(identity.to_string().as_str()) {
+        return false;
+    }
+    let Some(signature) = attestation.get("signature")
+        .and_then(Value::as_str)
+        .and_then(|s| s.parse::<Signature>().ok())
+    else {
+        return false;
+    };
+
+    signature.verify(identity.as_ref(), &attestation_digest(method, result))
+}
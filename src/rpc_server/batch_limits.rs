@@ -0,0 +1,186 @@
+use std::{collections::HashMap, future::Future, str::FromStr, sync::Arc};
+
+use jsonrpsee::{
+    core::TEN_MB_SIZE_BYTES,
+    server::{
+        middleware::rpc::{Batch, BatchEntry, Notification, RpcServiceT},
+        BatchResponseBuilder,
+        MethodResponse,
+    },
+    types::{error::TOO_BIG_BATCH_REQUEST_CODE, ErrorObject, Request},
+};
+use tokio::sync::Semaphore;
+
+use crate::metrics::metrics;
+
+/// Cost of a call to a method without one of its own
+const DEFAULT_METHOD_COST: u64 = 1;
+
+/// Methods that scan many accounts or records, and what they cost unless
+/// configured otherwise
+const HEAVY_METHOD_COSTS: [(&str, u64); 10] = [
+    ("simulateTransactionAtBatch", 100),
+    ("getDisputeData", 100),
+    ("getProgramAccounts", 50),
+    ("getAddressActivity", 10),
+    ("getBatch", 10),
+    ("getBlock", 10),
+    ("getFeeRateRecommendations", 10),
+    ("rollup_getProgramStats", 10),
+    ("simulateTransaction", 10),
+    ("getMultipleAccounts", 5),
+];
+
+/// What a call to each RPC method counts against a batch's cost limit.
+#[derive(Debug, Clone)]
+pub struct MethodCosts {
+    methods: HashMap<String, u64>,
+}
+
+impl MethodCosts {
+    /// The built-in costs of heavy methods, with `overrides` on top.
+    pub fn new(overrides: impl IntoIterator<Item = (String, u64)>) -> Self {
+        let mut methods: HashMap<_, _> = HEAVY_METHOD_COSTS.iter()
+            .map(|(method, cost)| (method.to_string(), *cost))
+            .collect();
+        methods.extend(overrides);
+
+        Self { methods }
+    }
+
+    /// Parses `METHOD=COST`.
+    pub fn parse_spec(spec: &str) -> anyhow::Result<(String, u64)> {
+        let (method, cost) = spec.split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid method cost {}: expected METHOD=COST", spec))?;
+        let cost = u64::from_str(cost)
+            .map_err(|e| anyhow::anyhow!("Invalid cost in {}: {}", spec, e))?;
+
+        Ok((method.to_string(), cost))
+    }
+
+    pub fn cost(&self, method: &str) -> u64 {
+        self.methods.get(method).copied().unwrap_or(DEFAULT_METHOD_COST)
+    }
+
+    /// Whether `method` costs more than an ordinary call.
+    pub fn is_heavy(&self, method: &str) -> bool {
+        self.cost(method) > DEFAULT_METHOD_COST
+    }
+}
+
+/// Limits what a single JSON-RPC batch can take from the node. Items past
+/// `max_cost`, summed in order, fail without running. The rest take turns
+/// with every other connection's batches: at most `concurrency` batch items
+/// run at once across the node, handed out first come first served, so a
+/// batch of heavy calls advances one item at a time alongside everyone
+/// else's. Single calls are never held back.
+#[derive(Debug, Clone)]
+pub struct BatchLimitLayer {
+    costs: Arc<MethodCosts>,
+    max_cost: u64,
+    slots: Arc<Semaphore>,
+}
+
+impl BatchLimitLayer {
+    pub fn new(costs: MethodCosts, max_cost: u64, concurrency: usize) -> Self {
+        Self { costs: Arc::new(costs), max_cost, slots: Arc::new(Semaphore::new(concurrency)) }
+    }
+}
+
+impl<S> tower::Layer<S> for BatchLimitLayer {
+    type Service = BatchLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BatchLimit { inner, layer: self.clone() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchLimit<S> {
+    inner: S,
+    layer: BatchLimitLayer,
+}
+
+impl<S> RpcServiceT for BatchLimit<S>
+where
+    S: RpcServiceT<MethodResponse = MethodResponse, BatchResponse = MethodResponse, NotificationResponse = MethodResponse>
+        + Send + Sync + Clone + 'static,
+{
+    type MethodResponse = MethodResponse;
+    type NotificationResponse = MethodResponse;
+    type BatchResponse = MethodResponse;
+
+    fn call<'a>(&self, request: Request<'a>) -> impl Future<Output = Self::MethodResponse> + Send + 'a {
+        self.inner.call(request)
+    }
+
+    /// Runs the items itself, rather than through the inner batch, so each
+    /// one waits for a slot of its own.
+    fn batch<'a>(&self, batch: Batch<'a>) -> impl Future<Output = Self::BatchResponse> + Send + 'a {
+        let inner = self.inner.clone();
+        let layer = self.layer.clone();
+
+        async move {
+            // The server's own response size limit, which is left at its default
+            let mut responses = BatchResponseBuilder::new_with_limit(TEN_MB_SIZE_BYTES as usize);
+            let mut got_notification = false;
+            let mut spent = 0u64;
+
+            for entry in batch {
+                let method = match &entry {
+                    Ok(BatchEntry::Call(request)) => request.method_name(),
+                    Ok(BatchEntry::Notification(notification)) => notification.method_name(),
+                    Err(_) => "",
+                };
+                spent = spent.saturating_add(layer.costs.cost(method));
+                let over_budget = spent > layer.max_cost;
+                if over_budget {
+                    metrics::increment("rpc_batch_items_rejected");
+                }
+
+                let response = match entry {
+                    Ok(BatchEntry::Call(request)) if over_budget => MethodResponse::error(
+                        request.id,
+                        ErrorObject::owned(
+                            TOO_BIG_BATCH_REQUEST_CODE,
+                            format!("Batch exceeds its cost limit of {}", layer.max_cost),
+                            None::<()>
+                        )
+                    ),
+                    Ok(BatchEntry::Call(request)) => {
+                        // Only fails once the semaphore is closed, which it never is
+                        let _slot = layer.slots.acquire().await.expect("batch slots are never closed");
+                        inner.call(request).await
+                    }
+                    Ok(BatchEntry::Notification(notification)) => {
+                        got_notification = true;
+                        if !over_budget {
+                            let _slot = layer.slots.acquire().await.expect("batch slots are never closed");
+                            inner.notification(notification).await;
+                        }
+                        continue;
+                    }
+                    Err(error) => {
+                        let (error, id) = error.into_parts();
+                        MethodResponse::error(id, error)
+                    }
+                };
+                if let Err(too_big) = responses.append(response) {
+                    return too_big;
+                }
+            }
+
+            // As jsonrpsee answers batches: nothing for notifications only,
+            // and an empty batch is an invalid request
+            if responses.is_empty() && got_notification {
+                MethodResponse::notification()
+            } else {
+                MethodResponse::from_batch(responses.finish())
+            }
+        }
+    }
+
+    fn notification<'a>(&self, n: Notification<'a>) -> impl Future<Output = Self::NotificationResponse> + Send + 'a {
+        self.inner.notification(n)
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Mutex;
+
+use jsonrpsee::{
+    core::{RpcResult, async_trait},
+    proc_macros::rpc,
+    types::ErrorObjectOwned,
+};
+use serde_json::Value;
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::{Keypair, Signature, Signer}};
+
+use crate::batch_processor::{batch_processor::BatchCommitment, cosigning::{batch_digest, request_digest}};
+
+/// Last batch co-signed and the state root it ended at, which the next one
+/// must start from. Batch ids are 8 byte keys, so this can't collide
+const TIP_KEY: &[u8] = b"tip";
+
+/// Served by a node running the `cosign` subcommand. Batch submissions need a
+/// threshold of these signatures, so the sequencer can't post alone.
+#[rpc(server)]
+pub trait CosignerRpc {
+    /// Signs the digest of a batch commitment, given hex encoded, for the
+    /// sequencer, whose identity signs the request digest. A batch id is only
+    /// ever signed for one commitment, and only once it extends the last
+    /// batch signed: the next id, starting from the state root that one
+    /// ended at.
+    #[method(name = "cosignBatch")]
+    async fn cosign_batch(&self, batch_id: u64, commitment: String, sequencer_signature: String) -> RpcResult<Value>;
+}
+
+pub struct CosignerRpcImpl {
+    keypair: Keypair,
+    /// Identity key requests must be signed with
+    sequencer: Pubkey,
+    /// Chain commitments must belong to; any chain when unset
+    genesis_hash: Option<Hash>,
+    /// Digest signed for each batch id, so a compromised sequencer can't get
+    /// two different commitments for the same batch co-signed, and the tip
+    signed: Mutex<rocksdb::DB>,
+}
+
+impl CosignerRpcImpl {
+    pub fn new(keypair: Keypair, sequencer: Pubkey, genesis_hash: Option<Hash>, db_path: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            keypair,
+            sequencer,
+            genesis_hash,
+            signed: Mutex::new(rocksdb::DB::open_default(db_path)?),
+        })
+    }
+
+    /// Refuses `commitment` unless it is for the batch after the last one
+    /// signed here and starts from the state root that one ended at, so a
+    /// compromised sequencer can't get a fork of the chain co-signed. The
+    /// first batch signed is taken as the start of the chain.
+    fn check_extends_tip(signed: &rocksdb::DB, commitment: &BatchCommitment) -> RpcResult<()> {
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load last co-signed batch", Some(e.to_string()))
+        };
+        let Some(tip) = signed.get(TIP_KEY).map_err(|e| internal_error(e.into()))? else {
+            println!(
+                "First co-signed batch {}; its pre-state root {} is trusted",
+                commitment.batch_id,
+                hex::encode(commitment.pre_state_root)
+            );
+            return Ok(());
+        };
+        let (last, post_state_root): (u64, [u8; 32]) = bincode::deserialize(&tip).map_err(|e| internal_error(e.into()))?;
+
+        if commitment.batch_id != last + 1 {
+            eprintln!("Refused to co-sign batch {}, which doesn't follow batch {}", commitment.batch_id, last);
+            return Err(ErrorObjectOwned::owned(
+                -32000,
+                "Batch doesn't extend the co-signed chain",
+                Some(format!("Batch {} was co-signed last; the next batch to sign is {}", last, last + 1))
+            ));
+        }
+        if commitment.pre_state_root != post_state_root {
+            eprintln!("Refused to co-sign batch {}, which doesn't start from batch {}'s state", commitment.batch_id, last);
+            return Err(ErrorObjectOwned::owned(
+                -32000,
+                "Batch doesn't extend the co-signed chain",
+                Some(format!(
+                    "Batch {} starts from state root {}, but batch {} ended at {}",
+                    commitment.batch_id,
+                    hex::encode(commitment.pre_state_root),
+                    last,
+                    hex::encode(post_state_root)
+                ))
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CosignerRpcServer for CosignerRpcImpl {
+    async fn cosign_batch(&self, batch_id: u64, commitment: String, sequencer_signature: String) -> RpcResult<Value> {
+        let batch_data = hex::decode(&commitment)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid commitment encoding", Some(e.to_string())))?;
+        let parsed = bincode::deserialize::<BatchCommitment>(&batch_data)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid batch commitment", Some(e.to_string())))?;
+        if parsed.batch_id != batch_id {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid batch commitment",
+                Some(format!("Commitment is for batch {}, not {}", parsed.batch_id, batch_id))
+            ));
+        }
+        if let Some(genesis_hash) = self.genesis_hash
+            && Hash::new_from_array(parsed.genesis_hash) != genesis_hash
+        {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid batch commitment",
+                Some(format!("Commitment is for chain {}, not {}", Hash::new_from_array(parsed.genesis_hash), genesis_hash))
+            ));
+        }
+
+        let digest = batch_digest(batch_id, &batch_data);
+        let authenticated = sequencer_signature.parse::<Signature>()
+            .is_ok_and(|signature| signature.verify(self.sequencer.as_ref(), &request_digest(&digest)));
+        if !authenticated {
+            eprintln!("Refused to co-sign batch {} for a request not signed by the sequencer", batch_id);
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid sequencer signature",
+                Some(format!("Requests must be signed by sequencer identity {}", self.sequencer))
+            ));
+        }
+
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to record co-signature", Some(e.to_string()))
+        };
+
+        {
+            let signed = self.signed.lock().unwrap();
+            match signed.get(batch_id.to_be_bytes()).map_err(|e| internal_error(e.into()))? {
+                Some(previous) if previous != digest => {
+                    eprintln!("Refused to co-sign a second commitment for batch {}", batch_id);
+                    return Err(ErrorObjectOwned::owned(
+                        -32000,
+                        "Batch already co-signed",
+                        Some(format!("Batch {} was co-signed for a different commitment", batch_id))
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    Self::check_extends_tip(&signed, &parsed)?;
+
+                    // Durable before the signature leaves this node
+                    let mut batch = rocksdb::WriteBatch::default();
+                    batch.put(batch_id.to_be_bytes(), digest);
+                    batch.put(TIP_KEY, bincode::serialize(&(batch_id, parsed.post_state_root)).map_err(|e| internal_error(e.into()))?);
+                    let mut options = rocksdb::WriteOptions::default();
+                    options.set_sync(true);
+                    signed.write_opt(batch, &options).map_err(|e| internal_error(e.into()))?;
+                }
+            }
+        }
+
+        println!("Co-signed batch {} ({})", batch_id, hex::encode(digest));
+
+        Ok(serde_json::json!({
+            "pubkey": self.keypair.pubkey().to_string(),
+            "signature": self.keypair.sign_message(&digest).to_string(),
+        }))
+    }
+}
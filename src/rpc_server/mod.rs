@@ -1 +1,13 @@
+pub mod admin;
+pub mod attestation;
+pub mod batch_limits;
+pub mod cosigner;
+pub mod routing;
 pub mod server;
+pub mod shedding;
+pub mod slo;
+pub mod snapshot_files;
+pub mod sse;
+pub mod token_list;
+pub mod unsupported;
+pub mod verifier;
@@ -0,0 +1,142 @@
+use jsonrpsee::{Methods, RpcModule, core::RegisterMethodError};
+
+/// Methods that change rollup state, and methods too expensive to serve
+/// alongside public reads.
+pub const WRITE_METHODS: [&str; 5] = [
+    "sendTransaction",
+    "requestAirdrop",
+    "rollup_requestWithdrawal",
+    "cosignBatch",
+    "getDisputeData",
+];
+
+/// Operator methods, which change node configuration or expose what users
+/// submitted. They are only served on the admin listener, never on the
+/// public or write endpoints.
+pub const ADMIN_METHODS: [&str; 10] = [
+    "rotateAuthority",
+    "registerProgram",
+    "unregisterProgram",
+    "setTokenMetadata",
+    "removeTokenMetadata",
+    "importMempool",
+    "simulateTransactionAtBatch",
+    "getDeadLetters",
+    "getPrograms",
+    "getMempoolSnapshot",
+];
+
+/// Moves the write methods out of `module`, so they can be served on a
+/// separate endpoint from reads.
+pub fn split_write_methods<Context: Send + Sync + 'static>(module: &mut RpcModule<Context>) -> Result<Methods, RegisterMethodError> {
+    split_methods(module, &WRITE_METHODS)
+}
+
+/// Moves the admin methods out of `module`, so they are only served on the
+/// admin listener.
+pub fn split_admin_methods<Context: Send + Sync + 'static>(module: &mut RpcModule<Context>) -> Result<Methods, RegisterMethodError> {
+    split_methods(module, &ADMIN_METHODS)
+}
+
+fn split_methods<Context: Send + Sync + 'static>(
+    module: &mut RpcModule<Context>,
+    names: &[&'static str]
+) -> Result<Methods, RegisterMethodError> {
+    let mut methods = Methods::new();
+    for &method in names {
+        if let Some(callback) = module.remove_method(method) {
+            methods.verify_and_insert(method, callback)?;
+        }
+    }
+
+    Ok(methods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ADMIN_METHODS, WRITE_METHODS};
+
+    /// Methods checked to only read, so a new method has to be added to
+    /// one list or the other
+    const READ_METHODS: [&str; 29] = [
+        "getAccountInfo",
+        "getMultipleAccounts",
+        "getProgramAccounts",
+        "getBalance",
+        "getTokenAccountBalance",
+        "getLatestBlockhash",
+        "getFeeForMessage",
+        "getFeeRateRecommendations",
+        "getSlot",
+        "getBlockHeight",
+        "getBlock",
+        "simulateTransaction",
+        "getSignatureStatuses",
+        "getTransaction",
+        "getAddressActivity",
+        "getBatch",
+        "getBatchReport",
+        "getDeposit",
+        "getTokenMetadata",
+        "rollup_getWithdrawal",
+        "getVersion",
+        "getMetrics",
+        "getEpochInfo",
+        "rollup_getProgramStats",
+        "getHighestSnapshotSlot",
+        "rollup_getSnapshots",
+        "getHealth",
+        "getRollupStatus",
+        "getPendingBatch",
+    ];
+
+    /// Sources of every RPC trait the node serves
+    const RPC_SOURCES: [&str; 4] = [
+        include_str!("server.rs"),
+        include_str!("admin.rs"),
+        include_str!("cosigner.rs"),
+        include_str!("verifier.rs"),
+    ];
+
+    fn declared_methods() -> Vec<&'static str> {
+        RPC_SOURCES.iter().flat_map(|source| methods_in(source)).collect()
+    }
+
+    fn methods_in(source: &'static str) -> impl Iterator<Item = &'static str> {
+        source.split("#[method(name = \"").skip(1)
+            .filter_map(|rest| rest.split('"').next())
+    }
+
+    #[test]
+    fn every_method_is_a_read_a_write_or_admin() {
+        let declared = declared_methods();
+        assert!(declared.contains(&"sendTransaction"), "no methods found in the RPC sources");
+
+        for method in declared {
+            let lists = [WRITE_METHODS.contains(&method), READ_METHODS.contains(&method), ADMIN_METHODS.contains(&method)];
+            match lists.iter().filter(|&&listed| listed).count() {
+                0 => panic!("{} is in none of WRITE_METHODS, READ_METHODS and ADMIN_METHODS", method),
+                1 => {}
+                _ => panic!("{} is in more than one of WRITE_METHODS, READ_METHODS and ADMIN_METHODS", method),
+            }
+        }
+    }
+
+    #[test]
+    fn every_write_and_admin_method_exists() {
+        let declared = declared_methods();
+        for method in WRITE_METHODS.iter().chain(&ADMIN_METHODS) {
+            assert!(declared.contains(method), "{} is routed but no RPC trait declares it", method);
+        }
+    }
+
+    #[test]
+    fn operator_methods_are_admin_and_verifier_methods_are_writes() {
+        for method in methods_in(include_str!("admin.rs")) {
+            assert!(ADMIN_METHODS.contains(&method), "{} is served outside the admin listener", method);
+        }
+        for method in methods_in(include_str!("verifier.rs")) {
+            assert!(WRITE_METHODS.contains(&method), "{} is served on the public read port", method);
+        }
+    }
+}
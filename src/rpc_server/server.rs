@@ -1,19 +1,92 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
+use base64::{Engine, prelude::BASE64_STANDARD};
 use jsonrpsee::{
-    core::{RpcResult, async_trait},
+    PendingSubscriptionSink, SubscriptionSink,
+    core::{RpcResult, SubscriptionResult, async_trait, to_json_raw_value},
     proc_macros::rpc,
-    types::{ErrorObjectOwned, ErrorObject},
+    types::ErrorObjectOwned,
 };
+use serde::Deserialize;
 use serde_json::Value;
-use solana_sdk::{pubkey::Pubkey, transaction::Transaction};
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_client::rpc_filter::RpcFilterType;
+use solana_sdk::{hash::Hash, message::VersionedMessage, pubkey::Pubkey, signature::{Keypair, Signature, Signer}, transaction::Transaction};
+use solana_transaction_status::{
+    BlockEncodingOptions, TransactionBinaryEncoding, TransactionDetails, UiTransactionEncoding,
+};
+use tokio::sync::{Semaphore, broadcast::{self, error::RecvError}};
 
 use crate::{
-    sequencer::sequencer::Sequencer, 
-    state_manager::state_manager::StateManager, 
-    transaction_processor::transaction_processor::TransactionProcessor
+    batch_processor::{
+        batch_processor::{BatchProcessor, PROTOCOL_VERSION},
+        health::DegradedMode,
+    },
+    block_stream::block_stream::{BlockFrame, StreamedBlock},
+    data_availability::data_availability::DataAvailability,
+    epochs::epochs::EpochConfig,
+    events::events::RollupEvent,
+    faucet::faucet::{AirdropError, Faucet},
+    load_shedding::load_shedding::LoadShedder,
+    metrics::{latency, metrics},
+    pipeline::pipeline::Pipeline,
+    replay::replay::{ExecutionConfig, Replayer},
+    rpc_server::attestation::ResponseAttestor,
+    state_export::state_export::{list_snapshots, SnapshotInfo},
+    sequencer::{block::{BlockHeader, BLOCKHASH_VALIDITY_SLOTS}, sequencer::Sequencer},
+    state_manager::{snapshots::Commitment, state_manager::{L2Account, StateManager}},
+    token_registry::token_registry::TokenRegistry,
+    transaction_processor::{
+        execution_error::ExecutionError,
+        transaction_processor::{Simulation, TransactionProcessor},
+        transaction_record::{ui_token_amount, TransactionRecord, TransactionStage},
+        wire_transaction::WireTransaction,
+    },
+    withdrawals::withdrawals::{withdrawal_proof, withdrawal_root, WithdrawalEntry, WithdrawalRequest, WithdrawalStatus},
 };
 
+/// Solana RPC API version this server mirrors.
+const SOLANA_RPC_VERSION: &str = "3.0.0";
+
+/// Solana's error code for a slot without a block at the requested commitment
+const BLOCK_NOT_AVAILABLE_CODE: i32 = -32004;
+
+/// Solana's cap on signatures per getSignatureStatuses call
+const MAX_SIGNATURE_STATUSES: usize = 256;
+
+/// Solana's cap on pubkeys per getMultipleAccounts call
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Longest base58 string a packet-sized transaction encodes to; longer input
+/// is rejected before it is decoded
+const MAX_BASE58_TRANSACTION_LEN: usize = 1683;
+
+/// Same, for base64
+const MAX_BASE64_TRANSACTION_LEN: usize = 1644;
+
+/// Solana's cap on filters per getProgramAccounts call
+const MAX_PROGRAM_ACCOUNT_FILTERS: usize = 4;
+
+/// Solana's error code for a read below the requested `minContextSlot`
+const MIN_CONTEXT_SLOT_NOT_REACHED_CODE: i32 = -32016;
+
+/// Blocks getFeeRateRecommendations samples by default, and at most
+const DEFAULT_FEE_RATE_BLOCKS: u64 = 150;
+const MAX_FEE_RATE_BLOCKS: u64 = 1000;
+
+const DEFAULT_ACTIVITY_LIMIT: usize = 100;
+const MAX_ACTIVITY_LIMIT: usize = 1000;
+
+/// How far back a subscription's `fromSlot` may reach; older cursors have
+/// to resync through getBlock
+const MAX_REPLAY_SLOTS: u64 = 1000;
+
+/// Solana's "node unhealthy" error code.
+const NODE_UNHEALTHY: i32 = -32005;
+
+/// Solana's code for getHighestSnapshotSlot on a node without snapshots
+const NO_SNAPSHOT: i32 = -32008;
+
 #[rpc(server)]
 pub trait RollupRpc {
     #[method(name = "getAccountInfo")]
@@ -23,40 +96,652 @@ pub trait RollupRpc {
         config: Option<Value>
     ) -> RpcResult<Option<Value>>;
 
+    #[method(name = "getMultipleAccounts")]
+    async fn get_multiple_accounts(&self, pubkeys: Vec<String>, config: Option<Value>) -> RpcResult<Value>;
+
+    #[method(name = "getProgramAccounts")]
+    async fn get_program_accounts(&self, program_id: String, config: Option<Value>) -> RpcResult<Value>;
+
     #[method(name = "getBalance")]
     async fn get_balance(&self, pubkey: String, config: Option<Value>) -> RpcResult<u64>;
+
+    /// Balance of an spl-token account, scaled by its mint's decimals.
+    #[method(name = "getTokenAccountBalance")]
+    async fn get_token_account_balance(&self, pubkey: String, config: Option<Value>) -> RpcResult<Value>;
     
     #[method(name = "sendTransaction")]
     async fn send_transaction(&self, transaction: String, config: Option<Value>) -> RpcResult<String>;
     
+    /// Mints `lamports` to `pubkey` as a transfer from a fresh faucet
+    /// account, sealed into the next block, returning its signature. Only
+    /// on nodes started with --faucet-enabled, up to the faucet's
+    /// per-airdrop maximum and per-address rate limit.
+    #[method(name = "requestAirdrop")]
+    async fn request_airdrop(&self, pubkey: String, lamports: u64, config: Option<Value>) -> RpcResult<String>;
+
     #[method(name = "getLatestBlockhash")]
     async fn get_latest_blockhash(&self, config: Option<Value>) -> RpcResult<Value>;
+
+    /// The fee the rollup would charge for a base64 encoded message.
+    #[method(name = "getFeeForMessage")]
+    async fn get_fee_for_message(&self, message: String, config: Option<Value>) -> RpcResult<Value>;
+
+    /// Low, medium and high compute unit prices (micro-lamports) from the
+    /// prices paid by transactions in the last `blocks` (150) blocks, with
+    /// `high` raised to outbid the next batch while the mempool is backed up.
+    #[method(name = "getFeeRateRecommendations")]
+    async fn get_fee_rate_recommendations(&self, config: Option<Value>) -> RpcResult<Value>;
+
+    /// Slots are block numbers: every sealed batch is a block.
+    #[method(name = "getSlot")]
+    async fn get_slot(&self, config: Option<Value>) -> RpcResult<u64>;
+
+    /// Same as the slot, since no slot is ever skipped.
+    #[method(name = "getBlockHeight")]
+    async fn get_block_height(&self, config: Option<Value>) -> RpcResult<u64>;
+
+    /// A sealed block with its transactions, fetched from DA if pruned
+    /// locally. Blocks only exist once sealed, so `processed` reads as
+    /// `confirmed`.
+    #[method(name = "getBlock")]
+    async fn get_block(&self, slot: u64, config: Option<Value>) -> RpcResult<Value>;
     
     #[method(name = "simulateTransaction")]
     async fn simulate_transaction(&self, transaction: String, config: Option<Value>) -> RpcResult<Value>;
+
+    /// Executes a transaction like simulateTransaction against the state as
+    /// of the end of `batch_id`, rebuilt by replaying stored batches from
+    /// genesis; one runs at a time (`--admin-rpc` only).
+    #[method(name = "simulateTransactionAtBatch")]
+    async fn simulate_transaction_at_batch(&self, transaction: String, batch_id: u64, config: Option<Value>) -> RpcResult<Value>;
     
+    /// Statuses of transactions this node executed: `processed` once
+    /// executed, which happens as their block is sealed, `confirmed` once
+    /// sealed into a batch (the slot) and `finalized` once that batch is
+    /// finalized on L1.
+    #[method(name = "getSignatureStatuses")]
+    async fn get_signature_statuses(&self, signatures: Vec<String>, config: Option<Value>) -> RpcResult<Value>;
+
+    /// Once the transaction is sealed into a batch (the slot); with
+    /// `finalized` commitment, once that batch is finalized on L1.
     #[method(name = "getTransaction")]
     async fn get_transaction(&self, signature: String, config: Option<Value>) -> RpcResult<Option<Value>>;
+
+    /// Transactions involving an address, newest first, each with typed
+    /// balance changes (transfers, fees, token movements). Page by passing
+    /// the returned `nextCursor` as `cursor`.
+    #[method(name = "getAddressActivity")]
+    async fn get_address_activity(&self, pubkey: String, cursor: Option<u64>, limit: Option<usize>) -> RpcResult<Value>;
+
+    /// A sealed batch's transactions, fetched from DA if pruned locally.
+    #[method(name = "getBatch")]
+    async fn get_batch(&self, batch_id: u64) -> RpcResult<Option<Value>>;
+
+    #[method(name = "getBatchReport")]
+    async fn get_batch_report(&self, batch_id: u64) -> RpcResult<Option<Value>>;
+
+    /// A deposit seen on L1 by its bridge-assigned id, with the block it was
+    /// credited in once it has been.
+    #[method(name = "getDeposit")]
+    async fn get_deposit(&self, deposit_id: u64) -> RpcResult<Option<Value>>;
+
+    /// Display metadata (symbol, name, decimals, logo) of L2 mints, `null`
+    /// for mints that aren't registered; every registered mint when `mints`
+    /// is omitted.
+    #[method(name = "getTokenMetadata")]
+    async fn get_token_metadata(&self, mints: Option<Vec<String>>) -> RpcResult<Value>;
+
+    /// Queues a withdrawal to L1 signed by the L2 sender; the lamports are
+    /// debited when the next block is sealed. Resubmitting the same signed
+    /// request returns the withdrawal already queued for it.
+    #[method(name = "rollup_requestWithdrawal")]
+    async fn request_withdrawal(&self, request: Value) -> RpcResult<Value>;
+
+    /// A withdrawal and its status; once sealed, its Merkle proof against
+    /// the withdrawal root its batch posted to L1, and when it can be
+    /// claimed there.
+    #[method(name = "rollup_getWithdrawal")]
+    async fn get_withdrawal(&self, withdrawal_id: u64) -> RpcResult<Option<Value>>;
+
+    /// Solana's version response plus rollup metadata, so clients can check
+    /// they are on the expected chain before sending funds.
+    #[method(name = "getVersion")]
+    async fn get_version(&self) -> RpcResult<Value>;
+
+    /// Node counters and gauges, e.g. mempool evictions by reason, and RPC
+    /// latency percentiles per method.
+    #[method(name = "getMetrics")]
+    async fn get_metrics(&self) -> RpcResult<Value>;
+
+    #[method(name = "getEpochInfo")]
+    async fn get_epoch_info(&self, config: Option<Value>) -> RpcResult<Value>;
+
+    /// Invocations, failures and compute units of every program invoked in
+    /// `epoch` (by default the current one), heaviest first, from the
+    /// blocks sealed so far.
+    #[method(name = "rollup_getProgramStats")]
+    async fn get_program_stats(&self, epoch: Option<u64>) -> RpcResult<Value>;
+
+    /// Slot of the newest complete export in the snapshot directory, as
+    /// `{full, incremental}`; exports are always full.
+    #[method(name = "getHighestSnapshotSlot")]
+    async fn get_highest_snapshot_slot(&self) -> RpcResult<Value>;
+
+    /// Every complete export in the snapshot directory, newest first, with
+    /// where to download it when the node serves its snapshots.
+    #[method(name = "rollup_getSnapshots")]
+    async fn get_snapshots(&self) -> RpcResult<Value>;
+
+    /// "ok", or a node-unhealthy error while L1 batch posting is failing.
+    #[method(name = "getHealth")]
+    async fn get_health(&self) -> RpcResult<String>;
+
+    #[method(name = "getRollupStatus")]
+    async fn get_rollup_status(&self) -> RpcResult<Value>;
+
+    /// The mempool transactions slated for the next batch, in sealing
+    /// order, with its estimated size and seal time. Not binding: higher
+    /// priority transactions arriving before the seal can displace them.
+    #[method(name = "getPendingBatch")]
+    async fn get_pending_batch(&self) -> RpcResult<Value>;
+
+    /// Notifies once, when the transaction is sealed into a batch or dropped from the mempool.
+    /// Transactions only execute as their block is built, so a dropped one changed no state
+    /// and can be rebuilt and resubmitted.
+    #[subscription(name = "signatureSubscribe" => "signatureNotification", unsubscribe = "signatureUnsubscribe", item = Value)]
+    async fn signature_subscribe(&self, signature: String, config: Option<Value>) -> SubscriptionResult;
+
+    /// Notifies on every write to the account, as soon as it executes. A
+    /// closed account is sent with no lamports or data, as on Solana.
+    #[subscription(name = "accountSubscribe" => "accountNotification", unsubscribe = "accountUnsubscribe", item = Value)]
+    async fn account_subscribe(&self, pubkey: String, config: Option<Value>) -> SubscriptionResult;
+
+    /// Notifies as each block is sealed; `root` is the last batch finalized
+    /// on L1. With `fromSlot`, stored blocks from that slot on are sent
+    /// first, so a reconnecting client misses none.
+    #[subscription(name = "slotSubscribe" => "slotNotification", unsubscribe = "slotUnsubscribe", item = Value)]
+    async fn slot_subscribe(&self, config: Option<Value>) -> SubscriptionResult;
+
+    /// Notifies with each block as it is sealed, encoded like getBlock, for
+    /// `all` blocks or those with a transaction referencing
+    /// `mentionsAccountOrProgram`. Takes `fromSlot` like slotSubscribe.
+    #[subscription(name = "blockSubscribe" => "blockNotification", unsubscribe = "blockUnsubscribe", item = Value)]
+    async fn block_subscribe(&self, filter: Value, config: Option<Value>) -> SubscriptionResult;
+
+    /// Streams every block from `fromSlot` on as a signed `BlockFrame`, for
+    /// replicas to apply without executing it. Needs --identity-keypair.
+    #[subscription(name = "blockStreamSubscribe" => "blockStreamNotification", unsubscribe = "blockStreamUnsubscribe", item = Value)]
+    async fn block_stream_subscribe(&self, from_slot: u64) -> SubscriptionResult;
+}
+
+/// What a block-following subscription sends.
+enum BlockFeed {
+    /// slotSubscribe's notifications
+    Slots,
+    /// blockSubscribe's, for blocks mentioning `mentions` or all of them
+    Blocks {
+        mentions: Option<Pubkey>,
+        encoding: UiTransactionEncoding,
+        options: BlockEncodingOptions,
+    },
+    /// blockStreamSubscribe's frames, signed by `attestor`
+    Frames {
+        attestor: Arc<ResponseAttestor>,
+        genesis_hash: [u8; 32],
+    },
 }
 
 pub struct RollupRpcImpl {
     state_manager: Arc<StateManager>,
     transaction_processor: Arc<TransactionProcessor>,
+    pipeline: Arc<Pipeline>,
     sequencer: Arc<Sequencer>,
+    batch_processor: Arc<BatchProcessor>,
+    max_response_bytes: usize,
+    epochs: EpochConfig,
+    execution: ExecutionConfig,
+    max_historical_batches: u64,
+    historical_replays: Arc<Semaphore>,
+    data_availability: Arc<DataAvailability>,
+    tokens: Arc<TokenRegistry>,
+    attestor: Option<Arc<ResponseAttestor>>,
+    withdrawal_challenge_slots: u64,
+    load_shedder: Option<Arc<LoadShedder>>,
+    snapshot_dir: Option<PathBuf>,
+    snapshot_base_url: Option<String>,
+    faucet: Option<Arc<Faucet>>,
 }
 
 impl RollupRpcImpl {
+    /// `max_response_bytes` caps the account data (after slicing) a single
+    /// account read may return.
     pub fn new(
         state_manager: Arc<StateManager>,
         transaction_processor: Arc<TransactionProcessor>,
-        sequencer: Arc<Sequencer>
+        pipeline: Arc<Pipeline>,
+        sequencer: Arc<Sequencer>,
+        batch_processor: Arc<BatchProcessor>,
+        max_response_bytes: usize,
+        epochs: EpochConfig
     ) -> Self {
         Self {
             state_manager,
             transaction_processor,
+            pipeline,
             sequencer,
+            batch_processor,
+            max_response_bytes,
+            epochs,
+            execution: ExecutionConfig::default(),
+            max_historical_batches: 0,
+            historical_replays: Arc::new(Semaphore::new(1)),
+            data_availability: Arc::new(DataAvailability::default()),
+            tokens: Arc::new(TokenRegistry::default()),
+            attestor: None,
+            withdrawal_challenge_slots: 0,
+            load_shedder: None,
+            snapshot_dir: None,
+            snapshot_base_url: None,
+            faucet: None,
+        }
+    }
+
+    /// Replays batches for historical simulation as `execution` says, which
+    /// must be how the node executes them.
+    pub fn with_execution(mut self, execution: ExecutionConfig) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    /// Lets historical simulation replay up to `max_batches` batches, so
+    /// it can reach batches `0..max_batches`. None can be reached otherwise.
+    pub fn with_historical_simulation(mut self, max_batches: u64) -> Self {
+        self.max_historical_batches = max_batches;
+        self
+    }
+
+    /// Serves batches pruned locally from DA, for getBatch and historical
+    /// simulation.
+    pub fn with_data_availability(mut self, data_availability: Arc<DataAvailability>) -> Self {
+        self.data_availability = data_availability;
+        self
+    }
+
+    /// Serves token display metadata from `tokens`.
+    pub fn with_token_registry(mut self, tokens: Arc<TokenRegistry>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Signs getLatestBlockhash, getBatch, getTransaction and
+    /// getSignatureStatuses responses with the node identity key.
+    pub fn with_attestor(mut self, attestor: Arc<ResponseAttestor>) -> Self {
+        self.attestor = Some(attestor);
+        self
+    }
+
+    /// Reports the node unhealthy in getHealth while `load_shedder` is
+    /// shedding load.
+    pub fn with_load_shedder(mut self, load_shedder: Arc<LoadShedder>) -> Self {
+        self.load_shedder = Some(load_shedder);
+        self
+    }
+
+    /// L1 slots after a batch lands before its withdrawals can be claimed,
+    /// as enforced by the L1 rollup program; only used to report when a
+    /// withdrawal becomes claimable.
+    pub fn with_withdrawal_challenge_slots(mut self, slots: u64) -> Self {
+        self.withdrawal_challenge_slots = slots;
+        self
+    }
+
+    /// Reports the exports in `dir` in getHighestSnapshotSlot and
+    /// rollup_getSnapshots; with a `base_url`, each at `<base_url>/<name>/`.
+    pub fn with_snapshots(mut self, dir: PathBuf, base_url: Option<String>) -> Self {
+        self.snapshot_dir = Some(dir);
+        self.snapshot_base_url = base_url.map(|url| url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Serves requestAirdrop, which is refused without a faucet.
+    pub fn with_faucet(mut self, faucet: Arc<Faucet>) -> Self {
+        self.faucet = Some(faucet);
+        self
+    }
+
+    /// Refuses transactions while L1 batch posting is failing, unless the
+    /// degraded mode only warns.
+    fn check_degraded(&self) -> RpcResult<()> {
+        let health = self.batch_processor.health();
+        if let Some(reason) = health.degraded_reason() {
+            match health.mode() {
+                DegradedMode::Reject => {
+                    metrics::increment("send_transaction_rejected_degraded");
+                    return Err(ErrorObjectOwned::owned(NODE_UNHEALTHY, "Node is degraded, not accepting transactions", Some(reason)));
+                }
+                DegradedMode::Warn => {
+                    metrics::increment("send_transaction_accepted_degraded");
+                    eprintln!("Accepting transaction while degraded: {}", reason);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The newest block's slot and blockhash. Before the first block, the
+    /// genesis hash stands in for its parent.
+    fn latest_blockhash(&self) -> anyhow::Result<(u64, Hash)> {
+        Ok(match self.state_manager.latest_block()? {
+            Some(block) => (block.slot, block.blockhash),
+            None => (0, Hash::new_from_array(self.state_manager.genesis_hash()?.unwrap_or_default())),
+        })
+    }
+
+    /// Complete exports in the snapshot directory, none without one.
+    async fn snapshots(&self) -> RpcResult<Vec<SnapshotInfo>> {
+        let Some(dir) = self.snapshot_dir.clone() else {
+            return Ok(Vec::new());
+        };
+        tokio::task::spawn_blocking(move || list_snapshots(&dir))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|snapshots| snapshots)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to list snapshots", Some(e.to_string())))
+    }
+
+    /// `result` with an attestation as the response to `method`, when the
+    /// node signs its responses.
+    fn attest(&self, method: &str, result: Value) -> Value {
+        match &self.attestor {
+            Some(attestor) => attestor.attest(method, result),
+            None => result,
         }
     }
+
+    /// Executes `tx` against the state as of the end of `batch_id`, rebuilt
+    /// by replaying stored batches from genesis into scratch state. Replays
+    /// are capped in length and run one at a time, as each rebuilds the
+    /// state it needs from scratch.
+    async fn simulate_at_batch(&self, tx: &Transaction, batch_id: u64, config: Option<&Value>) -> RpcResult<Value> {
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to rebuild historical state", Some(e.to_string()))
+        };
+
+        let batch_count = self.state_manager.batch_count().map_err(internal_error)?;
+        if batch_id >= batch_count {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid batchId",
+                Some(format!("Batch {} has not been sealed yet", batch_id))
+            ));
+        }
+        if batch_id >= self.max_historical_batches {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid batchId",
+                Some(format!("Historical simulation replays at most {} batches, so it can't reach batch {}", self.max_historical_batches, batch_id))
+            ));
+        }
+        let Ok(_replay) = self.historical_replays.try_acquire() else {
+            return Err(ErrorObjectOwned::owned(
+                -32000,
+                "Historical simulation busy",
+                Some("Another historical simulation is running".to_string())
+            ));
+        };
+
+        let mut replayer = Replayer::new(&self.state_manager, &self.execution).map_err(internal_error)?
+            .with_data_availability(self.data_availability.clone());
+        replayer.replay_until(&self.state_manager, batch_id, batch_id).await.map_err(internal_error)?;
+        let state_root = replayer.state_manager().get_state_root();
+        let simulation = replayer.simulate(tx).await;
+
+        let mut value = self.simulation_json(tx, &simulation, replayer.state_manager(), config).await?;
+        value["rollupStateRoot"] = serde_json::json!(hex::encode(state_root));
+        Ok(serde_json::json!({
+            "context": { "slot": batch_id },
+            "value": value,
+        }))
+    }
+
+    /// simulateTransaction's `value`, with the `accounts` the config asks
+    /// for as the transaction would leave them on top of `state_manager`.
+    async fn simulation_json(
+        &self,
+        tx: &Transaction,
+        simulation: &Simulation,
+        state_manager: &StateManager,
+        config: Option<&Value>
+    ) -> RpcResult<Value> {
+        let accounts = match config.and_then(|c| c.get("accounts")).filter(|accounts| !accounts.is_null()) {
+            Some(accounts_config) => {
+                let addresses: Vec<String> = serde_json::from_value(accounts_config.get("addresses").cloned().unwrap_or_default())
+                    .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid accounts", Some(e.to_string())))?;
+                if addresses.len() > tx.message.account_keys.len() {
+                    return Err(ErrorObjectOwned::owned(
+                        -32602,
+                        "Too many accounts provided",
+                        Some(format!("At most {} accounts, as many as the transaction references", tx.message.account_keys.len()))
+                    ));
+                }
+                let encoding = account_encoding(Some(accounts_config))?;
+                if matches!(encoding, UiAccountEncoding::Binary | UiAccountEncoding::Base58) {
+                    return Err(ErrorObjectOwned::owned(-32602, "Invalid encoding", Some("base58 encoding not supported")));
+                }
+
+                let mut accounts = Vec::with_capacity(addresses.len());
+                for address in &addresses {
+                    let pubkey = address.parse::<Pubkey>()
+                        .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+                    accounts.push(simulation.account(state_manager, &pubkey).await);
+                }
+                Value::Array(self.accounts_json(accounts.iter().map(Option::as_ref), None, encoding)?)
+            }
+            None => Value::Null,
+        };
+
+        Ok(serde_json::json!({
+            "err": simulation.result.as_ref().err().map(transaction_error),
+            "logs": simulation.trace.logs,
+            "accounts": accounts,
+            "unitsConsumed": simulation.trace.units_consumed,
+            "returnData": simulation.trace.return_data.as_ref().map(|return_data| serde_json::json!({
+                "programId": return_data.program_id.to_string(),
+                "data": [BASE64_STANDARD.encode(&return_data.data), "base64"],
+            })),
+        }))
+    }
+
+    /// A subscription's `fromSlot`, which must be within the last
+    /// `MAX_REPLAY_SLOTS` blocks.
+    fn replay_cursor(&self, config: Option<&Value>) -> RpcResult<Option<u64>> {
+        let Some(value) = config.and_then(|c| c.get("fromSlot")) else {
+            return Ok(None);
+        };
+        let from_slot: u64 = serde_json::from_value(value.clone())
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid fromSlot", Some(e.to_string())))?;
+        let next_slot = self.state_manager.batch_count()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load latest block", Some(e.to_string())))?;
+        if from_slot.saturating_add(MAX_REPLAY_SLOTS) < next_slot {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid fromSlot",
+                Some(format!("Only the last {} blocks can be replayed; resync from getBlock", MAX_REPLAY_SLOTS))
+            ));
+        }
+
+        Ok(Some(from_slot))
+    }
+
+    /// Sends `feed`'s notification of every block from `from_slot` (or the
+    /// next one sealed) on, in slot order without gaps or repeats, until the
+    /// subscriber goes away. Blocks
+    /// are stored before their event is published, so each is read back
+    /// from storage, which also catches up a subscriber that lagged behind
+    /// the event bus instead of dropping the blocks it missed.
+    async fn follow_blocks(
+        &self,
+        sink: &SubscriptionSink,
+        mut events: broadcast::Receiver<RollupEvent>,
+        from_slot: Option<u64>,
+        feed: BlockFeed
+    ) -> SubscriptionResult {
+        let mut next = match from_slot {
+            Some(from_slot) => from_slot,
+            None => self.state_manager.batch_count()?,
+        };
+        let mut sealed = self.state_manager.batch_count()?;
+
+        loop {
+            while next < sealed {
+                if let Some(header) = self.state_manager.get_block(next)?
+                    && let Some(notification) = self.block_notification(&feed, header).await?
+                {
+                    sink.send(to_json_raw_value(&notification)?).await?;
+                }
+                next += 1;
+            }
+
+            sealed = tokio::select! {
+                _ = sink.closed() => return Ok(()),
+                event = events.recv() => match event {
+                    Ok(RollupEvent::Block { header, .. }) => header.slot + 1,
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => self.state_manager.batch_count()?,
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            };
+        }
+    }
+
+    /// What `feed` sends for the block `header` heads, if anything.
+    async fn block_notification(&self, feed: &BlockFeed, header: BlockHeader) -> anyhow::Result<Option<Value>> {
+        let (mentions, encoding, options) = match feed {
+            BlockFeed::Slots => return Ok(Some(serde_json::json!({
+                "parent": header.slot.saturating_sub(1),
+                "root": self.batch_processor.last_finalized_batch().unwrap_or(0),
+                "slot": header.slot,
+            }))),
+            BlockFeed::Frames { attestor, genesis_hash } => return Ok(Some(serde_json::json!({
+                "slot": header.slot,
+                "frame": self.block_frame(header, attestor, *genesis_hash).await?.encode()?,
+            }))),
+            BlockFeed::Blocks { mentions, encoding, options } => (mentions, encoding, options),
+        };
+
+        let Some((header, mut block)) = self.data_availability.get_block(&self.state_manager, header.slot).await? else {
+            anyhow::bail!("Block {} is not available", header.slot);
+        };
+        if let Some(mentions) = mentions {
+            block.transactions.retain(|tx| tx.account_keys().iter().any(|key| key == mentions));
+            if block.transactions.is_empty() {
+                return Ok(None);
+            }
+        }
+
+        let options = BlockEncodingOptions { ..*options };
+        let mut block = serde_json::json!(block.encode_with_options(*encoding, options)?);
+        block["blockSeed"] = serde_json::json!(header.seed.to_string());
+        Ok(Some(serde_json::json!({
+            "context": { "slot": header.slot },
+            "value": {
+                "slot": header.slot,
+                "err": null,
+                "block": block,
+            },
+        })))
+    }
+
+    /// The block `header` heads as a signed block stream frame.
+    async fn block_frame(&self, header: BlockHeader, attestor: &ResponseAttestor, genesis_hash: [u8; 32]) -> anyhow::Result<BlockFrame> {
+        let Some((batch, _)) = self.data_availability.get_batch(&self.state_manager, header.slot).await? else {
+            anyhow::bail!("Block {} is not available", header.slot);
+        };
+        let Some(diff) = self.state_manager.get_block_diff(header.slot)? else {
+            anyhow::bail!("Block {} has no stored state diff", header.slot);
+        };
+        let mut records = Vec::with_capacity(batch.transactions.len());
+        for signature in batch.transactions.iter().filter_map(|tx| tx.signatures.first()) {
+            records.extend(self.state_manager.get_transaction(signature)?);
+        }
+
+        let block = StreamedBlock { header, transactions: batch.transactions, records, diff };
+        BlockFrame::seal(&block, genesis_hash, attestor)
+    }
+
+    /// The newest slot visible at `commitment`: the latest sealed block, or
+    /// the latest one finalized on L1.
+    fn slot(&self, commitment: Commitment) -> anyhow::Result<u64> {
+        Ok(match commitment {
+            Commitment::Processed | Commitment::Confirmed => self.state_manager.batch_count()?.saturating_sub(1),
+            Commitment::Finalized => self.batch_processor.last_finalized_batch().unwrap_or(0),
+        })
+    }
+
+    fn transaction_stage(&self, record: &TransactionRecord) -> anyhow::Result<TransactionStage> {
+        let Some(sealed) = record.sealed else {
+            return Ok(TransactionStage::Processed);
+        };
+        if self.batch_processor.last_finalized_batch().is_some_and(|finalized| sealed.batch_id <= finalized) {
+            return Ok(TransactionStage::Finalized);
+        }
+
+        let landed = self.state_manager.get_batch_report(sealed.batch_id)?
+            .is_some_and(|report| report.final_slot.is_some());
+        Ok(if landed { TransactionStage::PostedToL1 } else { TransactionStage::Sequenced })
+    }
+
+    /// Renders accounts in request order, enforcing the response cap. With
+    /// `jsonParsed`, data owned by a registered program is parsed where its
+    /// handler can, falling back to base58 like Solana does.
+    fn accounts_json<'a>(
+        &self,
+        accounts: impl IntoIterator<Item = Option<&'a L2Account>>,
+        slice: Option<DataSlice>,
+        encoding: UiAccountEncoding
+    ) -> RpcResult<Vec<Value>> {
+        let programs = self.transaction_processor.programs();
+        let mut total_bytes = 0usize;
+        accounts.into_iter()
+            .map(|account| {
+                let Some(account) = account else {
+                    return Ok(Value::Null);
+                };
+
+                let data = slice_data(&account.data, slice);
+                total_bytes = total_bytes.saturating_add(data.len());
+                if total_bytes > self.max_response_bytes {
+                    return Err(ErrorObjectOwned::owned(
+                        -32000,
+                        "Response too large",
+                        Some(format!(
+                            "Account data exceeds the {} byte response limit; request less with dataSlice",
+                            self.max_response_bytes
+                        ))
+                    ));
+                }
+
+                let data = match encoding {
+                    UiAccountEncoding::Binary => Value::String(bs58::encode(data).into_string()),
+                    UiAccountEncoding::Base64 => serde_json::json!([BASE64_STANDARD.encode(data), "base64"]),
+                    UiAccountEncoding::Base64Zstd => {
+                        let compressed = zstd::encode_all(data, 0).map_err(|e| {
+                            ErrorObjectOwned::owned(-32000, "Failed to compress account data", Some(e.to_string()))
+                        })?;
+                        serde_json::json!([BASE64_STANDARD.encode(compressed), "base64+zstd"])
+                    }
+                    UiAccountEncoding::JsonParsed if slice.is_none() => programs.parse_account(&account.owner, data)
+                        .unwrap_or_else(|| serde_json::json!([bs58::encode(data).into_string(), "base58"])),
+                    UiAccountEncoding::Base58 | UiAccountEncoding::JsonParsed => {
+                        serde_json::json!([bs58::encode(data).into_string(), "base58"])
+                    }
+                };
+
+                Ok(account_json(account, data))
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -64,84 +749,1122 @@ impl RollupRpcServer for RollupRpcImpl {
     async fn get_account_info(
         &self,
         pubkey: String,
-        _config: Option<Value>
+        config: Option<Value>
     ) -> RpcResult<Option<Value>> {
         let pubkey = pubkey.parse::<Pubkey>()
             .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+        let commitment = commitment(config.as_ref())?;
+        let slice = data_slice(config.as_ref())?;
 
-        match self.state_manager.get_account(&pubkey).await {
-            Some(account) => {
-                let account_info = serde_json::json!({
-                    "data": [bs58::encode(&account.data).into_string(), "base58"],
-                    "executable": account.executable,
-                    "lamports": account.lamports,
-                    "owner": account.owner.to_string(),
-                    "rentEpoch": account.rent_epoch
-                });
+        let account = self.state_manager.get_account_at(&pubkey, commitment).await;
+        let value = self.accounts_json([account.as_ref()], slice, account_encoding(config.as_ref())?)?.remove(0);
 
-                Ok(Some(serde_json::json!({
-                    "value": account_info
-                })))
-            }
-            None => Ok(Some(serde_json::json!({"value": null})))
+        Ok(Some(serde_json::json!({
+            "value": value
+        })))
+    }
+
+    async fn get_multiple_accounts(&self, pubkeys: Vec<String>, config: Option<Value>) -> RpcResult<Value> {
+        if pubkeys.len() > MAX_MULTIPLE_ACCOUNTS {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Too many inputs provided",
+                Some(format!("getMultipleAccounts takes at most {} pubkeys", MAX_MULTIPLE_ACCOUNTS))
+            ));
         }
+        let commitment = commitment(config.as_ref())?;
+        let slice = data_slice(config.as_ref())?;
+        let encoding = account_encoding(config.as_ref())?;
+        let pubkeys = pubkeys.iter()
+            .map(|pubkey| pubkey.parse::<Pubkey>()
+                .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string()))))
+            .collect::<RpcResult<Vec<_>>>()?;
+        let slot = self.slot(commitment)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load latest block", Some(e.to_string())))?;
+        check_min_context_slot(config.as_ref(), slot)?;
+
+        let accounts = self.state_manager.get_multiple_accounts_at(&pubkeys, commitment).await;
+
+        Ok(serde_json::json!({
+            "context": { "slot": slot },
+            "value": self.accounts_json(accounts.iter().map(Option::as_ref), slice, encoding)?
+        }))
     }
 
-    async fn get_balance(&self, pubkey: String, _config: Option<Value>) -> RpcResult<u64> {
+    async fn get_program_accounts(&self, program_id: String, config: Option<Value>) -> RpcResult<Value> {
+        let program_id = program_id.parse::<Pubkey>()
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+        let slice = data_slice(config.as_ref())?;
+        let filters = program_account_filters(config.as_ref())?;
+
+        let mut accounts = self.state_manager.get_accounts_by_owner(&program_id).await
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load program accounts", Some(e.to_string())))?;
+        let programs = self.transaction_processor.programs();
+        accounts.retain(|(_, account)| filters.iter().all(|filter| match filter {
+            RpcFilterType::DataSize(size) => account.data.len() as u64 == *size,
+            RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&account.data),
+            RpcFilterType::TokenAccountState => programs.token_account(&account.owner, &account.data).is_some(),
+        }));
+        let rendered = self.accounts_json(
+            accounts.iter().map(|(_, account)| Some(account)),
+            slice,
+            account_encoding(config.as_ref())?
+        )?;
+
+        Ok(Value::Array(accounts.iter().zip(rendered)
+            .map(|((pubkey, _), account)| serde_json::json!({
+                "pubkey": pubkey.to_string(),
+                "account": account,
+            }))
+            .collect()))
+    }
+
+    async fn get_balance(&self, pubkey: String, config: Option<Value>) -> RpcResult<u64> {
         let pubkey = pubkey.parse::<Pubkey>()
             .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+        let commitment = commitment(config.as_ref())?;
 
-        let account = self.state_manager.get_account(&pubkey).await;
+        let account = self.state_manager.get_account_at(&pubkey, commitment).await;
         Ok(account.map(|a| a.lamports).unwrap_or(0))
     }
 
-    async fn send_transaction(&self, transaction: String, _config: Option<Value>) -> RpcResult<String> {
-        let tx_bytes = bs58::decode(transaction)
-            .into_vec()
-            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid transaction encoding", Some(e.to_string())))?;
+    async fn get_token_account_balance(&self, pubkey: String, config: Option<Value>) -> RpcResult<Value> {
+        let pubkey = pubkey.parse::<Pubkey>()
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+        let commitment = commitment(config.as_ref())?;
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load token account balance", Some(e.to_string()))
+        };
+
+        let programs = self.transaction_processor.programs();
+        let token_account = self.state_manager.get_account_at(&pubkey, commitment).await
+            .and_then(|account| programs.token_account(&account.owner, &account.data))
+            .ok_or_else(|| ErrorObjectOwned::owned(-32602, "Invalid param: not a Token account", None::<()>))?;
+        let decimals = self.state_manager.get_account_at(&token_account.mint, commitment).await
+            .and_then(|mint| programs.mint_decimals(&mint.owner, &mint.data))
+            .ok_or_else(|| ErrorObjectOwned::owned(-32602, "Invalid param: not a Token mint", None::<()>))?;
+        let open_batch = self.state_manager.batch_count().map_err(internal_error)?;
+
+        Ok(serde_json::json!({
+            "context": { "slot": open_batch.saturating_sub(1) },
+            "value": ui_token_amount(token_account.amount, decimals),
+        }))
+    }
 
-        let tx: Transaction = bincode::deserialize(&tx_bytes)
-            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid transaction format", Some(e.to_string())))?;
+    async fn send_transaction(&self, transaction: String, config: Option<Value>) -> RpcResult<String> {
+        self.check_degraded()?;
 
-        let signature = self.transaction_processor.process_transaction(&tx).await
-            .map_err(|e| ErrorObjectOwned::owned(-32000, "Transaction processing failed", Some(e.to_string())))?;
+        let tx = decode_transaction(&transaction, binary_encoding(config.as_ref())?)?;
 
-        // Add to sequencer queue
-        self.sequencer.add_transaction(tx).await;
+        self.sequencer.check_admission(&tx).await
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Transaction not admitted to the mempool", Some(e.to_string())))?;
 
-        Ok(signature)
+        // Verified, executed and added to the mempool by the pipeline
+        let result = self.pipeline.submit(tx.clone()).await;
+        // Only count transactions the fee payer actually signed, so nobody
+        // can raise someone else's spam score with forged submissions
+        if result.is_ok() || tx.verify().is_ok() {
+            self.sequencer.record_outcome(&tx, result.is_err());
+        }
+
+        result.map_err(|e| ErrorObjectOwned::owned(-32000, "Transaction processing failed", Some(error_data(&e))))
+    }
+
+    async fn request_airdrop(&self, pubkey: String, lamports: u64, _config: Option<Value>) -> RpcResult<String> {
+        let Some(faucet) = &self.faucet else {
+            return Err(ErrorObjectOwned::owned(-32000, "Airdrops are disabled on this node", Some("Start the node with --faucet-enabled")));
+        };
+        let recipient = pubkey.parse::<Pubkey>()
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+        self.check_degraded()?;
+
+        faucet.admit(&recipient, lamports).map_err(|e| match e {
+            AirdropError::ZeroAmount | AirdropError::TooLarge { .. } => ErrorObjectOwned::owned(-32602, "Invalid airdrop amount", Some(e.to_string())),
+            AirdropError::RateLimited { retry_after, .. } => {
+                metrics::increment("faucet_airdrops_rate_limited");
+                ErrorObjectOwned::owned(-32000, e.to_string(), Some(serde_json::json!({ "retryAfterSecs": retry_after.as_secs().max(1) })))
+            }
+        })?;
+
+        let (_, blockhash) = self.latest_blockhash()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load latest block", Some(e.to_string())))?;
+        let payer = Keypair::new();
+        let tx = Faucet::airdrop_transaction(&payer, &recipient, lamports, blockhash);
+        let minted = self.transaction_processor.fees().fee(&tx.message.header).saturating_add(lamports);
+        let tx = bincode::serialize(&tx).map_err(anyhow::Error::from)
+            .and_then(WireTransaction::from_bytes)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to build airdrop transaction", Some(e.to_string())))?;
+
+        let signature = tx.signatures.first().copied().unwrap_or_default();
+        // Minted by the next block, which runs the transfer first thing
+        self.state_manager.queue_airdrop(payer.pubkey(), minted, tx)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to queue airdrop", Some(e.to_string())))?;
+
+        metrics::increment("faucet_airdrops");
+        metrics::add("faucet_lamports_minted", minted);
+        println!("🚰 Airdropped {} lamports to {}", lamports, recipient);
+        Ok(signature.to_string())
     }
 
     async fn get_latest_blockhash(&self, _config: Option<Value>) -> RpcResult<Value> {
-        let blockhash = format!("{}1111111111111111111111111111", hex::encode(&self.state_manager.get_state_root()[..8]));
+        let (slot, blockhash) = self.latest_blockhash()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load latest block", Some(e.to_string())))?;
 
-        Ok(serde_json::json!({
+        Ok(self.attest("getLatestBlockhash", serde_json::json!({
+            "context": { "slot": slot },
             "value": {
-                "blockhash": blockhash,
-                "lastValidBlockHeight": 999999999u64
+                "blockhash": blockhash.to_string(),
+                "lastValidBlockHeight": slot + BLOCKHASH_VALIDITY_SLOTS
             }
+        })))
+    }
+
+    async fn get_fee_for_message(&self, message: String, config: Option<Value>) -> RpcResult<Value> {
+        let message_bytes = BASE64_STANDARD.decode(message)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid message encoding", Some(e.to_string())))?;
+        let message: VersionedMessage = bincode::deserialize(&message_bytes)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid message format", Some(e.to_string())))?;
+
+        let slot = self.slot(commitment(config.as_ref())?)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load latest block", Some(e.to_string())))?;
+
+        Ok(serde_json::json!({
+            "context": { "slot": slot },
+            "value": self.transaction_processor.fees().fee(message.header()),
         }))
     }
 
-    async fn simulate_transaction(&self, transaction: String, _config: Option<Value>) -> RpcResult<Value> {
-        let tx_bytes = bs58::decode(transaction)
-            .into_vec()
-            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid transaction encoding", Some(e.to_string())))?;
+    async fn get_fee_rate_recommendations(&self, config: Option<Value>) -> RpcResult<Value> {
+        let blocks = match config.as_ref().and_then(|c| c.get("blocks")) {
+            Some(blocks) => blocks.as_u64()
+                .filter(|blocks| (1..=MAX_FEE_RATE_BLOCKS).contains(blocks))
+                .ok_or_else(|| ErrorObjectOwned::owned(
+                    -32602,
+                    "Invalid blocks",
+                    Some(format!("Expected 1 to {} blocks", MAX_FEE_RATE_BLOCKS))
+                ))?,
+            None => DEFAULT_FEE_RATE_BLOCKS,
+        };
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load fee rates", Some(e.to_string()))
+        };
 
-        let _tx: Transaction = bincode::deserialize(&tx_bytes)
-            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid transaction format", Some(e.to_string())))?;
+        let slot = self.slot(commitment(config.as_ref())?).map_err(internal_error)?;
+        let recommendations = self.sequencer.fee_rate_recommendations(slot, blocks).await.map_err(internal_error)?;
 
         Ok(serde_json::json!({
-            "value": {
-                "err": null,
-                "logs": [],
-                "accounts": null,
-                "unitsConsumed": 1000
+            "context": { "slot": slot },
+            "value": recommendations,
+        }))
+    }
+
+    async fn get_slot(&self, config: Option<Value>) -> RpcResult<u64> {
+        self.slot(commitment(config.as_ref())?)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load slot", Some(e.to_string())))
+    }
+
+    async fn get_block_height(&self, config: Option<Value>) -> RpcResult<u64> {
+        self.slot(commitment(config.as_ref())?)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load block height", Some(e.to_string())))
+    }
+
+    async fn get_block(&self, slot: u64, config: Option<Value>) -> RpcResult<Value> {
+        let commitment = commitment(config.as_ref())?;
+        let encoding = transaction_encoding(config.as_ref())?;
+        let options = block_encoding_options(config.as_ref())?;
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load block", Some(e.to_string()))
+        };
+        let not_available = || ErrorObjectOwned::owned(
+            BLOCK_NOT_AVAILABLE_CODE,
+            format!("Block not available for slot {}", slot),
+            None::<()>
+        );
+
+        if commitment == Commitment::Finalized && self.batch_processor.last_finalized_batch().is_none_or(|finalized| slot > finalized) {
+            return Err(not_available());
+        }
+        let Some((header, block)) = self.data_availability.get_block(&self.state_manager, slot).await.map_err(internal_error)? else {
+            return Err(not_available());
+        };
+        let encoded = block.encode_with_options(encoding, options)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Failed to encode block", Some(e.to_string())))?;
+
+        let mut block = serde_json::json!(encoded);
+        block["blockSeed"] = serde_json::json!(header.seed.to_string());
+        Ok(block)
+    }
+
+    async fn simulate_transaction(&self, transaction: String, config: Option<Value>) -> RpcResult<Value> {
+        let tx = decode_transaction(&transaction, binary_encoding(config.as_ref())?)?;
+        if config.as_ref().is_some_and(|c| c.get("rollupAtBatch").is_some()) {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid rollupAtBatch",
+                Some("Simulating at a past batch is served by simulateTransactionAtBatch".to_string())
+            ));
+        }
+
+        let simulation = self.transaction_processor.simulate(&tx).await;
+        let slot = self.slot(Commitment::Processed)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load latest block", Some(e.to_string())))?;
+
+        Ok(serde_json::json!({
+            "context": { "slot": slot },
+            "value": self.simulation_json(&tx, &simulation, &self.state_manager, config.as_ref()).await?,
+        }))
+    }
+
+    async fn simulate_transaction_at_batch(&self, transaction: String, batch_id: u64, config: Option<Value>) -> RpcResult<Value> {
+        let tx = decode_transaction(&transaction, binary_encoding(config.as_ref())?)?;
+        self.simulate_at_batch(&tx, batch_id, config.as_ref()).await
+    }
+
+    async fn get_signature_statuses(&self, signatures: Vec<String>, _config: Option<Value>) -> RpcResult<Value> {
+        if signatures.len() > MAX_SIGNATURE_STATUSES {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Too many inputs provided",
+                Some(format!("At most {} signatures per request", MAX_SIGNATURE_STATUSES))
+            ));
+        }
+        let signatures = signatures.iter()
+            .map(|signature| signature.parse::<Signature>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid signature", Some(e.to_string())))?;
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load signature statuses", Some(e.to_string()))
+        };
+
+        // Batches are the rollup's slots; executed transactions wait for the open one
+        let open_batch = self.state_manager.batch_count().map_err(internal_error)?;
+        let mut statuses = Vec::with_capacity(signatures.len());
+        for signature in &signatures {
+            let Some(record) = self.state_manager.get_transaction(signature).map_err(internal_error)? else {
+                statuses.push(Value::Null);
+                continue;
+            };
+            let stage = self.transaction_stage(&record).map_err(internal_error)?;
+            let slot = record.sealed.map_or(open_batch, |sealed| sealed.batch_id);
+
+            statuses.push(serde_json::json!({
+                "slot": slot,
+                "confirmations": (stage != TransactionStage::Finalized).then(|| open_batch.saturating_sub(slot)),
+                "err": record.error,
+                "status": match &record.error {
+                    Some(error) => serde_json::json!({ "Err": error }),
+                    None => serde_json::json!({ "Ok": null }),
+                },
+                "confirmationStatus": stage.confirmation_status(),
+                "rollupStage": stage,
+            }));
+        }
+
+        Ok(self.attest("getSignatureStatuses", serde_json::json!({
+            "context": { "slot": open_batch.saturating_sub(1) },
+            "value": statuses,
+        })))
+    }
+
+    async fn get_transaction(&self, signature: String, config: Option<Value>) -> RpcResult<Option<Value>> {
+        let signature = signature.parse::<Signature>()
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid signature", Some(e.to_string())))?;
+        let commitment = commitment(config.as_ref())?;
+        let encoding = transaction_encoding(config.as_ref())?;
+        let max_supported_transaction_version = match config.as_ref().and_then(|c| c.get("maxSupportedTransactionVersion")) {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid maxSupportedTransactionVersion", Some(e.to_string())))?,
+            None => None,
+        };
+
+        let record = self.state_manager.get_transaction(&signature)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load transaction", Some(e.to_string())))?;
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let stage = self.transaction_stage(&record)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load transaction", Some(e.to_string())))?;
+        if commitment == Commitment::Finalized && stage != TransactionStage::Finalized {
+            return Ok(None);
+        }
+
+        let encoded = record.encode(encoding, max_supported_transaction_version)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Failed to encode transaction", Some(e.to_string())))?;
+
+        Ok(encoded.map(|encoded| self.attest("getTransaction", serde_json::json!(encoded))))
+    }
+
+    async fn get_address_activity(&self, pubkey: String, cursor: Option<u64>, limit: Option<usize>) -> RpcResult<Value> {
+        let pubkey = pubkey.parse::<Pubkey>()
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+        let limit = limit.unwrap_or(DEFAULT_ACTIVITY_LIMIT).min(MAX_ACTIVITY_LIMIT);
+
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load address activity", Some(e.to_string()))
+        };
+        let activity = self.state_manager.address_activity(&pubkey, cursor, limit).map_err(internal_error)?;
+        let stages = activity.iter()
+            .map(|(_, record)| self.transaction_stage(record))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(internal_error)?;
+
+        // A short page means there is nothing older
+        let next_cursor = (activity.len() == limit).then(|| activity.last().map(|(sequence, _)| *sequence)).flatten();
+        Ok(serde_json::json!({
+            "activity": activity.iter().zip(stages)
+                .map(|((sequence, record), stage)| serde_json::json!({
+                    "cursor": sequence,
+                    "signature": record.transaction.signatures.first().map(|s| s.to_string()),
+                    "slot": record.sealed.map(|sealed| sealed.batch_id),
+                    "blockTime": record.sealed.map(|sealed| sealed.block_time),
+                    "confirmationStatus": stage.confirmation_status(),
+                    "entries": record.activity(&pubkey),
+                }))
+                .collect::<Vec<_>>(),
+            "nextCursor": next_cursor,
+        }))
+    }
+
+    async fn get_batch(&self, batch_id: u64) -> RpcResult<Option<Value>> {
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load batch", Some(e.to_string()))
+        };
+        let Some((batch, source)) = self.data_availability.get_batch(&self.state_manager, batch_id).await.map_err(internal_error)? else {
+            return Ok(None);
+        };
+        let pre_state_root = self.state_manager.pre_state_root(batch_id).map_err(internal_error)?;
+
+        Ok(Some(self.attest("getBatch", serde_json::json!({
+            "batchId": batch_id,
+            "preStateRoot": hex::encode(pre_state_root),
+            "stateRoot": hex::encode(batch.state_root),
+            "transactions": batch.transactions.iter()
+                .map(|tx| bs58::encode(tx.wire_bytes()).into_string())
+                .collect::<Vec<_>>(),
+            "source": source,
+        }))))
+    }
+
+    async fn get_batch_report(&self, batch_id: u64) -> RpcResult<Option<Value>> {
+        let report = self.state_manager.get_batch_report(batch_id)
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load batch report", Some(e.to_string())))?;
+
+        Ok(report.map(|r| serde_json::json!(r)))
+    }
+
+    async fn get_deposit(&self, deposit_id: u64) -> RpcResult<Option<Value>> {
+        let deposit = self.state_manager.get_deposit(deposit_id).map_err(|e| {
+            ErrorObjectOwned::owned(-32000, "Failed to load deposit", Some(e.to_string()))
+        })?;
+
+        Ok(deposit.map(|d| {
+            serde_json::json!({
+                "id": d.id,
+                "l1Signature": d.l1_signature,
+                "l1Slot": d.l1_slot,
+                "l1Sender": d.l1_sender.to_string(),
+                "l2Recipient": d.l2_recipient.to_string(),
+                "lamports": d.lamports,
+                "batchId": d.batch_id,
+            })
+        }))
+    }
+
+    async fn get_token_metadata(&self, mints: Option<Vec<String>>) -> RpcResult<Value> {
+        let Some(mints) = mints else {
+            return Ok(serde_json::json!(self.tokens.tokens()));
+        };
+
+        let mut tokens = Vec::with_capacity(mints.len());
+        for mint in mints {
+            let mint = mint.parse::<Pubkey>()
+                .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?;
+            tokens.push(self.tokens.get(&mint));
+        }
+
+        Ok(serde_json::json!(tokens))
+    }
+
+    async fn request_withdrawal(&self, request: Value) -> RpcResult<Value> {
+        let params: WithdrawalRequestParams = serde_json::from_value(request)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid withdrawal request", Some(e.to_string())))?;
+        let invalid = |e: String| ErrorObjectOwned::owned(-32602, "Invalid withdrawal request", Some(e));
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to queue withdrawal", Some(e.to_string()))
+        };
+
+        let request = WithdrawalRequest {
+            l2_sender: params.l2_sender.parse().map_err(|e| invalid(format!("l2Sender: {}", e)))?,
+            l1_recipient: params.l1_recipient.parse().map_err(|e| invalid(format!("l1Recipient: {}", e)))?,
+            lamports: params.lamports,
+            nonce: params.nonce,
+        };
+        let signature = params.signature.parse::<Signature>().map_err(|e| invalid(format!("signature: {}", e)))?;
+        if request.lamports == 0 {
+            return Err(invalid("lamports must be positive".to_string()));
+        }
+        let genesis_hash = self.state_manager.genesis_hash().map_err(internal_error)?
+            .ok_or_else(|| internal_error(anyhow::anyhow!("State database has no genesis hash")))?;
+        if !request.verify(&genesis_hash, &signature).map_err(internal_error)? {
+            return Err(invalid("signature does not verify for l2Sender".to_string()));
+        }
+        // Checked again when the block is sealed; this just fails fast
+        let balance = self.state_manager.get_account(&request.l2_sender).await.map_or(0, |a| a.lamports);
+        if balance < request.lamports {
+            return Err(ErrorObjectOwned::owned(
+                -32002,
+                "Insufficient funds for withdrawal",
+                Some(format!("{} has {} lamports, {} requested", request.l2_sender, balance, request.lamports))
+            ));
+        }
+
+        let entry = self.state_manager.queue_withdrawal(&request, &signature).map_err(internal_error)?;
+        if entry.status == WithdrawalStatus::Pending {
+            metrics::increment("withdrawals_requested");
+        }
+
+        Ok(withdrawal_json(&entry))
+    }
+
+    async fn get_withdrawal(&self, withdrawal_id: u64) -> RpcResult<Option<Value>> {
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load withdrawal", Some(e.to_string()))
+        };
+
+        let Some(entry) = self.state_manager.get_withdrawal(withdrawal_id).map_err(internal_error)? else {
+            return Ok(None);
+        };
+        let mut withdrawal = withdrawal_json(&entry);
+        if let WithdrawalStatus::Sealed { batch_id, index } = entry.status {
+            let withdrawals = self.state_manager.get_batch_withdrawals(batch_id).map_err(internal_error)?
+                .unwrap_or_default();
+            let hasher = self.state_manager.hasher();
+            let proof = withdrawal_proof(hasher, &withdrawals, index).map_err(internal_error)?;
+            withdrawal["leaf"] = serde_json::json!(hex::encode(entry.record.leaf(hasher).map_err(internal_error)?));
+            withdrawal["proof"] = serde_json::json!(proof.iter().map(hex::encode).collect::<Vec<_>>());
+            withdrawal["withdrawalRoot"] = serde_json::json!(hex::encode(withdrawal_root(hasher, &withdrawals).map_err(internal_error)?));
+
+            // Claimable once the batch is finalized on L1 and the challenge
+            // window since the slot it landed in has passed
+            let landed = self.state_manager.get_batch_report(batch_id).map_err(internal_error)?
+                .and_then(|report| report.final_slot);
+            let claimable_from = landed.map(|slot| slot + self.withdrawal_challenge_slots);
+            let finalized_slot = self.batch_processor.l1_watcher().and_then(|w| w.finalized()).map(|f| f.slot);
+            let batch_finalized = self.batch_processor.last_finalized_batch().is_some_and(|finalized| finalized >= batch_id);
+            withdrawal["l1Slot"] = serde_json::json!(landed);
+            withdrawal["claimableFromSlot"] = serde_json::json!(claimable_from);
+            withdrawal["claimable"] = serde_json::json!(
+                batch_finalized && claimable_from.zip(finalized_slot).is_some_and(|(from, finalized)| finalized >= from)
+            );
+        }
+
+        Ok(Some(withdrawal))
+    }
+
+    async fn get_version(&self) -> RpcResult<Value> {
+        let genesis_hash = self.state_manager.genesis_hash()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load genesis hash", Some(e.to_string())))?;
+
+        Ok(serde_json::json!({
+            "solana-core": SOLANA_RPC_VERSION,
+            "feature-set": null,
+            "rollup": {
+                "nodeVersion": env!("CARGO_PKG_VERSION"),
+                "protocolVersion": PROTOCOL_VERSION,
+                "genesisHash": genesis_hash.map(|hash| bs58::encode(hash).into_string()),
+                "rollupProgramId": self.batch_processor.rollup_program_id().to_string(),
+                "sequencer": self.batch_processor.authority().to_string(),
+                "identity": self.attestor.as_ref().map(|a| a.identity().to_string()),
+            }
+        }))
+    }
+
+    async fn get_metrics(&self) -> RpcResult<Value> {
+        Ok(serde_json::json!({
+            "counters": metrics::counters(),
+            "gauges": metrics::gauges(),
+            "rpcLatency": latency::summaries(),
+        }))
+    }
+
+    async fn get_epoch_info(&self, _config: Option<Value>) -> RpcResult<Value> {
+        let batch_count = self.state_manager.batch_count()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load epoch info", Some(e.to_string())))?;
+
+        // Batches are the rollup's slots and blocks
+        let current_batch = batch_count.saturating_sub(1);
+        let epoch = self.epochs.epoch(current_batch);
+
+        Ok(serde_json::json!({
+            "absoluteSlot": current_batch,
+            "blockHeight": current_batch,
+            "epoch": epoch,
+            "slotIndex": current_batch - self.epochs.first_batch(epoch),
+            "slotsInEpoch": self.epochs.batches_per_epoch,
+            "transactionCount": null,
+        }))
+    }
+
+    async fn get_program_stats(&self, epoch: Option<u64>) -> RpcResult<Value> {
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load program stats", Some(e.to_string()))
+        };
+        let current_batch = self.state_manager.batch_count().map_err(internal_error)?.saturating_sub(1);
+        let current_epoch = self.epochs.epoch(current_batch);
+        let epoch = epoch.unwrap_or(current_epoch);
+        if epoch > current_epoch {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid epoch",
+                Some(format!("The current epoch is {}", current_epoch))
+            ));
+        }
+
+        let first_slot = self.epochs.first_batch(epoch);
+        let last_slot = self.epochs.last_batch(epoch).min(current_batch);
+        let mut programs: Vec<_> = self.state_manager.program_stats(first_slot, last_slot)
+            .map_err(internal_error)?
+            .into_iter()
+            .collect();
+        programs.sort_by(|(a_id, a), (b_id, b)| b.compute_units.cmp(&a.compute_units).then(a_id.cmp(b_id)));
+        let compute_units: u64 = programs.iter().map(|(_, stats)| stats.compute_units).sum();
+
+        Ok(serde_json::json!({
+            "epoch": epoch,
+            "firstSlot": first_slot,
+            "lastSlot": last_slot,
+            "computeUnits": compute_units,
+            "programs": programs.iter().map(|(program_id, stats)| serde_json::json!({
+                "programId": program_id.to_string(),
+                "invocations": stats.invocations,
+                "failures": stats.failures,
+                "failureRate": stats.failure_rate(),
+                "computeUnits": stats.compute_units,
+                "computeUnitShare": if compute_units == 0 { 0.0 } else { stats.compute_units as f64 / compute_units as f64 },
+            })).collect::<Vec<_>>(),
+        }))
+    }
+
+    async fn get_highest_snapshot_slot(&self) -> RpcResult<Value> {
+        // Newest first, and exports without a slot sort last
+        match self.snapshots().await?.first().and_then(|snapshot| snapshot.manifest.slot) {
+            Some(slot) => Ok(serde_json::json!({ "full": slot, "incremental": null })),
+            None => Err(ErrorObjectOwned::owned(NO_SNAPSHOT, "No snapshot", None::<()>)),
+        }
+    }
+
+    async fn get_snapshots(&self) -> RpcResult<Value> {
+        let snapshots = self.snapshots().await?;
+
+        Ok(Value::Array(snapshots.iter().map(|snapshot| serde_json::json!({
+            "name": snapshot.name,
+            "slot": snapshot.manifest.slot,
+            "stateRoot": snapshot.manifest.state_root,
+            "genesisHash": snapshot.manifest.genesis_hash,
+            "accounts": snapshot.accounts(),
+            "chunks": snapshot.manifest.chunks.len(),
+            "bytes": snapshot.bytes,
+            "downloadUrl": self.snapshot_base_url.as_ref().map(|base_url| format!("{}/{}/", base_url, snapshot.name)),
+        })).collect()))
+    }
+
+    async fn get_health(&self) -> RpcResult<String> {
+        let reason = self.batch_processor.health().degraded_reason()
+            .or_else(|| self.load_shedder.as_ref().and_then(|load_shedder| load_shedder.pressure_reason()));
+        match reason {
+            Some(reason) => Err(ErrorObjectOwned::owned(NODE_UNHEALTHY, "Node is unhealthy", Some(reason))),
+            None => Ok("ok".to_string()),
+        }
+    }
+
+    async fn get_rollup_status(&self) -> RpcResult<Value> {
+        let internal_error = |e: anyhow::Error| {
+            ErrorObjectOwned::owned(-32000, "Failed to load rollup status", Some(e.to_string()))
+        };
+
+        let last_posted = self.state_manager.latest_batch_report(false).map_err(internal_error)?;
+        let last_confirmed = self.state_manager.latest_batch_report(true).map_err(internal_error)?;
+        let pending_withdrawals = self.state_manager.pending_withdrawal_count().map_err(internal_error)?;
+        let pending_deposits = self.state_manager.pending_deposits().map_err(internal_error)?.len();
+        let last_epoch = self.state_manager.latest_epoch_record().map_err(internal_error)?;
+        let mempool_depth = self.sequencer.pending_count().await;
+
+        // L1 being unreachable is a status to report, not an RPC failure
+        let authority_balance = self.batch_processor.authority_balance().await.ok();
+        let degraded_reason = self.batch_processor.health().degraded_reason();
+
+        Ok(serde_json::json!({
+            "stateRoot": hex::encode(self.state_manager.get_state_root()),
+            "lastPostedBatch": last_posted.as_ref().map(|r| serde_json::json!({
+                "batchId": r.batch_id,
+                "error": r.error,
+            })),
+            "lastConfirmedBatch": last_confirmed.as_ref().map(|r| serde_json::json!({
+                "batchId": r.batch_id,
+                "slot": r.final_slot,
+            })),
+            "lastFinalizedBatch": self.batch_processor.last_finalized_batch(),
+            "l1Finalized": self.batch_processor.l1_watcher().and_then(|w| w.finalized()).map(|f| serde_json::json!({
+                "slot": f.slot,
+                "blockhash": f.blockhash.to_string(),
+            })),
+            "lastClosedEpoch": last_epoch.as_ref().map(|r| serde_json::json!({
+                "epoch": r.epoch,
+                "checkpointRoot": hex::encode(r.checkpoint_root),
+                "anchorSignature": r.anchor_signature,
+                "rentCollected": r.rent_collected,
+                "feesDistributed": r.fees_distributed,
+                "accountsReaped": r.accounts_reaped,
+            })),
+            "authority": self.batch_processor.authority().to_string(),
+            "authorityBalance": authority_balance,
+            "mempoolDepth": mempool_depth,
+            "pendingWithdrawals": pending_withdrawals,
+            "pendingDeposits": pending_deposits,
+            "health": {
+                "l1Reachable": authority_balance.is_some(),
+                "authorityFunded": authority_balance.is_some_and(|b| b > 0),
+                "lastBatchFailed": last_posted.as_ref().is_some_and(|r| r.error.is_some()),
+                "degraded": degraded_reason.is_some(),
+                "degradedReason": degraded_reason,
+                "resourcePressure": self.load_shedder.as_ref().and_then(|load_shedder| load_shedder.pressure_reason()),
             }
         }))
     }
 
-    async fn get_transaction(&self, _signature: String, _config: Option<Value>) -> RpcResult<Option<Value>> {
-        Ok(None)
+    async fn get_pending_batch(&self) -> RpcResult<Value> {
+        let pending = self.sequencer.pending_batch().await
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to load pending batch", Some(e.to_string())))?;
+
+        Ok(serde_json::json!(pending))
     }
+
+    async fn signature_subscribe(
+        &self,
+        pending: PendingSubscriptionSink,
+        signature: String,
+        _config: Option<Value>
+    ) -> SubscriptionResult {
+        let signature = match signature.parse::<Signature>() {
+            Ok(signature) => signature,
+            Err(e) => {
+                pending.reject(ErrorObjectOwned::owned(-32602, "Invalid signature", Some(e.to_string()))).await;
+                return Ok(());
+            }
+        };
+
+        // Subscribe before accepting so no event can slip in between
+        let mut events = self.state_manager.events().subscribe();
+        let sink = pending.accept().await?;
+
+        let value = loop {
+            tokio::select! {
+                _ = sink.closed() => return Ok(()),
+                event = events.recv() => match event {
+                    Ok(event) if event.signature() != Some(&signature) => continue,
+                    Ok(RollupEvent::Transaction { stage: TransactionStage::Processed, .. }) => continue,
+                    Ok(RollupEvent::TransactionDropped { reason, .. }) => break serde_json::json!({
+                        "err": "TransactionDropped",
+                        "dropReason": reason,
+                    }),
+                    // A transaction that failed after paying its fee is sealed too
+                    Ok(_) => {
+                        let record = self.state_manager.get_transaction(&signature).ok().flatten();
+                        break serde_json::json!({ "err": record.and_then(|record| record.error) });
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        };
+        let notification = serde_json::json!({
+            "context": { "slot": 0 },
+            "value": value,
+        });
+        sink.send(to_json_raw_value(&notification)?).await?;
+
+        Ok(())
+    }
+
+    async fn account_subscribe(
+        &self,
+        pending: PendingSubscriptionSink,
+        pubkey: String,
+        config: Option<Value>
+    ) -> SubscriptionResult {
+        let pubkey = match pubkey.parse::<Pubkey>() {
+            Ok(pubkey) => pubkey,
+            Err(e) => {
+                pending.reject(ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string()))).await;
+                return Ok(());
+            }
+        };
+        let encoding = match account_encoding(config.as_ref()) {
+            Ok(encoding) => encoding,
+            Err(e) => {
+                pending.reject(e).await;
+                return Ok(());
+            }
+        };
+
+        let mut events = self.state_manager.events().subscribe();
+        let sink = pending.accept().await?;
+
+        loop {
+            let account = tokio::select! {
+                _ = sink.closed() => return Ok(()),
+                event = events.recv() => match event {
+                    Ok(RollupEvent::Account { pubkey: written, account }) if written == pubkey => account,
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            };
+
+            let account = account.unwrap_or_else(|| L2Account {
+                lamports: 0,
+                data: vec![],
+                owner: Pubkey::default(),
+                executable: false,
+                rent_epoch: 0,
+            });
+            let value = self.accounts_json([Some(&account)], None, encoding)?.remove(0);
+            // The write lands in the open batch, the slot it will be sealed into
+            let slot = self.state_manager.batch_count()?;
+            let notification = serde_json::json!({
+                "context": { "slot": slot },
+                "value": value,
+            });
+            sink.send(to_json_raw_value(&notification)?).await?;
+        }
+    }
+
+    async fn slot_subscribe(&self, pending: PendingSubscriptionSink, config: Option<Value>) -> SubscriptionResult {
+        let from_slot = match self.replay_cursor(config.as_ref()) {
+            Ok(from_slot) => from_slot,
+            Err(e) => {
+                pending.reject(e).await;
+                return Ok(());
+            }
+        };
+
+        let events = self.state_manager.events().subscribe();
+        let sink = pending.accept().await?;
+
+        self.follow_blocks(&sink, events, from_slot, BlockFeed::Slots).await
+    }
+
+    async fn block_subscribe(&self, pending: PendingSubscriptionSink, filter: Value, config: Option<Value>) -> SubscriptionResult {
+        let options = (|| {
+            let mentions = match &filter {
+                Value::String(all) if all == "all" => None,
+                _ => match filter.get("mentionsAccountOrProgram").and_then(Value::as_str) {
+                    Some(pubkey) => Some(pubkey.parse::<Pubkey>()
+                        .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid pubkey", Some(e.to_string())))?),
+                    None => return Err(ErrorObjectOwned::owned(
+                        -32602,
+                        "Invalid filter",
+                        Some("Expected \"all\" or { \"mentionsAccountOrProgram\": <pubkey> }")
+                    )),
+                },
+            };
+            let encoding = transaction_encoding(config.as_ref())?;
+            let options = block_encoding_options(config.as_ref())?;
+            Ok((mentions, encoding, options, self.replay_cursor(config.as_ref())?))
+        })();
+        let (mentions, encoding, options, from_slot) = match options {
+            Ok(options) => options,
+            Err(e) => {
+                pending.reject(e).await;
+                return Ok(());
+            }
+        };
+
+        let events = self.state_manager.events().subscribe();
+        let sink = pending.accept().await?;
+
+        self.follow_blocks(&sink, events, from_slot, BlockFeed::Blocks { mentions, encoding, options }).await
+    }
+
+    async fn block_stream_subscribe(&self, pending: PendingSubscriptionSink, from_slot: u64) -> SubscriptionResult {
+        let feed = (|| {
+            let Some(attestor) = self.attestor.clone() else {
+                return Err(ErrorObjectOwned::owned(
+                    -32000,
+                    "Block stream unavailable",
+                    Some("The node signs its block stream with its identity key; start it with --identity-keypair")
+                ));
+            };
+            let internal_error = |e: anyhow::Error| {
+                ErrorObjectOwned::owned(-32000, "Failed to load chain metadata", Some(e.to_string()))
+            };
+            let genesis_hash = self.state_manager.genesis_hash().map_err(internal_error)?
+                .ok_or_else(|| internal_error(anyhow::anyhow!("State database has no genesis hash")))?;
+            let next_slot = self.state_manager.batch_count().map_err(internal_error)?;
+            if from_slot > next_slot {
+                return Err(ErrorObjectOwned::owned(
+                    -32602,
+                    "Invalid fromSlot",
+                    Some(format!("The next block to be sealed is {}", next_slot))
+                ));
+            }
+            let first_stored = self.state_manager.first_stored_diff().map_err(internal_error)?;
+            if from_slot < first_stored {
+                return Err(ErrorObjectOwned::owned(
+                    -32602,
+                    "Invalid fromSlot",
+                    Some(format!(
+                        "Blocks below slot {} were pruned with their state diffs; start the replica from a copy of this node's database",
+                        first_stored
+                    ))
+                ));
+            }
+            Ok(BlockFeed::Frames { attestor, genesis_hash })
+        })();
+        let feed = match feed {
+            Ok(feed) => feed,
+            Err(e) => {
+                pending.reject(e).await;
+                return Ok(());
+            }
+        };
+
+        let events = self.state_manager.events().subscribe();
+        let sink = pending.accept().await?;
+
+        self.follow_blocks(&sink, events, Some(from_slot), feed).await
+    }
+}
+
+/// Error data for a failed transaction. Execution errors carry the Solana
+/// `TransactionError` as `err`, like L1 preflight failures, so SDK error
+/// matching works, plus the rollup's own structured error.
+fn error_data(e: &anyhow::Error) -> Value {
+    match e.downcast_ref::<ExecutionError>() {
+        Some(err) => serde_json::json!({
+            "err": err.transaction_error(),
+            "logs": [],
+            "rollupError": err,
+            "message": e.to_string(),
+        }),
+        None => Value::String(e.to_string()),
+    }
+}
+
+/// `err` of a simulation result: the Solana `TransactionError` when there is one.
+fn transaction_error(e: &anyhow::Error) -> Value {
+    match e.downcast_ref::<ExecutionError>() {
+        Some(err) => serde_json::json!(err.transaction_error()),
+        None => Value::String(e.to_string()),
+    }
+}
+
+fn commitment(config: Option<&Value>) -> RpcResult<Commitment> {
+    match config.and_then(|c| c.get("commitment")) {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid commitment", Some(e.to_string()))),
+        None => Ok(Commitment::default()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct DataSlice {
+    offset: usize,
+    length: usize,
+}
+
+fn withdrawal_json(entry: &WithdrawalEntry) -> Value {
+    let mut withdrawal = serde_json::json!({
+        "id": entry.record.id,
+        "l2Sender": entry.record.l2_sender.to_string(),
+        "l1Recipient": entry.record.l1_recipient.to_string(),
+        "lamports": entry.record.lamports,
+    });
+    match &entry.status {
+        WithdrawalStatus::Pending => withdrawal["status"] = serde_json::json!("pending"),
+        WithdrawalStatus::Sealed { batch_id, index } => {
+            withdrawal["status"] = serde_json::json!("sealed");
+            withdrawal["batchId"] = serde_json::json!(batch_id);
+            withdrawal["leafIndex"] = serde_json::json!(index);
+        }
+        WithdrawalStatus::Rejected { reason } => {
+            withdrawal["status"] = serde_json::json!("rejected");
+            withdrawal["reason"] = serde_json::json!(reason);
+        }
+    }
+    withdrawal
+}
+
+/// rollup_requestWithdrawal's request: the `WithdrawalRequest` fields, with
+/// pubkeys and the sender's signature over its message in base58.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawalRequestParams {
+    l2_sender: String,
+    l1_recipient: String,
+    lamports: u64,
+    #[serde(default)]
+    nonce: u64,
+    signature: String,
+}
+
+/// `encoding` of a transaction read; Solana defaults to `json`.
+fn transaction_encoding(config: Option<&Value>) -> RpcResult<UiTransactionEncoding> {
+    match config.and_then(|c| c.get("encoding")) {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid encoding", Some(e.to_string()))),
+        None => Ok(UiTransactionEncoding::Json),
+    }
+}
+
+/// `encoding` of a submitted transaction: base58 unless the config asks
+/// for base64 (or the legacy `binary`, which is base58).
+fn binary_encoding(config: Option<&Value>) -> RpcResult<TransactionBinaryEncoding> {
+    let Some(value) = config.and_then(|c| c.get("encoding")) else {
+        return Ok(TransactionBinaryEncoding::Base58);
+    };
+    let encoding: UiTransactionEncoding = serde_json::from_value(value.clone())
+        .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid encoding", Some(e.to_string())))?;
+
+    encoding.into_binary_encoding()
+        .ok_or_else(|| ErrorObjectOwned::owned(
+            -32602,
+            "Invalid encoding",
+            Some(format!("Unsupported transaction encoding {}, expected base58 or base64", encoding))
+        ))
+}
+
+/// `transactionDetails`, `rewards` and `maxSupportedTransactionVersion` of
+/// a block read, with Solana's defaults.
+fn block_encoding_options(config: Option<&Value>) -> RpcResult<BlockEncodingOptions> {
+    let field = |name: &str| config.and_then(|c| c.get(name)).cloned();
+    let invalid = |name: &'static str| move |e: serde_json::Error| {
+        ErrorObjectOwned::owned(-32602, format!("Invalid {}", name), Some(e.to_string()))
+    };
+
+    Ok(BlockEncodingOptions {
+        transaction_details: match field("transactionDetails") {
+            Some(value) => serde_json::from_value(value).map_err(invalid("transactionDetails"))?,
+            None => TransactionDetails::Full,
+        },
+        show_rewards: match field("rewards") {
+            Some(value) => serde_json::from_value(value).map_err(invalid("rewards"))?,
+            None => true,
+        },
+        max_supported_transaction_version: match field("maxSupportedTransactionVersion") {
+            Some(value) => serde_json::from_value(value).map_err(invalid("maxSupportedTransactionVersion"))?,
+            None => None,
+        },
+    })
+}
+
+/// `encoding` of an account read. Defaults to base58, which `binary` also
+/// means but answered as a bare string, as Solana did before encodings were
+/// tagged.
+fn account_encoding(config: Option<&Value>) -> RpcResult<UiAccountEncoding> {
+    match config.and_then(|c| c.get("encoding")) {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid encoding", Some(e.to_string()))),
+        None => Ok(UiAccountEncoding::Base58),
+    }
+}
+
+/// Solana's `minContextSlot`: fail rather than answer from a slot older than
+/// the client has already seen.
+fn check_min_context_slot(config: Option<&Value>, slot: u64) -> RpcResult<()> {
+    let min_context_slot: Option<u64> = match config.and_then(|c| c.get("minContextSlot")) {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid minContextSlot", Some(e.to_string())))?,
+        None => None,
+    };
+    if let Some(min_context_slot) = min_context_slot
+        && slot < min_context_slot
+    {
+        return Err(ErrorObjectOwned::owned(
+            MIN_CONTEXT_SLOT_NOT_REACHED_CODE,
+            "Minimum context slot has not been reached",
+            Some(serde_json::json!({ "contextSlot": slot }))
+        ));
+    }
+
+    Ok(())
+}
+
+fn data_slice(config: Option<&Value>) -> RpcResult<Option<DataSlice>> {
+    match config.and_then(|c| c.get("dataSlice")) {
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid dataSlice", Some(e.to_string()))),
+        None => Ok(None),
+    }
+}
+
+/// A transaction as submitted to sendTransaction or simulateTransaction.
+fn decode_transaction(encoded: &str, encoding: TransactionBinaryEncoding) -> RpcResult<WireTransaction> {
+    let (name, max_len) = match encoding {
+        TransactionBinaryEncoding::Base58 => ("base58", MAX_BASE58_TRANSACTION_LEN),
+        TransactionBinaryEncoding::Base64 => ("base64", MAX_BASE64_TRANSACTION_LEN),
+    };
+    if encoded.len() > max_len {
+        return Err(ErrorObjectOwned::owned(
+            -32602,
+            "Invalid transaction encoding",
+            Some(format!("{} {} characters, more than the {} limit", encoded.len(), name, max_len))
+        ));
+    }
+    let tx_bytes = match encoding {
+        TransactionBinaryEncoding::Base58 => bs58::decode(encoded).into_vec().map_err(|e| e.to_string()),
+        TransactionBinaryEncoding::Base64 => BASE64_STANDARD.decode(encoded).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid transaction encoding", Some(e)))?;
+
+    WireTransaction::from_bytes(tx_bytes)
+        .map_err(|e| ErrorObjectOwned::owned(-32602, "Invalid transaction format", Some(e.to_string())))
+}
+
+/// getProgramAccounts `filters`, with memcmp bytes decoded up front.
+fn program_account_filters(config: Option<&Value>) -> RpcResult<Vec<RpcFilterType>> {
+    let Some(value) = config.and_then(|c| c.get("filters")) else {
+        return Ok(vec![]);
+    };
+    let invalid = |e: String| ErrorObjectOwned::owned(-32602, "Invalid filters", Some(e));
+
+    let mut filters: Vec<RpcFilterType> = serde_json::from_value(value.clone())
+        .map_err(|e| invalid(e.to_string()))?;
+    if filters.len() > MAX_PROGRAM_ACCOUNT_FILTERS {
+        return Err(ErrorObjectOwned::owned(
+            -32602,
+            format!("Too many filters provided; max {}", MAX_PROGRAM_ACCOUNT_FILTERS),
+            None::<()>
+        ));
+    }
+    for filter in &mut filters {
+        filter.verify().map_err(|e| invalid(e.to_string()))?;
+        if let RpcFilterType::Memcmp(memcmp) = filter {
+            memcmp.convert_to_raw_bytes().map_err(|e| invalid(e.to_string()))?;
+        }
+    }
+
+    Ok(filters)
+}
+
+/// Like Solana, a slice past the end of the data is truncated rather than rejected.
+fn slice_data(data: &[u8], slice: Option<DataSlice>) -> &[u8] {
+    match slice {
+        Some(slice) => {
+            let start = slice.offset.min(data.len());
+            let end = start.saturating_add(slice.length).min(data.len());
+            &data[start..end]
+        }
+        None => data,
+    }
+}
+
+fn account_json(account: &L2Account, data: Value) -> Value {
+    serde_json::json!({
+        "data": data,
+        "executable": account.executable,
+        "lamports": account.lamports,
+        "owner": account.owner.to_string(),
+        "rentEpoch": account.rent_epoch
+    })
 }
@@ -0,0 +1,80 @@
+use std::{future::Future, sync::Arc};
+
+use jsonrpsee::{
+    server::{
+        middleware::rpc::{Batch, Notification, RpcServiceT},
+        MethodResponse,
+    },
+    types::{ErrorObject, Request},
+};
+
+use crate::{load_shedding::load_shedding::LoadShedder, metrics::metrics, rpc_server::batch_limits::MethodCosts};
+
+/// Answered to calls refused while shedding load, as getHealth does
+const NODE_UNHEALTHY: i32 = -32005;
+
+/// Refuses heavy calls (those costing more than an ordinary one, see
+/// `MethodCosts`) while the node is under resource pressure, so reads that
+/// scan many accounts don't add to it. Everything else, including
+/// sendTransaction, is still served. Sits inside `BatchLimitLayer`, so
+/// batch items are refused one by one.
+#[derive(Clone)]
+pub struct SheddingLayer {
+    shedder: Arc<LoadShedder>,
+    costs: Arc<MethodCosts>,
+}
+
+impl SheddingLayer {
+    pub fn new(shedder: Arc<LoadShedder>, costs: MethodCosts) -> Self {
+        Self { shedder, costs: Arc::new(costs) }
+    }
+}
+
+impl<S> tower::Layer<S> for SheddingLayer {
+    type Service = Shedding<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Shedding { inner, layer: self.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct Shedding<S> {
+    inner: S,
+    layer: SheddingLayer,
+}
+
+impl<S> RpcServiceT for Shedding<S>
+where
+    S: RpcServiceT<MethodResponse = MethodResponse, BatchResponse = MethodResponse, NotificationResponse = MethodResponse>
+        + Send + Sync + Clone + 'static,
+{
+    type MethodResponse = MethodResponse;
+    type NotificationResponse = MethodResponse;
+    type BatchResponse = MethodResponse;
+
+    fn call<'a>(&self, request: Request<'a>) -> impl Future<Output = Self::MethodResponse> + Send + 'a {
+        let inner = self.inner.clone();
+        let reason = self.layer.costs.is_heavy(request.method_name())
+            .then(|| self.layer.shedder.pressure_reason())
+            .flatten();
+
+        async move {
+            match reason {
+                Some(reason) => {
+                    metrics::increment("rpc_calls_shed");
+                    MethodResponse::error(request.id, ErrorObject::owned(NODE_UNHEALTHY, "Node is shedding load", Some(reason)))
+                }
+                None => inner.call(request).await,
+            }
+        }
+    }
+
+    fn batch<'a>(&self, batch: Batch<'a>) -> impl Future<Output = Self::BatchResponse> + Send + 'a {
+        self.inner.batch(batch)
+    }
+
+    fn notification<'a>(&self, n: Notification<'a>) -> impl Future<Output = Self::NotificationResponse> + Send + 'a {
+        self.inner.notification(n)
+    }
+}
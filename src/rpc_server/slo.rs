@@ -0,0 +1,102 @@
+use std::{collections::HashSet, future::Future, sync::Arc, time::Instant};
+
+use jsonrpsee::{
+    server::middleware::rpc::{Batch, Notification, RpcServiceT},
+    types::Request,
+};
+
+use crate::metrics::latency::{self, SloTargets};
+
+/// Characters of params a slow-query log entry keeps
+const PARAMS_SUMMARY_CHARS: usize = 120;
+
+/// Batches are timed as a whole under this name
+const BATCH_METHOD: &str = "batch";
+
+/// Times every RPC call against its method's SLO target, for the latency
+/// percentiles in getMetrics, and logs calls that miss it.
+#[derive(Debug, Clone)]
+pub struct SloLayer {
+    targets: Arc<SloTargets>,
+    /// Only registered methods are tracked, so clients can't grow the
+    /// latency table with made-up names
+    methods: Arc<HashSet<&'static str>>,
+}
+
+impl SloLayer {
+    pub fn new(targets: SloTargets, methods: impl IntoIterator<Item = &'static str>) -> Self {
+        Self { targets: Arc::new(targets), methods: Arc::new(methods.into_iter().collect()) }
+    }
+}
+
+impl<S> tower::Layer<S> for SloLayer {
+    type Service = Slo<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Slo { inner, layer: self.clone() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Slo<S> {
+    inner: S,
+    layer: SloLayer,
+}
+
+impl<S> RpcServiceT for Slo<S>
+where
+    S: RpcServiceT + Send + Sync + Clone + 'static,
+{
+    type MethodResponse = S::MethodResponse;
+    type NotificationResponse = S::NotificationResponse;
+    type BatchResponse = S::BatchResponse;
+
+    fn call<'a>(&self, request: Request<'a>) -> impl Future<Output = Self::MethodResponse> + Send + 'a {
+        let method = self.layer.methods.get(request.method_name()).copied();
+        let params = method.map(|_| summarize(request.params().as_str().unwrap_or("[]")));
+        let targets = self.layer.targets.clone();
+        let inner = self.inner.clone();
+
+        async move {
+            let started = Instant::now();
+            let response = inner.call(request).await;
+            if let (Some(method), Some(params)) = (method, params) {
+                observe(&targets, method, &params, started);
+            }
+            response
+        }
+    }
+
+    fn batch<'a>(&self, batch: Batch<'a>) -> impl Future<Output = Self::BatchResponse> + Send + 'a {
+        let params = summarize(&format!("{} calls", batch.len()));
+        let targets = self.layer.targets.clone();
+        let inner = self.inner.clone();
+
+        async move {
+            let started = Instant::now();
+            let response = inner.batch(batch).await;
+            observe(&targets, BATCH_METHOD, &params, started);
+            response
+        }
+    }
+
+    fn notification<'a>(&self, n: Notification<'a>) -> impl Future<Output = Self::NotificationResponse> + Send + 'a {
+        self.inner.notification(n)
+    }
+}
+
+fn observe(targets: &SloTargets, method: &'static str, params: &str, started: Instant) {
+    let elapsed = started.elapsed();
+    let slo = targets.target(method);
+    if latency::record(method, elapsed, slo) {
+        eprintln!("🐢 Slow RPC call: {} {} took {:?} (SLO {:?})", method, params, elapsed, slo);
+    }
+}
+
+/// `params` cut short, since e.g. sendTransaction carries a whole transaction.
+fn summarize(params: &str) -> String {
+    match params.char_indices().nth(PARAMS_SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}…", &params[..end]),
+        None => params.to_string(),
+    }
+}
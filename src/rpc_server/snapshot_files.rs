@@ -0,0 +1,102 @@
+use std::{future::Future, path::PathBuf, pin::Pin, task::{Context, Poll}};
+
+use http::{header, Method, StatusCode};
+use jsonrpsee::server::{HttpBody, HttpRequest, HttpResponse};
+use tower::{Layer, Service};
+
+use crate::state_export::state_export::list_snapshots;
+
+/// Path snapshots are served under, next to JSON-RPC:
+/// `/snapshots/<NAME>/manifest.json` and the chunk files it lists.
+pub const SNAPSHOTS_PATH: &str = "/snapshots";
+
+/// Serves the files of complete exports in a snapshot directory over plain
+/// HTTP, so new nodes can download a bootstrap point from a running one.
+/// Only the manifest and the chunks it lists are served. Every other
+/// request passes through to JSON-RPC.
+#[derive(Clone)]
+pub struct SnapshotFilesLayer {
+    dir: PathBuf,
+}
+
+impl SnapshotFilesLayer {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl<S> Layer<S> for SnapshotFilesLayer {
+    type Service = SnapshotFiles<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SnapshotFiles { inner, dir: self.dir.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct SnapshotFiles<S> {
+    inner: S,
+    dir: PathBuf,
+}
+
+impl<S, B> Service<HttpRequest<B>> for SnapshotFiles<S>
+where
+    S: Service<HttpRequest<B>, Response = HttpResponse>,
+    S::Future: Send + 'static,
+{
+    type Response = HttpResponse;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: HttpRequest<B>) -> Self::Future {
+        let path = request.uri().path();
+        if request.method() == Method::GET
+            && let Some(file_path) = path.strip_prefix(SNAPSHOTS_PATH).and_then(|rest| rest.strip_prefix('/'))
+        {
+            let dir = self.dir.clone();
+            let file_path = file_path.to_string();
+            return Box::pin(async move { Ok(serve(dir, &file_path).await) });
+        }
+
+        Box::pin(self.inner.call(request))
+    }
+}
+
+/// `<NAME>/<FILE>`, if `NAME` is a complete snapshot and `FILE` one of its files.
+async fn serve(dir: PathBuf, file_path: &str) -> HttpResponse {
+    let Some((name, file)) = file_path.split_once('/') else {
+        return error(StatusCode::NOT_FOUND, format!("{} is not a snapshot file", file_path));
+    };
+    let snapshots = match tokio::task::spawn_blocking({
+        let dir = dir.clone();
+        move || list_snapshots(&dir)
+    }).await {
+        Ok(Ok(snapshots)) => snapshots,
+        Ok(Err(e)) => return error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list snapshots: {}", e)),
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list snapshots: {}", e)),
+    };
+    if !snapshots.iter().any(|snapshot| snapshot.name == name && snapshot.contains(file)) {
+        return error(StatusCode::NOT_FOUND, format!("{} is not a snapshot file", file_path));
+    }
+
+    match tokio::fs::read(dir.join(name).join(file)).await {
+        Ok(data) => {
+            let content_type = if file.ends_with(".json") { "application/json" } else { "application/octet-stream" };
+            let mut response = HttpResponse::new(HttpBody::from(data));
+            response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(content_type));
+            response
+        }
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read {}: {}", file_path, e)),
+    }
+}
+
+fn error(status: StatusCode, message: String) -> HttpResponse {
+    let mut response = HttpResponse::new(HttpBody::from(serde_json::json!({ "error": message }).to_string()));
+    *response.status_mut() = status;
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+    response
+}
@@ -0,0 +1,155 @@
+use std::{future::Future, pin::Pin, task::{Context, Poll}, time::Duration};
+
+use bytes::Bytes;
+use http::{header, Method, StatusCode};
+use http_body_util::channel::Channel;
+use jsonrpsee::server::{HttpBody, HttpRequest, HttpResponse};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use tokio::sync::broadcast::error::RecvError;
+use tower::{Layer, Service};
+
+use crate::events::events::{EventBus, EventKind, RollupEvent};
+
+/// Path the event stream is served on, next to JSON-RPC
+pub const EVENTS_PATH: &str = "/events";
+
+/// How often an idle stream sends a comment, so proxies keep it open and a
+/// client that went away is noticed
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Frames buffered for a client that reads slower than events arrive
+const STREAM_BUFFER: usize = 64;
+
+/// What a client asked to see, from the query string:
+/// `types=block,transaction,account` (all when absent), and any number of
+/// `account=<pubkey>` and `signature=<signature>` values. Accounts and
+/// signatures narrow transaction and account events to the ones matching at
+/// least one of them; blocks are never narrowed.
+#[derive(Debug, Default)]
+struct EventFilter {
+    kinds: Vec<EventKind>,
+    accounts: Vec<Pubkey>,
+    signatures: Vec<Signature>,
+}
+
+impl EventFilter {
+    fn from_query(query: &str) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, values) = pair.split_once('=').unwrap_or((pair, ""));
+            for value in values.split(',').filter(|value| !value.is_empty()) {
+                match key {
+                    "types" => filter.kinds.push(value.parse()?),
+                    "account" => filter.accounts.push(value.parse().map_err(|e| format!("invalid account {}: {}", value, e))?),
+                    "signature" => filter.signatures.push(value.parse().map_err(|e| format!("invalid signature {}: {}", value, e))?),
+                    _ => return Err(format!("unknown parameter {}; expected types, account or signature", key)),
+                }
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn matches(&self, event: &RollupEvent) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&event.kind()) {
+            return false;
+        }
+        if event.kind() == EventKind::Block || (self.accounts.is_empty() && self.signatures.is_empty()) {
+            return true;
+        }
+
+        event.signature().is_some_and(|signature| self.signatures.contains(signature))
+            || self.accounts.iter().any(|account| event.involves(account))
+    }
+}
+
+/// Serves `GET /events` as a server-sent event stream of rollup events and
+/// passes every other request through to JSON-RPC.
+#[derive(Debug, Clone)]
+pub struct SseLayer {
+    events: EventBus,
+}
+
+impl SseLayer {
+    pub fn new(events: EventBus) -> Self {
+        Self { events }
+    }
+}
+
+impl<S> Layer<S> for SseLayer {
+    type Service = Sse<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Sse { inner, events: self.events.clone() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sse<S> {
+    inner: S,
+    events: EventBus,
+}
+
+impl<S> Sse<S> {
+    fn stream(&self, query: Option<&str>) -> HttpResponse {
+        let filter = match EventFilter::from_query(query.unwrap_or_default()) {
+            Ok(filter) => filter,
+            Err(e) => return response(StatusCode::BAD_REQUEST, "text/plain", HttpBody::from(e)),
+        };
+
+        // Subscribe before responding so nothing published meanwhile is missed
+        let mut events = self.events.subscribe();
+        let (mut sender, body) = Channel::<Bytes>::new(STREAM_BUFFER);
+        tokio::spawn(async move {
+            let mut keepalive = tokio::time::interval(KEEPALIVE_INTERVAL);
+            loop {
+                let frame = tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) if filter.matches(&event) => format!("event: {}\ndata: {}\n\n", event.name(), event.to_json()),
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(skipped)) => format!("event: lagged\ndata: {{\"skipped\":{}}}\n\n", skipped),
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = keepalive.tick() => ": keepalive\n\n".to_string(),
+                };
+                // Fails once the client disconnects and the body is dropped
+                if sender.send_data(Bytes::from(frame)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        response(StatusCode::OK, "text/event-stream", HttpBody::new(body))
+    }
+}
+
+impl<S, B> Service<HttpRequest<B>> for Sse<S>
+where
+    S: Service<HttpRequest<B>, Response = HttpResponse>,
+    S::Future: Send + 'static,
+{
+    type Response = HttpResponse;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: HttpRequest<B>) -> Self::Future {
+        if request.method() == Method::GET && request.uri().path() == EVENTS_PATH {
+            let response = self.stream(request.uri().query());
+            return Box::pin(async move { Ok(response) });
+        }
+
+        Box::pin(self.inner.call(request))
+    }
+}
+
+fn response(status: StatusCode, content_type: &'static str, body: HttpBody) -> HttpResponse {
+    let mut response = HttpResponse::new(body);
+    *response.status_mut() = status;
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(content_type));
+    response.headers_mut().insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-cache"));
+    response
+}
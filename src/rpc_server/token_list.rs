@@ -0,0 +1,91 @@
+use std::{future::Future, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+use http::{header, Method, StatusCode};
+use jsonrpsee::server::{HttpBody, HttpRequest, HttpResponse};
+use solana_sdk::pubkey::Pubkey;
+use tower::{Layer, Service};
+
+use crate::token_registry::token_registry::TokenRegistry;
+
+/// Path the token list is served on, next to JSON-RPC. `/tokens/<MINT>`
+/// serves a single token.
+pub const TOKENS_PATH: &str = "/tokens";
+
+/// Serves the token registry over plain HTTP, in the Solana token list
+/// format, for wallets that load token lists by URL. Every other request
+/// passes through to JSON-RPC.
+#[derive(Clone)]
+pub struct TokenListLayer {
+    tokens: Arc<TokenRegistry>,
+}
+
+impl TokenListLayer {
+    pub fn new(tokens: Arc<TokenRegistry>) -> Self {
+        Self { tokens }
+    }
+}
+
+impl<S> Layer<S> for TokenListLayer {
+    type Service = TokenList<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TokenList { inner, tokens: self.tokens.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct TokenList<S> {
+    inner: S,
+    tokens: Arc<TokenRegistry>,
+}
+
+impl<S> TokenList<S> {
+    fn respond(&self, path: &str) -> HttpResponse {
+        if path == TOKENS_PATH {
+            let list = serde_json::json!({ "tokens": self.tokens.tokens() });
+            return response(StatusCode::OK, list.to_string());
+        }
+
+        let mint = path.trim_start_matches(TOKENS_PATH).trim_start_matches('/');
+        match mint.parse::<Pubkey>() {
+            Ok(mint) => match self.tokens.get(&mint) {
+                Some(token) => response(StatusCode::OK, serde_json::json!(token).to_string()),
+                None => response(StatusCode::NOT_FOUND, serde_json::json!({ "error": format!("{} is not registered", mint) }).to_string()),
+            },
+            Err(e) => response(StatusCode::BAD_REQUEST, serde_json::json!({ "error": format!("invalid mint {}: {}", mint, e) }).to_string()),
+        }
+    }
+}
+
+impl<S, B> Service<HttpRequest<B>> for TokenList<S>
+where
+    S: Service<HttpRequest<B>, Response = HttpResponse>,
+    S::Future: Send + 'static,
+{
+    type Response = HttpResponse;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: HttpRequest<B>) -> Self::Future {
+        let path = request.uri().path();
+        if request.method() == Method::GET
+            && (path == TOKENS_PATH || path.starts_with(&format!("{}/", TOKENS_PATH)))
+        {
+            let response = self.respond(path);
+            return Box::pin(async move { Ok(response) });
+        }
+
+        Box::pin(self.inner.call(request))
+    }
+}
+
+fn response(status: StatusCode, body: String) -> HttpResponse {
+    let mut response = HttpResponse::new(HttpBody::from(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+    response
+}
@@ -0,0 +1,37 @@
+use jsonrpsee::{RpcModule, core::RegisterMethodError, types::ErrorObjectOwned};
+
+/// Error code for Solana RPC methods that exist but have no meaning on the rollup.
+pub const UNSUPPORTED_METHOD_CODE: i32 = -32050;
+
+/// Staking, voting and inflation methods. The rollup has a single sequencer
+/// and no stake, so there is nothing to report.
+const STAKING_METHODS: [&str; 7] = [
+    "getStakeMinimumDelegation",
+    "getStakeActivation",
+    "getVoteAccounts",
+    "getInflationGovernor",
+    "getInflationRate",
+    "getInflationReward",
+    "getLeaderSchedule",
+];
+
+/// Answers known-but-unsupported methods with an explicit error instead of
+/// method-not-found, so generic Solana tooling can tell the two apart.
+pub fn unsupported_methods() -> Result<RpcModule<()>, RegisterMethodError> {
+    let mut module = RpcModule::new(());
+
+    for method in STAKING_METHODS {
+        module.register_method(method, move |_, _, _| {
+            Err::<(), _>(ErrorObjectOwned::owned(
+                UNSUPPORTED_METHOD_CODE,
+                format!("{} is not supported on this rollup", method),
+                Some(serde_json::json!({
+                    "method": method,
+                    "reason": "The rollup is run by a single sequencer and has no staking or voting",
+                }))
+            ))
+        })?;
+    }
+
+    Ok(module)
+}
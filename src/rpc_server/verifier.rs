@@ -0,0 +1,134 @@
+use std::sync::{Arc, Mutex};
+
+use jsonrpsee::{
+    core::{RpcResult, async_trait},
+    proc_macros::rpc,
+    types::ErrorObjectOwned,
+};
+use serde_json::Value;
+use tokio::sync::Semaphore;
+
+use crate::{
+    replay::{dispute::{AccountWitness, DisputeData, build_dispute_data}, replay::ExecutionConfig},
+    state_manager::state_manager::StateManager,
+};
+
+/// Dispute data kept for the batches asked about most recently; a batch's
+/// dispute data never changes once it is built
+const DISPUTE_CACHE_ENTRIES: usize = 16;
+
+/// Methods only served when the node runs with `--verifier`.
+#[rpc(server)]
+pub trait VerifierRpc {
+    #[method(name = "getDisputeData")]
+    async fn get_dispute_data(&self, batch_id: u64) -> RpcResult<Value>;
+}
+
+pub struct VerifierRpcImpl {
+    state_manager: Arc<StateManager>,
+    execution: ExecutionConfig,
+    max_batches: u64,
+    replays: Semaphore,
+    /// Built dispute data, least recently asked for first
+    cache: Mutex<Vec<Arc<DisputeData>>>,
+}
+
+impl VerifierRpcImpl {
+    /// `execution` is how the chain executes, so batches replay as they did.
+    /// Building dispute data replays from genesis, so only batches below
+    /// `max_batches` can be disputed.
+    pub fn new(state_manager: Arc<StateManager>, execution: ExecutionConfig, max_batches: u64) -> Self {
+        Self {
+            state_manager,
+            execution,
+            max_batches,
+            replays: Semaphore::new(1),
+            cache: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn cached(&self, batch_id: u64) -> Option<Arc<DisputeData>> {
+        let mut cache = self.cache.lock().unwrap();
+        let position = cache.iter().position(|dispute| dispute.batch_id == batch_id)?;
+        let dispute = cache.remove(position);
+        cache.push(dispute.clone());
+        Some(dispute)
+    }
+
+    fn remember(&self, dispute: Arc<DisputeData>) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= DISPUTE_CACHE_ENTRIES {
+            cache.remove(0);
+        }
+        cache.push(dispute);
+    }
+
+    /// Dispute data for `batch_id`, from the cache or built by a replay.
+    /// Replays are capped in length and run one at a time; the rest fail as
+    /// busy.
+    async fn dispute_data(&self, batch_id: u64) -> RpcResult<Arc<DisputeData>> {
+        if let Some(dispute) = self.cached(batch_id) {
+            return Ok(dispute);
+        }
+        if batch_id >= self.max_batches {
+            return Err(ErrorObjectOwned::owned(
+                -32602,
+                "Invalid batchId",
+                Some(format!("Dispute data replays at most {} batches, so it can't reach batch {}", self.max_batches, batch_id))
+            ));
+        }
+        let Ok(_replay) = self.replays.try_acquire() else {
+            return Err(ErrorObjectOwned::owned(
+                -32000,
+                "Dispute data busy",
+                Some("Dispute data for another batch is being built".to_string())
+            ));
+        };
+
+        let dispute = build_dispute_data(&self.state_manager, &self.execution, batch_id).await
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to build dispute data", Some(e.to_string())))?;
+        let dispute = Arc::new(dispute);
+        self.remember(dispute.clone());
+
+        Ok(dispute)
+    }
+}
+
+#[async_trait]
+impl VerifierRpcServer for VerifierRpcImpl {
+    async fn get_dispute_data(&self, batch_id: u64) -> RpcResult<Value> {
+        let dispute = self.dispute_data(batch_id).await?;
+        let challenge_data = dispute.challenge_data()
+            .map_err(|e| ErrorObjectOwned::owned(-32000, "Failed to encode challenge data", Some(e.to_string())))?;
+
+        Ok(serde_json::json!({
+            "batchId": dispute.batch_id,
+            "fraudulent": dispute.is_fraudulent(),
+            "preStateRoot": hex::encode(dispute.pre_state_root),
+            "preState": witnesses_json(&dispute.pre_state),
+            "transactions": dispute.transactions.iter()
+                .map(|tx| bs58::encode(tx).into_string())
+                .collect::<Vec<_>>(),
+            "claimedPostStateRoot": hex::encode(dispute.claimed_post_state_root),
+            "expectedPostStateRoot": hex::encode(dispute.expected_post_state_root),
+            "postState": witnesses_json(&dispute.post_state),
+            "challengeData": [bs58::encode(challenge_data).into_string(), "base58"],
+        }))
+    }
+}
+
+fn witnesses_json(witnesses: &[AccountWitness]) -> Vec<Value> {
+    witnesses.iter()
+        .map(|w| serde_json::json!({
+            "pubkey": w.pubkey.to_string(),
+            "account": w.account.as_ref().map(|account| serde_json::json!({
+                "data": [bs58::encode(&account.data).into_string(), "base58"],
+                "executable": account.executable,
+                "lamports": account.lamports,
+                "owner": account.owner.to_string(),
+                "rentEpoch": account.rent_epoch
+            })),
+            "proof": w.proof.iter().map(hex::encode).collect::<Vec<_>>(),
+        }))
+        .collect()
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::{hash::Hash, pubkey::Pubkey};
+
+use crate::{
+    deposits::deposits::DepositRecord,
+    faucet::faucet::AirdropMint,
+    state_manager::state_manager::StateManager,
+    transaction_processor::{program_registry::ProgramChange, wire_transaction::WireTransaction},
+    withdrawals::withdrawals::WithdrawalRecord,
+};
+
+/// Slots a blockhash stays valid for, as on Solana
+pub const BLOCKHASH_VALIDITY_SLOTS: u64 = 150;
+
+/// Account holding the seed of the block being executed, laid out as a
+/// bincode `BlockSeed`. Like the real sysvars it only exists for the SVM.
+pub const BLOCK_SEED_SYSVAR_ID: Pubkey = Pubkey::from_str_const("SysvarB1ockSeed1111111111111111111111111111");
+
+/// Contents of the block seed sysvar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockSeed {
+    pub slot: u64,
+    pub seed: [u8; 32],
+}
+
+/// Seed of the block at `slot`. It only depends on the parent's blockhash
+/// and the slot, so it is known before the block's transactions run, and a
+/// replay derives the same one; the sequencer can't pick it without
+/// picking the parent block's contents.
+pub fn block_seed(slot: u64, parent_blockhash: &Hash) -> Hash {
+    let seed = Sha256::new()
+        .chain_update(b"tiny-rollup-seed")
+        .chain_update(parent_blockhash)
+        .chain_update(slot.to_be_bytes())
+        .finalize();
+
+    Hash::new_from_array(seed.into())
+}
+
+/// Header of an L2 block: the transactions the sequencer sealed together in
+/// one tick. Each block is chained to its parent through its blockhash and
+/// posted to L1 as the batch with the same id, so slots and batch ids are
+/// the same numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    pub slot: u64,
+    pub blockhash: Hash,
+    /// Blockhash of the previous block, or the chain's genesis hash for the
+    /// first one
+    pub parent_blockhash: Hash,
+    /// State root once the block's transactions executed
+    pub state_root: [u8; 32],
+    /// Commits to the block's transactions and their order
+    pub transactions_hash: Hash,
+    pub transaction_count: u64,
+    /// Unix time the block was sealed
+    pub block_time: i64,
+    /// `block_seed` of the block, which its programs could read
+    pub seed: Hash,
+}
+
+impl BlockHeader {
+    pub fn new(
+        slot: u64,
+        parent_blockhash: Hash,
+        state_root: [u8; 32],
+        transactions: &[WireTransaction],
+        block_time: i64
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        for tx in transactions {
+            hasher.update(tx.signatures.first().copied().unwrap_or_default());
+        }
+        let transactions_hash = Hash::new_from_array(hasher.finalize().into());
+
+        // The block time is left out so re-deriving a block from its batch
+        // gives the same hash
+        let blockhash = Sha256::new()
+            .chain_update(b"tiny-rollup-block")
+            .chain_update(slot.to_be_bytes())
+            .chain_update(parent_blockhash)
+            .chain_update(state_root)
+            .chain_update(transactions_hash)
+            .finalize();
+
+        Self {
+            slot,
+            blockhash: Hash::new_from_array(blockhash.into()),
+            parent_blockhash,
+            state_root,
+            transactions_hash,
+            transaction_count: transactions.len() as u64,
+            block_time,
+            seed: block_seed(slot, &parent_blockhash),
+        }
+    }
+}
+
+/// What a block applies besides executing its transactions.
+#[derive(Debug, Clone, Default)]
+pub struct BlockOperations {
+    /// Faucet mints, applied before the transactions, which start with the
+    /// airdrop transfers they pay for
+    pub airdrops: Vec<AirdropMint>,
+    /// Deposits credited to their L2 recipients after the transactions
+    pub deposits: Vec<DepositRecord>,
+    /// Withdrawals debited from their L2 senders, after the deposits
+    pub withdrawals: Vec<WithdrawalRecord>,
+    /// Program registrations, applied once the block is stored, so they
+    /// take effect from the next block on
+    pub programs: Vec<ProgramChange>,
+}
+
+impl BlockOperations {
+    /// The operations `source` recorded for the block at `slot`.
+    pub fn stored(source: &StateManager, slot: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            airdrops: source.get_batch_airdrops(slot)?,
+            deposits: source.get_batch_deposits(slot)?,
+            withdrawals: source.get_batch_withdrawals(slot)?.unwrap_or_default(),
+            programs: source.get_batch_program_changes(slot)?,
+        })
+    }
+}
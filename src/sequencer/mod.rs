@@ -1 +1,4 @@
+pub mod block;
 pub mod sequencer;
+pub mod snapshot;
+pub mod spam;
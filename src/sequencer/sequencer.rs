@@ -1,56 +1,974 @@
-use std::sync::Arc;
+use std::borrow::Borrow;
+use std::cmp::Reverse;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, RwLock};
-use tokio::time::interval;
 
-use solana_sdk::transaction::Transaction;
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature, transaction::Transaction};
 
-use crate::state_manager::state_manager::StateManager;
+use crate::{
+    batch_processor::{
+        batch_processor::{StoredBatch, BATCH_COMMITMENT_OVERHEAD, TRANSACTIONS_PAYLOAD_OVERHEAD},
+        compression::BatchCompression,
+    },
+    sequencer::{
+        block::{BlockHeader, BlockOperations},
+        snapshot::{MempoolEntry, MempoolSnapshot, MEMPOOL_SNAPSHOT_VERSION},
+        spam::SpamScorer,
+    },
+    chaos::chaos::{inject, FaultPoint},
+    faucet::faucet::AirdropMint,
+    clock::clock::{Clock, SystemClock},
+    metrics::metrics,
+    dead_letters::dead_letters::DeadLetterReason,
+    events::events::RollupEvent,
+    load_shedding::load_shedding::LoadShedder,
+    replay::audit::DeterminismAudit,
+    state_manager::state_manager::StateManager,
+    transaction_processor::{
+        transaction_processor::TransactionProcessor,
+        transaction_record::TransactionStage,
+        wire_transaction::WireTransaction,
+    },
+};
+
+/// How often a batch is sealed, unless configured otherwise
+const BATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How many times longer batches take to seal while the node sheds load,
+/// trading latency for fewer, fuller blocks
+const SHEDDING_INTERVAL_FACTOR: u32 = 3;
+
+/// Why a transaction left the mempool without being sealed into a batch.
+/// Transactions only execute as their block is built, so a dropped one
+/// changed no state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DropReason {
+    /// Waited longer than the mempool TTL
+    Expired,
+    /// Lowest priority transaction evicted while the mempool was at capacity
+    MempoolFull,
+    /// Failed to execute when its block was being built, against the state
+    /// the transactions ahead of it left
+    ExecutionFailed,
+}
+
+/// What to do when a transaction arrives and the mempool is full. Pending
+/// transactions haven't executed yet, so evicting one takes nothing back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum EvictionPolicy {
+    /// Turn the new transaction away
+    RejectNew,
+    /// Evict the pending transaction with the lowest effective priority
+    #[default]
+    EvictLowestFee,
+    /// Evict the transaction that has been pending longest
+    EvictOldest,
+}
+
+/// Mempool capacity limits.
+#[derive(Debug, Clone, Copy)]
+pub struct MempoolLimits {
+    pub max_pending: usize,
+    /// Pending transactions a single fee payer may have
+    pub max_pending_per_sender: usize,
+    pub eviction: EvictionPolicy,
+}
+
+#[derive(Debug, Clone)]
+struct PendingTransaction {
+    tx: WireTransaction,
+    compute_unit_price: u64,
+    received_at: Instant,
+    size: usize,
+}
+
+impl PendingTransaction {
+    /// Fee priority plus a bonus that grows with time spent waiting, so
+    /// low-fee transactions eventually outrank a sustained high-fee flood.
+    fn effective_priority(&self, now: Instant, aging_rate: u64) -> u64 {
+        let waited_secs = now.duration_since(self.received_at).as_secs();
+        self.compute_unit_price.saturating_add(waited_secs.saturating_mul(aging_rate))
+    }
+
+    fn signature(&self) -> Signature {
+        self.tx.signatures.first().copied().unwrap_or_default()
+    }
+
+    fn fee_payer(&self) -> Option<&Pubkey> {
+        self.tx.message.account_keys.first()
+    }
+}
+
+/// Pending transactions, with their signatures indexed, so a resubmission
+/// of one that is already pending is found without comparing against all.
+#[derive(Debug, Default)]
+struct Mempool {
+    transactions: Vec<PendingTransaction>,
+    signatures: HashSet<Signature>,
+}
+
+impl Mempool {
+    fn contains(&self, signature: &Signature) -> bool {
+        self.signatures.contains(signature)
+    }
+
+    fn push(&mut self, p: PendingTransaction) {
+        self.signatures.insert(p.signature());
+        self.transactions.push(p);
+    }
+
+    fn remove(&mut self, index: usize) -> PendingTransaction {
+        let p = self.transactions.remove(index);
+        self.signatures.remove(&p.signature());
+        p
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&PendingTransaction) -> bool) {
+        let signatures = &mut self.signatures;
+        self.transactions.retain(|p| {
+            let kept = keep(p);
+            if !kept {
+                signatures.remove(&p.signature());
+            }
+            kept
+        });
+    }
+
+    /// Reordering keeps every transaction, so the index stays valid.
+    fn as_mut_slice(&mut self) -> &mut [PendingTransaction] {
+        &mut self.transactions
+    }
+}
+
+impl std::ops::Deref for Mempool {
+    type Target = [PendingTransaction];
+
+    fn deref(&self) -> &Self::Target {
+        &self.transactions
+    }
+}
+
+/// A transaction slated for the next batch.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingBatchTransaction {
+    pub signature: String,
+    pub fee_payer: Option<String>,
+    pub compute_unit_price: u64,
+    /// Encoded size in bytes
+    pub size: usize,
+    pub waited_ms: u64,
+}
+
+/// What the next batch would seal if it were sealed now. Transactions that
+/// arrive before then with a higher priority can still displace these.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingBatch {
+    pub slot: u64,
+    /// Unix time in milliseconds
+    pub estimated_seal_time: u64,
+    /// Most bytes the batch commitment can take once its transactions are
+    /// compressed
+    pub estimated_bytes: usize,
+    pub max_batch_bytes: usize,
+    /// In sealing order
+    pub transactions: Vec<PendingBatchTransaction>,
+    /// Pending transactions left for later batches
+    pub deferred_count: usize,
+}
+
+/// Compute unit prices, in micro-lamports, a transaction can pay for a
+/// given chance of inclusion, from the prices paid in recent blocks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeRateRecommendations {
+    /// 25th percentile of recent included prices
+    pub low: u64,
+    /// Median of recent included prices
+    pub medium: u64,
+    /// 75th percentile of recent included prices, raised to
+    /// `next_block_minimum` when that is higher
+    pub high: u64,
+    /// What outbids the cheapest transaction slated for the next batch while
+    /// the mempool holds more than fits in it, otherwise 0. Aging can still
+    /// lift older transactions above it before the seal.
+    pub next_block_minimum: u64,
+    /// Blocks sampled, newest first from the latest one; pruned blocks are
+    /// skipped
+    pub blocks: u64,
+    /// Transactions the percentiles are taken over
+    pub transactions: usize,
+}
+
+/// A block built from the front of the ranked mempool.
+struct BuiltBlock {
+    /// Ranked transactions it went through, sealed or failed
+    examined: usize,
+    /// Positions among those of the ones that failed to execute, with why
+    failed: Vec<(usize, anyhow::Error)>,
+    /// None if no transaction executed and no deposit or withdrawal was
+    /// waiting, so there was nothing to seal
+    block: Option<(BlockHeader, StoredBatch)>,
+    batch_bytes: usize,
+}
 
 #[derive(Debug, Clone)]
 pub struct Sequencer {
-    pending_txs: Arc<RwLock<Vec<Transaction>>>,
-    batch_sender: mpsc::Sender<Vec<Transaction>>,
+    pending_txs: Arc<RwLock<Mempool>>,
+    /// Pending transactions the block being built may include. They stay
+    /// pending until it is built, but can't be replaced or evicted meanwhile
+    building: Arc<Mutex<HashSet<Signature>>>,
+    batch_sender: mpsc::Sender<(u64, StoredBatch)>,
+    /// Stored blocks the batch processor hasn't received yet, oldest first
+    unsent: Arc<tokio::sync::Mutex<VecDeque<(u64, StoredBatch)>>>,
+    aging_rate: u64,
+    max_batch_bytes: usize,
+    compression: BatchCompression,
+    ttl: Duration,
+    limits: MempoolLimits,
+    spam: Arc<Mutex<SpamScorer>>,
+    state_manager: Arc<StateManager>,
+    transaction_processor: Arc<TransactionProcessor>,
+    next_seal: Arc<Mutex<Instant>>,
+    load_shedder: Option<Arc<LoadShedder>>,
+    determinism_audit: Option<Arc<DeterminismAudit>>,
+    batch_interval: Duration,
+    clock: Arc<dyn Clock>,
 }
 
 impl Sequencer {
-    pub fn new(state_manager: Arc<StateManager>) -> (Self, mpsc::Receiver<Vec<Transaction>>) {
+    /// Transactions are executed by `transaction_processor` when their
+    /// block is built. `aging_rate` is the priority (micro-lamports per CU)
+    /// a pending transaction gains for every second it waits. Batches are
+    /// sealed once their encoded commitment would exceed `max_batch_bytes`.
+    /// Transactions pending longer than `ttl` are dropped, and `limits`
+    /// decides what happens at capacity. A fee payer's priority is scaled
+    /// down by its spam score.
+    pub fn new(
+        state_manager: Arc<StateManager>,
+        transaction_processor: Arc<TransactionProcessor>,
+        aging_rate: u64,
+        max_batch_bytes: usize,
+        ttl: Duration,
+        limits: MempoolLimits,
+        spam: SpamScorer
+    ) -> (Self, mpsc::Receiver<(u64, StoredBatch)>) {
         let (batch_sender, batch_receiver) = mpsc::channel(100);
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
 
         let sequencer = Self {
-            pending_txs: Arc::new(RwLock::new(Vec::new())),
+            pending_txs: Arc::new(RwLock::new(Mempool::default())),
+            building: Arc::new(Mutex::new(HashSet::new())),
             batch_sender,
+            unsent: Arc::new(tokio::sync::Mutex::new(VecDeque::new())),
+            aging_rate,
+            max_batch_bytes,
+            compression: BatchCompression::default(),
+            ttl,
+            limits,
+            spam: Arc::new(Mutex::new(spam)),
+            state_manager,
+            transaction_processor,
+            next_seal: Arc::new(Mutex::new(clock.now() + BATCH_INTERVAL)),
+            load_shedder: None,
+            determinism_audit: None,
+            batch_interval: BATCH_INTERVAL,
+            clock,
         };
 
         (sequencer, batch_receiver)
     }
 
-    pub async fn add_transaction(&self, tx: Transaction) {
+    /// Seals batches less often while `load_shedder` reports pressure.
+    pub fn with_load_shedder(mut self, load_shedder: Arc<LoadShedder>) -> Self {
+        self.load_shedder = Some(load_shedder);
+        self
+    }
+
+    /// Budgets batches for `compression`, the batch processor's, instead of
+    /// the default zstd.
+    pub fn with_compression(mut self, compression: BatchCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Seals a batch every `batch_interval` instead of every 2 seconds.
+    pub fn with_batch_interval(mut self, batch_interval: Duration) -> Self {
+        self.next_seal = Arc::new(Mutex::new(self.clock.now() + batch_interval));
+        self.batch_interval = batch_interval;
+        self
+    }
+
+    /// Executes every block again on `determinism_audit`'s shadow executor
+    /// before storing it.
+    pub fn with_determinism_audit(mut self, determinism_audit: Arc<DeterminismAudit>) -> Self {
+        self.determinism_audit = Some(determinism_audit);
+        self
+    }
+
+    /// Seals batches, expires and ages transactions and timestamps blocks by
+    /// `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.next_seal = Arc::new(Mutex::new(clock.now() + self.batch_interval));
+        self.clock = clock;
+        self
+    }
+
+    /// Rejects a transaction the mempool won't take: its fee payer already
+    /// has too many pending, or the mempool is full and rejects new ones.
+    pub async fn check_admission(&self, tx: &Transaction) -> anyhow::Result<()> {
+        let pending = self.pending_txs.read().await;
+        if self.limits.eviction == EvictionPolicy::RejectNew && pending.len() >= self.limits.max_pending {
+            metrics::increment("mempool_rejected_full");
+            anyhow::bail!("Mempool is full ({} pending transactions)", pending.len());
+        }
+
+        let Some(fee_payer) = tx.message.account_keys.first() else {
+            return Ok(());
+        };
+        let count = pending.iter().filter(|p| p.fee_payer() == Some(fee_payer)).count();
+        if count >= self.limits.max_pending_per_sender {
+            metrics::increment("mempool_rejected_sender_limit");
+            anyhow::bail!("{} already has {} pending transactions", fee_payer, count);
+        }
+
+        Ok(())
+    }
+
+    /// Rejects a transaction too large for any batch: alone, it would make
+    /// a commitment over the byte budget with compression at its worst.
+    pub fn check_size(&self, tx: &WireTransaction) -> anyhow::Result<()> {
+        let batch_bytes = self.commitment_bytes(TRANSACTIONS_PAYLOAD_OVERHEAD.saturating_add(tx.wire_bytes().len()));
+        if batch_bytes > self.max_batch_bytes {
+            metrics::increment("mempool_rejected_oversized");
+            anyhow::bail!(
+                "Transaction of {} bytes needs a {} byte batch, more than the {} byte budget",
+                tx.wire_bytes().len(), batch_bytes, self.max_batch_bytes
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Feeds the outcome of a signature-verified submission into the spam score.
+    pub fn record_outcome(&self, tx: &Transaction, failed: bool) {
+        if let Some(fee_payer) = tx.message.account_keys.first() {
+            self.spam.lock().unwrap().record(*fee_payer, failed, transfer_lamports(tx), self.clock.now());
+        }
+    }
+
+    /// Adds a preflighted transaction to the mempool as if it had been
+    /// waiting for `waited` already, which is zero unless it is restored
+    /// from a snapshot. Fails if the mempool turns it away; one that is
+    /// already pending stays as it is.
+    pub async fn add_transaction(&self, tx: WireTransaction, waited: Duration) -> anyhow::Result<()> {
         let mut pending = self.pending_txs.write().await;
-        pending.push(tx);
+        let now = self.clock.now();
+        let incoming = PendingTransaction {
+            compute_unit_price: compute_unit_price(&tx),
+            size: tx.wire_bytes().len(),
+            tx,
+            received_at: now.checked_sub(waited).unwrap_or(now),
+        };
+        if pending.contains(&incoming.signature()) {
+            return Ok(());
+        }
+
+        pending.push(incoming);
+
+        if pending.len() > self.limits.max_pending {
+            let now = self.clock.now();
+            // The incoming transaction is always evictable
+            let building = self.building.lock().unwrap();
+            let evictable = pending.iter()
+                .enumerate()
+                .filter(|(_, p)| !building.contains(&p.signature()));
+            let victim = match self.limits.eviction {
+                // Admission already checked, so only a race gets here
+                EvictionPolicy::RejectNew => Some(pending.len() - 1),
+                EvictionPolicy::EvictLowestFee => {
+                    let spam = self.spam.lock().unwrap();
+                    evictable
+                        .min_by_key(|(_, p)| self.ranked_priority(p, now, &spam))
+                        .map(|(index, _)| index)
+                }
+                EvictionPolicy::EvictOldest => evictable
+                    .min_by_key(|(_, p)| p.received_at)
+                    .map(|(index, _)| index),
+            };
+            drop(building);
+            match victim {
+                // Its submitter is told right away instead of notified
+                Some(index) if index == pending.len() - 1 => {
+                    pending.remove(index);
+                    metrics::increment("mempool_rejected_full");
+                    anyhow::bail!("Mempool is full ({} pending transactions)", pending.len());
+                }
+                Some(index) => {
+                    let evicted = pending.remove(index);
+                    self.publish_dropped(&evicted, DropReason::MempoolFull);
+                }
+                None => {}
+            }
+        }
+        metrics::set_gauge("mempool_depth", pending.len() as u64);
+
+        Ok(())
+    }
+
+    pub async fn pending_count(&self) -> usize {
+        self.pending_txs.read().await.len()
+    }
+
+    /// The transactions the next batch would seal if it were sealed now,
+    /// ranked and budgeted the same way.
+    pub async fn pending_batch(&self) -> anyhow::Result<PendingBatch> {
+        let pending = self.pending_txs.read().await;
+        let now = self.clock.now();
+        let mut ranked: Vec<&PendingTransaction> = pending.iter()
+            .filter(|p| now.duration_since(p.received_at) <= self.ttl)
+            .collect();
+        self.rank(&mut ranked, now);
+        let (batch_size, batch_bytes) = self.fill(&ranked);
+
+        let until_seal = self.next_seal.lock().unwrap().saturating_duration_since(now);
+        let estimated_seal_time = (self.clock.system_time() + until_seal).duration_since(UNIX_EPOCH)?.as_millis() as u64;
+
+        Ok(PendingBatch {
+            slot: self.state_manager.batch_count()?,
+            estimated_seal_time,
+            estimated_bytes: if batch_size > 0 { batch_bytes } else { 0 },
+            max_batch_bytes: self.max_batch_bytes,
+            transactions: ranked[..batch_size].iter()
+                .map(|p| PendingBatchTransaction {
+                    signature: p.signature().to_string(),
+                    fee_payer: p.fee_payer().map(|fee_payer| fee_payer.to_string()),
+                    compute_unit_price: p.compute_unit_price,
+                    size: p.size,
+                    waited_ms: now.duration_since(p.received_at).as_millis() as u64,
+                })
+                .collect(),
+            deferred_count: ranked.len() - batch_size,
+        })
+    }
+
+    /// Every live mempool transaction in sealing order, with the settings
+    /// that ranked them.
+    pub async fn mempool_snapshot(&self) -> anyhow::Result<MempoolSnapshot> {
+        let pending = self.pending_txs.read().await;
+        let now = self.clock.now();
+        let mut ranked: Vec<&PendingTransaction> = pending.iter()
+            .filter(|p| now.duration_since(p.received_at) <= self.ttl)
+            .collect();
+        self.rank(&mut ranked, now);
+        let (batch_size, _) = self.fill(&ranked);
+
+        let spam = self.spam.lock().unwrap();
+        let transactions = ranked.iter()
+            .enumerate()
+            .map(|(index, p)| MempoolEntry {
+                signature: p.signature().to_string(),
+                fee_payer: p.fee_payer().map(|fee_payer| fee_payer.to_string()),
+                compute_unit_price: p.compute_unit_price,
+                effective_priority: self.ranked_priority(p, now, &spam),
+                size: p.size,
+                waited_ms: now.duration_since(p.received_at).as_millis() as u64,
+                next_batch: index < batch_size,
+                transaction: BASE64_STANDARD.encode(p.tx.wire_bytes()),
+            })
+            .collect();
+
+        Ok(MempoolSnapshot {
+            format_version: MEMPOOL_SNAPSHOT_VERSION,
+            genesis_hash: self.state_manager.genesis_hash()?.map(|hash| bs58::encode(hash).into_string()),
+            slot: self.state_manager.batch_count()?,
+            taken_at: self.clock.system_time().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+            aging_rate: self.aging_rate,
+            max_batch_bytes: self.max_batch_bytes,
+            ttl_ms: self.ttl.as_millis() as u64,
+            transactions,
+        })
+    }
+
+    /// Recommendations from the transactions of up to `blocks` blocks up to
+    /// and including `slot`, and the mempool as it stands.
+    pub async fn fee_rate_recommendations(&self, slot: u64, blocks: u64) -> anyhow::Result<FeeRateRecommendations> {
+        let mut prices = Vec::new();
+        let mut sampled = 0;
+        for block in (slot + 1).saturating_sub(blocks)..=slot {
+            if let Some(batch) = self.state_manager.get_batch(block)? {
+                prices.extend(batch.transactions.iter().map(|tx| compute_unit_price(tx)));
+                sampled += 1;
+            }
+        }
+        prices.sort_unstable();
+
+        let pending = self.pending_batch().await?;
+        let next_block_minimum = match pending.transactions.iter().map(|tx| tx.compute_unit_price).min() {
+            Some(cheapest) if pending.deferred_count > 0 => cheapest.saturating_add(1),
+            _ => 0,
+        };
+
+        Ok(FeeRateRecommendations {
+            low: percentile(&prices, 25),
+            medium: percentile(&prices, 50),
+            high: percentile(&prices, 75).max(next_block_minimum),
+            next_block_minimum,
+            blocks: sampled,
+            transactions: prices.len(),
+        })
     }
 
     pub async fn start_batching(&self) {
-        let mut interval = interval(tokio::time::Duration::from_secs(2));
+        let mut next_seal = self.clock.now();
         loop {
-            interval.tick().await;
+            self.clock.sleep_until(next_seal).await;
+            next_seal += self.batch_interval();
+            *self.next_seal.lock().unwrap() = next_seal;
             self.create_batch().await;
         }
     }
 
+    fn batch_interval(&self) -> Duration {
+        match &self.load_shedder {
+            Some(load_shedder) if load_shedder.is_shedding() => self.batch_interval * SHEDDING_INTERVAL_FACTOR,
+            _ => self.batch_interval,
+        }
+    }
+
     async fn create_batch(&self) {
+        if let Some(audit) = &self.determinism_audit
+            && audit.halted().is_some()
+        {
+            return;
+        }
+        self.send_batches(None).await;
         let mut pending = self.pending_txs.write().await;
 
-        if pending.is_empty() {
+        let now = self.clock.now();
+        let ttl = self.ttl;
+        pending.retain(|p| {
+            let expired = now.duration_since(p.received_at) > ttl;
+            if expired {
+                self.publish_dropped(p, DropReason::Expired);
+            }
+            !expired
+        });
+
+        // Airdrops, deposits and withdrawals waiting to be applied seal a
+        // block on their own, so funds move even when nobody else is
+        // transacting
+        let operations_pending = match self.operations_pending() {
+            Ok(operations_pending) => operations_pending,
+            Err(e) => {
+                eprintln!("Failed to check for pending airdrops, deposits and withdrawals: {}", e);
+                false
+            }
+        };
+        if pending.is_empty() && !operations_pending {
+            return;
+        }
+
+        // Forget fee payers whose scores decayed away, once per tick
+        self.spam.lock().unwrap().prune(now);
+        self.rank(pending.as_mut_slice(), now);
+
+        // The block is built from a copy, with the mempool unlocked, so
+        // submissions and reads don't wait for it to execute
+        let ranked = pending.to_vec();
+        metrics::set_gauge("pipeline_execute_queue_depth", ranked.len() as u64);
+        *self.building.lock().unwrap() = ranked.iter().map(PendingTransaction::signature).collect();
+        drop(pending);
+
+        let built = {
+            let _blocks = self.state_manager.lock_blocks().await;
+            let built = match self.state_manager.begin_block() {
+                Ok(()) => self.produce_block(&ranked, operations_pending).await,
+                Err(e) => Err(e),
+            };
+            if !matches!(built, Ok(BuiltBlock { block: Some(_), .. })) {
+                self.state_manager.discard_block();
+            }
+            built
+        };
+
+        let mut pending = self.pending_txs.write().await;
+        self.building.lock().unwrap().clear();
+        let built = match built {
+            Ok(built) => built,
+            Err(e) => {
+                // Nothing was taken from the mempool; the next tick tries again
+                eprintln!("Failed to produce block: {}", e);
+                return;
+            }
+        };
+        let examined = &ranked[..built.examined];
+        metrics::add("pipeline_execute_processed", built.examined as u64);
+        let done: HashSet<Signature> = examined.iter().map(PendingTransaction::signature).collect();
+        pending.retain(|p| !done.contains(&p.signature()));
+        metrics::set_gauge("mempool_depth", pending.len() as u64);
+        drop(pending);
+        if !built.failed.is_empty() {
+            metrics::add("block_transactions_failed", built.failed.len() as u64);
+        }
+        for (index, error) in &built.failed {
+            self.publish_failed(&examined[*index], error);
+        }
+        let Some((header, batch)) = built.block else {
             return;
+        };
+        for tx in &batch.transactions {
+            self.state_manager.events().publish(|| RollupEvent::Transaction {
+                signature: tx.signatures.first().copied().unwrap_or_default(),
+                stage: TransactionStage::Sequenced,
+                accounts: tx.message.account_keys.clone(),
+            });
+        }
+
+        println!(
+            "Produced block {} ({}) with {} transactions ({} bytes)",
+            header.slot, header.blockhash, header.transaction_count, built.batch_bytes
+        );
+
+        self.send_batches(Some((header.slot, batch))).await;
+    }
+
+    /// Hands stored blocks to the batch processor in slot order, `next`
+    /// last. The block is already stored, so one that fails to send stays
+    /// queued with every block after it and goes again on the next tick,
+    /// instead of never being posted.
+    async fn send_batches(&self, next: Option<(u64, StoredBatch)>) {
+        let mut unsent = self.unsent.lock().await;
+        unsent.extend(next);
+        while let Some((slot, batch)) = unsent.pop_front() {
+            if let Err(e) = inject(FaultPoint::ChannelSend).await {
+                eprintln!("Failed to send batch {}, retrying next tick: {}", slot, e);
+                unsent.push_front((slot, batch));
+                break;
+            }
+            if let Err(mpsc::error::SendError(block)) = self.batch_sender.send((slot, batch)).await {
+                eprintln!("Failed to send batch {}, retrying next tick: the batch processor stopped", slot);
+                unsent.push_front(block);
+                break;
+            }
+        }
+        metrics::set_gauge("batches_unsent", unsent.len() as u64);
+        metrics::set_gauge(
+            "pipeline_commit_queue_depth",
+            (self.batch_sender.max_capacity() - self.batch_sender.capacity()) as u64
+        );
+    }
+
+    fn operations_pending(&self) -> anyhow::Result<bool> {
+        Ok(self.state_manager.has_pending_airdrops()?
+            || self.state_manager.has_pending_deposits()?
+            || self.state_manager.has_pending_withdrawals()?
+            || self.state_manager.has_pending_program_changes()?)
+    }
+
+    /// Builds the next block on top of the latest one and stores it,
+    /// together with the batch that carries it to L1. Queued airdrops are
+    /// minted and their transfers run first. The ranked `pending`
+    /// transactions are then executed in order against the block's state
+    /// until the byte budget is spent; those that fail before paying their
+    /// fee are left out. Queued
+    /// deposits are credited and queued withdrawals debited as part of the
+    /// block, after its transactions and before its state root is taken, and
+    /// queued program changes recorded with it, taking effect once it is
+    /// stored. Call with a block begun; it is only stored if one is returned.
+    async fn produce_block(&self, pending: &[PendingTransaction], operations_pending: bool) -> anyhow::Result<BuiltBlock> {
+        let parent_blockhash = match self.state_manager.latest_block()? {
+            Some(parent) => parent.blockhash,
+            None => self.state_manager.genesis_hash()?
+                .map(Hash::new_from_array)
+                .ok_or_else(|| anyhow::anyhow!("State database has no genesis hash"))?,
+        };
+        let slot = self.state_manager.batch_count()?;
+
+        let mut payload_bytes = TRANSACTIONS_PAYLOAD_OVERHEAD;
+        let mut transactions = Vec::new();
+        let airdrops = self.state_manager.pending_airdrops()?;
+        let mints: Vec<AirdropMint> = airdrops.iter().map(|airdrop| airdrop.mint.clone()).collect();
+        self.state_manager.mint_airdrops(slot, mints.clone()).await?;
+        for airdrop in airdrops {
+            // Funded exactly, so this only fails if the node is broken; the
+            // mint then stays with a payer nobody holds the key to
+            match self.transaction_processor.process_verified_transaction(&airdrop.transaction).await {
+                Ok(_) => {
+                    payload_bytes = payload_bytes.saturating_add(airdrop.transaction.wire_bytes().len());
+                    transactions.push(airdrop.transaction);
+                }
+                Err(e) => eprintln!("Airdrop {} failed to execute: {}", airdrop.mint.id, e),
+            }
+        }
+        if !mints.is_empty() {
+            println!("Minted {} airdrops in block {}", mints.len(), slot);
+        }
+
+        let mut failed = Vec::new();
+        let mut examined = 0;
+        for p in pending {
+            if !transactions.is_empty() && self.commitment_bytes(payload_bytes.saturating_add(p.size)) > self.max_batch_bytes {
+                break;
+            }
+            examined += 1;
+            match self.transaction_processor.process_verified_transaction(&p.tx).await {
+                // One that failed after paying its fee is sealed all the same
+                Ok(processed) => {
+                    if let Some(error) = processed.error {
+                        println!("Transaction {} failed to execute and only paid its fee: {}", processed.signature, error);
+                    }
+                    payload_bytes = payload_bytes.saturating_add(p.size);
+                    transactions.push(p.tx.clone());
+                }
+                Err(e) => failed.push((examined - 1, e)),
+            }
+        }
+        let batch_bytes = self.commitment_bytes(payload_bytes);
+        if transactions.is_empty() && !operations_pending {
+            return Ok(BuiltBlock { examined, failed, block: None, batch_bytes });
+        }
+
+        let deposits = self.state_manager.pending_deposits()?;
+        let credited = self.state_manager.credit_deposits(slot, deposits).await?;
+        if !credited.is_empty() {
+            metrics::add("deposits_credited", credited.len() as u64);
+            println!("Credited {} deposits in block {}", credited.len(), slot);
+        }
+        let sealed = self.state_manager.seal_withdrawals(slot).await?;
+        if !sealed.is_empty() {
+            metrics::add("withdrawals_sealed", sealed.len() as u64);
+            println!("Sealed {} withdrawals in block {}", sealed.len(), slot);
+        }
+        let programs = self.state_manager.pending_program_changes()?;
+        self.state_manager.record_program_changes(slot, &programs).await?;
+        let operations = BlockOperations { airdrops: mints, deposits: credited, withdrawals: sealed, programs };
+        let (state_root, diff) = self.state_manager.seal_state()?;
+        let batch = StoredBatch { transactions, state_root };
+        let block_time = self.clock.system_time().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let header = BlockHeader::new(slot, parent_blockhash, batch.state_root, &batch.transactions, block_time);
+        if let Some(audit) = &self.determinism_audit {
+            audit.check(&self.state_manager, &header, &batch, &operations).await?;
+        }
+
+        self.state_manager.put_block(&header, &batch, &diff)?;
+        self.state_manager.seal_snapshot(slot);
+        for change in &operations.programs {
+            match self.transaction_processor.programs().apply(change) {
+                Ok(_) => println!("Program {} {} from block {}", change.program_id, match change.handler {
+                    Some(handler) => format!("registered with the {:?} handler", handler),
+                    None => "unregistered".to_string(),
+                }, slot + 1),
+                Err(e) => eprintln!("Program change {} failed: {}", change.id, e),
+            }
         }
 
-        let batch_size = std::cmp::min(pending.len(), 100);
-        let batch: Vec<Transaction> = pending.drain(..batch_size).collect();
+        Ok(BuiltBlock { examined, failed, block: Some((header, batch)), batch_bytes })
+    }
+
+    /// Sorts `pending` highest effective priority first; the stable sort
+    /// keeps FIFO order on ties. Read-only, so previews rank the same way
+    /// without touching the spam scores.
+    fn rank<P: Borrow<PendingTransaction>>(&self, pending: &mut [P], now: Instant) {
+        let spam = self.spam.lock().unwrap();
+        pending.sort_by_key(|p| Reverse(self.ranked_priority(p.borrow(), now, &spam)));
+    }
+
+    /// How many of the ranked `pending` fit the byte budget, and the size of
+    /// the batch commitment they make. Compression is budgeted at its worst
+    /// case, since incompressible transactions come out slightly larger;
+    /// netted transfers only shrink the commitment further.
+    fn fill<P: Borrow<PendingTransaction>>(&self, pending: &[P]) -> (usize, usize) {
+        let mut payload_bytes = TRANSACTIONS_PAYLOAD_OVERHEAD;
+        let mut batch_size = 0;
+        for p in pending {
+            let size = p.borrow().size;
+            if batch_size > 0 && self.commitment_bytes(payload_bytes.saturating_add(size)) > self.max_batch_bytes {
+                break;
+            }
+            payload_bytes = payload_bytes.saturating_add(size);
+            batch_size += 1;
+        }
+
+        (batch_size, self.commitment_bytes(payload_bytes))
+    }
+
+    /// Most bytes a commitment carrying `payload_bytes` of serialized
+    /// transactions takes once they are compressed and framed.
+    fn commitment_bytes(&self, payload_bytes: usize) -> usize {
+        BATCH_COMMITMENT_OVERHEAD.saturating_add(self.compression.max_framed_len(payload_bytes))
+    }
+
+    /// Effective priority scaled down by the fee payer's spam score.
+    fn ranked_priority(&self, p: &PendingTransaction, now: Instant, spam: &SpamScorer) -> u64 {
+        let priority = p.effective_priority(now, self.aging_rate);
+        let score = p.fee_payer().map_or(0.0, |fee_payer| spam.score(fee_payer, now));
+        (priority as f64 * (1.0 - score)) as u64
+    }
 
-        println!("Creating batch with {} transactions", batch.len());
+    fn publish_failed(&self, p: &PendingTransaction, error: &anyhow::Error) {
+        println!("Dropping transaction {} from mempool: failed to execute: {}", p.signature(), error);
+        let reason = DeadLetterReason::ExecutionFailed { error: error.to_string() };
+        if let Err(e) = self.state_manager.put_dead_letter(reason, &p.tx) {
+            eprintln!("Failed to record failed transaction {}: {}", p.signature(), e);
+        }
+        self.state_manager.events().publish(|| RollupEvent::TransactionDropped {
+            signature: p.signature(),
+            reason: DropReason::ExecutionFailed,
+        });
+    }
 
-        if let Err(e) = self.batch_sender.send(batch).await {
-            eprintln!("Failed to send batch: {}", e);
+    fn publish_dropped(&self, p: &PendingTransaction, reason: DropReason) {
+        println!("Dropping transaction {} from mempool: {:?}", p.signature(), reason);
+        if let Err(e) = self.state_manager.put_dead_letter(DeadLetterReason::Dropped { drop_reason: reason }, &p.tx) {
+            eprintln!("Failed to record dropped transaction {}: {}", p.signature(), e);
         }
+        self.state_manager.events().publish(|| RollupEvent::TransactionDropped { signature: p.signature(), reason });
+    }
+}
+
+/// Compute unit price requested through a ComputeBudget instruction, if any.
+fn compute_unit_price(tx: &Transaction) -> u64 {
+    tx.message.instructions.iter()
+        .filter(|ix| tx.message.account_keys.get(ix.program_id_index as usize)
+            .is_some_and(solana_compute_budget_interface::check_id))
+        .find_map(|ix| match ix.data.as_slice() {
+            [3, price @ ..] => price.try_into().ok().map(u64::from_le_bytes),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// Nearest-rank percentile of `sorted`, 0 when it is empty.
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Lamports moved by the smallest system transfer (plain or with seed) in
+/// `tx`, whichever instruction it is, if it makes any.
+fn transfer_lamports(tx: &Transaction) -> Option<u64> {
+    let keys = &tx.message.account_keys;
+    tx.message.instructions.iter()
+        .filter(|ix| keys.get(ix.program_id_index as usize) == Some(&Pubkey::default()))
+        .filter_map(|ix| match ix.data.as_slice() {
+            [2, 0, 0, 0, lamports @ ..] => lamports.try_into().ok().map(u64::from_le_bytes),
+            [11, 0, 0, 0, lamports @ ..] => lamports.get(..8)?.try_into().ok().map(u64::from_le_bytes),
+            _ => None,
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::{Keypair, Signer};
+
+    use super::*;
+    use crate::transaction_processor::{access_policy::AccessPolicy, transaction_processor::UnknownProgramPolicy};
+
+    fn sequencer(state_manager: &Arc<StateManager>) -> (Sequencer, mpsc::Receiver<(u64, StoredBatch)>) {
+        let limits = MempoolLimits { max_pending: 100, max_pending_per_sender: 100, eviction: EvictionPolicy::EvictLowestFee };
+        sequencer_with_limits(state_manager, limits)
+    }
+
+    fn sequencer_with_limits(
+        state_manager: &Arc<StateManager>,
+        limits: MempoolLimits
+    ) -> (Sequencer, mpsc::Receiver<(u64, StoredBatch)>) {
+        let transaction_processor = Arc::new(TransactionProcessor::new(
+            state_manager.clone(), AccessPolicy::default(), UnknownProgramPolicy::Reject
+        ));
+        Sequencer::new(
+            state_manager.clone(),
+            transaction_processor,
+            0,
+            100_000,
+            Duration::from_secs(60),
+            limits,
+            SpamScorer::new(Duration::from_secs(60), 0)
+        )
+    }
+
+    fn wire(tx: &Transaction) -> WireTransaction {
+        WireTransaction::from_bytes(bincode::serialize(tx).unwrap()).unwrap()
+    }
+
+    /// A transfer from a freshly funded payer.
+    async fn transfer(state_manager: &StateManager) -> WireTransaction {
+        let payer = Keypair::new();
+        state_manager.fund(&payer.pubkey(), 10_000_000).await;
+
+        let genesis_hash = Hash::new_from_array(state_manager.genesis_hash().unwrap().unwrap());
+        wire(&StateManager::transfer(&payer, &Pubkey::new_unique(), 1_000_000, genesis_hash))
+    }
+
+    #[tokio::test]
+    async fn takes_transactions_while_a_block_is_built() {
+        let state_manager = StateManager::temporary();
+        let (sequencer, _batches) = sequencer(&state_manager);
+        let first = transfer(&state_manager).await;
+        let second = transfer(&state_manager).await;
+        sequencer.add_transaction(first.clone(), Duration::ZERO).await.unwrap();
+
+        // Keeps the block from being built until the mempool has been used
+        let blocks = state_manager.lock_blocks().await;
+        let building = tokio::spawn({
+            let sequencer = sequencer.clone();
+            async move { sequencer.create_batch().await }
+        });
+        while sequencer.building.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let submitted = async {
+            sequencer.add_transaction(second.clone(), Duration::ZERO).await.unwrap();
+            sequencer.pending_count().await
+        };
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), submitted).await.unwrap(), 2);
+        drop(blocks);
+        building.await.unwrap();
+
+        // Only the transaction the block was built from left the mempool
+        let sealed = state_manager.get_batch(0).unwrap().unwrap().transactions;
+        assert_eq!(sealed.iter().map(|tx| tx.signatures[0]).collect::<Vec<_>>(), vec![first.signatures[0]]);
+        let pending = sequencer.pending_txs.read().await;
+        assert_eq!(pending.iter().map(PendingTransaction::signature).collect::<Vec<_>>(), vec![second.signatures[0]]);
+    }
+
+    #[tokio::test]
+    async fn keeps_identical_payments_and_fails_transactions_it_turns_away() {
+        let state_manager = StateManager::temporary();
+        let limits = MempoolLimits { max_pending: 2, max_pending_per_sender: 100, eviction: EvictionPolicy::RejectNew };
+        let (sequencer, _batches) = sequencer_with_limits(&state_manager, limits);
+        let payer = Keypair::new();
+        state_manager.fund(&payer.pubkey(), 10_000_000).await;
+
+        // The same payment twice, only under different blockhashes
+        let recipient = Pubkey::new_unique();
+        let genesis_hash = Hash::new_from_array(state_manager.genesis_hash().unwrap().unwrap());
+        let blockhash = state_manager.put_empty_block().blockhash;
+        let payment = |blockhash| wire(&StateManager::transfer(&payer, &recipient, 1_000_000, blockhash));
+        let (first, second) = (payment(genesis_hash), payment(blockhash));
+        sequencer.add_transaction(first.clone(), Duration::ZERO).await.unwrap();
+        sequencer.add_transaction(second.clone(), Duration::ZERO).await.unwrap();
+        // Submitted again while pending, it stays as it is
+        sequencer.add_transaction(first.clone(), Duration::ZERO).await.unwrap();
+
+        let rejected = transfer(&state_manager).await;
+        assert!(sequencer.add_transaction(rejected, Duration::ZERO).await.is_err());
+        let pending = sequencer.pending_txs.read().await;
+        assert_eq!(
+            pending.iter().map(PendingTransaction::signature).collect::<Vec<_>>(),
+            vec![first.signatures[0], second.signatures[0]]
+        );
     }
 }
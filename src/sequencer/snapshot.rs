@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+pub const MEMPOOL_SNAPSHOT_VERSION: u32 = 1;
+
+/// The mempool of a running node at one instant, with the settings that
+/// decide its order, for debugging ordering offline or loading it into
+/// another node to reproduce an incident.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MempoolSnapshot {
+    pub format_version: u32,
+    /// Base58, as in getVersion
+    pub genesis_hash: Option<String>,
+    /// Slot the next batch will be sealed as
+    pub slot: u64,
+    /// Unix time in milliseconds
+    pub taken_at: u64,
+    pub aging_rate: u64,
+    pub max_batch_bytes: usize,
+    pub ttl_ms: u64,
+    /// In sealing order, as ranked when the snapshot was taken
+    pub transactions: Vec<MempoolEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MempoolEntry {
+    pub signature: String,
+    pub fee_payer: Option<String>,
+    pub compute_unit_price: u64,
+    /// Priority it was ranked by: its price plus aging, scaled down by its
+    /// fee payer's spam score
+    pub effective_priority: u64,
+    /// Encoded size in bytes
+    pub size: usize,
+    pub waited_ms: u64,
+    /// Whether it would have been sealed into the next batch
+    pub next_batch: bool,
+    /// Base64 wire bytes
+    pub transaction: String,
+}
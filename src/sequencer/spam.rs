@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use solana_sdk::pubkey::Pubkey;
+
+/// A sender needs this many (decayed) submissions before it can be scored,
+/// so one early failure doesn't bury a new account.
+const MIN_SAMPLES: f64 = 3.0;
+
+/// Stats below this many decayed submissions are forgotten.
+const PRUNE_BELOW: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy)]
+struct SenderStats {
+    submitted: f64,
+    failed: f64,
+    dust: f64,
+    updated: Instant,
+}
+
+impl SenderStats {
+    fn decayed(&self, now: Instant, half_life: Duration) -> Self {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let factor = 0.5f64.powf(elapsed / half_life.as_secs_f64().max(f64::EPSILON));
+        Self {
+            submitted: self.submitted * factor,
+            failed: self.failed * factor,
+            dust: self.dust * factor,
+            updated: now,
+        }
+    }
+}
+
+/// Per fee payer spam score from recent failed and dust transactions.
+/// Counters decay exponentially, so a sender that behaves recovers.
+#[derive(Debug)]
+pub struct SpamScorer {
+    stats: HashMap<Pubkey, SenderStats>,
+    half_life: Duration,
+    dust_threshold: u64,
+}
+
+impl SpamScorer {
+    /// Transfers below `dust_threshold` lamports count as dust.
+    pub fn new(half_life: Duration, dust_threshold: u64) -> Self {
+        Self {
+            stats: HashMap::new(),
+            half_life,
+            dust_threshold,
+        }
+    }
+
+    /// Records a signature-verified submission made at `now`.
+    /// `transfer_lamports` is the smallest system transfer it makes, if any.
+    pub fn record(&mut self, sender: Pubkey, failed: bool, transfer_lamports: Option<u64>, now: Instant) {
+        let half_life = self.half_life;
+        let stats = self.stats.entry(sender)
+            .or_insert(SenderStats { submitted: 0.0, failed: 0.0, dust: 0.0, updated: now });
+
+        *stats = stats.decayed(now, half_life);
+        stats.submitted += 1.0;
+        if failed {
+            stats.failed += 1.0;
+        }
+        if transfer_lamports.is_some_and(|lamports| lamports < self.dust_threshold) {
+            stats.dust += 1.0;
+        }
+    }
+
+    /// 0.0 for a well-behaved or unknown sender, up to 1.0 for one whose
+    /// recent transactions all failed or moved dust.
+    pub fn score(&self, sender: &Pubkey, now: Instant) -> f64 {
+        let Some(stats) = self.stats.get(sender) else {
+            return 0.0;
+        };
+        let stats = stats.decayed(now, self.half_life);
+        if stats.submitted < MIN_SAMPLES {
+            return 0.0;
+        }
+
+        ((stats.failed + stats.dust) / stats.submitted).min(1.0)
+    }
+
+    pub fn prune(&mut self, now: Instant) {
+        let half_life = self.half_life;
+        self.stats.retain(|_, stats| stats.decayed(now, half_life).submitted >= PRUNE_BELOW);
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::state_manager::{
+    state_hasher::HashFunction,
+    state_manager::{L2Account, StateManager},
+};
+
+pub const FORMAT_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Index of an export directory. Rewritten after every chunk, so an
+/// interrupted export or import picks up where it stopped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifest {
+    pub format_version: u32,
+    pub hash_function: HashFunction,
+    /// Hex, if the source database has one
+    pub genesis_hash: Option<String>,
+    /// Hex state root every chunk is read at, set when the export starts
+    pub state_root: Option<String>,
+    /// Newest block sealed at that state root; None for a chain without
+    /// blocks and exports from before slots were noted
+    #[serde(default)]
+    pub slot: Option<u64>,
+    pub chunk_accounts: usize,
+    pub chunks: Vec<ChunkInfo>,
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkInfo {
+    pub file: String,
+    pub accounts: usize,
+    /// Accounts are exported in key order; the next chunk starts after this one
+    pub last_key: String,
+    /// Hex hash of the chunk file, with the state's hash function
+    pub hash: String,
+}
+
+pub fn read_manifest(dir: &Path) -> anyhow::Result<Option<ExportManifest>> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let manifest: ExportManifest = serde_json::from_slice(&fs::read(&path)?)?;
+    if manifest.format_version != FORMAT_VERSION {
+        anyhow::bail!("Unsupported export format version {}", manifest.format_version);
+    }
+
+    Ok(Some(manifest))
+}
+
+/// Writes every account in `source` to `dir` in chunks of `chunk_accounts`,
+/// holding one chunk in memory at a time, all read from one snapshot of the
+/// database so blocks sealed meanwhile don't mix in. Resumes a previous
+/// unfinished export into the same directory if the source is still at the
+/// state root it started from.
+pub fn export_state(source: &StateManager, dir: &Path, chunk_accounts: usize) -> anyhow::Result<ExportManifest> {
+    fs::create_dir_all(dir)?;
+    let view = source.view();
+    let state_root = hex::encode(view.state_root()?);
+
+    let mut manifest = match read_manifest(dir)? {
+        Some(manifest) if manifest.complete => {
+            anyhow::bail!("{} already holds a complete export", dir.display());
+        }
+        Some(manifest) => {
+            if manifest.hash_function != source.hash_function() {
+                anyhow::bail!("{} holds an export of a different state", dir.display());
+            }
+            if manifest.state_root.as_ref() != Some(&state_root) {
+                anyhow::bail!("State changed since the export in {} started; export into a new directory", dir.display());
+            }
+            println!("Resuming export after {} chunks", manifest.chunks.len());
+            manifest
+        }
+        None => ExportManifest {
+            format_version: FORMAT_VERSION,
+            hash_function: source.hash_function(),
+            genesis_hash: source.genesis_hash()?.map(hex::encode),
+            state_root: Some(state_root),
+            slot: view.last_batch()?,
+            chunk_accounts,
+            chunks: Vec::new(),
+            complete: false,
+        },
+    };
+
+    let mut after = manifest.chunks.last()
+        .map(|chunk| chunk.last_key.parse::<Pubkey>())
+        .transpose()?;
+    loop {
+        let accounts = view.account_page(after.as_ref(), manifest.chunk_accounts)?;
+        let Some((last_key, _)) = accounts.last() else {
+            break;
+        };
+
+        let data = bincode::serialize(&accounts)?;
+        let file = format!("chunk-{:06}.bin", manifest.chunks.len());
+        write_atomically(&dir.join(&file), &data)?;
+
+        manifest.chunks.push(ChunkInfo {
+            file,
+            accounts: accounts.len(),
+            last_key: last_key.to_string(),
+            hash: hex::encode(source.hasher().hash(&data)),
+        });
+        after = Some(*last_key);
+        write_manifest(dir, &manifest)?;
+    }
+
+    manifest.complete = true;
+    write_manifest(dir, &manifest)?;
+
+    Ok(manifest)
+}
+
+/// Loads a complete export into `target`, checking every chunk's hash
+/// first and then the state root of everything loaded. Resumes a previous
+/// unfinished import into the same database; until the root checks out, a
+/// node won't start on it.
+pub fn import_state(target: &StateManager, dir: &Path) -> anyhow::Result<ExportManifest> {
+    let manifest = read_manifest(dir)?
+        .ok_or_else(|| anyhow::anyhow!("No export manifest in {}", dir.display()))?;
+    if !manifest.complete {
+        anyhow::bail!("Export in {} is incomplete; rerun the export to finish it", dir.display());
+    }
+    if manifest.hash_function != target.hash_function() {
+        anyhow::bail!("Export uses {:?} state hashing, the target database {:?}",
+            manifest.hash_function, target.hash_function());
+    }
+
+    let next_chunk = target.import_progress()?;
+    if next_chunk == 0 && !target.account_page(None, 1)?.is_empty() {
+        anyhow::bail!("Target database already has state");
+    }
+    if next_chunk > 0 {
+        println!("Resuming import at chunk {}", next_chunk);
+    }
+
+    for (index, chunk) in manifest.chunks.iter().enumerate().skip(next_chunk as usize) {
+        let data = fs::read(dir.join(&chunk.file))?;
+        if hex::encode(target.hasher().hash(&data)) != chunk.hash {
+            anyhow::bail!("{} does not match its hash in the manifest", chunk.file);
+        }
+
+        let accounts: Vec<(Pubkey, L2Account)> = bincode::deserialize(&data)?;
+        target.import_chunk(index as u64, &accounts)?;
+    }
+
+    let genesis_hash = manifest.genesis_hash.as_deref().map(decode_hash).transpose()?;
+    let state_root = manifest.state_root.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Export in {} has no state root", dir.display()))?;
+    target.restore_chain_metadata(genesis_hash, Some(decode_hash(state_root)?))?;
+
+    Ok(manifest)
+}
+
+/// A complete export found in a snapshot directory.
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    /// Name of its directory
+    pub name: String,
+    pub manifest: ExportManifest,
+    /// Chunk files and manifest together
+    pub bytes: u64,
+}
+
+impl SnapshotInfo {
+    pub fn accounts(&self) -> usize {
+        self.manifest.chunks.iter().map(|chunk| chunk.accounts).sum()
+    }
+
+    /// Whether `file` is part of the snapshot.
+    pub fn contains(&self, file: &str) -> bool {
+        file == MANIFEST_FILE || self.manifest.chunks.iter().any(|chunk| chunk.file == file)
+    }
+}
+
+/// Every complete export directly under `dir`, newest slot first. Exports
+/// still being written or of another format are skipped.
+pub fn list_snapshots(dir: &Path) -> anyhow::Result<Vec<SnapshotInfo>> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()).map(str::to_string) else {
+            continue;
+        };
+        let Ok(Some(manifest)) = read_manifest(&path) else {
+            continue;
+        };
+        if !manifest.complete {
+            continue;
+        }
+
+        let mut bytes = fs::metadata(path.join(MANIFEST_FILE))?.len();
+        for chunk in &manifest.chunks {
+            bytes += fs::metadata(path.join(&chunk.file))?.len();
+        }
+        snapshots.push(SnapshotInfo { name, manifest, bytes });
+    }
+    snapshots.sort_by(|a, b| b.manifest.slot.cmp(&a.manifest.slot).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(snapshots)
+}
+
+fn decode_hash(hex_hash: &str) -> anyhow::Result<[u8; 32]> {
+    hex::decode(hex_hash)?.try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid hash in manifest: {}", hex_hash))
+}
+
+fn write_manifest(dir: &Path, manifest: &ExportManifest) -> anyhow::Result<()> {
+    write_atomically(&dir.join(MANIFEST_FILE), &serde_json::to_vec_pretty(manifest)?)
+}
+
+// A crash leaves either the old file or the new one, never half of it
+fn write_atomically(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+/// Column families kept in cold storage; named like their hot counterparts.
+const COLD_COLUMN_FAMILIES: [&str; 2] = ["batches", "batch_reports"];
+
+/// Slower, cheaper tier for old blocks (stored batches) and receipts (batch
+/// reports): a separate RocksDB database, e.g. on bulk disks. Entries only
+/// ever arrive here by migration from the hot database.
+#[derive(Debug)]
+pub struct ColdStorage {
+    db: rocksdb::DB,
+}
+
+impl ColdStorage {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        Ok(Self { db: rocksdb::DB::open_cf(&opts, path, COLD_COLUMN_FAMILIES)? })
+    }
+
+    /// Opens without taking the write lock, alongside a running node.
+    pub fn open_read_only(path: &str) -> anyhow::Result<Self> {
+        let db = rocksdb::DB::open_cf_for_read_only(
+            &rocksdb::Options::default(),
+            path,
+            COLD_COLUMN_FAMILIES,
+            false
+        )?;
+
+        Ok(Self { db })
+    }
+
+    pub(crate) fn get(&self, cf: &str, key: impl AsRef<[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.db.get_cf(self.cf(cf)?, key)?)
+    }
+
+    /// Values of `cf` from the highest key down.
+    pub(crate) fn values_rev(&self, cf: &str) -> anyhow::Result<impl Iterator<Item = anyhow::Result<Box<[u8]>>> + '_> {
+        Ok(self.db.iterator_cf(self.cf(cf)?, rocksdb::IteratorMode::End)
+            .map(|entry| Ok(entry?.1)))
+    }
+
+    /// Keys of `cf` from the lowest up.
+    pub(crate) fn keys(&self, cf: &str) -> anyhow::Result<impl Iterator<Item = anyhow::Result<Box<[u8]>>> + '_> {
+        Ok(self.db.iterator_cf(self.cf(cf)?, rocksdb::IteratorMode::Start)
+            .map(|entry| Ok(entry?.0)))
+    }
+
+    /// Writes `entries` durably; the hot copies are only deleted afterwards.
+    pub(crate) fn put_all<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, cf: &str, entries: &[(K, V)]) -> anyhow::Result<()> {
+        let cf = self.cf(cf)?;
+        let mut batch = rocksdb::WriteBatch::default();
+        for (key, value) in entries {
+            batch.put_cf(cf, key, value);
+        }
+
+        let mut options = rocksdb::WriteOptions::default();
+        options.set_sync(true);
+        self.db.write_opt(batch, &options)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn delete_all<K: AsRef<[u8]>>(&self, cf: &str, keys: &[K]) -> anyhow::Result<()> {
+        let cf = self.cf(cf)?;
+        let mut batch = rocksdb::WriteBatch::default();
+        for key in keys {
+            batch.delete_cf(cf, key);
+        }
+        self.db.write(batch)?;
+
+        Ok(())
+    }
+
+    fn cf(&self, name: &str) -> anyhow::Result<&rocksdb::ColumnFamily> {
+        self.db.cf_handle(name)
+            .ok_or_else(|| anyhow::anyhow!("Missing cold storage column family: {}", name))
+    }
+}
@@ -1 +1,5 @@
 pub mod  state_manager;
+pub mod snapshots;
+pub mod cold_storage;
+pub mod sparse_merkle;
+pub mod state_hasher;
@@ -0,0 +1,75 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::state_manager::state_manager::L2Account;
+
+/// Which view of state a read is served from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    /// Latest executed state
+    #[default]
+    Processed,
+    /// State as of the latest sealed batch
+    Confirmed,
+    /// State as of the latest batch finalized on L1
+    Finalized,
+}
+
+/// Account pre-images, so confirmed and finalized views can be served as
+/// overlays on top of live state instead of full copies. A `None` pre-image
+/// means the account did not exist.
+#[derive(Debug, Default)]
+pub struct SnapshotJournal {
+    /// Changed since the latest sealed batch
+    open: HashMap<Pubkey, Option<L2Account>>,
+    /// Changed within each sealed batch that is not finalized yet, keyed by batch id
+    sealed: BTreeMap<u64, HashMap<Pubkey, Option<L2Account>>>,
+}
+
+impl SnapshotJournal {
+    /// Remembers the value an account had before its first change in the open batch.
+    pub fn record(&mut self, pubkey: Pubkey, previous: Option<L2Account>) {
+        self.open.entry(pubkey).or_insert(previous);
+    }
+
+    /// The pre-images the next `seal` keeps, once `pre_images` of changes
+    /// still to be recorded are.
+    pub fn layer_with(&self, pre_images: Option<&HashMap<Pubkey, Option<L2Account>>>) -> HashMap<Pubkey, Option<L2Account>> {
+        let mut layer = self.open.clone();
+        for (pubkey, previous) in pre_images.into_iter().flatten() {
+            layer.entry(*pubkey).or_insert_with(|| previous.clone());
+        }
+        layer
+    }
+
+    pub fn seal(&mut self, batch_id: u64) {
+        let changes = std::mem::take(&mut self.open);
+        self.sealed.insert(batch_id, changes);
+    }
+
+    /// Puts back the pre-images a sealed batch kept, as stored with its block.
+    pub fn restore(&mut self, batch_id: u64, changes: HashMap<Pubkey, Option<L2Account>>) {
+        self.sealed.insert(batch_id, changes);
+    }
+
+    /// Batches up to and including `batch_id` are part of the finalized view
+    /// now, so their pre-images are no longer needed.
+    pub fn finalize(&mut self, batch_id: u64) {
+        self.sealed = self.sealed.split_off(&(batch_id + 1));
+    }
+
+    /// The account as seen at `commitment`, or `None` if it is unchanged from live state.
+    pub fn lookup(&self, pubkey: &Pubkey, commitment: Commitment) -> Option<Option<L2Account>> {
+        let layers: Box<dyn Iterator<Item = &HashMap<Pubkey, Option<L2Account>>>> = match commitment {
+            Commitment::Processed => return None,
+            Commitment::Confirmed => Box::new(std::iter::once(&self.open)),
+            // The oldest pre-image after the finalized batch wins
+            Commitment::Finalized => Box::new(self.sealed.values().chain(std::iter::once(&self.open))),
+        };
+
+        layers.into_iter().find_map(|layer| layer.get(pubkey).cloned())
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::state_manager::{state_hasher::StateHasher, state_manager::L2Account};
+
+/// Levels between a leaf and the root: one per bit of the pubkey.
+const TREE_DEPTH: u16 = 256;
+
+/// Empty subtrees hash to all zeroes at every height, so only nodes with an
+/// account below them are stored.
+pub const EMPTY: [u8; 32] = [0u8; 32];
+
+/// Height (big endian) followed by the path to the node: the key with every
+/// bit below the node's height cleared.
+pub type NodeKey = [u8; 34];
+
+fn node_key(height: u16, key: &[u8; 32]) -> NodeKey {
+    let mut node = [0u8; 34];
+    node[..2].copy_from_slice(&height.to_be_bytes());
+    node[2..].copy_from_slice(key);
+
+    // Clear the low `height` bits of the path
+    let path = &mut node[2..];
+    let cleared = height as usize;
+    for byte in path[32 - cleared / 8..].iter_mut() {
+        *byte = 0;
+    }
+    if !cleared.is_multiple_of(8) {
+        path[31 - cleared / 8] &= 0xffu8 << (cleared % 8);
+    }
+
+    node
+}
+
+/// Whether the child at `height` on the way to `key` is the right one.
+fn goes_right(key: &[u8; 32], height: u16) -> bool {
+    let bit = height as usize;
+    key[31 - bit / 8] >> (bit % 8) & 1 == 1
+}
+
+fn sibling_path(key: &[u8; 32], height: u16) -> [u8; 32] {
+    let mut sibling = *key;
+    let bit = height as usize;
+    sibling[31 - bit / 8] ^= 1 << (bit % 8);
+    sibling
+}
+
+/// Leaf committed for an account; absent accounts are empty leaves.
+pub fn account_leaf(hasher: &dyn StateHasher, pubkey: &Pubkey, account: Option<&L2Account>) -> anyhow::Result<[u8; 32]> {
+    match account {
+        Some(account) => Ok(hasher.hash(&bincode::serialize(&(pubkey, account))?)),
+        None => Ok(EMPTY),
+    }
+}
+
+/// Key the root is persisted under.
+pub fn root_key() -> NodeKey {
+    node_key(TREE_DEPTH, &EMPTY)
+}
+
+/// Root of the tree as persisted in `cf`.
+pub fn stored_root(db: &rocksdb::DB, cf: &rocksdb::ColumnFamily) -> anyhow::Result<[u8; 32]> {
+    match db.get_cf(cf, root_key())? {
+        Some(node) => Ok(node.as_slice().try_into()?),
+        None => Ok(EMPTY),
+    }
+}
+
+/// Siblings of the nodes on `pubkey`'s path as persisted in `cf`, from its
+/// leaf up to just below the root: the proof of its leaf, present or empty.
+pub fn prove(db: &rocksdb::DB, cf: &rocksdb::ColumnFamily, pubkey: &Pubkey) -> anyhow::Result<Vec<[u8; 32]>> {
+    let key = pubkey.to_bytes();
+    (0..TREE_DEPTH)
+        .map(|height| match db.get_pinned_cf(cf, node_key(height, &sibling_path(&key, height)))? {
+            Some(node) => Ok(node.as_ref().try_into()?),
+            None => Ok(EMPTY),
+        })
+        .collect()
+}
+
+/// Root that `leaf` at `pubkey` hashes up to with `proof`'s siblings, to
+/// compare with the root the proof is claimed against.
+pub fn proof_root(hasher: &dyn StateHasher, pubkey: &Pubkey, leaf: [u8; 32], proof: &[[u8; 32]]) -> anyhow::Result<[u8; 32]> {
+    if proof.len() != TREE_DEPTH as usize {
+        anyhow::bail!("Proof has {} siblings, not {}", proof.len(), TREE_DEPTH);
+    }
+
+    let key = pubkey.to_bytes();
+    let mut node = leaf;
+    for (height, sibling) in (0..TREE_DEPTH).zip(proof) {
+        let (left, right) = if goes_right(&key, height) { (*sibling, node) } else { (node, *sibling) };
+        node = if left == EMPTY && right == EMPTY {
+            EMPTY
+        } else {
+            hasher.hash_pair(&left, &right)
+        };
+    }
+
+    Ok(node)
+}
+
+/// Highest height at which `a` and `b` take different children; they must
+/// differ.
+fn split_height(a: &[u8; 32], b: &[u8; 32]) -> u16 {
+    let (byte, diff) = a.iter().zip(b).enumerate()
+        .map(|(byte, (a, b))| (byte, a ^ b))
+        .find(|(_, diff)| *diff != 0)
+        .expect("keys differ");
+    ((31 - byte) * 8 + 7 - diff.leading_zeros() as usize) as u16
+}
+
+/// Computes the root over leaves added in key order, in memory and without
+/// reading stored nodes, to check a stored tree against. Holds one node per
+/// level of the last leaf's path at most.
+pub struct RootBuilder<'a> {
+    hasher: &'a dyn StateHasher,
+    /// Subtrees not yet joined to their sibling, lowest on top: a key under
+    /// each, its height and its node
+    stack: Vec<([u8; 32], u16, [u8; 32])>,
+}
+
+impl<'a> RootBuilder<'a> {
+    pub fn new(hasher: &'a dyn StateHasher) -> Self {
+        Self { hasher, stack: Vec::new() }
+    }
+
+    /// Adds the leaf of `pubkey`, which must come after every key added so far.
+    pub fn push(&mut self, pubkey: &Pubkey, leaf: [u8; 32]) -> anyhow::Result<()> {
+        let key = pubkey.to_bytes();
+        if let Some(&(last, _, _)) = self.stack.last() {
+            if key <= last {
+                anyhow::bail!("Leaf {} added out of key order", pubkey);
+            }
+            // Nothing added from here on is below the last leaf's side of the split
+            self.raise(split_height(&last, &key));
+        }
+        self.stack.push((key, 0, leaf));
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> [u8; 32] {
+        self.raise(TREE_DEPTH);
+        self.stack.pop().map_or(EMPTY, |(_, _, node)| node)
+    }
+
+    /// Hashes the top subtree up to `height`, joining the subtrees on the
+    /// stack it passes on the way.
+    fn raise(&mut self, height: u16) {
+        while let Some(&(key, below, node)) = self.stack.last()
+            && below < height
+        {
+            self.stack.pop();
+            let (left, right) = if goes_right(&key, below) {
+                match self.stack.last() {
+                    Some(&(_, sibling_height, sibling)) if sibling_height == below => {
+                        self.stack.pop();
+                        (sibling, node)
+                    }
+                    _ => (EMPTY, node),
+                }
+            } else {
+                (node, EMPTY)
+            };
+            let parent = if left == EMPTY && right == EMPTY {
+                EMPTY
+            } else {
+                self.hasher.hash_pair(&left, &right)
+            };
+            self.stack.push((key, below + 1, parent));
+        }
+    }
+}
+
+/// Changes to the sparse Merkle tree over all accounts, keyed by pubkey,
+/// staged in memory and written into the same RocksDB batch as the accounts
+/// themselves, so the persisted root always matches persisted state.
+/// Callers must serialize updates; siblings are read from the database.
+pub struct TreeUpdate<'a> {
+    db: &'a rocksdb::DB,
+    cf: &'a rocksdb::ColumnFamily,
+    hasher: &'a dyn StateHasher,
+    pending: HashMap<NodeKey, [u8; 32]>,
+    root: Option<[u8; 32]>,
+}
+
+impl<'a> TreeUpdate<'a> {
+    pub fn new(db: &'a rocksdb::DB, cf: &'a rocksdb::ColumnFamily, hasher: &'a dyn StateHasher) -> Self {
+        Self { db, cf, hasher, pending: HashMap::new(), root: None }
+    }
+
+    fn node(&self, key: &NodeKey) -> anyhow::Result<[u8; 32]> {
+        if let Some(node) = self.pending.get(key) {
+            return Ok(*node);
+        }
+
+        match self.db.get_pinned_cf(self.cf, key)? {
+            Some(node) => Ok(node.as_ref().try_into()?),
+            None => Ok(EMPTY),
+        }
+    }
+
+    /// Sets the leaf of `pubkey` and rehashes its path up to the root.
+    pub fn set_leaf(&mut self, pubkey: &Pubkey, leaf: [u8; 32]) -> anyhow::Result<()> {
+        let key = pubkey.to_bytes();
+        let mut node = leaf;
+        for height in 0..TREE_DEPTH {
+            self.pending.insert(node_key(height, &key), node);
+
+            let sibling = self.node(&node_key(height, &sibling_path(&key, height)))?;
+            let (left, right) = if goes_right(&key, height) { (sibling, node) } else { (node, sibling) };
+            node = if left == EMPTY && right == EMPTY {
+                EMPTY
+            } else {
+                self.hasher.hash_pair(&left, &right)
+            };
+        }
+
+        self.pending.insert(node_key(TREE_DEPTH, &key), node);
+        self.root = Some(node);
+
+        Ok(())
+    }
+
+    /// Stages every changed node into `batch` and returns the new root, or
+    /// `None` if no leaf was set.
+    pub fn finish(self, batch: &mut rocksdb::WriteBatch) -> Option<[u8; 32]> {
+        for (key, node) in &self.pending {
+            if *node == EMPTY {
+                batch.delete_cf(self.cf, key);
+            } else {
+                batch.put_cf(self.cf, key, node);
+            }
+        }
+
+        self.root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_manager::{state_hasher::Sha256Hasher, state_manager::StateManager};
+
+    const CF: &str = "state_tree";
+
+    fn open_tree() -> rocksdb::DB {
+        let mut options = rocksdb::Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        rocksdb::DB::open_cf(&options, StateManager::temporary_path(), [CF]).unwrap()
+    }
+
+    /// Sets `leaves` in one write, as a block's accounts are, returning the
+    /// new root.
+    fn set_leaves(db: &rocksdb::DB, leaves: &[(Pubkey, [u8; 32])]) -> [u8; 32] {
+        let cf = db.cf_handle(CF).unwrap();
+        let mut update = TreeUpdate::new(db, cf, &Sha256Hasher);
+        for (pubkey, leaf) in leaves {
+            update.set_leaf(pubkey, *leaf).unwrap();
+        }
+        let mut batch = rocksdb::WriteBatch::default();
+        let root = update.finish(&mut batch).unwrap();
+        db.write(batch).unwrap();
+        root
+    }
+
+    fn stored_nodes(db: &rocksdb::DB) -> Vec<(Box<[u8]>, Box<[u8]>)> {
+        db.iterator_cf(db.cf_handle(CF).unwrap(), rocksdb::IteratorMode::Start)
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    /// Keys sharing long prefixes as well as keys splitting at the root.
+    fn leaves() -> Vec<(Pubkey, [u8; 32])> {
+        let mut keys: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        keys.extend([[0u8; 32], [0xff; 32], [0x80; 32], [0x7f; 32]].map(Pubkey::new_from_array));
+        keys.into_iter()
+            .enumerate()
+            .map(|(i, pubkey)| (pubkey, Sha256Hasher.hash(&[i as u8])))
+            .collect()
+    }
+
+    #[test]
+    fn proofs_of_present_and_absent_keys_lead_to_the_stored_root() {
+        let db = open_tree();
+        let leaves = leaves();
+        let root = set_leaves(&db, &leaves);
+        let cf = db.cf_handle(CF).unwrap();
+        assert_eq!(stored_root(&db, cf).unwrap(), root);
+
+        for (pubkey, leaf) in &leaves {
+            let proof = prove(&db, cf, pubkey).unwrap();
+            assert_eq!(proof_root(&Sha256Hasher, pubkey, *leaf, &proof).unwrap(), root);
+            assert_ne!(proof_root(&Sha256Hasher, pubkey, EMPTY, &proof).unwrap(), root);
+        }
+
+        let absent = Pubkey::new_unique();
+        let proof = prove(&db, cf, &absent).unwrap();
+        assert_eq!(proof_root(&Sha256Hasher, &absent, EMPTY, &proof).unwrap(), root);
+        assert!(proof_root(&Sha256Hasher, &absent, EMPTY, &proof[1..]).is_err());
+    }
+
+    #[test]
+    fn deleting_a_leaf_restores_the_previous_tree() {
+        let db = open_tree();
+        let leaves = leaves();
+        let (last, earlier) = leaves.split_last().unwrap();
+        let previous_root = set_leaves(&db, earlier);
+        let previous_nodes = stored_nodes(&db);
+
+        assert_ne!(set_leaves(&db, &[*last]), previous_root);
+        assert_eq!(set_leaves(&db, &[(last.0, EMPTY)]), previous_root);
+        assert_eq!(stored_nodes(&db), previous_nodes);
+
+        // Deleting every leaf leaves nothing stored
+        let deleted: Vec<_> = earlier.iter().map(|(pubkey, _)| (*pubkey, EMPTY)).collect();
+        assert_eq!(set_leaves(&db, &deleted), EMPTY);
+        assert!(stored_nodes(&db).is_empty());
+    }
+
+    #[test]
+    fn root_builder_matches_the_incrementally_updated_root() {
+        let db = open_tree();
+        let mut leaves = leaves();
+        let mut root = EMPTY;
+        for leaf in &leaves {
+            root = set_leaves(&db, &[*leaf]);
+        }
+
+        leaves.sort_by_key(|(pubkey, _)| pubkey.to_bytes());
+        let mut builder = RootBuilder::new(&Sha256Hasher);
+        for (pubkey, leaf) in &leaves {
+            builder.push(pubkey, *leaf).unwrap();
+        }
+        assert_eq!(builder.finish(), root);
+
+        let mut builder = RootBuilder::new(&Sha256Hasher);
+        builder.push(&leaves[1].0, leaves[1].1).unwrap();
+        assert!(builder.push(&leaves[0].0, leaves[0].1).is_err());
+        assert_eq!(RootBuilder::new(&Sha256Hasher).finish(), EMPTY);
+    }
+}
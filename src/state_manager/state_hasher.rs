@@ -0,0 +1,135 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Hash function backing the state commitment. It is fixed at genesis because
+/// switching it would change every state root posted to L1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum HashFunction {
+    Blake3,
+    Sha256,
+}
+
+impl HashFunction {
+    pub fn hasher(&self) -> Arc<dyn StateHasher> {
+        match self {
+            HashFunction::Blake3 => Arc::new(Blake3Hasher),
+            HashFunction::Sha256 => Arc::new(Sha256Hasher),
+        }
+    }
+}
+
+pub trait StateHasher: Debug + Send + Sync {
+    /// Hashes an arbitrary leaf preimage.
+    fn hash(&self, data: &[u8]) -> [u8; 32];
+
+    /// Combines two child nodes. Field-friendly hashes (e.g. Poseidon) should
+    /// override this rather than hashing the concatenated bytes.
+    fn hash_pair(&self, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut data = [0u8; 64];
+        data[..32].copy_from_slice(left);
+        data[32..].copy_from_slice(right);
+        self.hash(&data)
+    }
+}
+
+#[derive(Debug)]
+pub struct Blake3Hasher;
+
+impl StateHasher for Blake3Hasher {
+    fn hash(&self, data: &[u8]) -> [u8; 32] {
+        *blake3::hash(data).as_bytes()
+    }
+}
+
+#[derive(Debug)]
+pub struct Sha256Hasher;
+
+impl StateHasher for Sha256Hasher {
+    fn hash(&self, data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+}
+
+/// Prefixes of leaf and internal node preimages in `merkle_root` trees, so
+/// neither can be passed off as the other
+const MERKLE_LEAF_PREFIX: u8 = 0;
+const MERKLE_NODE_PREFIX: u8 = 1;
+/// Sibling of a level's last node when it has none
+const MERKLE_EMPTY: [u8; 32] = [0u8; 32];
+
+/// Hashes a leaf preimage of a `merkle_root` tree.
+pub fn merkle_leaf(hasher: &dyn StateHasher, data: &[u8]) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(1 + data.len());
+    preimage.push(MERKLE_LEAF_PREFIX);
+    preimage.extend_from_slice(data);
+    hasher.hash(&preimage)
+}
+
+/// Combines two child nodes of a `merkle_root` tree.
+pub fn merkle_node(hasher: &dyn StateHasher, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = [0u8; 65];
+    preimage[0] = MERKLE_NODE_PREFIX;
+    preimage[1..33].copy_from_slice(left);
+    preimage[33..].copy_from_slice(right);
+    hasher.hash(&preimage)
+}
+
+/// Binary Merkle root over `leaves`, pairing a level's last node without a
+/// sibling with the all-zero node, so no two leaf lists share a root.
+/// An empty tree has the all-zero root.
+pub fn merkle_root(hasher: &dyn StateHasher, mut leaves: Vec<[u8; 32]>) -> [u8; 32] {
+    while leaves.len() > 1 {
+        leaves = leaves.chunks(2)
+            .map(|pair| merkle_node(hasher, &pair[0], pair.get(1).unwrap_or(&MERKLE_EMPTY)))
+            .collect();
+    }
+
+    leaves.first().copied().unwrap_or(MERKLE_EMPTY)
+}
+
+/// Siblings of leaf `index` from the bottom of the `merkle_root` tree up.
+pub fn merkle_proof(hasher: &dyn StateHasher, mut leaves: Vec<[u8; 32]>, mut index: usize) -> anyhow::Result<Vec<[u8; 32]>> {
+    if index >= leaves.len() {
+        anyhow::bail!("No leaf at index {} of {}", index, leaves.len());
+    }
+
+    let mut proof = Vec::new();
+    while leaves.len() > 1 {
+        proof.push(*leaves.get(index ^ 1).unwrap_or(&MERKLE_EMPTY));
+        leaves = leaves.chunks(2)
+            .map(|pair| merkle_node(hasher, &pair[0], pair.get(1).unwrap_or(&MERKLE_EMPTY)))
+            .collect();
+        index /= 2;
+    }
+
+    Ok(proof)
+}
+
+/// Whether `proof` places `leaf` at `index` of the `merkle_root` tree with
+/// `root`. Past the last leaf a level holds the all-zero node, so no leaf
+/// proves at an index beyond it.
+pub fn verify_merkle_proof(
+    hasher: &dyn StateHasher,
+    root: &[u8; 32],
+    leaf: &[u8; 32],
+    mut index: usize,
+    proof: &[[u8; 32]]
+) -> bool {
+    if index.checked_shr(proof.len() as u32).unwrap_or(0) != 0 {
+        return false;
+    }
+
+    let mut node = *leaf;
+    for sibling in proof {
+        node = match index % 2 {
+            0 => merkle_node(hasher, &node, sibling),
+            _ => merkle_node(hasher, sibling, &node),
+        };
+        index /= 2;
+    }
+
+    node == *root
+}
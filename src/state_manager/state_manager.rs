@@ -1,7 +1,126 @@
-use std::{collections::HashMap, sync::{Arc, RwLock}};
+use std::{collections::{HashMap, HashSet}, fmt, sync::{Arc, Mutex, MutexGuard, RwLock}};
 
 use serde::{Deserialize, Serialize};
-use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature};
+use solana_sdk_ids::system_program;
+
+use crate::batch_processor::batch_processor::{BatchReport, PostedCommitment, StoredBatch};
+use crate::chaos::chaos::{inject_blocking, FaultPoint};
+use crate::dead_letters::dead_letters::{DeadLetter, DeadLetterReason};
+use crate::deposits::deposits::DepositRecord;
+use crate::epochs::epochs::EpochRecord;
+use crate::faucet::faucet::{AirdropMint, PendingAirdrop};
+use crate::events::events::{EventBus, RollupEvent};
+use crate::program_stats::program_stats::ProgramStats;
+use crate::sequencer::block::{BlockHeader, BLOCKHASH_VALIDITY_SLOTS};
+use crate::state_manager::cold_storage::ColdStorage;
+use crate::state_manager::snapshots::{Commitment, SnapshotJournal};
+use crate::state_manager::sparse_merkle::{account_leaf, prove, root_key, stored_root, RootBuilder, TreeUpdate, EMPTY};
+use crate::state_manager::state_hasher::{HashFunction, StateHasher};
+use crate::transaction_processor::{program_registry::{ProgramChange, ProgramHandler}, transaction_record::{SealedIn, TransactionRecord, TransactionStage}, wire_transaction::WireTransaction};
+use crate::withdrawals::withdrawals::{WithdrawalEntry, WithdrawalRecord, WithdrawalRequest, WithdrawalStatus};
+
+const CF_METADATA: &str = "metadata";
+const CF_BATCH_REPORTS: &str = "batch_reports";
+const CF_PENDING_WITHDRAWALS: &str = "pending_withdrawals";
+const CF_BATCH_WITHDRAWALS: &str = "batch_withdrawals";
+const CF_OWNER_INDEX: &str = "owner_index";
+const CF_BATCHES: &str = "batches";
+const CF_NONCES: &str = "nonces";
+const CF_PROCESSED_SIGNATURES: &str = "processed_signatures";
+const CF_RECENT_ACCOUNTS: &str = "recent_accounts";
+const CF_EPOCHS: &str = "epochs";
+const CF_DEAD_LETTERS: &str = "dead_letters";
+const CF_POSTED_COMMITMENTS: &str = "posted_commitments";
+const CF_STATE_TREE: &str = "state_tree";
+const CF_TRANSACTIONS: &str = "transactions";
+const CF_ADDRESS_ACTIVITY: &str = "address_activity";
+const CF_BLOCKS: &str = "blocks";
+const CF_PENDING_DEPOSITS: &str = "pending_deposits";
+const CF_DEPOSITS: &str = "deposits";
+const CF_BATCH_DEPOSITS: &str = "batch_deposits";
+const CF_WITHDRAWALS: &str = "withdrawals";
+const CF_WITHDRAWAL_SIGNATURES: &str = "withdrawal_signatures";
+const CF_BLOCK_DIFFS: &str = "block_diffs";
+const CF_PROGRAM_STATS: &str = "program_stats";
+const CF_PENDING_AIRDROPS: &str = "pending_airdrops";
+const CF_BATCH_AIRDROPS: &str = "batch_airdrops";
+const CF_PENDING_PROGRAM_CHANGES: &str = "pending_program_changes";
+const CF_BATCH_PROGRAM_CHANGES: &str = "batch_program_changes";
+const CF_SIGNATURE_SLOTS: &str = "signature_slots";
+const CF_BLOCKHASHES: &str = "blockhashes";
+const CF_SNAPSHOT_LAYERS: &str = "snapshot_layers";
+
+const COLUMN_FAMILIES: [&str; 30] = [
+    CF_METADATA,
+    CF_BATCH_REPORTS,
+    CF_PENDING_WITHDRAWALS,
+    CF_BATCH_WITHDRAWALS,
+    CF_OWNER_INDEX,
+    CF_BATCHES,
+    CF_NONCES,
+    CF_PROCESSED_SIGNATURES,
+    CF_RECENT_ACCOUNTS,
+    CF_EPOCHS,
+    CF_DEAD_LETTERS,
+    CF_POSTED_COMMITMENTS,
+    CF_STATE_TREE,
+    CF_TRANSACTIONS,
+    CF_ADDRESS_ACTIVITY,
+    CF_BLOCKS,
+    CF_PENDING_DEPOSITS,
+    CF_DEPOSITS,
+    CF_BATCH_DEPOSITS,
+    CF_WITHDRAWALS,
+    CF_WITHDRAWAL_SIGNATURES,
+    CF_BLOCK_DIFFS,
+    CF_PROGRAM_STATS,
+    CF_PENDING_AIRDROPS,
+    CF_BATCH_AIRDROPS,
+    CF_PENDING_PROGRAM_CHANGES,
+    CF_BATCH_PROGRAM_CHANGES,
+    CF_SIGNATURE_SLOTS,
+    CF_BLOCKHASHES,
+    CF_SNAPSHOT_LAYERS,
+];
+
+/// Layout of the state database. Bumped whenever a change needs a migration
+/// older builds don't know about, so they refuse the database instead of
+/// misreading it
+pub const SCHEMA_VERSION: u32 = 1;
+
+const NEXT_BATCH_ID_KEY: &[u8] = b"next_batch_id";
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+const HASH_FUNCTION_KEY: &[u8] = b"state_hash_function";
+const NEXT_WITHDRAWAL_ID_KEY: &[u8] = b"next_withdrawal_id";
+const NEXT_DEAD_LETTER_ID_KEY: &[u8] = b"next_dead_letter_id";
+const NEXT_AIRDROP_ID_KEY: &[u8] = b"next_airdrop_id";
+const NEXT_PROGRAM_CHANGE_ID_KEY: &[u8] = b"next_program_change_id";
+/// Orders transactions in the address activity index
+const NEXT_TRANSACTION_SEQUENCE_KEY: &[u8] = b"next_transaction_sequence";
+/// Present once the state tree covers every account; databases from before
+/// the tree existed are rebuilt on open
+const STATE_TREE_KEY: &[u8] = b"state_tree";
+const GENESIS_HASH_KEY: &[u8] = b"genesis_hash";
+/// State root before the first block, when the chain didn't start empty
+const GENESIS_STATE_ROOT_KEY: &[u8] = b"genesis_state_root";
+/// Last L1 bridge transaction the deposit watcher has read
+const DEPOSIT_CURSOR_KEY: &[u8] = b"deposit_cursor";
+/// Last L1 challenge program transaction the challenge watcher has read
+const CHALLENGE_CURSOR_KEY: &[u8] = b"challenge_cursor";
+/// Last batch whose commitment was seen finalized on L1
+const FINALIZED_BATCH_KEY: &[u8] = b"finalized_batch";
+/// First slot whose state diff, and every later one, is still stored;
+/// pruning deletes the diffs below it
+const FIRST_STORED_DIFF_KEY: &[u8] = b"first_stored_diff";
+const IMPORT_PROGRESS_KEY: &[u8] = b"import_next_chunk";
+/// Present while a node has the database open for writing; still there on
+/// the next start means the last shutdown was unclean
+const OPEN_MARKER_KEY: &[u8] = b"open";
+/// Entries moved to cold storage per write
+const COLD_MIGRATION_CHUNK: usize = 1000;
+/// Accounts added to the state tree per write when rebuilding it
+const TREE_REBUILD_CHUNK: usize = 1000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct L2Account {
@@ -12,78 +131,2073 @@ pub struct L2Account {
     pub rent_epoch: u64
 }
 
+/// Every account a block changed, as it was once the block was sealed;
+/// `None` for closed accounts. Sorted by pubkey.
+pub type StateDiff = Vec<(Pubkey, Option<L2Account>)>;
+
+/// L1 program histories read by a `ProgramLogWatcher`, each with the last
+/// transaction read kept as its cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L1Cursor {
+    /// The bridge program's deposits
+    Deposits,
+    /// The challenge program's challenges
+    Challenges,
+}
+
+impl L1Cursor {
+    fn key(&self) -> &'static [u8] {
+        match self {
+            L1Cursor::Deposits => DEPOSIT_CURSOR_KEY,
+            L1Cursor::Challenges => CHALLENGE_CURSOR_KEY,
+        }
+    }
+}
+
+/// What a cache warmup loaded.
+#[derive(Debug, Clone, Copy)]
+pub struct WarmupStats {
+    pub accounts: usize,
+    pub bytes: usize,
+    pub elapsed: std::time::Duration,
+}
+
+/// Everything the block being built changed so far, held back so it is
+/// stored together with the block's header, or not at all.
+#[derive(Default)]
+struct StagedBlock {
+    /// Account, index, transaction and bridge writes, and once the block is
+    /// sealed, its state tree update
+    write: rocksdb::WriteBatch,
+    /// Every account the block changed, as it leaves it so far
+    accounts: HashMap<Pubkey, Option<L2Account>>,
+    /// Each of those as it was before the block
+    pre_images: HashMap<Pubkey, Option<L2Account>>,
+    /// In the order they were committed, published once the block is stored
+    changes: Vec<(Pubkey, Option<L2Account>)>,
+    nonces: HashMap<Pubkey, u64>,
+    signatures: HashSet<Signature>,
+    /// Executed transactions' records, in execution order
+    records: Vec<(Signature, TransactionRecord)>,
+    /// Set once the block is sealed, after which nothing more is staged
+    state_root: Option<[u8; 32]>,
+}
+
+impl fmt::Debug for StagedBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StagedBlock")
+            .field("accounts", &self.accounts.len())
+            .field("records", &self.records.len())
+            .field("state_root", &self.state_root.map(hex::encode))
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StateManager {
     accounts: Arc<RwLock<HashMap<Pubkey, L2Account>>>,
     db: Arc<rocksdb::DB>,
-    state_root: Arc<RwLock<[u8; 32]>>
+    state_root: Arc<RwLock<[u8; 32]>>,
+    /// Held while staging and writing a state tree update, since siblings
+    /// are read from the database. Guards the accounts committed since the
+    /// last block was sealed, so a block's diff is taken with its root.
+    tree_lock: Arc<Mutex<HashSet<Pubkey>>>,
+    /// Held while checking a withdrawal request's signature and queuing it
+    withdrawal_lock: Arc<Mutex<()>>,
+    /// Held while a block is built, and by anything changing state between
+    /// blocks, so that never lands in a block halfway
+    block_lock: Arc<tokio::sync::Mutex<()>>,
+    /// The block being built, between `begin_block` and `put_block`
+    staged: Arc<Mutex<Option<StagedBlock>>>,
+    /// Program usage of transactions executed since the last block was
+    /// sealed, stored with the next block
+    program_usage: Arc<Mutex<HashMap<Pubkey, ProgramStats>>>,
+    snapshots: Arc<RwLock<SnapshotJournal>>,
+    // Write-through caches over CF_NONCES / CF_PROCESSED_SIGNATURES
+    nonces: Arc<RwLock<HashMap<Pubkey, u64>>>,
+    processed_signatures: Arc<RwLock<HashSet<Signature>>>,
+    hash_function: HashFunction,
+    hasher: Arc<dyn StateHasher>,
+    sync_writes: bool,
+    unclean_shutdown: bool,
+    // Old batches and reports, read through when missing from the hot database
+    cold: Option<Arc<ColdStorage>>,
+    events: EventBus
 }
 
 impl StateManager {
-    pub fn new(db_path: &str) -> anyhow::Result<Self> {
-        let db = rocksdb::DB::open_default(db_path)?;
+    /// Opens the state database. `hash_function` is only honoured at genesis;
+    /// afterwards it must match the function the database was created with.
+    /// With `sync_writes`, every write fsyncs the WAL before returning.
+    pub fn new(db_path: &str, hash_function: Option<HashFunction>, sync_writes: bool) -> anyhow::Result<Self> {
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        // Accounts live in the default column family, everything else gets its own
+        let db = rocksdb::DB::open_cf(&opts, db_path, COLUMN_FAMILIES)?;
+        Self::check_schema_version(&db, db_path)?;
+        let hash_function = Self::load_hash_function(&db, hash_function)?;
+
+        let mut state_manager = Self::from_db(db, hash_function)?;
+        state_manager.sync_writes = sync_writes;
+        state_manager.ensure_state_tree()?;
+        state_manager.ensure_blockhash_index()?;
+        state_manager.restore_snapshots()?;
+
+        // Mark the database open; close() removes the marker again
+        state_manager.ensure_genesis_hash()?;
+        state_manager.put_cf(state_manager.cf(CF_METADATA)?, SCHEMA_VERSION_KEY, bincode::serialize(&SCHEMA_VERSION)?)?;
+
+        let unclean_shutdown = state_manager.db.get_cf(state_manager.cf(CF_METADATA)?, OPEN_MARKER_KEY)?.is_some();
+        state_manager.unclean_shutdown = unclean_shutdown;
+        state_manager.put_cf(state_manager.cf(CF_METADATA)?, OPEN_MARKER_KEY, [])?;
+
+        Ok(state_manager)
+    }
+
+    /// Opens an existing database without taking the write lock, so tooling
+    /// can inspect it while a node is running.
+    pub fn open_read_only(db_path: &str) -> anyhow::Result<Self> {
+        // Column families added since the database was last opened for
+        // writing don't exist yet, and can't be created read-only
+        let existing = rocksdb::DB::list_cf(&rocksdb::Options::default(), db_path)?;
+        let db = rocksdb::DB::open_cf_for_read_only(
+            &rocksdb::Options::default(),
+            db_path,
+            COLUMN_FAMILIES.iter().filter(|name| existing.iter().any(|cf| cf == *name)),
+            false
+        )?;
+        Self::check_schema_version(&db, db_path)?;
+
+        let metadata = db.cf_handle(CF_METADATA)
+            .ok_or_else(|| anyhow::anyhow!("Missing column family: {}", CF_METADATA))?;
+        let hash_function = match db.get_cf(metadata, HASH_FUNCTION_KEY)? {
+            Some(data) => bincode::deserialize(&data)?,
+            None => anyhow::bail!("State database at {} has no genesis metadata", db_path),
+        };
+
+        Self::from_db(db, hash_function)
+    }
+
+    fn from_db(db: rocksdb::DB, hash_function: HashFunction) -> anyhow::Result<Self> {
+        let state_tree = db.cf_handle(CF_STATE_TREE)
+            .ok_or_else(|| anyhow::anyhow!("Missing column family: {}", CF_STATE_TREE))?;
+        let state_root = stored_root(&db, state_tree)?;
 
         Ok(Self {
             accounts: Arc::new(RwLock::new(HashMap::new())),
             db: Arc::new(db),
-            state_root: Arc::new(RwLock::new([0u8; 32]))
+            state_root: Arc::new(RwLock::new(state_root)),
+            tree_lock: Arc::new(Mutex::new(HashSet::new())),
+            withdrawal_lock: Arc::new(Mutex::new(())),
+            block_lock: Arc::new(tokio::sync::Mutex::new(())),
+            staged: Arc::new(Mutex::new(None)),
+            program_usage: Arc::new(Mutex::new(HashMap::new())),
+            snapshots: Arc::new(RwLock::new(SnapshotJournal::default())),
+            nonces: Arc::new(RwLock::new(HashMap::new())),
+            processed_signatures: Arc::new(RwLock::new(HashSet::new())),
+            hash_function,
+            hasher: hash_function.hasher(),
+            sync_writes: false,
+            unclean_shutdown: false,
+            cold: None,
+            events: EventBus::default()
         })
     }
 
+    /// Reads batches and reports missing from this database from `cold`, and
+    /// lets `migrate_to_cold` move old ones there.
+    pub fn with_cold_storage(mut self, cold: ColdStorage) -> Self {
+        self.cold = Some(Arc::new(cold));
+        self
+    }
+
+    /// Blocks, transactions and account writes as they happen.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Identifies this chain. Created along with the database (or on first
+    /// open, for databases that predate it) and never changes afterwards.
+    pub fn genesis_hash(&self) -> anyhow::Result<Option<[u8; 32]>> {
+        match self.db.get_cf(self.cf(CF_METADATA)?, GENESIS_HASH_KEY)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn ensure_genesis_hash(&self) -> anyhow::Result<()> {
+        if self.genesis_hash()?.is_some() {
+            return Ok(());
+        }
+
+        // Creation time makes two chains with the same settings distinguishable
+        let created_at = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_nanos();
+        let genesis_hash = self.hasher.hash(&bincode::serialize(&(self.hash_function, created_at))?);
+        self.put_cf(self.cf(CF_METADATA)?, GENESIS_HASH_KEY, bincode::serialize(&genesis_hash)?)?;
+
+        Ok(())
+    }
+
+    /// Builds the state tree from every stored account if this database
+    /// predates it.
+    fn ensure_state_tree(&self) -> anyhow::Result<()> {
+        if self.db.get_cf(self.cf(CF_METADATA)?, STATE_TREE_KEY)?.is_some() {
+            return Ok(());
+        }
+
+        let mut after = None;
+        let mut accounts = 0;
+        loop {
+            let page = self.account_page(after.as_ref(), TREE_REBUILD_CHUNK)?;
+            let Some((last, _)) = page.last() else {
+                break;
+            };
+            after = Some(*last);
+            accounts += page.len();
+
+            let changes: Vec<_> = page.iter().map(|(pubkey, account)| (*pubkey, Some(account))).collect();
+            drop(self.write_with_tree(rocksdb::WriteBatch::default(), &changes)?);
+        }
+
+        self.put_cf(self.cf(CF_METADATA)?, STATE_TREE_KEY, [])?;
+        if accounts > 0 {
+            println!("Built the state tree over {} accounts: root {}", accounts, hex::encode(self.get_state_root()));
+        }
+
+        Ok(())
+    }
+
+    /// Indexes the blockhashes of the blocks still in the validity window, in
+    /// case they were stored before blockhashes were indexed.
+    fn ensure_blockhash_index(&self) -> anyhow::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        let recent = self.db.iterator_cf(self.cf(CF_BLOCKS)?, rocksdb::IteratorMode::End)
+            .take(BLOCKHASH_VALIDITY_SLOTS as usize + 1);
+        for entry in recent {
+            let header: BlockHeader = bincode::deserialize(&entry?.1)?;
+            batch.put_cf(self.cf(CF_BLOCKHASHES)?, header.blockhash, bincode::serialize(&header.slot)?);
+        }
+        self.write(batch)
+    }
+
+    /// Reloads the pre-images of the batches sealed after the finalized one,
+    /// so confirmed and finalized reads of them survive a restart.
+    fn restore_snapshots(&self) -> anyhow::Result<()> {
+        let finalized_batch = self.finalized_batch()?;
+        let mut snapshots = self.snapshots.write().unwrap();
+        for entry in self.db.iterator_cf(self.cf(CF_SNAPSHOT_LAYERS)?, rocksdb::IteratorMode::Start) {
+            let (key, value) = entry?;
+            let batch_id = u64::from_be_bytes(key[..].try_into()?);
+            if finalized_batch.is_none_or(|finalized| batch_id > finalized) {
+                snapshots.restore(batch_id, bincode::deserialize(&value)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `batch` together with the state tree changes for `changes`
+    /// (`None` for removed accounts), and adopts the new root. Returns the
+    /// tree lock, still held.
+    fn write_with_tree(
+        &self,
+        mut batch: rocksdb::WriteBatch,
+        changes: &[(Pubkey, Option<&L2Account>)]
+    ) -> anyhow::Result<MutexGuard<'_, HashSet<Pubkey>>> {
+        let tree_lock = self.tree_lock.lock().unwrap();
+
+        let mut tree = TreeUpdate::new(&self.db, self.cf(CF_STATE_TREE)?, self.hasher.as_ref());
+        for (pubkey, account) in changes {
+            tree.set_leaf(pubkey, account_leaf(self.hasher.as_ref(), pubkey, *account)?)?;
+        }
+        let root = tree.finish(&mut batch);
+
+        self.write(batch)?;
+        if let Some(root) = root {
+            *self.state_root.write().unwrap() = root;
+        }
+
+        Ok(tree_lock)
+    }
+
+    /// Whether the previous node using this database exited without close().
+    pub fn unclean_shutdown(&self) -> bool {
+        self.unclean_shutdown
+    }
+
+    /// Flushes every memtable and syncs the WAL, then marks the shutdown
+    /// clean. Call once writers have stopped.
+    pub fn close(&self) -> anyhow::Result<()> {
+        let metadata = self.cf(CF_METADATA)?;
+
+        self.db.flush()?;
+        for name in COLUMN_FAMILIES {
+            self.db.flush_cf(self.cf(name)?)?;
+        }
+
+        self.db.delete_cf_opt(metadata, OPEN_MARKER_KEY, &self.write_options())?;
+        self.db.flush_wal(true)?;
+
+        Ok(())
+    }
+
+    /// Whether RocksDB is currently stopping or delaying writes because
+    /// compaction has fallen behind.
+    pub fn write_stalled(&self) -> anyhow::Result<bool> {
+        let stopped = self.db.property_int_value("rocksdb.is-write-stopped")?.unwrap_or(0);
+        let delayed_rate = self.db.property_int_value("rocksdb.actual-delayed-write-rate")?.unwrap_or(0);
+        Ok(stopped > 0 || delayed_rate > 0)
+    }
+
+    /// Layout version of the database, or `None` for one created before
+    /// versioning, which opening for writing migrates.
+    pub fn schema_version(&self) -> anyhow::Result<Option<u32>> {
+        Self::stored_schema_version(&self.db)
+    }
+
+    fn stored_schema_version(db: &rocksdb::DB) -> anyhow::Result<Option<u32>> {
+        let metadata = db.cf_handle(CF_METADATA)
+            .ok_or_else(|| anyhow::anyhow!("Missing column family: {}", CF_METADATA))?;
+
+        match db.get_cf(metadata, SCHEMA_VERSION_KEY)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn check_schema_version(db: &rocksdb::DB, db_path: &str) -> anyhow::Result<()> {
+        if let Some(version) = Self::stored_schema_version(db)?
+            && version > SCHEMA_VERSION
+        {
+            anyhow::bail!(
+                "State database at {} has schema version {}, but this build only supports up to {}; upgrade tiny-rollup",
+                db_path, version, SCHEMA_VERSION
+            );
+        }
+
+        Ok(())
+    }
+
+    fn load_hash_function(db: &rocksdb::DB, requested: Option<HashFunction>) -> anyhow::Result<HashFunction> {
+        let metadata = db.cf_handle(CF_METADATA)
+            .ok_or_else(|| anyhow::anyhow!("Missing column family: {}", CF_METADATA))?;
+
+        match db.get_cf(metadata, HASH_FUNCTION_KEY)? {
+            Some(data) => {
+                let stored: HashFunction = bincode::deserialize(&data)?;
+                if let Some(requested) = requested
+                    && requested != stored
+                {
+                    anyhow::bail!(
+                        "State database was created with {:?} state hashing, but {:?} was requested",
+                        stored, requested
+                    );
+                }
+                Ok(stored)
+            }
+            None => {
+                // Genesis: pin the hash function for the lifetime of this state
+                let hash_function = requested.unwrap_or(HashFunction::Blake3);
+                db.put_cf(metadata, HASH_FUNCTION_KEY, bincode::serialize(&hash_function)?)?;
+                Ok(hash_function)
+            }
+        }
+    }
+
     pub async fn get_account(&self, pubkey: &Pubkey) -> Option<L2Account> {
+        self.load_account(pubkey)
+    }
+
+    /// Reads an account as of `commitment`. The pre-images of batches not
+    /// finalized yet are stored with their blocks and survive a restart;
+    /// changes made between blocks before a restart read the same at every
+    /// commitment.
+    pub async fn get_account_at(&self, pubkey: &Pubkey, commitment: Commitment) -> Option<L2Account> {
+        // Hold the journal while reading live state so a concurrent write
+        // can't be half-applied from our point of view
+        let snapshots = self.snapshots.read().unwrap();
+        match snapshots.lookup(pubkey, commitment).or_else(|| self.staged_account(pubkey, commitment)) {
+            Some(snapshot) => snapshot,
+            None => self.load_stored_account(pubkey),
+        }
+    }
+
+    /// Reads several accounts as of `commitment` in one pass: a single view
+    /// of the snapshots and the cache, and one RocksDB multi-get for what
+    /// neither holds. Results are in the order of `pubkeys`.
+    pub async fn get_multiple_accounts_at(&self, pubkeys: &[Pubkey], commitment: Commitment) -> Vec<Option<L2Account>> {
+        // Held throughout, as in get_account_at, so no write lands halfway
+        let snapshots = self.snapshots.read().unwrap();
+        let mut accounts: Vec<Option<Option<L2Account>>> = {
+            let cache = self.accounts.read().unwrap();
+            pubkeys.iter()
+                .map(|pubkey| snapshots.lookup(pubkey, commitment)
+                    .or_else(|| self.staged_account(pubkey, commitment))
+                    .or_else(|| cache.get(pubkey).map(|account| Some(account.clone()))))
+                .collect()
+        };
+
+        let missing: Vec<usize> = (0..pubkeys.len()).filter(|&i| accounts[i].is_none()).collect();
+        let stored = self.db.multi_get(missing.iter().map(|&i| pubkeys[i].to_bytes()));
+        let mut cache = self.accounts.write().unwrap();
+        for (i, data) in missing.into_iter().zip(stored) {
+            let account = data.ok()
+                .flatten()
+                .and_then(|data| bincode::deserialize::<L2Account>(&data).ok());
+            if let Some(account) = &account {
+                cache.insert(pubkeys[i], account.clone());
+            }
+            accounts[i] = Some(account);
+        }
+
+        accounts.into_iter().map(Option::flatten).collect()
+    }
+
+    /// Reads live state without awaiting, for the SVM's account loader.
+    /// Includes what the block being built changed so far.
+    pub fn load_account(&self, pubkey: &Pubkey) -> Option<L2Account> {
+        match self.staged_account(pubkey, Commitment::Processed) {
+            Some(account) => account,
+            None => self.load_stored_account(pubkey),
+        }
+    }
+
+    /// `pubkey` as the block being built left it so far, or for other
+    /// commitments as it was before that block; None if the block didn't
+    /// change it or no block is being built.
+    fn staged_account(&self, pubkey: &Pubkey, commitment: Commitment) -> Option<Option<L2Account>> {
+        let staged = self.staged.lock().unwrap();
+        let block = staged.as_ref()?;
+        match commitment {
+            Commitment::Processed => block.accounts.get(pubkey).cloned(),
+            Commitment::Confirmed | Commitment::Finalized => block.pre_images.get(pubkey).cloned(),
+        }
+    }
+
+    /// Reads state as stored, without the block being built.
+    fn load_stored_account(&self, pubkey: &Pubkey) -> Option<L2Account> {
         // First we check memory
         if let Some(account) = self.accounts.read().unwrap().get(pubkey) {
             return Some(account.clone());
         }
 
         // Then check persistent storage
-        if let Ok(Some(data)) = self.db.get(pubkey.to_bytes()) {
-            if let Ok(account) = bincode::deserialize::<L2Account>(&data) {
-                // cache result in memory
-                self.accounts.write().unwrap().insert(*pubkey, account.clone());
-                return Some(account);
-            }
+        if let Ok(Some(data)) = self.db.get(pubkey.to_bytes())
+            && let Ok(account) = bincode::deserialize::<L2Account>(&data)
+        {
+            // cache result in memory
+            self.accounts.write().unwrap().insert(*pubkey, account.clone());
+            return Some(account);
         }
 
         None
     }
 
     pub async fn update_account(&self, pubkey: &Pubkey, account: L2Account) -> anyhow::Result<()> {
-        // Update memory
-        self.accounts.write().unwrap().insert(*pubkey, account.clone());
+        self.commit_accounts(vec![(*pubkey, Some(account))]).await
+    }
+
+    /// Removes an account from state along with every index entry pointing at it.
+    pub async fn close_account(&self, pubkey: &Pubkey) -> anyhow::Result<()> {
+        self.commit_accounts(vec![(*pubkey, None)]).await
+    }
+
+    /// Writes every account of `changes` (`None` closes one) with its index
+    /// entries and state tree leaves in a single RocksDB write, so either all
+    /// of them land or, if the write fails, none do and the cache, snapshots
+    /// and subscribers never see any. Each pubkey may appear once. While a
+    /// block is being built, the changes are staged into it instead.
+    pub async fn commit_accounts(&self, changes: Vec<(Pubkey, Option<L2Account>)>) -> anyhow::Result<()> {
+        self.commit_accounts_with(|_| Ok(()), changes).await
+    }
+
+    /// `commit_accounts`, with what `writes` adds written (or staged) along
+    /// with the accounts.
+    async fn commit_accounts_with(
+        &self,
+        writes: impl FnOnce(&mut rocksdb::WriteBatch) -> anyhow::Result<()>,
+        changes: Vec<(Pubkey, Option<L2Account>)>
+    ) -> anyhow::Result<()> {
+        let mut previous = Vec::with_capacity(changes.len());
+        for (pubkey, _) in &changes {
+            previous.push(self.get_account(pubkey).await);
+        }
+
+        if let Some(block) = self.staged.lock().unwrap().as_mut() {
+            if block.state_root.is_some() {
+                anyhow::bail!("The block being built is already sealed");
+            }
+            writes(&mut block.write)?;
+            for ((pubkey, account), before) in changes.into_iter().zip(previous) {
+                self.write_account(&mut block.write, &pubkey, before.as_ref(), account.as_ref())?;
+                block.pre_images.entry(pubkey).or_insert(before);
+                block.accounts.insert(pubkey, account.clone());
+                block.changes.push((pubkey, account));
+            }
+            return Ok(());
+        }
+
+        let mut batch = rocksdb::WriteBatch::default();
+        writes(&mut batch)?;
+        for ((pubkey, account), before) in changes.iter().zip(&previous) {
+            self.write_account(&mut batch, pubkey, before.as_ref(), account.as_ref())?;
+        }
+        let tree_changes: Vec<_> = changes.iter().map(|(pubkey, account)| (*pubkey, account.as_ref())).collect();
+        self.write_with_tree(batch, &tree_changes)?.extend(changes.iter().map(|(pubkey, _)| *pubkey));
 
-        // persist to storage
-        let serialized = bincode::serialize(&account)?;
-        self.db.put(pubkey.to_bytes(), serialized)?;
+        // Only touched once the write landed
+        let mut snapshots = self.snapshots.write().unwrap();
+        let mut accounts = self.accounts.write().unwrap();
+        for ((pubkey, account), before) in changes.into_iter().zip(previous) {
+            snapshots.record(pubkey, before);
+            self.events.publish(|| RollupEvent::Account { pubkey, account: account.clone() });
+            match account {
+                Some(account) => accounts.insert(pubkey, account),
+                None => accounts.remove(&pubkey),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Writes `pubkey`'s change from `previous` to `account` into `batch`,
+    /// with its index entries.
+    fn write_account(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        pubkey: &Pubkey,
+        previous: Option<&L2Account>,
+        account: Option<&L2Account>
+    ) -> anyhow::Result<()> {
+        match account {
+            Some(account) => batch.put(pubkey.to_bytes(), bincode::serialize(account)?),
+            None => batch.delete(pubkey.to_bytes()),
+        }
+        self.update_indexes(batch, pubkey, previous, account)
+    }
+
+    /// Loads up to `limit` of the most recently written accounts into the
+    /// in-memory cache, so the first requests after a restart don't all go
+    /// to RocksDB.
+    pub fn warm_cache(&self, limit: usize) -> anyhow::Result<WarmupStats> {
+        let started = std::time::Instant::now();
+
+        // Keep only the `limit` newest entries while scanning
+        let mut newest = std::collections::BinaryHeap::with_capacity(limit + 1);
+        for entry in self.db.iterator_cf(self.cf(CF_RECENT_ACCOUNTS)?, rocksdb::IteratorMode::Start) {
+            let (key, value) = entry?;
+            let touched_at = u64::from_be_bytes(value.as_ref().try_into()?);
+            newest.push(std::cmp::Reverse((touched_at, Pubkey::try_from(key.as_ref())?)));
+            if newest.len() > limit {
+                newest.pop();
+            }
+        }
+
+        let mut stats = WarmupStats { accounts: 0, bytes: 0, elapsed: Default::default() };
+        for std::cmp::Reverse((_, pubkey)) in newest {
+            if let Some(account) = self.load_account(&pubkey) {
+                stats.accounts += 1;
+                stats.bytes += account.data.len();
+            }
+        }
+        stats.elapsed = started.elapsed();
+
+        Ok(stats)
+    }
+
+    /// Every account in state, read from RocksDB.
+    pub fn all_accounts(&self) -> anyhow::Result<Vec<(Pubkey, L2Account)>> {
+        let mut accounts = Vec::new();
+        for entry in self.db.iterator(rocksdb::IteratorMode::Start) {
+            let (key, value) = entry?;
+            accounts.push((Pubkey::try_from(key.as_ref())?, bincode::deserialize(&value)?));
+        }
+
+        Ok(accounts)
+    }
+
+    /// Up to `limit` accounts in key order, starting after `after`.
+    pub fn account_page(&self, after: Option<&Pubkey>, limit: usize) -> anyhow::Result<Vec<(Pubkey, L2Account)>> {
+        let start = after.map(|pubkey| pubkey.to_bytes());
+        account_page(self.db.iterator(page_start(start.as_ref())), start, limit)
+    }
+
+    /// A consistent view of the accounts, state root and sealed blocks as
+    /// they are now, unaffected by later writes.
+    pub fn view(&self) -> StateView<'_> {
+        StateView { state_manager: self, snapshot: self.db.snapshot() }
+    }
+
+    /// Root of every stored account, computed from the accounts themselves
+    /// rather than the stored tree.
+    pub fn recompute_state_root(&self) -> anyhow::Result<[u8; 32]> {
+        let mut root = RootBuilder::new(self.hasher.as_ref());
+        for entry in self.db.iterator(rocksdb::IteratorMode::Start) {
+            let (key, value) = entry?;
+            let pubkey = Pubkey::try_from(key.as_ref())?;
+            let account: L2Account = bincode::deserialize(&value)?;
+            root.push(&pubkey, account_leaf(self.hasher.as_ref(), &pubkey, Some(&account))?)?;
+        }
+
+        Ok(root.finish())
+    }
+
+    /// Whether an import into this database started without its state
+    /// root being verified, so the database must not be served.
+    pub fn unverified_import(&self) -> anyhow::Result<bool> {
+        let metadata = self.cf(CF_METADATA)?;
+        Ok(self.db.get_cf(metadata, IMPORT_PROGRESS_KEY)?.is_some()
+            && self.db.get_cf(metadata, GENESIS_STATE_ROOT_KEY)?.is_none())
+    }
+
+    /// Index of the next chunk an import into this database expects.
+    pub fn import_progress(&self) -> anyhow::Result<u64> {
+        match self.db.get_cf(self.cf(CF_METADATA)?, IMPORT_PROGRESS_KEY)? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Writes one chunk of imported accounts, their indexes and state tree
+    /// leaves, together with the import progress, in one write. Bypasses the
+    /// cache.
+    pub fn import_chunk(&self, index: u64, accounts: &[(Pubkey, L2Account)]) -> anyhow::Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for (pubkey, account) in accounts {
+            batch.put(pubkey.to_bytes(), bincode::serialize(account)?);
+            self.update_indexes(&mut batch, pubkey, None, Some(account))?;
+        }
+        batch.put_cf(self.cf(CF_METADATA)?, IMPORT_PROGRESS_KEY, bincode::serialize(&(index + 1))?);
+
+        let changes: Vec<_> = accounts.iter().map(|(pubkey, account)| (*pubkey, Some(account))).collect();
+        drop(self.write_with_tree(batch, &changes)?);
+        Ok(())
+    }
+
+    /// Adopts the identity of the chain an import came from, after checking
+    /// both the imported accounts and the state tree built from them commit
+    /// to `state_root`, the root the export was taken at. Until then an
+    /// import counts as unverified.
+    pub fn restore_chain_metadata(&self, genesis_hash: Option<[u8; 32]>, state_root: Option<[u8; 32]>) -> anyhow::Result<()> {
+        if let Some(state_root) = state_root {
+            let recomputed = self.recompute_state_root()?;
+            if recomputed != state_root {
+                anyhow::bail!(
+                    "Imported accounts have state root {}, but the export was taken at {}",
+                    hex::encode(recomputed),
+                    hex::encode(state_root)
+                );
+            }
+            if self.get_state_root() != state_root {
+                anyhow::bail!(
+                    "State tree built by the import has root {}, but its accounts have {}",
+                    hex::encode(self.get_state_root()),
+                    hex::encode(state_root)
+                );
+            }
+        }
+
+        let metadata = self.cf(CF_METADATA)?;
+        let mut batch = rocksdb::WriteBatch::default();
+        if let Some(genesis_hash) = genesis_hash {
+            batch.put_cf(metadata, GENESIS_HASH_KEY, bincode::serialize(&genesis_hash)?);
+        }
+        batch.put_cf(metadata, GENESIS_STATE_ROOT_KEY, self.get_state_root());
+        self.write(batch)?;
+
+        Ok(())
+    }
+
+    /// State root `batch_id` was executed on top of: the root its parent
+    /// block was sealed with, or for the first batch, the state the chain
+    /// started from.
+    pub fn pre_state_root(&self, batch_id: u64) -> anyhow::Result<[u8; 32]> {
+        let Some(parent) = batch_id.checked_sub(1) else {
+            return match self.db.get_cf(self.cf(CF_METADATA)?, GENESIS_STATE_ROOT_KEY)? {
+                Some(data) => Ok(data.as_slice().try_into()?),
+                None => Ok(EMPTY),
+            };
+        };
+        if let Some(header) = self.get_block(parent)? {
+            return Ok(header.state_root);
+        }
+        match self.get_batch(parent)? {
+            Some(batch) => Ok(batch.state_root),
+            None => anyhow::bail!("No state root for batch {}, the parent of batch {}", parent, batch_id),
+        }
+    }
+
+    /// Every account owned by `owner`, read through the owner index.
+    pub async fn get_accounts_by_owner(&self, owner: &Pubkey) -> anyhow::Result<Vec<(Pubkey, L2Account)>> {
+        let owner_index = self.cf(CF_OWNER_INDEX)?;
+        let prefix = owner.to_bytes();
+
+        let mut accounts = Vec::new();
+        let mode = rocksdb::IteratorMode::From(&prefix, rocksdb::Direction::Forward);
+        for entry in self.db.iterator_cf(owner_index, mode) {
+            let (key, _) = entry?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+
+            let pubkey = Pubkey::try_from(&key[32..])?;
+            // The account may have been closed or reassigned since the index was read
+            if let Some(account) = self.load_account(&pubkey)
+                && account.owner == *owner
+            {
+                accounts.push((pubkey, account));
+            }
+        }
+
+        Ok(accounts)
+    }
+
+    /// Stages secondary index changes for an account transition into `batch`,
+    /// so indexes are written atomically with the account itself.
+    fn update_indexes(
+        &self,
+        batch: &mut rocksdb::WriteBatch,
+        pubkey: &Pubkey,
+        previous: Option<&L2Account>,
+        current: Option<&L2Account>
+    ) -> anyhow::Result<()> {
+        // Last write time, used to pick accounts to warm on startup
+        let recent_accounts = self.cf(CF_RECENT_ACCOUNTS)?;
+        match current {
+            Some(_) => {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_millis() as u64;
+                batch.put_cf(recent_accounts, pubkey.to_bytes(), now.to_be_bytes());
+            }
+            None => batch.delete_cf(recent_accounts, pubkey.to_bytes()),
+        }
+
+        let owner_index = self.cf(CF_OWNER_INDEX)?;
+
+        let previous_owner = previous.map(|a| a.owner);
+        let current_owner = current.map(|a| a.owner);
+        if previous_owner == current_owner {
+            return Ok(());
+        }
 
-        // Update state root
-        self.update_state_root().await?;
+        if let Some(owner) = previous_owner {
+            batch.delete_cf(owner_index, owner_index_key(&owner, pubkey));
+        }
+        if let Some(owner) = current_owner {
+            batch.put_cf(owner_index, owner_index_key(&owner, pubkey), []);
+        }
 
         Ok(())
     }
 
-    async fn update_state_root(&self) -> anyhow::Result<()> {
-        // TODO: use proper Merkle tree
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+    /// The state root a block sealed now commits to, with the diff of every
+    /// account committed since the last block was sealed. Both are read
+    /// under the tree lock, so no commit lands between them. While a block
+    /// is being built, this seals it: its accounts are hashed into the tree
+    /// and nothing more can be staged.
+    pub fn seal_state(&self) -> anyhow::Result<([u8; 32], StateDiff)> {
+        let mut unsealed = self.tree_lock.lock().unwrap();
+        let mut staged = self.staged.lock().unwrap();
+
+        let mut pubkeys: Vec<Pubkey> = match staged.as_ref() {
+            // Left in the set until the block is stored
+            Some(_) => unsealed.iter().copied().collect(),
+            None => unsealed.drain().collect(),
+        };
+        if let Some(block) = staged.as_ref() {
+            pubkeys.extend(block.accounts.keys().filter(|pubkey| !unsealed.contains(pubkey)));
+        }
+        pubkeys.sort();
 
-        let accounts = self.accounts.read().unwrap();
-        let mut hasher = DefaultHasher::new();
+        // From the database, which the cache may still lag behind
+        let mut diff = Vec::with_capacity(pubkeys.len());
+        for pubkey in pubkeys {
+            if let Some(account) = staged.as_ref().and_then(|block| block.accounts.get(&pubkey)) {
+                diff.push((pubkey, account.clone()));
+                continue;
+            }
+            let account = match self.db.get(pubkey.to_bytes())? {
+                Some(data) => Some(bincode::deserialize(&data)?),
+                None => None,
+            };
+            diff.push((pubkey, account));
+        }
 
-        for (pubkey, account) in accounts.iter() {
-            pubkey.hash(&mut hasher);
-            account.lamports.hash(&mut hasher);
-            account.data.hash(&mut hasher);
+        let Some(block) = staged.as_mut() else {
+            return Ok((self.get_state_root(), diff));
+        };
+        if block.state_root.is_some() {
+            anyhow::bail!("The block being built is already sealed");
+        }
+        let mut changes: Vec<_> = block.accounts.iter().collect();
+        changes.sort_by_key(|(pubkey, _)| **pubkey);
+        let mut tree = TreeUpdate::new(&self.db, self.cf(CF_STATE_TREE)?, self.hasher.as_ref());
+        for (pubkey, account) in changes {
+            tree.set_leaf(pubkey, account_leaf(self.hasher.as_ref(), pubkey, account.as_ref())?)?;
         }
+        let root = tree.finish(&mut block.write).unwrap_or_else(|| self.get_state_root());
+        block.state_root = Some(root);
 
-        let hash = hasher.finish();
-        let mut state_root = [0u8; 32];
-        state_root[..8].copy_from_slice(&hash.to_le_bytes());
-        *self.state_root.write().unwrap() = state_root;
+        Ok((root, diff))
+    }
+
+    /// Starts building a block: until `put_block` stores it or
+    /// `discard_block` drops it, account commits and executed transactions
+    /// are staged into it rather than written. Call with `lock_blocks` held.
+    pub fn begin_block(&self) -> anyhow::Result<()> {
+        let mut staged = self.staged.lock().unwrap();
+        if staged.is_some() {
+            anyhow::bail!("A block is already being built");
+        }
+        *staged = Some(StagedBlock::default());
         Ok(())
     }
 
+    /// Drops the block being built, and everything staged into it.
+    pub fn discard_block(&self) {
+        if self.staged.lock().unwrap().take().is_some() {
+            self.program_usage.lock().unwrap().clear();
+        }
+    }
+
+    /// Held while a block is built; anything else changing state between
+    /// blocks takes it too.
+    pub async fn lock_blocks(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.block_lock.lock().await
+    }
+
     pub fn get_state_root(&self) -> [u8; 32] {
         *self.state_root.read().unwrap()
     }
+
+    /// Proof of `pubkey`'s leaf against the persisted state root, which is
+    /// the state root between blocks; see `sparse_merkle::prove`.
+    pub fn account_proof(&self, pubkey: &Pubkey) -> anyhow::Result<Vec<[u8; 32]>> {
+        prove(&self.db, self.cf(CF_STATE_TREE)?, pubkey)
+    }
+
+    pub fn hash_function(&self) -> HashFunction {
+        self.hash_function
+    }
+
+    pub fn hasher(&self) -> &dyn StateHasher {
+        self.hasher.as_ref()
+    }
+
+    /// Freezes the confirmed view at the current state for `batch_id`.
+    pub fn seal_snapshot(&self, batch_id: u64) {
+        self.snapshots.write().unwrap().seal(batch_id);
+    }
+
+    /// Advances the finalized view to the state sealed with `batch_id`, and
+    /// forgets the stored pre-images of it and the batches before.
+    pub fn finalize_snapshot(&self, batch_id: u64) -> anyhow::Result<()> {
+        self.snapshots.write().unwrap().finalize(batch_id);
+        self.db.delete_range_cf(self.cf(CF_SNAPSHOT_LAYERS)?, 0u64.to_be_bytes(), (batch_id + 1).to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Number of transactions `pubkey` has paid for so far. Only a count:
+    /// replays are refused by signature within the blockhash window.
+    pub fn get_nonce(&self, pubkey: &Pubkey) -> anyhow::Result<u64> {
+        if let Some(nonce) = self.staged.lock().unwrap().as_ref().and_then(|block| block.nonces.get(pubkey).copied()) {
+            return Ok(nonce);
+        }
+        if let Some(nonce) = self.nonces.read().unwrap().get(pubkey) {
+            return Ok(*nonce);
+        }
+
+        let nonce = match self.db.get_cf(self.cf(CF_NONCES)?, pubkey.to_bytes())? {
+            Some(data) => bincode::deserialize(&data)?,
+            None => 0,
+        };
+        self.nonces.write().unwrap().insert(*pubkey, nonce);
+
+        Ok(nonce)
+    }
+
+    /// Whether `signature` executed in the last `BLOCKHASH_VALIDITY_SLOTS`
+    /// blocks. Older ones are forgotten, as their blockhashes have expired.
+    pub fn is_signature_processed(&self, signature: &Signature) -> anyhow::Result<bool> {
+        if self.staged.lock().unwrap().as_ref().is_some_and(|block| block.signatures.contains(signature)) {
+            return Ok(true);
+        }
+        if self.processed_signatures.read().unwrap().contains(signature) {
+            return Ok(true);
+        }
+
+        let processed = self.db.get_cf(self.cf(CF_PROCESSED_SIGNATURES)?, signature)?.is_some();
+        if processed {
+            self.processed_signatures.write().unwrap().insert(*signature);
+        }
+
+        Ok(processed)
+    }
+
+    /// Whether a transaction signed against `blockhash` can still execute
+    /// in the block at `slot`: it is a block's, or the genesis hash, which
+    /// getLatestBlockhash hands out as slot 0's before the first block, and
+    /// at most `BLOCKHASH_VALIDITY_SLOTS` slots old.
+    pub fn is_recent_blockhash(&self, blockhash: &Hash, slot: u64) -> anyhow::Result<bool> {
+        let issued = match self.db.get_cf(self.cf(CF_BLOCKHASHES)?, blockhash)? {
+            Some(data) => bincode::deserialize::<u64>(&data)?,
+            None if self.genesis_hash()? == Some(blockhash.to_bytes()) => 0,
+            None => return Ok(false),
+        };
+
+        Ok(slot <= issued.saturating_add(BLOCKHASH_VALIDITY_SLOTS))
+    }
+
+    /// Records an executed transaction for replay protection and
+    /// `getTransaction`, and bumps its fee payer's nonce, all in one write.
+    /// While a block is being built, the record is staged into it and
+    /// stored, already sealed, with the block. Returns the new nonce.
+    pub fn record_processed_transaction(
+        &self,
+        signature: &Signature,
+        fee_payer: &Pubkey,
+        record: &TransactionRecord
+    ) -> anyhow::Result<u64> {
+        let nonce = self.get_nonce(fee_payer)? + 1;
+        let sequence = self.next_id(NEXT_TRANSACTION_SEQUENCE_KEY)?;
+        // The block the transaction executes in, which its signature expires with
+        let slot = self.batch_count()?;
+        let write = |batch: &mut rocksdb::WriteBatch| -> anyhow::Result<()> {
+            batch.put_cf(self.cf(CF_NONCES)?, fee_payer.to_bytes(), bincode::serialize(&nonce)?);
+            batch.put_cf(self.cf(CF_PROCESSED_SIGNATURES)?, signature, bincode::serialize(&slot)?);
+            batch.put_cf(self.cf(CF_SIGNATURE_SLOTS)?, signature_slot_key(slot, signature), []);
+            for address in record.addresses() {
+                batch.put_cf(self.cf(CF_ADDRESS_ACTIVITY)?, activity_key(&address, sequence), signature);
+            }
+            Ok(())
+        };
+
+        if let Some(block) = self.staged.lock().unwrap().as_mut() {
+            if block.state_root.is_some() {
+                anyhow::bail!("The block being built is already sealed");
+            }
+            write(&mut block.write)?;
+            block.nonces.insert(*fee_payer, nonce);
+            block.signatures.insert(*signature);
+            block.records.push((*signature, record.clone()));
+            return Ok(nonce);
+        }
+
+        let mut batch = rocksdb::WriteBatch::default();
+        write(&mut batch)?;
+        batch.put_cf(self.cf(CF_TRANSACTIONS)?, signature, bincode::serialize(record)?);
+        self.write(batch)?;
+        self.processed(signature, fee_payer, nonce, record);
+
+        Ok(nonce)
+    }
+
+    /// Caches a stored transaction's nonce and signature, and tells
+    /// subscribers it was processed.
+    fn processed(&self, signature: &Signature, fee_payer: &Pubkey, nonce: u64, record: &TransactionRecord) {
+        self.nonces.write().unwrap().insert(*fee_payer, nonce);
+        self.processed_signatures.write().unwrap().insert(*signature);
+        self.events.publish(|| RollupEvent::Transaction {
+            signature: *signature,
+            stage: TransactionStage::Processed,
+            accounts: record.transaction.message.account_keys.clone(),
+        });
+    }
+
+    /// Number of batch ids handed out so far.
+    pub fn batch_count(&self) -> anyhow::Result<u64> {
+        match self.db.get_cf(self.cf(CF_METADATA)?, NEXT_BATCH_ID_KEY)? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(0),
+        }
+    }
+
+    fn next_id(&self, key: &[u8]) -> anyhow::Result<u64> {
+        let metadata = self.cf(CF_METADATA)?;
+
+        let id = match self.db.get_cf(metadata, key)? {
+            Some(data) => bincode::deserialize::<u64>(&data)?,
+            None => 0,
+        };
+        self.put_cf(metadata, key, bincode::serialize(&(id + 1))?)?;
+
+        Ok(id)
+    }
+
+    /// Queues a signed withdrawal request to be sealed into the next block.
+    /// A request whose signature was already seen isn't queued again; its
+    /// existing entry is returned.
+    pub fn queue_withdrawal(&self, request: &WithdrawalRequest, signature: &Signature) -> anyhow::Result<WithdrawalEntry> {
+        let _guard = self.withdrawal_lock.lock().unwrap();
+        let signatures_cf = self.cf(CF_WITHDRAWAL_SIGNATURES)?;
+        if let Some(data) = self.db.get_cf(signatures_cf, signature)? {
+            let id = bincode::deserialize(&data)?;
+            return self.get_withdrawal(id)?
+                .ok_or_else(|| anyhow::anyhow!("Withdrawal {} is missing", id));
+        }
+
+        let entry = WithdrawalEntry {
+            record: WithdrawalRecord {
+                id: self.next_id(NEXT_WITHDRAWAL_ID_KEY)?,
+                l2_sender: request.l2_sender,
+                l1_recipient: request.l1_recipient,
+                lamports: request.lamports,
+            },
+            status: WithdrawalStatus::Pending,
+        };
+        let key = entry.record.id.to_be_bytes();
+        let mut write_batch = rocksdb::WriteBatch::default();
+        write_batch.put_cf(self.cf(CF_PENDING_WITHDRAWALS)?, key, bincode::serialize(&entry.record)?);
+        write_batch.put_cf(self.cf(CF_WITHDRAWALS)?, key, bincode::serialize(&entry)?);
+        write_batch.put_cf(signatures_cf, signature, bincode::serialize(&entry.record.id)?);
+        self.write(write_batch)?;
+
+        Ok(entry)
+    }
+
+    /// Seals every pending withdrawal into `batch_id`, in id order, debiting
+    /// the senders in the same write. A withdrawal its sender can't cover
+    /// when it is sealed, or from an account that isn't a plain system
+    /// account, is rejected instead. Returns the sealed ones.
+    pub async fn seal_withdrawals(&self, batch_id: u64) -> anyhow::Result<Vec<WithdrawalRecord>> {
+        let pending_cf = self.cf(CF_PENDING_WITHDRAWALS)?;
+
+        let mut entries = Vec::new();
+        let mut senders: Vec<(Pubkey, L2Account)> = Vec::new();
+        let mut sealed = Vec::new();
+        for entry in self.db.iterator_cf(pending_cf, rocksdb::IteratorMode::Start) {
+            let (_, value) = entry?;
+            let record = bincode::deserialize::<WithdrawalRecord>(&value)?;
+
+            let status = match self.debit(&mut senders, &record).await {
+                Ok(()) => {
+                    sealed.push(record.clone());
+                    WithdrawalStatus::Sealed { batch_id, index: sealed.len() - 1 }
+                }
+                Err(reason) => WithdrawalStatus::Rejected { reason },
+            };
+            entries.push(WithdrawalEntry { record, status });
+        }
+        self.finish_withdrawals(batch_id, entries, senders, &sealed).await?;
+        Ok(sealed)
+    }
+
+    /// Debits and records `withdrawals` as sealed into `batch_id`, as the
+    /// sequencer did; for replaying a batch.
+    pub async fn apply_withdrawals(&self, batch_id: u64, withdrawals: &[WithdrawalRecord]) -> anyhow::Result<()> {
+        let mut entries = Vec::with_capacity(withdrawals.len());
+        let mut senders: Vec<(Pubkey, L2Account)> = Vec::new();
+        for (index, record) in withdrawals.iter().enumerate() {
+            self.debit(&mut senders, record).await
+                .map_err(|reason| anyhow::anyhow!("Withdrawal {} of batch {} can't be applied: {}", record.id, batch_id, reason))?;
+            entries.push(WithdrawalEntry { record: record.clone(), status: WithdrawalStatus::Sealed { batch_id, index } });
+        }
+
+        self.finish_withdrawals(batch_id, entries, senders, withdrawals).await
+    }
+
+    /// Takes `record.lamports` from its sender's entry in `senders`, loading
+    /// the account on first use.
+    async fn debit(&self, senders: &mut Vec<(Pubkey, L2Account)>, record: &WithdrawalRecord) -> Result<(), String> {
+        let position = match senders.iter().position(|(pubkey, _)| *pubkey == record.l2_sender) {
+            Some(position) => position,
+            None => {
+                let Some(account) = self.get_account(&record.l2_sender).await else {
+                    return Err("sender account does not exist".to_string());
+                };
+                if !system_program::check_id(&account.owner) || !account.data.is_empty() {
+                    return Err("sender is not a system account".to_string());
+                }
+                senders.push((record.l2_sender, account));
+                senders.len() - 1
+            }
+        };
+        let account = &mut senders[position].1;
+        account.lamports = account.lamports.checked_sub(record.lamports)
+            .ok_or_else(|| format!("sender has {} lamports, {} requested", account.lamports, record.lamports))?;
+
+        Ok(())
+    }
+
+    /// Writes `entries`, taking them off the pending queue, the batch's
+    /// withdrawal list and the debited `senders` in one write. Emptied
+    /// accounts are closed.
+    async fn finish_withdrawals(
+        &self,
+        batch_id: u64,
+        entries: Vec<WithdrawalEntry>,
+        senders: Vec<(Pubkey, L2Account)>,
+        sealed: &[WithdrawalRecord]
+    ) -> anyhow::Result<()> {
+        let changes = senders.into_iter()
+            .map(|(pubkey, account)| (pubkey, (account.lamports > 0).then_some(account)))
+            .collect();
+
+        self.commit_accounts_with(|write_batch| {
+            for entry in &entries {
+                let key = entry.record.id.to_be_bytes();
+                write_batch.delete_cf(self.cf(CF_PENDING_WITHDRAWALS)?, key);
+                write_batch.put_cf(self.cf(CF_WITHDRAWALS)?, key, bincode::serialize(entry)?);
+            }
+            write_batch.put_cf(self.cf(CF_BATCH_WITHDRAWALS)?, batch_id.to_be_bytes(), bincode::serialize(sealed)?);
+            Ok(())
+        }, changes).await
+    }
+
+    /// A withdrawal by id, with where it is.
+    pub fn get_withdrawal(&self, id: u64) -> anyhow::Result<Option<WithdrawalEntry>> {
+        match self.db.get_cf(self.cf(CF_WITHDRAWALS)?, id.to_be_bytes())? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn has_pending_withdrawals(&self) -> anyhow::Result<bool> {
+        let mut pending = self.db.iterator_cf(self.cf(CF_PENDING_WITHDRAWALS)?, rocksdb::IteratorMode::Start);
+        Ok(pending.next().transpose()?.is_some())
+    }
+
+    pub fn pending_withdrawal_count(&self) -> anyhow::Result<usize> {
+        let mut count = 0;
+        for entry in self.db.iterator_cf(self.cf(CF_PENDING_WITHDRAWALS)?, rocksdb::IteratorMode::Start) {
+            entry?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn get_batch_withdrawals(&self, batch_id: u64) -> anyhow::Result<Option<Vec<WithdrawalRecord>>> {
+        match self.db.get_cf(self.cf(CF_BATCH_WITHDRAWALS)?, batch_id.to_be_bytes())? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Queues a deposit seen on L1 to be credited with the next sealed
+    /// block. Returns false, queuing nothing, if a deposit with its id was
+    /// already queued or credited.
+    pub fn queue_deposit(&self, deposit: &DepositRecord) -> anyhow::Result<bool> {
+        let key = deposit.id.to_be_bytes();
+        if self.db.get_cf(self.cf(CF_PENDING_DEPOSITS)?, key)?.is_some() || self.db.get_cf(self.cf(CF_DEPOSITS)?, key)?.is_some() {
+            return Ok(false);
+        }
+        self.put_cf(self.cf(CF_PENDING_DEPOSITS)?, key, bincode::serialize(deposit)?)?;
+
+        Ok(true)
+    }
+
+    /// Queued deposits, in id order.
+    pub fn pending_deposits(&self) -> anyhow::Result<Vec<DepositRecord>> {
+        self.db
+            .iterator_cf(self.cf(CF_PENDING_DEPOSITS)?, rocksdb::IteratorMode::Start)
+            .map(|entry| Ok(bincode::deserialize(&entry?.1)?))
+            .collect()
+    }
+
+    pub fn has_pending_deposits(&self) -> anyhow::Result<bool> {
+        let mut pending = self.db.iterator_cf(self.cf(CF_PENDING_DEPOSITS)?, rocksdb::IteratorMode::Start);
+        Ok(pending.next().transpose()?.is_some())
+    }
+
+    /// Credits `deposits` to their recipients as part of `batch_id`, moving
+    /// them out of the queue and recording them with the batch in the same
+    /// write as the accounts, so a deposit is credited exactly once.
+    pub async fn credit_deposits(&self, batch_id: u64, deposits: Vec<DepositRecord>) -> anyhow::Result<Vec<DepositRecord>> {
+        if deposits.is_empty() {
+            return Ok(deposits);
+        }
+
+        let mut recipients: Vec<(Pubkey, L2Account)> = Vec::new();
+        let mut credited = Vec::with_capacity(deposits.len());
+        for mut deposit in deposits {
+            deposit.batch_id = Some(batch_id);
+
+            let position = match recipients.iter().position(|(pubkey, _)| *pubkey == deposit.l2_recipient) {
+                Some(position) => position,
+                None => {
+                    let account = self
+                        .get_account(&deposit.l2_recipient)
+                        .await
+                        .unwrap_or_else(|| L2Account {
+                            lamports: 0,
+                            data: vec![],
+                            owner: Pubkey::default(),
+                            executable: false,
+                            rent_epoch: 0,
+                        });
+                    recipients.push((deposit.l2_recipient, account));
+                    recipients.len() - 1
+                }
+            };
+            let account = &mut recipients[position].1;
+            account.lamports = account.lamports.saturating_add(deposit.lamports);
+            credited.push(deposit);
+        }
+
+        let changes = recipients.into_iter().map(|(pubkey, account)| (pubkey, Some(account))).collect();
+        self.commit_accounts_with(|write_batch| {
+            for deposit in &credited {
+                write_batch.delete_cf(self.cf(CF_PENDING_DEPOSITS)?, deposit.id.to_be_bytes());
+                write_batch.put_cf(self.cf(CF_DEPOSITS)?, deposit.id.to_be_bytes(), bincode::serialize(deposit)?);
+            }
+            write_batch.put_cf(self.cf(CF_BATCH_DEPOSITS)?, batch_id.to_be_bytes(), bincode::serialize(&credited)?);
+            Ok(())
+        }, changes).await?;
+
+        Ok(credited)
+    }
+
+    /// A deposit by id, queued or credited.
+    pub fn get_deposit(&self, id: u64) -> anyhow::Result<Option<DepositRecord>> {
+        let key = id.to_be_bytes();
+        let data = match self.db.get_cf(self.cf(CF_DEPOSITS)?, key)? {
+            Some(data) => Some(data),
+            None => self.db.get_cf(self.cf(CF_PENDING_DEPOSITS)?, key)?,
+        };
+
+        Ok(data.map(|data| bincode::deserialize(&data)).transpose()?)
+    }
+
+    /// Deposits credited when `batch_id` was sealed.
+    pub fn get_batch_deposits(&self, batch_id: u64) -> anyhow::Result<Vec<DepositRecord>> {
+        match self.db.get_cf(self.cf(CF_BATCH_DEPOSITS)?, batch_id.to_be_bytes())? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Queues an airdrop's transfer, paid by `payer` once it is minted
+    /// `lamports`, for the next block.
+    pub fn queue_airdrop(&self, payer: Pubkey, lamports: u64, transaction: WireTransaction) -> anyhow::Result<PendingAirdrop> {
+        let airdrop = PendingAirdrop {
+            mint: AirdropMint { id: self.next_id(NEXT_AIRDROP_ID_KEY)?, payer, lamports },
+            transaction,
+        };
+        self.put_cf(self.cf(CF_PENDING_AIRDROPS)?, airdrop.mint.id.to_be_bytes(), bincode::serialize(&airdrop)?)?;
+
+        Ok(airdrop)
+    }
+
+    /// Queued airdrops, in id order.
+    pub fn pending_airdrops(&self) -> anyhow::Result<Vec<PendingAirdrop>> {
+        self.db
+            .iterator_cf(self.cf(CF_PENDING_AIRDROPS)?, rocksdb::IteratorMode::Start)
+            .map(|entry| Ok(bincode::deserialize(&entry?.1)?))
+            .collect()
+    }
+
+    pub fn has_pending_airdrops(&self) -> anyhow::Result<bool> {
+        let mut pending = self.db.iterator_cf(self.cf(CF_PENDING_AIRDROPS)?, rocksdb::IteratorMode::Start);
+        Ok(pending.next().transpose()?.is_some())
+    }
+
+    /// Mints `mints` into their payers as part of `batch_id`, moving them out
+    /// of the queue and recording them with the batch in the same write as
+    /// the accounts, so an airdrop is minted exactly once.
+    pub async fn mint_airdrops(&self, batch_id: u64, mints: Vec<AirdropMint>) -> anyhow::Result<()> {
+        if mints.is_empty() {
+            return Ok(());
+        }
+
+        let mut payers: Vec<(Pubkey, L2Account)> = Vec::new();
+        for mint in &mints {
+            let position = match payers.iter().position(|(pubkey, _)| *pubkey == mint.payer) {
+                Some(position) => position,
+                None => {
+                    let account = self
+                        .get_account(&mint.payer)
+                        .await
+                        .unwrap_or_else(|| L2Account {
+                            lamports: 0,
+                            data: vec![],
+                            owner: Pubkey::default(),
+                            executable: false,
+                            rent_epoch: 0,
+                        });
+                    payers.push((mint.payer, account));
+                    payers.len() - 1
+                }
+            };
+            let account = &mut payers[position].1;
+            account.lamports = account.lamports.saturating_add(mint.lamports);
+        }
+
+        let changes = payers.into_iter().map(|(pubkey, account)| (pubkey, Some(account))).collect();
+        self.commit_accounts_with(|write_batch| {
+            for mint in &mints {
+                write_batch.delete_cf(self.cf(CF_PENDING_AIRDROPS)?, mint.id.to_be_bytes());
+            }
+            write_batch.put_cf(self.cf(CF_BATCH_AIRDROPS)?, batch_id.to_be_bytes(), bincode::serialize(&mints)?);
+            Ok(())
+        }, changes).await
+    }
+
+    /// Airdrops minted when `batch_id` was sealed.
+    pub fn get_batch_airdrops(&self, batch_id: u64) -> anyhow::Result<Vec<AirdropMint>> {
+        match self.db.get_cf(self.cf(CF_BATCH_AIRDROPS)?, batch_id.to_be_bytes())? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Queues registering `program_id` with `handler`, or unregistering it
+    /// for `None`, for the next block.
+    pub fn queue_program_change(&self, program_id: Pubkey, handler: Option<ProgramHandler>) -> anyhow::Result<ProgramChange> {
+        let change = ProgramChange { id: self.next_id(NEXT_PROGRAM_CHANGE_ID_KEY)?, program_id, handler };
+        self.put_cf(self.cf(CF_PENDING_PROGRAM_CHANGES)?, change.id.to_be_bytes(), bincode::serialize(&change)?)?;
+
+        Ok(change)
+    }
+
+    /// Queued program changes, in id order.
+    pub fn pending_program_changes(&self) -> anyhow::Result<Vec<ProgramChange>> {
+        self.db
+            .iterator_cf(self.cf(CF_PENDING_PROGRAM_CHANGES)?, rocksdb::IteratorMode::Start)
+            .map(|entry| Ok(bincode::deserialize(&entry?.1)?))
+            .collect()
+    }
+
+    pub fn has_pending_program_changes(&self) -> anyhow::Result<bool> {
+        let mut pending = self.db.iterator_cf(self.cf(CF_PENDING_PROGRAM_CHANGES)?, rocksdb::IteratorMode::Start);
+        Ok(pending.next().transpose()?.is_some())
+    }
+
+    /// Records `changes` with `batch_id`, moving them out of the queue in
+    /// the same write as the block's accounts.
+    pub async fn record_program_changes(&self, batch_id: u64, changes: &[ProgramChange]) -> anyhow::Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        self.commit_accounts_with(|write_batch| {
+            for change in changes {
+                write_batch.delete_cf(self.cf(CF_PENDING_PROGRAM_CHANGES)?, change.id.to_be_bytes());
+            }
+            write_batch.put_cf(self.cf(CF_BATCH_PROGRAM_CHANGES)?, batch_id.to_be_bytes(), bincode::serialize(changes)?);
+            Ok(())
+        }, vec![]).await
+    }
+
+    /// Program changes recorded with `batch_id`.
+    pub fn get_batch_program_changes(&self, batch_id: u64) -> anyhow::Result<Vec<ProgramChange>> {
+        match self.db.get_cf(self.cf(CF_BATCH_PROGRAM_CHANGES)?, batch_id.to_be_bytes())? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Every program change recorded with a stored block, in block order,
+    /// to bring a registry built from the node's `--program`s up to the
+    /// chain's tip.
+    pub fn program_changes(&self) -> anyhow::Result<Vec<ProgramChange>> {
+        let mut changes = Vec::new();
+        for entry in self.db.iterator_cf(self.cf(CF_BATCH_PROGRAM_CHANGES)?, rocksdb::IteratorMode::Start) {
+            changes.extend(bincode::deserialize::<Vec<ProgramChange>>(&entry?.1)?);
+        }
+
+        Ok(changes)
+    }
+
+    pub fn l1_cursor(&self, cursor: L1Cursor) -> anyhow::Result<Option<Signature>> {
+        match self.db.get_cf(self.cf(CF_METADATA)?, cursor.key())? {
+            Some(data) => Ok(Some(Signature::try_from(data.as_slice())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_l1_cursor(&self, cursor: L1Cursor, signature: &Signature) -> anyhow::Result<()> {
+        self.put_cf(self.cf(CF_METADATA)?, cursor.key(), signature)
+    }
+
+    pub fn finalized_batch(&self) -> anyhow::Result<Option<u64>> {
+        match self.db.get_cf(self.cf(CF_METADATA)?, FINALIZED_BATCH_KEY)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_finalized_batch(&self, batch_id: u64) -> anyhow::Result<()> {
+        self.put_cf(self.cf(CF_METADATA)?, FINALIZED_BATCH_KEY, bincode::serialize(&batch_id)?)
+    }
+
+    pub fn put_batch_report(&self, report: &BatchReport) -> anyhow::Result<()> {
+        let serialized = bincode::serialize(report)?;
+        self.put_cf(self.cf(CF_BATCH_REPORTS)?, report.batch_id.to_be_bytes(), serialized)?;
+        Ok(())
+    }
+
+    /// Stores a block's header with its batch and state diff and marks its
+    /// transactions' records as sealed into it, in one write. A block being
+    /// built is stored with everything staged into it, once sealed.
+    pub fn put_block(&self, header: &BlockHeader, batch: &StoredBatch, diff: &StateDiff) -> anyhow::Result<()> {
+        let sealed = SealedIn {
+            batch_id: header.slot,
+            block_time: header.block_time,
+        };
+
+        let staged = self.staged.lock().unwrap().take();
+        let mut staged = match staged {
+            Some(block) if block.state_root.is_none() => anyhow::bail!("The block being built isn't sealed"),
+            staged => staged,
+        };
+        let mut write = staged.as_mut().map(|block| std::mem::take(&mut block.write)).unwrap_or_default();
+        write.put_cf(self.cf(CF_METADATA)?, NEXT_BATCH_ID_KEY, bincode::serialize(&(header.slot + 1))?);
+        write.put_cf(self.cf(CF_BLOCKS)?, header.slot.to_be_bytes(), bincode::serialize(header)?);
+        write.put_cf(self.cf(CF_BATCHES)?, header.slot.to_be_bytes(), bincode::serialize(batch)?);
+        write.put_cf(self.cf(CF_BLOCK_DIFFS)?, header.slot.to_be_bytes(), bincode::serialize(diff)?);
+        write.put_cf(self.cf(CF_BLOCKHASHES)?, header.blockhash, bincode::serialize(&header.slot)?);
+        // What `seal_snapshot` keeps for this block, so it can be restored
+        let layer = self.snapshots.read().unwrap().layer_with(staged.as_ref().map(|block| &block.pre_images));
+        write.put_cf(self.cf(CF_SNAPSHOT_LAYERS)?, header.slot.to_be_bytes(), bincode::serialize(&layer)?);
+        let expired = self.expire_signatures(header.slot, &mut write)?;
+        let program_usage = std::mem::take(&mut *self.program_usage.lock().unwrap());
+        for (program_id, stats) in &program_usage {
+            write.put_cf(self.cf(CF_PROGRAM_STATS)?, program_stats_key(header.slot, program_id), bincode::serialize(stats)?);
+        }
+        let mut records: HashMap<Signature, TransactionRecord> = staged.as_ref()
+            .map(|block| block.records.iter().cloned().collect())
+            .unwrap_or_default();
+        for tx in &batch.transactions {
+            let Some(signature) = tx.signatures.first() else {
+                continue;
+            };
+            let record = match records.remove(signature) {
+                Some(record) => Some(record),
+                None => self.get_transaction(signature)?,
+            };
+            if let Some(mut record) = record {
+                record.sealed = Some(sealed);
+                write.put_cf(self.cf(CF_TRANSACTIONS)?, signature, bincode::serialize(&record)?);
+            }
+        }
+        self.write(write)?;
+        {
+            let mut processed_signatures = self.processed_signatures.write().unwrap();
+            for signature in &expired {
+                processed_signatures.remove(signature);
+            }
+        }
+        if let Some(block) = staged {
+            self.apply_staged(block);
+        }
+        self.events.publish(|| RollupEvent::Block {
+            header: *header,
+            signatures: batch.transactions.iter().filter_map(|tx| tx.signatures.first().copied()).collect(),
+        });
+
+        Ok(())
+    }
+
+    /// Forgets, in `write`, the signatures of transactions that executed
+    /// `BLOCKHASH_VALIDITY_SLOTS` blocks before `slot`: once it is stored,
+    /// their blockhashes have expired, so they can't execute again anyway.
+    /// Returns the signatures forgotten.
+    fn expire_signatures(&self, slot: u64, write: &mut rocksdb::WriteBatch) -> anyhow::Result<Vec<Signature>> {
+        let Some(expired_slot) = slot.checked_sub(BLOCKHASH_VALIDITY_SLOTS) else {
+            return Ok(Vec::new());
+        };
+
+        let signature_slots = self.cf(CF_SIGNATURE_SLOTS)?;
+        let prefix = expired_slot.to_be_bytes();
+        let mut expired = Vec::new();
+        let mode = rocksdb::IteratorMode::From(&prefix, rocksdb::Direction::Forward);
+        for entry in self.db.iterator_cf(signature_slots, mode) {
+            let (key, _) = entry?;
+            let Some(signature) = key.strip_prefix(&prefix[..]) else {
+                break;
+            };
+            let signature = Signature::try_from(signature)?;
+            write.delete_cf(signature_slots, &key);
+            write.delete_cf(self.cf(CF_PROCESSED_SIGNATURES)?, signature);
+            expired.push(signature);
+        }
+
+        Ok(expired)
+    }
+
+    /// Brings the in-memory state up to a stored block that was staged.
+    fn apply_staged(&self, block: StagedBlock) {
+        {
+            let mut unsealed = self.tree_lock.lock().unwrap();
+            if let Some(root) = block.state_root {
+                *self.state_root.write().unwrap() = root;
+            }
+            unsealed.clear();
+        }
+
+        {
+            let mut snapshots = self.snapshots.write().unwrap();
+            let mut accounts = self.accounts.write().unwrap();
+            for (pubkey, before) in block.pre_images {
+                snapshots.record(pubkey, before);
+            }
+            for (pubkey, account) in block.changes {
+                self.events.publish(|| RollupEvent::Account { pubkey, account: account.clone() });
+            }
+            for (pubkey, account) in block.accounts {
+                match account {
+                    Some(account) => accounts.insert(pubkey, account),
+                    None => accounts.remove(&pubkey),
+                };
+            }
+        }
+
+        for (signature, record) in &block.records {
+            let Some(fee_payer) = record.transaction.message.account_keys.first() else {
+                continue;
+            };
+            let nonce = block.nonces.get(fee_payer).copied().unwrap_or_default();
+            self.processed(signature, fee_payer, nonce, record);
+        }
+    }
+
+    pub fn get_block(&self, slot: u64) -> anyhow::Result<Option<BlockHeader>> {
+        match self.db.get_cf(self.cf(CF_BLOCKS)?, slot.to_be_bytes())? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The accounts the block at `slot` changed, for blocks sealed since
+    /// diffs were recorded.
+    pub fn get_block_diff(&self, slot: u64) -> anyhow::Result<Option<StateDiff>> {
+        match self.db.get_cf(self.cf(CF_BLOCK_DIFFS)?, slot.to_be_bytes())? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Adds an executed transaction's usage of each program to the block
+    /// being built.
+    pub fn record_program_usage(&self, usage: &[(Pubkey, ProgramStats)]) {
+        let mut program_usage = self.program_usage.lock().unwrap();
+        for (program_id, stats) in usage {
+            program_usage.entry(*program_id).or_default().add(stats);
+        }
+    }
+
+    /// Usage of every program in blocks `first_slot` to `last_slot`, summed.
+    pub fn program_stats(&self, first_slot: u64, last_slot: u64) -> anyhow::Result<HashMap<Pubkey, ProgramStats>> {
+        let mut totals: HashMap<Pubkey, ProgramStats> = HashMap::new();
+        let start = first_slot.to_be_bytes();
+        let mode = rocksdb::IteratorMode::From(&start, rocksdb::Direction::Forward);
+        for entry in self.db.iterator_cf(self.cf(CF_PROGRAM_STATS)?, mode) {
+            let (key, value) = entry?;
+            let (Some(slot), Some(program_id)) = (key.get(..8), key.get(8..)) else {
+                continue;
+            };
+            if u64::from_be_bytes(slot.try_into()?) > last_slot {
+                break;
+            }
+            let stats: ProgramStats = bincode::deserialize(&value)?;
+            totals.entry(Pubkey::try_from(program_id)?).or_default().add(&stats);
+        }
+
+        Ok(totals)
+    }
+
+    /// The newest block; `None` until the first is produced, including on
+    /// databases whose batches predate blocks.
+    pub fn latest_block(&self) -> anyhow::Result<Option<BlockHeader>> {
+        match self.db.iterator_cf(self.cf(CF_BLOCKS)?, rocksdb::IteratorMode::End).next() {
+            Some(entry) => Ok(Some(bincode::deserialize(&entry?.1)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Transactions involving `address`, newest first, with the sequence
+    /// number that orders them; page with `before`.
+    pub fn address_activity(
+        &self,
+        address: &Pubkey,
+        before: Option<u64>,
+        limit: usize
+    ) -> anyhow::Result<Vec<(u64, TransactionRecord)>> {
+        let start = match before {
+            Some(0) => return Ok(vec![]),
+            Some(before) => activity_key(address, before - 1),
+            None => activity_key(address, u64::MAX),
+        };
+
+        let mut activity = Vec::new();
+        let mode = rocksdb::IteratorMode::From(&start, rocksdb::Direction::Reverse);
+        for entry in self.db.iterator_cf(self.cf(CF_ADDRESS_ACTIVITY)?, mode) {
+            if activity.len() == limit {
+                break;
+            }
+            let (key, signature) = entry?;
+            if !key.starts_with(address.as_ref()) {
+                break;
+            }
+
+            let sequence = u64::from_be_bytes(key[32..].try_into()?);
+            let signature = Signature::try_from(&signature[..])?;
+            if let Some(record) = self.get_transaction(&signature)? {
+                activity.push((sequence, record));
+            }
+        }
+
+        Ok(activity)
+    }
+
+    pub fn get_transaction(&self, signature: &Signature) -> anyhow::Result<Option<TransactionRecord>> {
+        match self.db.get_cf(self.cf(CF_TRANSACTIONS)?, signature)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_batch(&self, batch_id: u64) -> anyhow::Result<Option<StoredBatch>> {
+        match self.get_tiered(CF_BATCHES, batch_id)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_posted_commitment(&self, batch_id: u64, commitment: &PostedCommitment) -> anyhow::Result<()> {
+        self.put_cf(self.cf(CF_POSTED_COMMITMENTS)?, batch_id.to_be_bytes(), bincode::serialize(commitment)?)?;
+        Ok(())
+    }
+
+    pub fn get_posted_commitment(&self, batch_id: u64) -> anyhow::Result<Option<PostedCommitment>> {
+        match self.db.get_cf(self.cf(CF_POSTED_COMMITMENTS)?, batch_id.to_be_bytes())? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// First slot the block stream can serve: state diffs below it were
+    /// deleted with their pruned batches and DA can't rebuild them.
+    pub fn first_stored_diff(&self) -> anyhow::Result<u64> {
+        match self.db.get_cf(self.cf(CF_METADATA)?, FIRST_STORED_DIFF_KEY)? {
+            Some(data) => Ok(bincode::deserialize(&data)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Deletes stored batches below `before_batch` (hot and cold) that can be
+    /// rebuilt from DA: finalized on L1, so never posted again, with a posted
+    /// commitment and nothing netted out of it. Reports and commitments are
+    /// kept; state diffs go with their batches, so the first stored diff
+    /// moves past them. Returns the number of batches pruned.
+    pub fn prune_batches(&self, before_batch: u64) -> anyhow::Result<usize> {
+        let bound = before_batch.to_be_bytes();
+        let hot = self.db.iterator_cf(self.cf(CF_BATCHES)?, rocksdb::IteratorMode::Start)
+            .map(|entry| Ok(entry?.0));
+        let keys: Box<dyn Iterator<Item = anyhow::Result<Box<[u8]>>>> = match &self.cold {
+            Some(cold) => Box::new(hot.chain(cold.keys(CF_BATCHES)?)),
+            None => Box::new(hot),
+        };
+
+        let finalized_batch = self.finalized_batch()?;
+        let mut prunable = Vec::new();
+        let mut last_pruned = None;
+        for key in keys {
+            let key = key?;
+            if *key >= bound[..] {
+                continue;
+            }
+
+            let batch_id = u64::from_be_bytes(key[..].try_into()?);
+            let finalized = finalized_batch.is_some_and(|finalized| batch_id <= finalized);
+            let rebuildable = self.get_posted_commitment(batch_id)?.is_some_and(|c| c.netted_transactions == 0);
+            if finalized && rebuildable {
+                prunable.push(key);
+                last_pruned = last_pruned.max(Some(batch_id));
+            }
+        }
+
+        if let Some(cold) = &self.cold {
+            cold.delete_all(CF_BATCHES, &prunable)?;
+        }
+        let mut write_batch = rocksdb::WriteBatch::default();
+        for key in &prunable {
+            write_batch.delete_cf(self.cf(CF_BATCHES)?, key);
+            write_batch.delete_cf(self.cf(CF_BLOCK_DIFFS)?, key);
+        }
+        if let Some(last_pruned) = last_pruned {
+            let first_stored = self.first_stored_diff()?.max(last_pruned + 1);
+            write_batch.put_cf(self.cf(CF_METADATA)?, FIRST_STORED_DIFF_KEY, bincode::serialize(&first_stored)?);
+        }
+        self.write(write_batch)?;
+
+        Ok(prunable.len())
+    }
+
+    /// Most recent report, optionally restricted to batches that landed on L1.
+    pub fn latest_batch_report(&self, confirmed_only: bool) -> anyhow::Result<Option<BatchReport>> {
+        let hot = self.db.iterator_cf(self.cf(CF_BATCH_REPORTS)?, rocksdb::IteratorMode::End)
+            .map(|entry| Ok(entry?.1));
+        let values: Box<dyn Iterator<Item = anyhow::Result<Box<[u8]>>>> = match &self.cold {
+            Some(cold) => Box::new(hot.chain(cold.values_rev(CF_BATCH_REPORTS)?)),
+            None => Box::new(hot),
+        };
+
+        for value in values {
+            let report: BatchReport = bincode::deserialize(&value?)?;
+            if !confirmed_only || report.final_slot.is_some() {
+                return Ok(Some(report));
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn get_batch_report(&self, batch_id: u64) -> anyhow::Result<Option<BatchReport>> {
+        match self.get_tiered(CF_BATCH_REPORTS, batch_id)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_tiered(&self, cf: &str, batch_id: u64) -> anyhow::Result<Option<Vec<u8>>> {
+        let key = batch_id.to_be_bytes();
+        match (self.db.get_cf(self.cf(cf)?, key)?, &self.cold) {
+            (Some(data), _) => Ok(Some(data)),
+            (None, Some(cold)) => cold.get(cf, key),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Moves stored batches and reports below `before_batch` to cold storage.
+    /// Each chunk is written to cold storage before it is deleted here, so
+    /// an interrupted migration leaves duplicates, never gaps. Returns the
+    /// number of batches moved; nothing moves without cold storage.
+    pub fn migrate_to_cold(&self, before_batch: u64) -> anyhow::Result<usize> {
+        let Some(cold) = &self.cold else {
+            return Ok(0);
+        };
+
+        let mut moved_batches = 0;
+        for name in [CF_BATCHES, CF_BATCH_REPORTS] {
+            let cf = self.cf(name)?;
+            let bound = before_batch.to_be_bytes();
+            loop {
+                let mut entries = Vec::new();
+                for entry in self.db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+                    let (key, value) = entry?;
+                    if *key >= bound[..] || entries.len() == COLD_MIGRATION_CHUNK {
+                        break;
+                    }
+                    entries.push((key, value));
+                }
+                if entries.is_empty() {
+                    break;
+                }
+
+                cold.put_all(name, &entries)?;
+                let mut write_batch = rocksdb::WriteBatch::default();
+                for (key, _) in &entries {
+                    write_batch.delete_cf(cf, key);
+                }
+                self.write(write_batch)?;
+
+                if name == CF_BATCHES {
+                    moved_batches += entries.len();
+                }
+            }
+        }
+
+        Ok(moved_batches)
+    }
+
+    /// Records an accepted transaction that will never be finalized.
+    pub fn put_dead_letter(&self, reason: DeadLetterReason, transaction: &WireTransaction) -> anyhow::Result<DeadLetter> {
+        let dead_letter = DeadLetter {
+            id: self.next_id(NEXT_DEAD_LETTER_ID_KEY)?,
+            reason,
+            recorded_at: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs(),
+            transaction: transaction.clone(),
+        };
+        self.put_cf(
+            self.cf(CF_DEAD_LETTERS)?,
+            dead_letter.id.to_be_bytes(),
+            bincode::serialize(&dead_letter)?
+        )?;
+
+        Ok(dead_letter)
+    }
+
+    /// Up to `limit` dead letters, newest first, starting below `before` if given.
+    pub fn dead_letters(&self, before: Option<u64>, limit: usize) -> anyhow::Result<Vec<DeadLetter>> {
+        let start;
+        let mode = match before {
+            Some(0) => return Ok(vec![]),
+            Some(before) => {
+                start = (before - 1).to_be_bytes();
+                rocksdb::IteratorMode::From(&start, rocksdb::Direction::Reverse)
+            }
+            None => rocksdb::IteratorMode::End,
+        };
+
+        let mut dead_letters = Vec::new();
+        for entry in self.db.iterator_cf(self.cf(CF_DEAD_LETTERS)?, mode).take(limit) {
+            dead_letters.push(bincode::deserialize(&entry?.1)?);
+        }
+
+        Ok(dead_letters)
+    }
+
+    pub fn put_epoch_record(&self, record: &EpochRecord) -> anyhow::Result<()> {
+        self.put_cf(self.cf(CF_EPOCHS)?, record.epoch.to_be_bytes(), bincode::serialize(record)?)?;
+        Ok(())
+    }
+
+    pub fn get_epoch_record(&self, epoch: u64) -> anyhow::Result<Option<EpochRecord>> {
+        match self.db.get_cf(self.cf(CF_EPOCHS)?, epoch.to_be_bytes())? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn latest_epoch_record(&self) -> anyhow::Result<Option<EpochRecord>> {
+        match self.db.iterator_cf(self.cf(CF_EPOCHS)?, rocksdb::IteratorMode::End).next() {
+            Some(entry) => Ok(Some(bincode::deserialize(&entry?.1)?)),
+            None => Ok(None),
+        }
+    }
+
+    // All writes after genesis go through these two, so faults can be injected
+    fn write(&self, batch: rocksdb::WriteBatch) -> anyhow::Result<()> {
+        inject_blocking(FaultPoint::DbWrite)?;
+        self.db.write_opt(batch, &self.write_options())?;
+        Ok(())
+    }
+
+    fn put_cf(&self, cf: &rocksdb::ColumnFamily, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> anyhow::Result<()> {
+        inject_blocking(FaultPoint::DbWrite)?;
+        self.db.put_cf_opt(cf, key, value, &self.write_options())?;
+        Ok(())
+    }
+
+    fn write_options(&self) -> rocksdb::WriteOptions {
+        let mut options = rocksdb::WriteOptions::default();
+        options.set_sync(self.sync_writes);
+        options
+    }
+
+    fn cf(&self, name: &str) -> anyhow::Result<&rocksdb::ColumnFamily> {
+        self.db.cf_handle(name)
+            .ok_or_else(|| anyhow::anyhow!("Missing column family: {}", name))
+    }
+}
+
+/// A RocksDB snapshot of a `StateManager`'s database, see `view`.
+pub struct StateView<'a> {
+    state_manager: &'a StateManager,
+    snapshot: rocksdb::Snapshot<'a>,
+}
+
+impl StateView<'_> {
+    /// Up to `limit` accounts in key order, starting after `after`.
+    pub fn account_page(&self, after: Option<&Pubkey>, limit: usize) -> anyhow::Result<Vec<(Pubkey, L2Account)>> {
+        let start = after.map(|pubkey| pubkey.to_bytes());
+        account_page(self.snapshot.iterator(page_start(start.as_ref())), start, limit)
+    }
+
+    pub fn state_root(&self) -> anyhow::Result<[u8; 32]> {
+        match self.snapshot.get_cf(self.state_manager.cf(CF_STATE_TREE)?, root_key())? {
+            Some(node) => Ok(node.as_slice().try_into()?),
+            None => Ok(EMPTY),
+        }
+    }
+
+    /// Newest sealed batch, if any.
+    pub fn last_batch(&self) -> anyhow::Result<Option<u64>> {
+        match self.snapshot.get_cf(self.state_manager.cf(CF_METADATA)?, NEXT_BATCH_ID_KEY)? {
+            Some(data) => Ok(bincode::deserialize::<u64>(&data)?.checked_sub(1)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn page_start(start: Option<&[u8; 32]>) -> rocksdb::IteratorMode<'_> {
+    match start {
+        Some(key) => rocksdb::IteratorMode::From(key, rocksdb::Direction::Forward),
+        None => rocksdb::IteratorMode::Start,
+    }
+}
+
+fn account_page(
+    entries: impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>>,
+    start: Option<[u8; 32]>,
+    limit: usize
+) -> anyhow::Result<Vec<(Pubkey, L2Account)>> {
+    let mut accounts = Vec::with_capacity(limit);
+    for entry in entries {
+        let (key, value) = entry?;
+        if start.is_some_and(|start| key.as_ref() == start) {
+            continue;
+        }
+        if accounts.len() == limit {
+            break;
+        }
+        accounts.push((Pubkey::try_from(key.as_ref())?, bincode::deserialize(&value)?));
+    }
+
+    Ok(accounts)
+}
+
+fn owner_index_key(owner: &Pubkey, pubkey: &Pubkey) -> [u8; 64] {
+    let mut key = [0u8; 64];
+    key[..32].copy_from_slice(owner.as_ref());
+    key[32..].copy_from_slice(pubkey.as_ref());
+    key
+}
+
+/// Address followed by the transaction's sequence number (big endian), so an
+/// address's transactions are adjacent and in order.
+fn activity_key(address: &Pubkey, sequence: u64) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..32].copy_from_slice(address.as_ref());
+    key[32..].copy_from_slice(&sequence.to_be_bytes());
+    key
+}
+
+/// Slot (big endian) followed by the signature, so the signatures of
+/// transactions executed in a block are adjacent and blocks in order.
+fn signature_slot_key(slot: u64, signature: &Signature) -> [u8; 72] {
+    let mut key = [0u8; 72];
+    key[..8].copy_from_slice(&slot.to_be_bytes());
+    key[8..].copy_from_slice(signature.as_ref());
+    key
+}
+
+/// Slot (big endian) followed by the program id, so a block's programs are
+/// adjacent and blocks in order.
+fn program_stats_key(slot: u64, program_id: &Pubkey) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..8].copy_from_slice(&slot.to_be_bytes());
+    key[8..].copy_from_slice(program_id.as_ref());
+    key
+}
+
+#[cfg(test)]
+impl StateManager {
+    /// A fresh database in the temporary directory, for tests.
+    pub fn temporary() -> Arc<Self> {
+        Arc::new(Self::new(&Self::temporary_path(), None, false).unwrap())
+    }
+
+    /// Makes `pubkey` a system account holding `lamports`.
+    pub async fn fund(&self, pubkey: &Pubkey, lamports: u64) {
+        self.update_account(pubkey, L2Account {
+            lamports,
+            data: vec![],
+            owner: system_program::id(),
+            executable: false,
+            rent_epoch: 0,
+        }).await.unwrap();
+    }
+
+    /// A system transfer of `lamports` from `payer`, which pays its fee, to
+    /// `recipient`.
+    pub fn transfer(
+        payer: &solana_sdk::signature::Keypair,
+        recipient: &Pubkey,
+        lamports: u64,
+        blockhash: Hash
+    ) -> solana_sdk::transaction::Transaction {
+        use solana_sdk::signature::Signer;
+
+        let instruction = solana_system_interface::instruction::transfer(&payer.pubkey(), recipient, lamports);
+        solana_sdk::transaction::Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], blockhash)
+    }
+
+    /// A path in the temporary directory no database is at yet.
+    pub fn temporary_path() -> String {
+        std::env::temp_dir().join(format!(
+            "tiny-rollup-test-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos()
+        )).to_str().unwrap().to_string()
+    }
+
+    /// Seals and stores a block of no transactions on top of the chain,
+    /// returning it.
+    pub fn put_empty_block(&self) -> BlockHeader {
+        let slot = self.batch_count().unwrap();
+        let parent_blockhash = match self.latest_block().unwrap() {
+            Some(parent) => parent.blockhash,
+            None => Hash::new_from_array(self.genesis_hash().unwrap().unwrap()),
+        };
+        let batch = StoredBatch { transactions: vec![], state_root: self.get_state_root() };
+        let header = BlockHeader::new(slot, parent_blockhash, batch.state_root, &batch.transactions, 0);
+        let (_, diff) = self.seal_state().unwrap();
+        self.put_block(&header, &batch, &diff).unwrap();
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(lamports: u64) -> L2Account {
+        L2Account { lamports, data: vec![], owner: Pubkey::default(), executable: false, rent_epoch: 0 }
+    }
+
+    /// Stores a block setting `pubkey` to `lamports`, as the sequencer does.
+    async fn put_block_with(state_manager: &StateManager, pubkey: &Pubkey, lamports: u64) {
+        state_manager.update_account(pubkey, account(lamports)).await.unwrap();
+        let header = state_manager.put_empty_block();
+        state_manager.seal_snapshot(header.slot);
+    }
+
+    async fn lamports_at(state_manager: &StateManager, pubkey: &Pubkey, commitment: Commitment) -> Option<u64> {
+        state_manager.get_account_at(pubkey, commitment).await.map(|account| account.lamports)
+    }
+
+    #[tokio::test]
+    async fn keeps_the_finalized_view_across_a_restart() {
+        let path = StateManager::temporary_path();
+        let pubkey = Pubkey::new_unique();
+        {
+            let state_manager = StateManager::new(&path, None, false).unwrap();
+            put_block_with(&state_manager, &pubkey, 1).await;
+            state_manager.set_finalized_batch(0).unwrap();
+            state_manager.finalize_snapshot(0).unwrap();
+            put_block_with(&state_manager, &pubkey, 2).await;
+            put_block_with(&state_manager, &pubkey, 3).await;
+            assert_eq!(lamports_at(&state_manager, &pubkey, Commitment::Finalized).await, Some(1));
+        }
+
+        let state_manager = StateManager::new(&path, None, false).unwrap();
+        assert_eq!(lamports_at(&state_manager, &pubkey, Commitment::Processed).await, Some(3));
+        assert_eq!(lamports_at(&state_manager, &pubkey, Commitment::Confirmed).await, Some(3));
+        assert_eq!(lamports_at(&state_manager, &pubkey, Commitment::Finalized).await, Some(1));
+
+        state_manager.finalize_snapshot(1).unwrap();
+        assert_eq!(lamports_at(&state_manager, &pubkey, Commitment::Finalized).await, Some(2));
+    }
+
+    #[test]
+    fn prunes_only_finalized_rebuildable_batches_and_moves_the_first_stored_diff_past_them() {
+        let state_manager = StateManager::temporary();
+        for _ in 0..4 {
+            state_manager.put_empty_block();
+        }
+        state_manager.set_finalized_batch(2).unwrap();
+        // Batch 1 can't be rebuilt from DA, and batch 3 could still be
+        // dropped from L1 and need posting again, so both are kept
+        for (batch_id, netted_transactions) in [(0, 0), (1, 1), (2, 0), (3, 0)] {
+            state_manager.put_posted_commitment(batch_id, &PostedCommitment {
+                digest: [0; 32],
+                state_root: [0; 32],
+                netted_transactions,
+            }).unwrap();
+        }
+        assert_eq!(state_manager.first_stored_diff().unwrap(), 0);
+
+        assert_eq!(state_manager.prune_batches(4).unwrap(), 2);
+        assert_eq!(state_manager.first_stored_diff().unwrap(), 3);
+        assert!(state_manager.get_block_diff(1).unwrap().is_some());
+        assert!(state_manager.get_block_diff(2).unwrap().is_none());
+        assert!(state_manager.get_batch(3).unwrap().is_some());
+
+        // Pruning nothing more never moves it back
+        assert_eq!(state_manager.prune_batches(1).unwrap(), 0);
+        assert_eq!(state_manager.first_stored_diff().unwrap(), 3);
+    }
 }
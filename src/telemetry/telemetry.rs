@@ -0,0 +1,94 @@
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{sequencer::sequencer::Sequencer, state_manager::state_manager::StateManager};
+
+/// Node stats sent to the telemetry endpoint. Deliberately carries no keys,
+/// addresses or account data.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryReport {
+    /// Random per process, so reports from one run can be grouped without
+    /// identifying the node
+    pub node_id: String,
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    pub chain_tip: Option<u64>,
+    pub last_confirmed_batch: Option<u64>,
+    /// Sealed batches not yet confirmed on L1
+    pub batch_lag: u64,
+    pub mempool_depth: usize,
+}
+
+/// Opt-in periodic reporting of node stats, for operators running fleets of
+/// replicas.
+pub struct Telemetry {
+    client: reqwest::Client,
+    endpoint: String,
+    interval: Duration,
+    node_id: String,
+    started: Instant,
+    state_manager: Arc<StateManager>,
+    sequencer: Arc<Sequencer>,
+}
+
+impl Telemetry {
+    pub fn new(
+        endpoint: String,
+        interval: Duration,
+        state_manager: Arc<StateManager>,
+        sequencer: Arc<Sequencer>
+    ) -> Self {
+        let node_id = std::collections::hash_map::RandomState::new().build_hasher().finish();
+
+        Self {
+            client: reqwest::Client::new(),
+            endpoint,
+            interval,
+            node_id: format!("{:016x}", node_id),
+            started: Instant::now(),
+            state_manager,
+            sequencer,
+        }
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.send_report().await {
+                eprintln!("Failed to send telemetry to {}: {}", self.endpoint, e);
+            }
+        }
+    }
+
+    async fn send_report(&self) -> anyhow::Result<()> {
+        let report = self.report().await?;
+        self.client.post(&self.endpoint)
+            .json(&report)
+            .timeout(self.interval)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    pub async fn report(&self) -> anyhow::Result<TelemetryReport> {
+        let batch_count = self.state_manager.batch_count()?;
+        let last_confirmed_batch = self.state_manager.latest_batch_report(true)?.map(|r| r.batch_id);
+        let confirmed_count = last_confirmed_batch.map_or(0, |batch_id| batch_id + 1);
+
+        Ok(TelemetryReport {
+            node_id: self.node_id.clone(),
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started.elapsed().as_secs(),
+            chain_tip: batch_count.checked_sub(1),
+            last_confirmed_batch,
+            batch_lag: batch_count.saturating_sub(confirmed_count),
+            mempool_depth: self.sequencer.pending_count().await,
+        })
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_sdk::pubkey::Pubkey;
+
+/// Longest symbol and name accepted, so a registry entry can't be used to
+/// push arbitrary text into wallet UIs.
+const MAX_SYMBOL_LEN: usize = 16;
+const MAX_NAME_LEN: usize = 64;
+const MAX_LOGO_URI_LEN: usize = 512;
+
+/// How a wallet should display an L2 mint. Fields are named as in the
+/// Solana token list, so files in that format load as they are and the
+/// REST endpoint can be consumed as one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    #[serde(serialize_with = "serialize_pubkey", deserialize_with = "deserialize_pubkey")]
+    pub address: Pubkey,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    #[serde(rename = "logoURI", default, skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<String>,
+}
+
+impl TokenMetadata {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.symbol.is_empty() || self.symbol.len() > MAX_SYMBOL_LEN {
+            anyhow::bail!("Symbol of {} must be 1 to {} bytes", self.address, MAX_SYMBOL_LEN);
+        }
+        if self.name.is_empty() || self.name.len() > MAX_NAME_LEN {
+            anyhow::bail!("Name of {} must be 1 to {} bytes", self.address, MAX_NAME_LEN);
+        }
+        if let Some(logo_uri) = &self.logo_uri
+            && (logo_uri.len() > MAX_LOGO_URI_LEN || !(logo_uri.starts_with("https://") || logo_uri.starts_with("ipfs://")))
+        {
+            anyhow::bail!("Logo of {} must be an https:// or ipfs:// URI of at most {} bytes", self.address, MAX_LOGO_URI_LEN);
+        }
+
+        Ok(())
+    }
+}
+
+/// A token list file: either a bare array of tokens or a Solana token list
+/// object with a `tokens` array.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenListFile {
+    Tokens(Vec<TokenMetadata>),
+    TokenList { tokens: Vec<TokenMetadata> },
+}
+
+/// Display metadata for L2 mints, seeded from `--token-list` and changed
+/// through the admin RPC until the node restarts. Nothing here affects
+/// execution; it only spares wallets from rendering mints as raw pubkeys.
+#[derive(Default)]
+pub struct TokenRegistry {
+    tokens: RwLock<BTreeMap<Pubkey, TokenMetadata>>,
+}
+
+impl TokenRegistry {
+    pub fn new(tokens: impl IntoIterator<Item = TokenMetadata>) -> anyhow::Result<Self> {
+        let registry = Self::default();
+        for token in tokens {
+            registry.set(token)?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Reads the tokens in the token list file at `path`.
+    pub fn load(path: &str) -> anyhow::Result<Vec<TokenMetadata>> {
+        let file = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read token list {}: {}", path, e))?;
+        let tokens = match serde_json::from_slice(&file)
+            .map_err(|e| anyhow::anyhow!("Invalid token list {}: {}", path, e))?
+        {
+            TokenListFile::Tokens(tokens) | TokenListFile::TokenList { tokens } => tokens,
+        };
+
+        Ok(tokens)
+    }
+
+    pub fn get(&self, mint: &Pubkey) -> Option<TokenMetadata> {
+        self.tokens.read().unwrap().get(mint).cloned()
+    }
+
+    /// Adds or replaces the metadata of a mint, returning what it replaced.
+    pub fn set(&self, token: TokenMetadata) -> anyhow::Result<Option<TokenMetadata>> {
+        token.validate()?;
+
+        Ok(self.tokens.write().unwrap().insert(token.address, token))
+    }
+
+    /// Removes a mint, returning its metadata if it was registered.
+    pub fn remove(&self, mint: &Pubkey) -> Option<TokenMetadata> {
+        self.tokens.write().unwrap().remove(mint)
+    }
+
+    /// Every registered token, ordered by mint.
+    pub fn tokens(&self) -> Vec<TokenMetadata> {
+        self.tokens.read().unwrap().values().cloned().collect()
+    }
+}
+
+fn serialize_pubkey<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&pubkey.to_string())
+}
+
+fn deserialize_pubkey<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    let pubkey = String::deserialize(deserializer)?;
+    Pubkey::from_str(&pubkey).map_err(serde::de::Error::custom)
+}
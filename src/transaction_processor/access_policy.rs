@@ -0,0 +1,47 @@
+use std::collections::HashSet;
+
+use solana_sdk::{pubkey::Pubkey, transaction::Transaction};
+
+/// Admission gate for permissioned rollups. A transaction is accepted if any
+/// of its signers is registered, or if every program it invokes is
+/// allowlisted. An empty policy accepts everything.
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    signers: HashSet<Pubkey>,
+    programs: HashSet<Pubkey>,
+}
+
+impl AccessPolicy {
+    pub fn new(signers: impl IntoIterator<Item = Pubkey>, programs: impl IntoIterator<Item = Pubkey>) -> Self {
+        Self {
+            signers: signers.into_iter().collect(),
+            programs: programs.into_iter().collect(),
+        }
+    }
+
+    pub fn is_permissioned(&self) -> bool {
+        !self.signers.is_empty() || !self.programs.is_empty()
+    }
+
+    pub fn check(&self, tx: &Transaction) -> anyhow::Result<()> {
+        if !self.is_permissioned() {
+            return Ok(());
+        }
+
+        let message = &tx.message;
+        let num_signers = message.header.num_required_signatures as usize;
+        if message.account_keys.iter().take(num_signers).any(|k| self.signers.contains(k)) {
+            return Ok(());
+        }
+
+        let mut programs = message.instructions.iter()
+            .map(|ix| message.account_keys.get(ix.program_id_index as usize));
+        if !message.instructions.is_empty()
+            && programs.all(|program| program.is_some_and(|p| self.programs.contains(p)))
+        {
+            return Ok(());
+        }
+
+        anyhow::bail!("Transaction rejected: signer is not registered and it invokes programs outside the allowlist")
+    }
+}
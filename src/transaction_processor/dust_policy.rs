@@ -0,0 +1,62 @@
+use solana_sdk::{pubkey::Pubkey, transaction::Transaction};
+
+/// What happens to a transfer below the dust threshold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DustAction {
+    /// Fail the transaction with `DustTransfer`
+    #[default]
+    Reject,
+    /// Execute it, charging the fee payer the surcharge for every dust
+    /// transfer that creates its destination account
+    Surcharge,
+}
+
+/// A system transfer of fewer lamports than the dust threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DustTransfer {
+    pub instruction_index: usize,
+    pub destination: Pubkey,
+    pub lamports: u64,
+}
+
+/// Limits on dust: system transfers of fewer than `min_transfer_lamports`,
+/// which on a public rollup mostly come from spam creating accounts nobody
+/// will use. Surcharges are burned. A zero minimum allows every transfer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DustPolicy {
+    pub min_transfer_lamports: u64,
+    pub action: DustAction,
+    pub surcharge_lamports: u64,
+}
+
+impl DustPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.min_transfer_lamports > 0
+    }
+
+    /// The system transfers (plain or with seed) in `tx` below the threshold.
+    pub fn dust_transfers(&self, tx: &Transaction) -> Vec<DustTransfer> {
+        if !self.is_enabled() {
+            return vec![];
+        }
+
+        let keys = &tx.message.account_keys;
+        tx.message.instructions.iter()
+            .enumerate()
+            .filter(|(_, ix)| keys.get(ix.program_id_index as usize) == Some(&Pubkey::default()))
+            .filter_map(|(instruction_index, ix)| {
+                // Transfer: from, to. TransferWithSeed: from, base, to
+                let (lamports, destination) = match ix.data.as_slice() {
+                    [2, 0, 0, 0, lamports @ ..] => (u64::from_le_bytes(lamports.try_into().ok()?), ix.accounts.get(1)?),
+                    [11, 0, 0, 0, lamports @ ..] => (u64::from_le_bytes(lamports.get(..8)?.try_into().ok()?), ix.accounts.get(2)?),
+                    _ => return None,
+                };
+                (lamports < self.min_transfer_lamports).then_some(DustTransfer {
+                    instruction_index,
+                    destination: *keys.get(*destination as usize)?,
+                    lamports,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,124 @@
+use serde::{Serialize, Serializer};
+use solana_sdk::{instruction::InstructionError, pubkey::Pubkey, transaction::{Transaction, TransactionError}};
+use solana_sdk_ids::system_program;
+
+/// SystemError::ResultWithNegativeLamports, what L1 fails an underfunded
+/// system transfer with.
+const SYSTEM_INSUFFICIENT_FUNDS: u32 = 1;
+
+/// Why a submitted transaction failed validation or execution. Returned to
+/// the submitter as structured error data alongside the equivalent Solana
+/// `TransactionError`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, thiserror::Error)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum ExecutionError {
+    /// The message is malformed, e.g. an instruction refers to an account
+    /// index past the end of its account keys
+    #[error("Transaction failed sanitization: {reason}")]
+    SanitizeFailure {
+        reason: String,
+    },
+    #[error("Invalid transaction signatures")]
+    SignatureFailure,
+    #[error("Transaction has already been processed")]
+    AlreadyProcessed,
+    /// The recent blockhash isn't one of the last `BLOCKHASH_VALIDITY_SLOTS`
+    /// blocks', so the transaction has expired (or never could execute)
+    #[error("Blockhash not found")]
+    BlockhashNotFound,
+    /// An ed25519/secp256k1 verification instruction failed; `code` is the
+    /// `PrecompileError` discriminant, as on L1
+    #[error("Precompile verification failed in instruction {instruction_index}")]
+    #[serde(rename_all = "camelCase")]
+    PrecompileFailed {
+        instruction_index: usize,
+        code: u32,
+    },
+    #[error("Insufficient funds for transfer in instruction {instruction_index}")]
+    #[serde(rename_all = "camelCase")]
+    InsufficientFunds {
+        instruction_index: usize,
+    },
+    /// The program isn't registered, and unknown programs are rejected
+    /// rather than executed on the SVM
+    #[error("Instruction {instruction_index} for program {program_id} is not supported")]
+    #[serde(rename_all = "camelCase")]
+    ProgramNotSupported {
+        instruction_index: usize,
+        #[serde(serialize_with = "serialize_pubkey")]
+        program_id: Pubkey,
+    },
+    /// A system transfer below the dust threshold, which is rejected
+    #[error("Transfer of {lamports} lamports in instruction {instruction_index} is below the {minimum} lamport minimum")]
+    #[serde(rename_all = "camelCase")]
+    DustTransfer {
+        instruction_index: usize,
+        lamports: u64,
+        minimum: u64,
+    },
+    /// The fee payer can't cover the transaction fee
+    #[error("Insufficient funds for the fee of {fee} lamports")]
+    InsufficientFundsForFee {
+        fee: u64,
+    },
+    /// The fee payer can't cover the dust surcharge
+    #[error("Insufficient funds for the dust surcharge of {surcharge} lamports")]
+    InsufficientFundsForSurcharge {
+        surcharge: u64,
+    },
+    /// The SVM failed the transaction; `error` is what L1 would report
+    #[error("Transaction failed: {error}")]
+    ExecutionFailed {
+        error: TransactionError,
+    },
+}
+
+impl ExecutionError {
+    /// Wraps an SVM failure of `tx`. An underfunded system transfer keeps
+    /// its own kind so clients can tell it apart without decoding the code.
+    pub fn from_transaction_error(tx: &Transaction, error: TransactionError) -> Self {
+        match error {
+            TransactionError::InstructionError(index, InstructionError::Custom(SYSTEM_INSUFFICIENT_FUNDS))
+                if tx.message.program_id(index as usize).is_some_and(system_program::check_id) =>
+            {
+                ExecutionError::InsufficientFunds { instruction_index: index as usize }
+            }
+            error => ExecutionError::ExecutionFailed { error },
+        }
+    }
+
+    /// The error L1 would report for the same failure, so SDK error matching
+    /// (and Anchor's custom error decoding) works unchanged.
+    pub fn transaction_error(&self) -> TransactionError {
+        match self {
+            ExecutionError::SanitizeFailure { .. } => TransactionError::SanitizeFailure,
+            ExecutionError::SignatureFailure => TransactionError::SignatureFailure,
+            ExecutionError::AlreadyProcessed => TransactionError::AlreadyProcessed,
+            ExecutionError::BlockhashNotFound => TransactionError::BlockhashNotFound,
+            ExecutionError::PrecompileFailed { instruction_index, code } => {
+                instruction_error(*instruction_index, InstructionError::Custom(*code))
+            }
+            ExecutionError::InsufficientFunds { instruction_index } => {
+                instruction_error(*instruction_index, InstructionError::Custom(SYSTEM_INSUFFICIENT_FUNDS))
+            }
+            ExecutionError::ProgramNotSupported { instruction_index, .. } => {
+                instruction_error(*instruction_index, InstructionError::UnsupportedProgramId)
+            }
+            ExecutionError::DustTransfer { instruction_index, .. } => {
+                instruction_error(*instruction_index, InstructionError::InvalidArgument)
+            }
+            ExecutionError::InsufficientFundsForFee { .. }
+            | ExecutionError::InsufficientFundsForSurcharge { .. } => TransactionError::InsufficientFundsForFee,
+            ExecutionError::ExecutionFailed { error } => error.clone(),
+        }
+    }
+}
+
+fn instruction_error(index: usize, error: InstructionError) -> TransactionError {
+    // Messages are capped well below 256 instructions
+    TransactionError::InstructionError(index as u8, error)
+}
+
+fn serialize_pubkey<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(pubkey)
+}
@@ -0,0 +1,24 @@
+use solana_sdk::{message::MessageHeader, pubkey::Pubkey};
+
+/// What the rollup charges to execute a transaction: a flat base fee plus a
+/// fee per required signature, as on L1. Fees are taken from the fee payer
+/// and credited to `fee_collector`, or burned when it is unset.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FeeSchedule {
+    pub base_fee_lamports: u64,
+    pub lamports_per_signature: u64,
+    pub fee_collector: Option<Pubkey>,
+}
+
+impl FeeSchedule {
+    pub fn is_enabled(&self) -> bool {
+        self.base_fee_lamports > 0 || self.lamports_per_signature > 0
+    }
+
+    /// The fee for a message with `header`.
+    pub fn fee(&self, header: &MessageHeader) -> u64 {
+        self.lamports_per_signature
+            .saturating_mul(header.num_required_signatures as u64)
+            .saturating_add(self.base_fee_lamports)
+    }
+}
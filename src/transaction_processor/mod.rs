@@ -1 +1,12 @@
-pub mod transaction_processor;
\ No newline at end of file
+pub mod access_policy;
+pub mod dust_policy;
+pub mod execution_error;
+pub mod fee_schedule;
+pub mod precompiles;
+pub mod preflight_cache;
+pub mod program_registry;
+pub mod svm;
+pub mod token_program;
+pub mod transaction_processor;
+pub mod transaction_record;
+pub mod wire_transaction;
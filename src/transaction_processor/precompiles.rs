@@ -0,0 +1,40 @@
+use std::sync::LazyLock;
+
+use agave_feature_set::FeatureSet;
+use solana_sdk::{ed25519_program, pubkey::Pubkey, secp256k1_program, transaction::Transaction};
+
+use crate::transaction_processor::execution_error::ExecutionError;
+
+/// Precompiles are verified, and transactions executed, as on mainnet, e.g.
+/// with strict ed25519 checks.
+pub(crate) static FEATURE_SET: LazyLock<FeatureSet> = LazyLock::new(FeatureSet::all_enabled);
+
+pub fn is_precompile(program_id: &Pubkey) -> bool {
+    ed25519_program::check_id(program_id) || secp256k1_program::check_id(program_id)
+}
+
+/// Runs every ed25519/secp256k1 signature verification instruction in `tx`.
+/// Like on L1, a single failing instruction fails the whole transaction.
+pub fn verify_precompiles(tx: &Transaction) -> anyhow::Result<()> {
+    let instruction_datas: Vec<&[u8]> = tx.message.instructions.iter()
+        .map(|ix| ix.data.as_slice())
+        .collect();
+
+    for (index, ix) in tx.message.instructions.iter().enumerate() {
+        let Some(program_id) = tx.message.account_keys.get(ix.program_id_index as usize) else {
+            continue;
+        };
+
+        let result = if ed25519_program::check_id(program_id) {
+            agave_precompiles::ed25519::verify(&ix.data, &instruction_datas, &FEATURE_SET)
+        } else if secp256k1_program::check_id(program_id) {
+            agave_precompiles::secp256k1::verify(&ix.data, &instruction_datas, &FEATURE_SET)
+        } else {
+            continue;
+        };
+
+        result.map_err(|e| ExecutionError::PrecompileFailed { instruction_index: index, code: e as u32 })?;
+    }
+
+    Ok(())
+}
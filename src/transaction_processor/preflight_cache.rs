@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use solana_sdk::signature::Signature;
+
+use crate::metrics::metrics;
+
+/// Preflights remembered at once; past this, expired entries are pruned and
+/// new ones are skipped until there is room
+const MAX_ENTRIES: usize = 10_000;
+
+struct Entry {
+    /// Exact message the checks passed for. A signature alone isn't enough,
+    /// since anyone can pair a seen signature with a different message.
+    message: Vec<u8>,
+    expires_at: Instant,
+}
+
+/// Remembers, for a short while, transactions whose signature, access and
+/// precompile checks passed during simulation, so sending the same
+/// transaction right after doesn't verify it again.
+pub struct PreflightCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<Signature, Entry>>,
+}
+
+impl PreflightCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `message` already passed preflight under `signature`, and
+    /// hasn't expired by `now`.
+    pub fn contains(&self, signature: &Signature, message: &[u8], now: Instant) -> bool {
+        let entries = self.entries.lock().unwrap();
+        let hit = entries.get(signature)
+            .is_some_and(|entry| entry.expires_at > now && entry.message == message);
+
+        metrics::increment(if hit { "preflight_cache_hits" } else { "preflight_cache_misses" });
+        hit
+    }
+
+    /// Remembers a preflight passed at `now`.
+    pub fn insert(&self, signature: Signature, message: Vec<u8>, now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires_at > now);
+        }
+        if entries.len() < MAX_ENTRIES {
+            entries.insert(signature, Entry { message, expires_at: now + self.ttl });
+        }
+
+        metrics::set_gauge("preflight_cache_entries", entries.len() as u64);
+    }
+
+    /// Forgets a transaction once it has been processed.
+    pub fn remove(&self, signature: &Signature) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(signature);
+        metrics::set_gauge("preflight_cache_entries", entries.len() as u64);
+    }
+}
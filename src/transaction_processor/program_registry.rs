@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+
+pub const SYSTEM_PROGRAM_ID: Pubkey = Pubkey::from_str_const("11111111111111111111111111111111");
+pub const MEMO_PROGRAM_ID: Pubkey = Pubkey::from_str_const("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+pub const TOKEN_PROGRAM_ID: Pubkey = spl_token_interface::ID;
+
+/// spl-token account layout sizes
+const TOKEN_ACCOUNT_LEN: usize = 165;
+const TOKEN_MINT_LEN: usize = 82;
+
+/// What the rollup knows about a program: how the accounts it owns render,
+/// and for memo and token programs, that they run as the matching builtin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgramHandler {
+    /// The builtin system program; only valid for the system program
+    System,
+    /// Runs as the builtin memo program, without any effect on state
+    Memo,
+    /// Runs as the builtin spl-token emulation; token accounts and mints
+    /// render as jsonParsed
+    SplToken,
+    /// Any other program deployed to L2
+    Svm,
+}
+
+impl ProgramHandler {
+    /// Name of the program in jsonParsed output.
+    fn program_name(&self) -> &'static str {
+        match self {
+            ProgramHandler::System => "system",
+            ProgramHandler::Memo => "spl-memo",
+            ProgramHandler::SplToken => "spl-token",
+            ProgramHandler::Svm => "unknown",
+        }
+    }
+}
+
+/// The balance fields of an spl-token account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+/// A registration made through the admin RPC. It is recorded with the next
+/// block sealed and takes effect once that block is stored, so a replay
+/// changes its registry at the same point of the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgramChange {
+    pub id: u64,
+    pub program_id: Pubkey,
+    /// The new handler, or `None` to unregister the program
+    pub handler: Option<ProgramHandler>,
+}
+
+/// Programs this network knows, mapped to their handler. Only registered
+/// programs are executed (unless unknown programs are allowed), and `jsonParsed`
+/// account encoding only parses data owned by a registered program, so
+/// support for a program is enabled per network (`--program`, or the admin
+/// RPC from the next block on) rather than compiled in.
+pub struct ProgramRegistry {
+    programs: RwLock<HashMap<Pubkey, ProgramHandler>>,
+}
+
+impl Default for ProgramRegistry {
+    /// The system, memo and spl-token programs.
+    fn default() -> Self {
+        Self {
+            programs: RwLock::new(HashMap::from([
+                (SYSTEM_PROGRAM_ID, ProgramHandler::System),
+                (MEMO_PROGRAM_ID, ProgramHandler::Memo),
+                (TOKEN_PROGRAM_ID, ProgramHandler::SplToken),
+            ])),
+        }
+    }
+}
+
+impl ProgramRegistry {
+    /// The default programs plus `programs`, which take precedence.
+    pub fn new(programs: impl IntoIterator<Item = (Pubkey, ProgramHandler)>) -> anyhow::Result<Self> {
+        let registry = Self::default();
+        for (program_id, handler) in programs {
+            registry.register(program_id, handler)?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Parses `PROGRAM_ID=HANDLER`.
+    pub fn parse_spec(spec: &str) -> anyhow::Result<(Pubkey, ProgramHandler)> {
+        let (program_id, handler) = spec.split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid program {}: expected PROGRAM_ID=HANDLER", spec))?;
+        let program_id = Pubkey::from_str(program_id)
+            .map_err(|e| anyhow::anyhow!("Invalid program id in {}: {}", spec, e))?;
+        let handler = ProgramHandler::from_str(handler, true)
+            .map_err(|e| anyhow::anyhow!("Invalid program handler in {}: {}", spec, e))?;
+
+        Ok((program_id, handler))
+    }
+
+    pub fn handler(&self, program_id: &Pubkey) -> Option<ProgramHandler> {
+        self.programs.read().unwrap().get(program_id).copied()
+    }
+
+    /// Registers or replaces the handler of `program_id`, returning the one
+    /// it replaced.
+    pub fn register(&self, program_id: Pubkey, handler: ProgramHandler) -> anyhow::Result<Option<ProgramHandler>> {
+        Self::check(&program_id, Some(handler))?;
+
+        Ok(self.programs.write().unwrap().insert(program_id, handler))
+    }
+
+    /// Removes `program_id`, returning its handler if it was registered.
+    pub fn unregister(&self, program_id: &Pubkey) -> anyhow::Result<Option<ProgramHandler>> {
+        Self::check(program_id, None)?;
+
+        Ok(self.programs.write().unwrap().remove(program_id))
+    }
+
+    /// Registers or unregisters the program `change` is for, returning the
+    /// handler it replaced.
+    pub fn apply(&self, change: &ProgramChange) -> anyhow::Result<Option<ProgramHandler>> {
+        match change.handler {
+            Some(handler) => self.register(change.program_id, handler),
+            None => self.unregister(&change.program_id),
+        }
+    }
+
+    /// Fails if `program_id` can't be given `handler`, or unregistered for
+    /// `None`, so a change can be refused before it is queued.
+    pub fn check(program_id: &Pubkey, handler: Option<ProgramHandler>) -> anyhow::Result<()> {
+        match handler {
+            Some(handler) if (handler == ProgramHandler::System) != (*program_id == SYSTEM_PROGRAM_ID) => {
+                anyhow::bail!("The system program must use the system handler, and only it can")
+            }
+            None if *program_id == SYSTEM_PROGRAM_ID => anyhow::bail!("The system program can't be unregistered"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Every registered program, ordered by id.
+    pub fn programs(&self) -> Vec<(Pubkey, ProgramHandler)> {
+        let mut programs: Vec<_> = self.programs.read().unwrap()
+            .iter()
+            .map(|(program_id, handler)| (*program_id, *handler))
+            .collect();
+        programs.sort_by_key(|(program_id, _)| *program_id);
+        programs
+    }
+
+    /// Balance of a token account, if `owner` is a registered token program
+    /// and `data` an initialized token account.
+    pub fn token_account(&self, owner: &Pubkey, data: &[u8]) -> Option<TokenAccount> {
+        if self.handler(owner) != Some(ProgramHandler::SplToken) || data.len() != TOKEN_ACCOUNT_LEN || data.get(108)? == &0 {
+            return None;
+        }
+
+        Some(TokenAccount {
+            mint: read_pubkey(data, 0)?,
+            owner: read_pubkey(data, 32)?,
+            amount: read_u64(data, 64)?,
+        })
+    }
+
+    /// Decimals of a mint, if `owner` is a registered token program and
+    /// `data` an initialized mint.
+    pub fn mint_decimals(&self, owner: &Pubkey, data: &[u8]) -> Option<u8> {
+        if self.handler(owner) != Some(ProgramHandler::SplToken) || data.len() != TOKEN_MINT_LEN || data.get(45)? != &1 {
+            return None;
+        }
+
+        data.get(44).copied()
+    }
+
+    /// Account data in Solana's `jsonParsed` form, or `None` when the owner
+    /// isn't registered or its handler can't parse the data.
+    pub fn parse_account(&self, owner: &Pubkey, data: &[u8]) -> Option<Value> {
+        let handler = self.handler(owner)?;
+        let parsed = match handler {
+            ProgramHandler::SplToken => parse_token_account(data)?,
+            ProgramHandler::System | ProgramHandler::Memo | ProgramHandler::Svm => return None,
+        };
+
+        Some(serde_json::json!({
+            "program": handler.program_name(),
+            "parsed": parsed,
+            "space": data.len(),
+        }))
+    }
+}
+
+fn parse_token_account(data: &[u8]) -> Option<Value> {
+    match data.len() {
+        TOKEN_ACCOUNT_LEN => {
+            let state = match data.get(108)? {
+                1 => "initialized",
+                2 => "frozen",
+                _ => return None,
+            };
+            Some(serde_json::json!({
+                "type": "account",
+                "info": {
+                    "mint": read_pubkey(data, 0)?.to_string(),
+                    "owner": read_pubkey(data, 32)?.to_string(),
+                    "amount": read_u64(data, 64)?.to_string(),
+                    "delegate": read_optional_pubkey(data, 72)?.map(|p| p.to_string()),
+                    "state": state,
+                    "isNative": read_u32(data, 109)? == 1,
+                    "delegatedAmount": read_u64(data, 121)?.to_string(),
+                    "closeAuthority": read_optional_pubkey(data, 129)?.map(|p| p.to_string()),
+                }
+            }))
+        }
+        TOKEN_MINT_LEN => {
+            if data.get(45)? != &1 {
+                return None;
+            }
+            Some(serde_json::json!({
+                "type": "mint",
+                "info": {
+                    "mintAuthority": read_optional_pubkey(data, 0)?.map(|p| p.to_string()),
+                    "supply": read_u64(data, 36)?.to_string(),
+                    "decimals": data.get(44)?,
+                    "isInitialized": true,
+                    "freezeAuthority": read_optional_pubkey(data, 46)?.map(|p| p.to_string()),
+                }
+            }))
+        }
+        _ => None,
+    }
+}
+
+// Readers return None rather than panic when `data` is too short
+
+fn read_pubkey(data: &[u8], offset: usize) -> Option<Pubkey> {
+    Some(Pubkey::new_from_array(data.get(offset..offset + 32)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// A `COption<Pubkey>`: a u32 tag followed by the key.
+/// A `COption<Pubkey>`: a u32 tag, then the key.
+fn read_optional_pubkey(data: &[u8], offset: usize) -> Option<Option<Pubkey>> {
+    match read_u32(data, offset)? {
+        1 => read_pubkey(data, offset + 4).map(Some),
+        _ => Some(None),
+    }
+}
@@ -0,0 +1,378 @@
+use std::{cmp::Ordering, collections::HashMap, slice, sync::{Arc, RwLock}};
+
+use agave_syscalls::{create_program_runtime_environment_v1, create_program_runtime_environment_v2};
+use solana_compute_budget_instruction::instructions_processor::process_compute_budget_instructions;
+use solana_precompile_error::PrecompileError;
+use solana_program_runtime::{
+    declare_process_instruction,
+    execution_budget::SVMTransactionExecutionBudget,
+    invoke_context::BuiltinFunctionWithContext,
+    loaded_programs::{BlockRelation, ForkGraph, ProgramCacheEntry},
+};
+use solana_sdk::{
+    account::{Account, AccountSharedData, ReadableAccount},
+    clock::{Clock, Slot},
+    epoch_schedule::EpochSchedule,
+    fee::FeeDetails,
+    hash::Hash,
+    instruction::InstructionError,
+    native_loader,
+    pubkey::Pubkey,
+    rent::Rent,
+    transaction::{SanitizedTransaction, TransactionError},
+};
+use solana_sdk_ids::{bpf_loader, bpf_loader_deprecated, bpf_loader_upgradeable, compute_budget, loader_v4, system_program, sysvar};
+use solana_svm::{
+    account_loader::CheckedTransactionDetails,
+    transaction_processing_result::ProcessedTransaction,
+    transaction_processor::{
+        ExecutionRecordingConfig, TransactionBatchProcessor, TransactionProcessingConfig, TransactionProcessingEnvironment,
+    },
+};
+use solana_svm_callback::{InvokeContextCallback, TransactionProcessingCallback};
+use solana_svm_transaction::svm_message::SVMMessage;
+use solana_transaction_context::TransactionReturnData;
+
+use crate::{
+    sequencer::block::{BlockSeed, BLOCK_SEED_SYSVAR_ID},
+    state_manager::state_manager::{L2Account, StateManager},
+    transaction_processor::{
+        precompiles::{is_precompile, FEATURE_SET},
+        program_registry::{MEMO_PROGRAM_ID, TOKEN_PROGRAM_ID},
+        token_program::TokenProgram,
+    },
+};
+
+/// What the memo program charges, the same as the system program
+const MEMO_COMPUTE_UNITS: u64 = 150;
+
+// Like spl-memo: the data must be UTF-8 and every listed account must sign
+declare_process_instruction!(MemoProgram, MEMO_COMPUTE_UNITS, |invoke_context| {
+    let instruction_context = invoke_context.transaction_context.get_current_instruction_context()?;
+    for index in 0..instruction_context.get_number_of_instruction_accounts() {
+        if !instruction_context.is_instruction_account_signer(index)? {
+            return Err(InstructionError::MissingRequiredSignature);
+        }
+    }
+    std::str::from_utf8(instruction_context.get_instruction_data())
+        .map_err(|_| InstructionError::InvalidInstructionData)?;
+
+    Ok(())
+});
+
+/// The block a transaction executes in, as programs see it through the
+/// Clock and block seed sysvars.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockContext {
+    /// Slot of the block being built
+    pub slot: u64,
+    /// Blockhash of its parent, or the genesis hash before the first block
+    pub blockhash: Hash,
+    /// Time its parent was sealed
+    pub unix_timestamp: i64,
+    /// The block's `block_seed`
+    pub seed: Hash,
+}
+
+/// A transaction executed on the SVM but not yet written to state.
+pub struct SvmExecution {
+    /// Writable accounts the transaction changed, in message order. Those
+    /// left with no lamports are closed on commit
+    pub accounts: Vec<(Pubkey, L2Account)>,
+    programs_modified: HashMap<Pubkey, Arc<ProgramCacheEntry>>,
+}
+
+/// What a transaction did on the SVM, whether or not it succeeded.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionTrace {
+    /// Program logs, as on L1
+    pub logs: Vec<String>,
+    pub units_consumed: u64,
+    /// Only recorded by simulations
+    pub return_data: Option<TransactionReturnData>,
+}
+
+/// Blocks form a single chain, so every earlier slot is an ancestor.
+struct LinearForkGraph;
+
+impl ForkGraph for LinearForkGraph {
+    fn relationship(&self, a: Slot, b: Slot) -> BlockRelation {
+        match a.cmp(&b) {
+            Ordering::Less => BlockRelation::Ancestor,
+            Ordering::Equal => BlockRelation::Equal,
+            Ordering::Greater => BlockRelation::Descendant,
+        }
+    }
+}
+
+/// Executes transactions against L2 state with Agave's SVM: the system,
+/// compute budget and memo programs are built in, and programs deployed to
+/// L2 through the BPF loaders run with CPIs, as on L1. The rollup charges no
+/// fees and collects rent per epoch itself, so the SVM sees a zero fee and
+/// treats every account as rent exempt.
+pub struct SvmEngine {
+    state_manager: Arc<StateManager>,
+    processor: TransactionBatchProcessor<LinearForkGraph>,
+    /// The program cache only holds a weak reference
+    _fork_graph: Arc<RwLock<LinearForkGraph>>,
+    /// Loadable accounts of the builtins, which don't exist in L2 state
+    builtin_accounts: RwLock<HashMap<Pubkey, AccountSharedData>>,
+}
+
+impl SvmEngine {
+    pub fn new(state_manager: Arc<StateManager>) -> Self {
+        let features = FEATURE_SET.runtime_features();
+        let budget = SVMTransactionExecutionBudget::new_with_defaults(features.raise_cpi_nesting_limit_to_8);
+        // Only fails if two syscalls are registered under the same name
+        let environment_v1 = create_program_runtime_environment_v1(&features, &budget, false, false)
+            .expect("valid program runtime environment");
+        let environment_v2 = create_program_runtime_environment_v2(&budget, false);
+
+        let fork_graph = Arc::new(RwLock::new(LinearForkGraph));
+        let engine = Self {
+            state_manager,
+            processor: TransactionBatchProcessor::new(
+                0,
+                0,
+                Arc::downgrade(&fork_graph),
+                Some(Arc::new(environment_v1)),
+                Some(Arc::new(environment_v2)),
+            ),
+            _fork_graph: fork_graph,
+            builtin_accounts: RwLock::new(HashMap::new()),
+        };
+
+        let builtins: [(Pubkey, &str, BuiltinFunctionWithContext); 8] = [
+            (system_program::id(), "system_program", solana_system_program::system_processor::Entrypoint::vm),
+            (compute_budget::id(), "compute_budget_program", solana_compute_budget_program::Entrypoint::vm),
+            (bpf_loader_deprecated::id(), "solana_bpf_loader_deprecated_program", solana_bpf_loader_program::Entrypoint::vm),
+            (bpf_loader::id(), "solana_bpf_loader_program", solana_bpf_loader_program::Entrypoint::vm),
+            (bpf_loader_upgradeable::id(), "solana_bpf_loader_upgradeable_program", solana_bpf_loader_program::Entrypoint::vm),
+            (loader_v4::id(), "solana_loader_v4_program", solana_loader_v4_program::Entrypoint::vm),
+            (MEMO_PROGRAM_ID, "spl_memo", MemoProgram::vm),
+            (TOKEN_PROGRAM_ID, "spl_token", TokenProgram::vm),
+        ];
+        for (program_id, name, entrypoint) in builtins {
+            engine.add_builtin(program_id, name, entrypoint);
+        }
+
+        engine
+    }
+
+    /// Runs `program_id` as the memo program, for networks that register
+    /// another memo program id.
+    pub fn add_memo_program(&self, program_id: Pubkey) {
+        if !self.processor.builtin_program_ids.read().unwrap().contains(&program_id) {
+            self.add_builtin(program_id, "spl_memo", MemoProgram::vm);
+        }
+    }
+
+    /// Runs `program_id` as the emulated spl-token program, for networks
+    /// that register another token program id.
+    pub fn add_token_program(&self, program_id: Pubkey) {
+        if !self.processor.builtin_program_ids.read().unwrap().contains(&program_id) {
+            self.add_builtin(program_id, "spl_token", TokenProgram::vm);
+        }
+    }
+
+    fn add_builtin(&self, program_id: Pubkey, name: &str, entrypoint: BuiltinFunctionWithContext) {
+        let accounts = Accounts::new(self, HashMap::new());
+        let entry = ProgramCacheEntry::new_builtin(0, name.len(), entrypoint);
+        self.processor.add_builtin(&accounts, program_id, name, entry);
+    }
+
+    /// Executes `tx` in `block` without changing state. A transaction that
+    /// fails leaves nothing to commit, so its error is returned instead.
+    /// Logs are recorded, including a failed execution's, for the compute
+    /// units each program consumed.
+    pub fn execute(&self, tx: &SanitizedTransaction, block: &BlockContext) -> (Result<SvmExecution, TransactionError>, ExecutionTrace) {
+        self.run(tx, block, false)
+    }
+
+    /// Like `execute`, also recording the return data of the execution.
+    pub fn simulate(&self, tx: &SanitizedTransaction, block: &BlockContext) -> (Result<SvmExecution, TransactionError>, ExecutionTrace) {
+        self.run(tx, block, true)
+    }
+
+    fn run(&self, tx: &SanitizedTransaction, block: &BlockContext, simulation: bool) -> (Result<SvmExecution, TransactionError>, ExecutionTrace) {
+        let accounts = Accounts::new(self, sysvars(block));
+        let processor = self.processor.new_from(block.slot, 0);
+        processor.fill_missing_sysvar_cache_entries(&accounts);
+
+        let budget = process_compute_budget_instructions(SVMMessage::program_instructions_iter(tx), &FEATURE_SET)
+            .map(|limits| limits.get_compute_budget_and_limits(
+                limits.loaded_accounts_bytes,
+                FeeDetails::default(),
+                FEATURE_SET.runtime_features().raise_cpi_nesting_limit_to_8,
+            ));
+        let environment = TransactionProcessingEnvironment {
+            blockhash: block.blockhash,
+            feature_set: FEATURE_SET.runtime_features(),
+            rent: rent(),
+            ..TransactionProcessingEnvironment::default()
+        };
+        let config = TransactionProcessingConfig {
+            recording_config: ExecutionRecordingConfig {
+                enable_log_recording: true,
+                enable_return_data_recording: simulation,
+                ..ExecutionRecordingConfig::default()
+            },
+            ..TransactionProcessingConfig::default()
+        };
+        let output = processor.load_and_execute_sanitized_transactions(
+            &accounts,
+            slice::from_ref(tx),
+            vec![Ok(CheckedTransactionDetails::new(None, budget))],
+            &environment,
+            &config,
+        );
+
+        let processed = match output.processing_results.into_iter().next().expect("one result per transaction") {
+            Ok(processed) => processed,
+            Err(error) => return (Err(error), ExecutionTrace::default()),
+        };
+        let ProcessedTransaction::Executed(executed) = processed else {
+            return (Err(processed.status().unwrap_err()), ExecutionTrace::default());
+        };
+        let details = executed.execution_details;
+        let trace = ExecutionTrace {
+            logs: details.log_messages.unwrap_or_default(),
+            units_consumed: details.executed_units,
+            return_data: details.return_data,
+        };
+        if let Err(error) = details.status {
+            return (Err(error), trace);
+        }
+
+        let changed = executed.loaded_transaction.accounts.into_iter()
+            .enumerate()
+            .filter(|(index, _)| tx.is_writable(*index))
+            .filter_map(|(_, (pubkey, account))| {
+                let previous = self.state_manager.load_account(&pubkey);
+                changed_account(previous.as_ref(), &account).map(|account| (pubkey, account))
+            })
+            .collect();
+
+        (Ok(SvmExecution { accounts: changed, programs_modified: executed.programs_modified_by_tx }), trace)
+    }
+
+    /// Writes an execution's accounts to state in one atomic commit, and
+    /// makes programs it deployed or upgraded visible to later transactions.
+    ///
+    /// As on Solana, an account drained of lamports no longer exists,
+    /// whatever data or owner it was left with: closing it removes it from
+    /// the state tree and its indexes, and subscribers see it deleted.
+    pub async fn commit(&self, execution: SvmExecution) -> anyhow::Result<()> {
+        let changes = execution.accounts.into_iter()
+            .map(|(pubkey, account)| (pubkey, (account.lamports > 0).then_some(account)))
+            .collect();
+        self.state_manager.commit_accounts(changes).await?;
+        self.processor.global_program_cache.write().unwrap().merge(&execution.programs_modified);
+
+        Ok(())
+    }
+}
+
+/// L2 state, plus the builtins and sysvars that only exist for the SVM.
+struct Accounts<'a> {
+    engine: &'a SvmEngine,
+    sysvars: HashMap<Pubkey, AccountSharedData>,
+}
+
+impl<'a> Accounts<'a> {
+    fn new(engine: &'a SvmEngine, sysvars: HashMap<Pubkey, AccountSharedData>) -> Self {
+        Self { engine, sysvars }
+    }
+}
+
+impl InvokeContextCallback for Accounts<'_> {
+    fn is_precompile(&self, program_id: &Pubkey) -> bool {
+        is_precompile(program_id)
+    }
+
+    fn process_precompile(&self, _program_id: &Pubkey, _data: &[u8], _instruction_datas: Vec<&[u8]>) -> Result<(), PrecompileError> {
+        // Every precompile instruction was verified before execution
+        Ok(())
+    }
+}
+
+impl TransactionProcessingCallback for Accounts<'_> {
+    fn get_account_shared_data(&self, pubkey: &Pubkey) -> Option<(AccountSharedData, Slot)> {
+        if let Some(account) = self.sysvars.get(pubkey).or(self.engine.builtin_accounts.read().unwrap().get(pubkey)) {
+            return Some((account.clone(), 0));
+        }
+        if is_precompile(pubkey) {
+            return Some((native_loader::create_loadable_account_with_fields("precompile", (1, 0)), 0));
+        }
+
+        let account = self.engine.state_manager.load_account(pubkey)?;
+        Some((AccountSharedData::from(Account {
+            lamports: account.lamports,
+            data: account.data,
+            owner: account.owner,
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+        }), 0))
+    }
+
+    fn add_builtin_account(&self, name: &str, program_id: &Pubkey) {
+        self.engine.builtin_accounts.write().unwrap()
+            .insert(*program_id, native_loader::create_loadable_account_with_fields(name, (1, 0)));
+    }
+}
+
+/// Rent the SVM checks accounts against: none, since epochs collect it.
+fn rent() -> Rent {
+    Rent { lamports_per_byte_year: 0, ..Rent::default() }
+}
+
+fn sysvars(block: &BlockContext) -> HashMap<Pubkey, AccountSharedData> {
+    let clock = Clock {
+        slot: block.slot,
+        epoch_start_timestamp: 0,
+        epoch: 0,
+        leader_schedule_epoch: 0,
+        unix_timestamp: block.unix_timestamp,
+    };
+
+    HashMap::from([
+        (sysvar::clock::id(), sysvar_account(&clock)),
+        (sysvar::rent::id(), sysvar_account(&rent())),
+        (sysvar::epoch_schedule::id(), sysvar_account(&EpochSchedule::default())),
+        (BLOCK_SEED_SYSVAR_ID, sysvar_account(&BlockSeed { slot: block.slot, seed: block.seed.to_bytes() })),
+    ])
+}
+
+fn sysvar_account<T: serde::Serialize>(value: &T) -> AccountSharedData {
+    AccountSharedData::from(Account {
+        lamports: 1,
+        data: bincode::serialize(value).unwrap(),
+        owner: sysvar::id(),
+        executable: false,
+        rent_epoch: 0,
+    })
+}
+
+/// `account` as an L2 account if it differs from `previous`. An account the
+/// transaction only loaded, or left empty without it existing, is not
+/// written; the SVM's rent epoch is ignored in favour of the rollup's own.
+fn changed_account(previous: Option<&L2Account>, account: &AccountSharedData) -> Option<L2Account> {
+    let unchanged = match previous {
+        Some(previous) => previous.lamports == account.lamports()
+            && previous.data == account.data()
+            && previous.owner == *account.owner()
+            && previous.executable == account.executable(),
+        None => account.lamports() == 0,
+    };
+    if unchanged {
+        return None;
+    }
+
+    Some(L2Account {
+        lamports: account.lamports(),
+        data: account.data().to_vec(),
+        owner: *account.owner(),
+        executable: account.executable(),
+        rent_epoch: previous.map_or(0, |previous| previous.rent_epoch),
+    })
+}
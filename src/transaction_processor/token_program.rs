@@ -0,0 +1,291 @@
+//! spl-token emulated as a builtin, so mints and token transfers work on L2
+//! against account data without deploying the spl-token program. Supports
+//! InitializeMint, InitializeAccount, Transfer, MintTo and Burn, including
+//! their `2`/`3` and `Checked` variants; anything else fails with
+//! spl-token's InvalidInstruction error. Multisig authorities aren't
+//! supported.
+
+use solana_program_option::COption;
+use solana_program_pack::{IsInitialized, Pack};
+use solana_program_runtime::declare_process_instruction;
+use solana_sdk::{instruction::InstructionError, pubkey::Pubkey};
+use solana_transaction_context::{BorrowedAccount, IndexOfAccount, InstructionContext};
+use spl_token_interface::{
+    error::TokenError,
+    instruction::TokenInstruction,
+    state::{Account, AccountState, Mint},
+};
+
+/// About what spl-token's transfer costs on Solana
+const TOKEN_COMPUTE_UNITS: u64 = 4_500;
+
+declare_process_instruction!(TokenProgram, TOKEN_COMPUTE_UNITS, |invoke_context| {
+    let instruction_context = invoke_context.transaction_context.get_current_instruction_context()?;
+    let instruction = TokenInstruction::unpack(instruction_context.get_instruction_data())
+        .map_err(|_| token_error(TokenError::InvalidInstruction))?;
+
+    match instruction {
+        TokenInstruction::InitializeMint { decimals, mint_authority, freeze_authority }
+        | TokenInstruction::InitializeMint2 { decimals, mint_authority, freeze_authority } => {
+            initialize_mint(&instruction_context, decimals, mint_authority, freeze_authority)
+        }
+        TokenInstruction::InitializeAccount => {
+            let owner = *instruction_context.get_key_of_instruction_account(2)?;
+            initialize_account(&instruction_context, owner)
+        }
+        TokenInstruction::InitializeAccount2 { owner } | TokenInstruction::InitializeAccount3 { owner } => {
+            initialize_account(&instruction_context, owner)
+        }
+        TokenInstruction::Transfer { amount } => transfer(&instruction_context, amount, None),
+        TokenInstruction::TransferChecked { amount, decimals } => transfer(&instruction_context, amount, Some(decimals)),
+        TokenInstruction::MintTo { amount } => mint_to(&instruction_context, amount, None),
+        TokenInstruction::MintToChecked { amount, decimals } => mint_to(&instruction_context, amount, Some(decimals)),
+        TokenInstruction::Burn { amount } => burn(&instruction_context, amount, None),
+        TokenInstruction::BurnChecked { amount, decimals } => burn(&instruction_context, amount, Some(decimals)),
+        _ => Err(token_error(TokenError::InvalidInstruction)),
+    }
+});
+
+/// Accounts: mint. Rent is free on L2, so the mint needn't be rent exempt.
+fn initialize_mint(
+    instruction_context: &InstructionContext,
+    decimals: u8,
+    mint_authority: Pubkey,
+    freeze_authority: COption<Pubkey>
+) -> Result<(), InstructionError> {
+    let mut mint_account = instruction_context.try_borrow_instruction_account(0)?;
+    check_uninitialized::<Mint>(&mint_account)?;
+
+    let mint = Mint {
+        mint_authority: COption::Some(mint_authority),
+        supply: 0,
+        decimals,
+        is_initialized: true,
+        freeze_authority,
+    };
+    pack(mint, &mut mint_account)
+}
+
+/// Accounts: token account, mint.
+fn initialize_account(instruction_context: &InstructionContext, owner: Pubkey) -> Result<(), InstructionError> {
+    let mut token_account = instruction_context.try_borrow_instruction_account(0)?;
+    check_uninitialized::<Account>(&token_account)?;
+
+    let mint_account = instruction_context.try_borrow_instruction_account(1)?;
+    unpack::<Mint>(&mint_account).map_err(|_| token_error(TokenError::InvalidMint))?;
+
+    let account = Account {
+        mint: *mint_account.get_key(),
+        owner,
+        amount: 0,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    };
+    pack(account, &mut token_account)
+}
+
+/// Accounts: source, destination, authority; or with `decimals`, source,
+/// mint, destination, authority.
+fn transfer(instruction_context: &InstructionContext, amount: u64, decimals: Option<u8>) -> Result<(), InstructionError> {
+    let (destination_index, authority_index) = match decimals {
+        Some(_) => (2, 3),
+        None => (1, 2),
+    };
+
+    let mut source_account = instruction_context.try_borrow_instruction_account(0)?;
+    let mut source = unpack::<Account>(&source_account)?;
+    if source.is_frozen() {
+        return Err(token_error(TokenError::AccountFrozen));
+    }
+    if source.amount < amount {
+        return Err(token_error(TokenError::InsufficientFunds));
+    }
+    if let Some(decimals) = decimals {
+        let mint_account = instruction_context.try_borrow_instruction_account(1)?;
+        check_mint(&source, &mint_account, Some(decimals))?;
+    }
+    let delegated = authorize(instruction_context, &source, authority_index, amount)?;
+
+    // A transfer to itself only has to pass the checks
+    if instruction_context.get_key_of_instruction_account(0)?
+        == instruction_context.get_key_of_instruction_account(destination_index)?
+    {
+        return Ok(());
+    }
+
+    let mut destination_account = instruction_context.try_borrow_instruction_account(destination_index)?;
+    let mut destination = unpack::<Account>(&destination_account)?;
+    if destination.is_frozen() {
+        return Err(token_error(TokenError::AccountFrozen));
+    }
+    if destination.mint != source.mint {
+        return Err(token_error(TokenError::MintMismatch));
+    }
+
+    source.amount -= amount;
+    destination.amount = destination.amount.checked_add(amount)
+        .ok_or_else(|| token_error(TokenError::Overflow))?;
+    if delegated {
+        spend_delegation(&mut source, amount);
+    }
+
+    pack(source, &mut source_account)?;
+    pack(destination, &mut destination_account)
+}
+
+/// Accounts: mint, destination, mint authority.
+fn mint_to(instruction_context: &InstructionContext, amount: u64, decimals: Option<u8>) -> Result<(), InstructionError> {
+    let mut mint_account = instruction_context.try_borrow_instruction_account(0)?;
+    let mut mint = unpack::<Mint>(&mint_account)?;
+    if let Some(decimals) = decimals
+        && decimals != mint.decimals
+    {
+        return Err(token_error(TokenError::MintDecimalsMismatch));
+    }
+    match mint.mint_authority {
+        COption::Some(mint_authority) => check_signer(instruction_context, 2, &mint_authority)?,
+        COption::None => return Err(token_error(TokenError::FixedSupply)),
+    }
+
+    let mut destination_account = instruction_context.try_borrow_instruction_account(1)?;
+    let mut destination = unpack::<Account>(&destination_account)?;
+    if destination.is_frozen() {
+        return Err(token_error(TokenError::AccountFrozen));
+    }
+    if destination.mint != *mint_account.get_key() {
+        return Err(token_error(TokenError::MintMismatch));
+    }
+
+    destination.amount = destination.amount.checked_add(amount)
+        .ok_or_else(|| token_error(TokenError::Overflow))?;
+    mint.supply = mint.supply.checked_add(amount)
+        .ok_or_else(|| token_error(TokenError::Overflow))?;
+
+    pack(mint, &mut mint_account)?;
+    pack(destination, &mut destination_account)
+}
+
+/// Accounts: token account, mint, authority.
+fn burn(instruction_context: &InstructionContext, amount: u64, decimals: Option<u8>) -> Result<(), InstructionError> {
+    let mut token_account = instruction_context.try_borrow_instruction_account(0)?;
+    let mut account = unpack::<Account>(&token_account)?;
+    if account.is_frozen() {
+        return Err(token_error(TokenError::AccountFrozen));
+    }
+    if account.amount < amount {
+        return Err(token_error(TokenError::InsufficientFunds));
+    }
+
+    let mut mint_account = instruction_context.try_borrow_instruction_account(1)?;
+    let mut mint = check_mint(&account, &mint_account, decimals)?;
+    let delegated = authorize(instruction_context, &account, 2, amount)?;
+
+    account.amount -= amount;
+    mint.supply = mint.supply.checked_sub(amount)
+        .ok_or_else(|| token_error(TokenError::Overflow))?;
+    if delegated {
+        spend_delegation(&mut account, amount);
+    }
+
+    pack(account, &mut token_account)?;
+    pack(mint, &mut mint_account)
+}
+
+/// Checks that the authority at `index` signed and is `account`'s owner
+/// or, for up to its delegated amount, its delegate. Returns whether it
+/// acted as the delegate.
+fn authorize(
+    instruction_context: &InstructionContext,
+    account: &Account,
+    index: IndexOfAccount,
+    amount: u64
+) -> Result<bool, InstructionError> {
+    let authority = instruction_context.get_key_of_instruction_account(index)?;
+    match account.delegate {
+        COption::Some(delegate) if delegate == *authority => {
+            check_signer(instruction_context, index, &delegate)?;
+            if account.delegated_amount < amount {
+                return Err(token_error(TokenError::InsufficientFunds));
+            }
+            Ok(true)
+        }
+        _ => {
+            check_signer(instruction_context, index, &account.owner)?;
+            Ok(false)
+        }
+    }
+}
+
+fn spend_delegation(account: &mut Account, amount: u64) {
+    account.delegated_amount -= amount;
+    if account.delegated_amount == 0 {
+        account.delegate = COption::None;
+    }
+}
+
+/// Checks that the account at `index` is `expected` and signed.
+fn check_signer(instruction_context: &InstructionContext, index: IndexOfAccount, expected: &Pubkey) -> Result<(), InstructionError> {
+    if instruction_context.get_key_of_instruction_account(index)? != expected {
+        return Err(token_error(TokenError::OwnerMismatch));
+    }
+    if !instruction_context.is_instruction_account_signer(index)? {
+        return Err(InstructionError::MissingRequiredSignature);
+    }
+
+    Ok(())
+}
+
+/// Unpacks `account`'s mint from `mint_account`, checking its decimals if
+/// the instruction named them.
+fn check_mint(account: &Account, mint_account: &BorrowedAccount, decimals: Option<u8>) -> Result<Mint, InstructionError> {
+    if account.mint != *mint_account.get_key() {
+        return Err(token_error(TokenError::MintMismatch));
+    }
+    let mint = unpack::<Mint>(mint_account)?;
+    if let Some(decimals) = decimals
+        && decimals != mint.decimals
+    {
+        return Err(token_error(TokenError::MintDecimalsMismatch));
+    }
+
+    Ok(mint)
+}
+
+/// Checks that `account` is ours, sized for a `T` and not yet initialized.
+fn check_uninitialized<T: Pack + IsInitialized>(account: &BorrowedAccount) -> Result<(), InstructionError> {
+    if !account.is_owned_by_current_program() {
+        return Err(InstructionError::IncorrectProgramId);
+    }
+    let state = T::unpack_unchecked(account.get_data())
+        .map_err(|_| InstructionError::InvalidAccountData)?;
+    if state.is_initialized() {
+        return Err(token_error(TokenError::AlreadyInUse));
+    }
+
+    Ok(())
+}
+
+/// Unpacks an initialized `T` from an account this program owns.
+fn unpack<T: Pack + IsInitialized>(account: &BorrowedAccount) -> Result<T, InstructionError> {
+    if !account.is_owned_by_current_program() {
+        return Err(InstructionError::IncorrectProgramId);
+    }
+    let state = T::unpack_unchecked(account.get_data())
+        .map_err(|_| InstructionError::InvalidAccountData)?;
+    if !state.is_initialized() {
+        return Err(token_error(TokenError::UninitializedState));
+    }
+
+    Ok(state)
+}
+
+fn pack<T: Pack>(state: T, account: &mut BorrowedAccount) -> Result<(), InstructionError> {
+    T::pack(state, account.get_data_mut()?).map_err(|_| InstructionError::InvalidAccountData)
+}
+
+fn token_error(error: TokenError) -> InstructionError {
+    InstructionError::Custom(error as u32)
+}
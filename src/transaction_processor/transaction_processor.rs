@@ -1,184 +1,719 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{collections::HashSet, fmt, sync::Mutex};
 use std::sync::Arc;
+use std::time::Duration;
 
 use solana_sdk::{
-    pubkey::Pubkey, 
-    // system_program,
-    transaction::Transaction
+    hash::Hash,
+    pubkey::Pubkey,
+    sanitize::Sanitize,
+    signature::Signature,
+    transaction::{SanitizedTransaction, Transaction}
 };
 
-use crate::state_manager::state_manager::{L2Account, StateManager};
+use crate::{
+    clock::clock::{Clock, SystemClock},
+    metrics::metrics,
+    program_stats::program_stats::program_usage,
+    sequencer::block::block_seed,
+    state_manager::state_manager::{L2Account, StateManager},
+    transaction_processor::{
+        access_policy::AccessPolicy,
+        dust_policy::{DustAction, DustPolicy},
+        execution_error::ExecutionError,
+        fee_schedule::FeeSchedule,
+        precompiles::{is_precompile, verify_precompiles},
+        preflight_cache::PreflightCache,
+        program_registry::{ProgramHandler, ProgramRegistry},
+        svm::{BlockContext, ExecutionTrace, SvmEngine, SvmExecution},
+        transaction_record::{TokenBalance, TransactionRecord},
+        wire_transaction::WireTransaction,
+    },
+};
 
+/// What to do with instructions for programs that aren't registered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum UnknownProgramPolicy {
+    /// Fail the transaction with `ProgramNotSupported`
+    #[default]
+    Reject,
+    /// Execute on the SVM engine
+    Svm,
+}
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct L2Transaction {
-    pub signature: String,
-    pub from: Pubkey,
-    pub to: Option<Pubkey>,
-    pub lamports: u64,
-    pub instruction_data: Vec<u8>,
-    pub nonce: u64
+/// A transaction that was recorded as processed, so it belongs in its block
+/// whether or not it executed: one that fails after paying its fee keeps
+/// the fee charged and nothing else.
+#[derive(Debug)]
+pub struct Processed {
+    pub signature: Signature,
+    /// Why execution failed, if it did
+    pub error: Option<ExecutionError>,
+}
+
+/// Outcome of a simulated transaction; nothing of it is written to state.
+pub struct Simulation {
+    pub result: anyhow::Result<()>,
+    /// Empty when the transaction failed before reaching the SVM
+    pub trace: ExecutionTrace,
+    /// Accounts the transaction would change, as it would leave them, fee
+    /// and surcharge included. Those left with no lamports would be closed
+    pub accounts: Vec<(Pubkey, L2Account)>,
+}
+
+impl Simulation {
+    /// `pubkey` as the transaction would leave it: None if it wouldn't exist.
+    pub async fn account(&self, state_manager: &StateManager, pubkey: &Pubkey) -> Option<L2Account> {
+        match self.accounts.iter().find(|(key, _)| key == pubkey) {
+            Some((_, account)) => (account.lamports > 0).then(|| account.clone()),
+            None => state_manager.get_account(pubkey).await,
+        }
+    }
 }
 
 pub struct TransactionProcessor {
     state_manager: Arc<StateManager>,
-    nonce_tracker: Arc<RwLock<HashMap<Pubkey, u64>>>
+    in_flight: Mutex<HashSet<Signature>>,
+    access_policy: AccessPolicy,
+    dust_policy: DustPolicy,
+    fees: FeeSchedule,
+    unknown_programs: UnknownProgramPolicy,
+    programs: Arc<ProgramRegistry>,
+    svm: SvmEngine,
+    preflight_cache: Option<PreflightCache>,
+    clock: Arc<dyn Clock>
+}
+
+impl fmt::Debug for TransactionProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionProcessor")
+            .field("dust_policy", &self.dust_policy)
+            .field("fees", &self.fees)
+            .field("unknown_programs", &self.unknown_programs)
+            .finish_non_exhaustive()
+    }
 }
 
 impl TransactionProcessor {
-    pub fn new(state_manager: Arc<StateManager>) -> Self {
+    pub fn new(
+        state_manager: Arc<StateManager>,
+        access_policy: AccessPolicy,
+        unknown_programs: UnknownProgramPolicy
+    ) -> Self {
         Self {
+            svm: SvmEngine::new(state_manager.clone()),
             state_manager,
-            nonce_tracker: Arc::new(RwLock::new(HashMap::new()))
+            in_flight: Mutex::new(HashSet::new()),
+            access_policy,
+            dust_policy: DustPolicy::default(),
+            fees: FeeSchedule::default(),
+            unknown_programs,
+            programs: Arc::new(ProgramRegistry::default()),
+            preflight_cache: None,
+            clock: Arc::new(SystemClock)
         }
     }
 
-    pub async fn process_transaction(&self, tx: &Transaction) -> anyhow::Result<String> {
-        // validate tx
-        self.validate_transaction(tx).await?;
+    /// Remembers passed signature, access and precompile checks for `ttl`,
+    /// so a simulate followed by a send only verifies the transaction once.
+    pub fn with_preflight_cache(mut self, ttl: Duration) -> Self {
+        self.preflight_cache = Some(PreflightCache::new(ttl));
+        self
+    }
+
+    /// Expires remembered preflights by `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Dispatches instructions through `programs` instead of the default
+    /// registry of the system and memo programs.
+    pub fn with_programs(mut self, programs: Arc<ProgramRegistry>) -> Self {
+        self.programs = programs;
+        self
+    }
+
+    /// Rejects or surcharges transfers below the dust threshold.
+    pub fn with_dust_policy(mut self, dust_policy: DustPolicy) -> Self {
+        self.dust_policy = dust_policy;
+        self
+    }
+
+    /// Charges every transaction a fee per `fees`.
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    pub fn fees(&self) -> &FeeSchedule {
+        &self.fees
+    }
+
+    pub fn programs(&self) -> &Arc<ProgramRegistry> {
+        &self.programs
+    }
+
+    /// Runs every check process_transaction would and executes the
+    /// transaction on the SVM, recording its logs and compute units, without
+    /// changing state.
+    pub async fn simulate(&self, tx: &Transaction) -> Simulation {
+        let mut trace = ExecutionTrace::default();
+        let mut accounts = Vec::new();
+        let result = self.simulate_traced(tx, &mut trace, &mut accounts).await;
+
+        Simulation { result, trace, accounts }
+    }
+
+    async fn simulate_traced(
+        &self,
+        tx: &Transaction,
+        trace: &mut ExecutionTrace,
+        accounts: &mut Vec<(Pubkey, L2Account)>
+    ) -> anyhow::Result<()> {
+        self.verify_transaction(tx)?;
+        self.simulate_verified(tx, trace, accounts).await
+    }
 
-        // convert to l2
-        let l2_tx = self.convert_to_l2_transaction(tx)?;
+    /// Simulates a transaction that already passed `verify_transaction`
+    /// against current state before it waits for its block, which is where
+    /// it executes. Returns its signature.
+    pub async fn preflight(&self, tx: &Transaction) -> anyhow::Result<String> {
+        self.simulate_verified(tx, &mut ExecutionTrace::default(), &mut Vec::new()).await?;
+        Ok(first_signature(tx)?.to_string())
+    }
 
-        // exec tx
-        self.execute_l2_transaction(&l2_tx).await?;
-        Ok(l2_tx.signature)
+    async fn simulate_verified(
+        &self,
+        tx: &Transaction,
+        trace: &mut ExecutionTrace,
+        accounts: &mut Vec<(Pubkey, L2Account)>
+    ) -> anyhow::Result<()> {
+        let signature = first_signature(tx)?;
+        self.check_not_processed(&signature)?;
+        let (sanitized, block) = self.prepare(tx)?;
+        self.check_fee_payer(tx).await?;
+        let (execution, executed) = self.svm.simulate(&sanitized, &block);
+        *trace = executed;
+        let mut execution = execution.map_err(|error| ExecutionError::from_transaction_error(tx, error))?;
+        self.charge_fee(tx, &mut execution).await?;
+        self.charge_dust_surcharge(tx, &mut execution).await?;
+        *accounts = execution.accounts;
+
+        Ok(())
     }
 
-    async fn validate_transaction(&self, tx: &Transaction) -> anyhow::Result<()> {
-        if !tx.verify().is_ok() {
-            anyhow::bail!("Invalid transaction signatures");
+    pub async fn process_transaction(&self, tx: &Transaction) -> anyhow::Result<Processed> {
+        self.verify_transaction(tx)?;
+        self.process_verified_transaction(tx).await
+    }
+
+    /// Executes a transaction that already passed `verify_transaction`.
+    /// Fails, changing nothing, if it can't be processed at all; once its
+    /// fee is paid it is processed even if execution fails.
+    pub async fn process_verified_transaction(&self, tx: &Transaction) -> anyhow::Result<Processed> {
+        let signature = first_signature(tx)?;
+
+        // Claim the signature so two concurrent submissions of the same
+        // transaction can't both pass the duplicate check. The loser is a
+        // replay like any other, whichever way the winner ends up.
+        if !self.in_flight.lock().unwrap().insert(signature) {
+            return Err(ExecutionError::AlreadyProcessed.into());
         }
+        let result = self.process_claimed_transaction(tx, &signature).await;
+        self.in_flight.lock().unwrap().remove(&signature);
 
-        // check nonce
-        if let Some(fee_payer) = tx.message.account_keys.get(0) {
-            let current_nonce = self.nonce_tracker
-                .read()
-                .unwrap()
-                .get(fee_payer)
-                .copied()
-                .unwrap_or(0);
+        result
+    }
 
-            // TODO: extract nonce from transaction
-            // For now, just increment
+    async fn process_claimed_transaction(&self, tx: &Transaction, signature: &Signature) -> anyhow::Result<Processed> {
+        self.check_not_processed(signature)?;
+        let (sanitized, block) = self.prepare(tx)?;
+
+        let (pre_balances, pre_token_balances) = self.balances(tx).await;
+        let fee = self.collect_fee(tx).await?;
+        let executed = async {
+            let mut execution = self.execute(tx, &sanitized, &block)?;
+            let surcharge = self.charge_dust_surcharge(tx, &mut execution).await?;
+            Ok::<_, anyhow::Error>((execution, surcharge))
+        }.await;
+        let (surcharge, error) = match executed {
+            Ok((execution, surcharge)) => {
+                self.svm.commit(execution).await?;
+                (surcharge, None)
+            }
+            // With no fee paid there is nothing to keep, so it isn't processed
+            Err(e) if fee == 0 => return Err(e),
+            Err(e) => match e.downcast::<ExecutionError>() {
+                Ok(error) => (0, Some(error)),
+                Err(e) => return Err(e),
+            },
+        };
+
+        let (post_balances, post_token_balances) = self.balances(tx).await;
+        if fee > 0 {
+            metrics::add("fees_charged_lamports", fee);
+        }
+        if surcharge > 0 {
+            metrics::add("dust_surcharge_lamports", surcharge);
+        }
+        let record = TransactionRecord {
+            transaction: tx.clone(),
+            fee: fee.saturating_add(surcharge),
+            pre_balances,
+            post_balances,
+            pre_token_balances,
+            post_token_balances,
+            sealed: None,
+            error: error.as_ref().map(ExecutionError::transaction_error),
+        };
+        self.state_manager.record_processed_transaction(signature, &fee_payer(tx)?, &record)?;
+        if let Some(cache) = &self.preflight_cache {
+            cache.remove(signature);
         }
 
-        Ok(())
+        Ok(Processed { signature: *signature, error })
     }
 
-    fn convert_to_l2_transaction(&self, tx: &Transaction) -> anyhow::Result<L2Transaction> {
-        let signature = tx.signatures.get(0)
-            .ok_or_else(|| anyhow::anyhow!("No signature found"))?;
-
-        let fee_payer = tx.message.account_keys.get(0)
-            .ok_or_else(||anyhow::anyhow!("No fee payer found"))?;
-
-        // Handle different instruction types
-        if let Some(instruction) = tx.message.instructions.get(0) {
-            if instruction.program_id_index == 0 { // System program
-                let instruction_data = &instruction.data;
-
-                // decode system instruction
-                if instruction_data.len() >=4 {
-                    let instruction_type = u32::from_le_bytes([
-                        instruction_data[0],instruction_data[1],instruction_data[2],instruction_data[3]
-                    ]);
-
-                    match instruction_type {
-                        2 => { // Transfer
-                            let lamports = u64::from_le_bytes([
-                                instruction_data[4], instruction_data[5],
-                                instruction_data[6], instruction_data[7],
-                                instruction_data[8], instruction_data[9],
-                                instruction_data[10], instruction_data[11]
-                            ]);
-
-                            let to_pubkey = if instruction.accounts.len() > 1 {
-                                Some(*tx.message.account_keys.get(instruction.accounts[1] as usize).unwrap())
-                            } else {
-                                None
-                            };
-
-                            return Ok(L2Transaction {
-                                signature: signature.to_string(),
-                                from: *fee_payer,
-                                to: to_pubkey,
-                                lamports,
-                                instruction_data: instruction_data.to_vec(),
-                                nonce: 0 // TODO: increment
-                            })
-                        }
-                         _ => {}
-                    }
+    /// Checks that only depend on the transaction itself (signatures, access
+    /// policy, precompiles), so they can run in parallel ahead of execution.
+    /// Passed checks are remembered when a preflight cache is configured.
+    pub fn verify_transaction(&self, tx: &Transaction) -> anyhow::Result<()> {
+        let signature = first_signature(tx)?;
+        match &self.preflight_cache {
+            Some(cache) => {
+                let message = tx.message_data();
+                let now = self.clock.now();
+                if !cache.contains(&signature, &message, now) {
+                    self.check_transaction(tx)?;
+                    cache.insert(signature, message, now);
                 }
             }
+            None => self.check_transaction(tx)?,
         }
 
-        // Default
-        Ok(L2Transaction {
-            signature: signature.to_string(),
-            from: *fee_payer,
-            to: None,
-            lamports: 0,
-            instruction_data: vec![],
-            nonce: 0
+        Ok(())
+    }
+
+    /// Runs `verify_transaction` on every transaction of a batch in one pass
+    /// spread across the available cores, ahead of executing any of them.
+    /// Returns the positions of the transactions that fail, in order.
+    pub fn verify_batch(&self, transactions: &[WireTransaction]) -> Vec<usize> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_len = transactions.len().div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            let chunks: Vec<_> = transactions.chunks(chunk_len)
+                .enumerate()
+                .map(|(chunk, txs)| scope.spawn(move || {
+                    txs.iter()
+                        .enumerate()
+                        .filter(|(_, tx)| self.verify_transaction(tx).is_err())
+                        .map(|(index, _)| chunk * chunk_len + index)
+                        .collect::<Vec<_>>()
+                }))
+                .collect();
+
+            chunks.into_iter()
+                .flat_map(|chunk| chunk.join().expect("verification threads don't panic"))
+                .collect()
         })
     }
 
-    async fn execute_l2_transaction(&self, tx: &L2Transaction) -> anyhow::Result<()> {
-        match tx.to {
-            Some(to_pubkey) => {
-                self.transfer_lamports(&tx.from, &to_pubkey, tx.lamports).await?;
-            },
-            None => {
-                // Handle other tx types
-                println!("Non-transfer transaction: {:?}", tx);
+    /// Lamports of every account the message references, in order, and the
+    /// balances of those that are token accounts.
+    async fn balances(&self, tx: &Transaction) -> (Vec<u64>, Vec<TokenBalance>) {
+        let mut balances = Vec::with_capacity(tx.message.account_keys.len());
+        let mut token_balances = Vec::new();
+        for (index, pubkey) in tx.message.account_keys.iter().enumerate() {
+            let account = self.state_manager.get_account(pubkey).await;
+            balances.push(account.as_ref().map_or(0, |a| a.lamports));
+
+            let Some(account) = account else {
+                continue;
+            };
+            if let Some(token_account) = self.programs.token_account(&account.owner, &account.data) {
+                let decimals = self.state_manager.get_account(&token_account.mint).await
+                    .and_then(|mint| self.programs.mint_decimals(&mint.owner, &mint.data))
+                    .unwrap_or(0);
+                token_balances.push(TokenBalance {
+                    account_index: index as u8,
+                    mint: token_account.mint,
+                    owner: token_account.owner,
+                    program_id: account.owner,
+                    amount: token_account.amount,
+                    decimals,
+                });
             }
         }
 
-        // update nonce
-        let mut nonces = self.nonce_tracker.write().unwrap();
-        let current_nonce = nonces.get(&tx.from).copied().unwrap_or(0);
-        nonces.insert(tx.from, current_nonce + 1);
+        (balances, token_balances)
+    }
+
+    /// Replay protection.
+    fn check_not_processed(&self, signature: &Signature) -> anyhow::Result<()> {
+        if self.state_manager.is_signature_processed(signature)? {
+            return Err(ExecutionError::AlreadyProcessed.into());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that only depend on the transaction itself.
+    fn check_transaction(&self, tx: &Transaction) -> anyhow::Result<()> {
+        // Everything after this may rely on the message's indexes being in bounds
+        tx.sanitize().map_err(|e| ExecutionError::SanitizeFailure { reason: e.to_string() })?;
+        if tx.verify().is_err() {
+            return Err(ExecutionError::SignatureFailure.into());
+        }
+
+        self.access_policy.check(tx)?;
+        verify_precompiles(tx)?;
+
+        Ok(())
+    }
+
+    /// Executes `tx`, prepared for the block being built, on the SVM,
+    /// recording what its programs used whether or not it succeeds.
+    fn execute(&self, tx: &Transaction, sanitized: &SanitizedTransaction, block: &BlockContext) -> anyhow::Result<SvmExecution> {
+        let (execution, trace) = self.svm.execute(sanitized, block);
+        self.state_manager.record_program_usage(&program_usage(tx, &trace, execution.as_ref().err()));
+
+        Ok(execution.map_err(|error| ExecutionError::from_transaction_error(tx, error))?)
+    }
+
+    /// Checks `tx` can run at all, and in the block being built (its
+    /// blockhash hasn't expired), and returns that block.
+    fn prepare(&self, tx: &Transaction) -> anyhow::Result<(SanitizedTransaction, BlockContext)> {
+        self.check_programs(tx)?;
+        self.check_dust(tx)?;
+        let sanitized = SanitizedTransaction::try_from_legacy_transaction(tx.clone(), &HashSet::new())
+            .map_err(|error| ExecutionError::from_transaction_error(tx, error))?;
+
+        let slot = self.state_manager.batch_count()?;
+        if !self.state_manager.is_recent_blockhash(&tx.message.recent_blockhash, slot)? {
+            return Err(ExecutionError::BlockhashNotFound.into());
+        }
+        let (blockhash, unix_timestamp) = match self.state_manager.latest_block()? {
+            Some(parent) => (parent.blockhash, parent.block_time),
+            None => (Hash::new_from_array(self.state_manager.genesis_hash()?.unwrap_or_default()), 0),
+        };
+
+        Ok((sanitized, BlockContext { slot, blockhash, unix_timestamp, seed: block_seed(slot, &blockhash) }))
+    }
+
+    fn check_dust(&self, tx: &Transaction) -> anyhow::Result<()> {
+        if self.dust_policy.action != DustAction::Reject {
+            return Ok(());
+        }
+        if let Some(dust) = self.dust_policy.dust_transfers(tx).first() {
+            return Err(ExecutionError::DustTransfer {
+                instruction_index: dust.instruction_index,
+                lamports: dust.lamports,
+                minimum: self.dust_policy.min_transfer_lamports,
+            }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Fails with `InsufficientFundsForFee` unless the fee payer's balance
+    /// before execution covers the fee.
+    async fn check_fee_payer(&self, tx: &Transaction) -> anyhow::Result<()> {
+        let fee = self.fees.fee(&tx.message.header);
+        if fee == 0 {
+            return Ok(());
+        }
+
+        let lamports = self.state_manager.get_account(&fee_payer(tx)?).await.map_or(0, |account| account.lamports);
+        if lamports < fee {
+            return Err(ExecutionError::InsufficientFundsForFee { fee }.into());
+        }
 
         Ok(())
     }
 
-    async fn transfer_lamports(&self, from: &Pubkey, to: &Pubkey, amount: u64) -> anyhow::Result<()>{
-        let system_program_id = Pubkey::from_str_const("11111111111111111111111111111111");
+    /// Takes the transaction fee from the fee payer's balance before the
+    /// transaction executes and credits it to the fee collector, so it is
+    /// kept even if execution fails. Fails, charging nothing, if the fee
+    /// payer can't pay. Returns the lamports charged.
+    async fn collect_fee(&self, tx: &Transaction) -> anyhow::Result<u64> {
+        self.check_fee_payer(tx).await?;
+        let fee = self.fees.fee(&tx.message.header);
+        if fee == 0 {
+            return Ok(0);
+        }
+
+        let fee_payer = fee_payer(tx)?;
+        let mut changes = Vec::new();
+        if let Some(mut account) = self.state_manager.get_account(&fee_payer).await {
+            account.lamports -= fee;
+            changes.push((fee_payer, account));
+        }
+        if let Some(fee_collector) = self.fees.fee_collector {
+            let position = match changes.iter().position(|(key, _)| *key == fee_collector) {
+                Some(position) => position,
+                None => {
+                    let account = self.state_manager.get_account(&fee_collector).await.unwrap_or(L2Account {
+                        lamports: 0,
+                        data: vec![],
+                        owner: Pubkey::default(),
+                        executable: false,
+                        rent_epoch: 0,
+                    });
+                    changes.push((fee_collector, account));
+                    changes.len() - 1
+                }
+            };
+            changes[position].1.lamports = changes[position].1.lamports.saturating_add(fee);
+        }
+        let changes = changes.into_iter()
+            .map(|(pubkey, account)| (pubkey, (account.lamports > 0).then_some(account)))
+            .collect();
+        self.state_manager.commit_accounts(changes).await?;
+
+        Ok(fee)
+    }
+
+    /// Takes the transaction fee from the fee payer's balance as a simulated
+    /// execution leaves it and credits it to the fee collector, failing the
+    /// simulation if the fee payer can't pay. Returns the lamports charged.
+    async fn charge_fee(&self, tx: &Transaction, execution: &mut SvmExecution) -> anyhow::Result<u64> {
+        let fee = self.fees.fee(&tx.message.header);
+        if fee == 0 {
+            return Ok(0);
+        }
+
+        let fee_payer = fee_payer(tx)?;
+        let insufficient_funds = || ExecutionError::InsufficientFundsForFee { fee };
+        let account = self.execution_account(execution, fee_payer).await.ok_or_else(insufficient_funds)?;
+        account.lamports = account.lamports.checked_sub(fee).ok_or_else(insufficient_funds)?;
+
+        if let Some(fee_collector) = self.fees.fee_collector {
+            let collector = match self.execution_account(execution, fee_collector).await {
+                Some(account) => account,
+                None => {
+                    execution.accounts.push((fee_collector, L2Account {
+                        lamports: 0,
+                        data: vec![],
+                        owner: Pubkey::default(),
+                        executable: false,
+                        rent_epoch: 0,
+                    }));
+                    &mut execution.accounts.last_mut().unwrap().1
+                }
+            };
+            collector.lamports = collector.lamports.saturating_add(fee);
+        }
+
+        Ok(fee)
+    }
+
+    /// `pubkey` as `execution` leaves it, taken from state into the execution
+    /// if the transaction didn't change it. None if the account doesn't exist.
+    async fn execution_account<'a>(&self, execution: &'a mut SvmExecution, pubkey: Pubkey) -> Option<&'a mut L2Account> {
+        match execution.accounts.iter().position(|(key, _)| *key == pubkey) {
+            Some(position) => Some(&mut execution.accounts[position].1),
+            None => {
+                let account = self.state_manager.get_account(&pubkey).await?;
+                execution.accounts.push((pubkey, account));
+                Some(&mut execution.accounts.last_mut()?.1)
+            }
+        }
+    }
 
-        let mut from_account = self.state_manager.get_account(from).await
-            .unwrap_or_else(|| L2Account {
-                lamports: 0,
-                data: vec![],
-                owner: system_program_id,
-                executable: false,
-                rent_epoch: 0
-            });
+    /// Takes the dust surcharge for every account `tx` creates with a dust
+    /// transfer from the fee payer's post-execution balance, failing the
+    /// transaction if it can't pay (its fee stays charged). Returns the
+    /// lamports charged.
+    async fn charge_dust_surcharge(&self, tx: &Transaction, execution: &mut SvmExecution) -> anyhow::Result<u64> {
+        if self.dust_policy.action != DustAction::Surcharge {
+            return Ok(0);
+        }
 
-        //  check sufficent balance
-        if from_account.lamports < amount {
-            anyhow::bail!("Insufficient funds");
+        let mut created = HashSet::new();
+        for dust in self.dust_policy.dust_transfers(tx) {
+            if self.state_manager.get_account(&dust.destination).await.is_none() {
+                created.insert(dust.destination);
+            }
+        }
+        let surcharge = self.dust_policy.surcharge_lamports.saturating_mul(created.len() as u64);
+        if surcharge == 0 {
+            return Ok(0);
         }
 
-        let mut to_account = self.state_manager.get_account(to).await
-            .unwrap_or_else(|| L2Account {
-                lamports: 0,
-                data: vec![],
-                owner: system_program_id,
-                executable: false,
-                rent_epoch: 0,
-            });
+        let fee_payer = fee_payer(tx)?;
+        let insufficient_funds = || ExecutionError::InsufficientFundsForSurcharge { surcharge };
+        let account = self.execution_account(execution, fee_payer).await.ok_or_else(insufficient_funds)?;
+        account.lamports = account.lamports.checked_sub(surcharge).ok_or_else(insufficient_funds)?;
 
-        // update balances
-        from_account.lamports -= amount;
-        to_account.lamports += amount;
+        Ok(surcharge)
+    }
 
-        // save state
-        self.state_manager.update_account(from, from_account).await?;
-        self.state_manager.update_account(to, to_account).await?;
+    /// Only registered programs run, unless unknown programs are routed to
+    /// the SVM too. Precompiles and ComputeBudget are always allowed.
+    fn check_programs(&self, tx: &Transaction) -> anyhow::Result<()> {
+        for (index, instruction) in tx.message.instructions.iter().enumerate() {
+            let Some(program_id) = tx.message.account_keys.get(instruction.program_id_index as usize).copied() else {
+                anyhow::bail!("Instruction {} references a missing program account", index);
+            };
+            if is_precompile(&program_id) || solana_compute_budget_interface::check_id(&program_id) {
+                continue;
+            }
+
+            match self.programs.handler(&program_id) {
+                Some(ProgramHandler::Memo) => self.svm.add_memo_program(program_id),
+                Some(ProgramHandler::SplToken) => self.svm.add_token_program(program_id),
+                Some(_) => {}
+                None if self.unknown_programs == UnknownProgramPolicy::Svm => {}
+                None => return Err(ExecutionError::ProgramNotSupported { instruction_index: index, program_id }.into()),
+            }
+        }
 
         Ok(())
     }
-    
+}
+
+fn first_signature(tx: &Transaction) -> anyhow::Result<Signature> {
+    tx.signatures.first()
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("No signature found"))
+}
+
+fn fee_payer(tx: &Transaction) -> anyhow::Result<Pubkey> {
+    tx.message.account_keys.first()
+        .copied()
+        .ok_or_else(|| ExecutionError::SanitizeFailure { reason: "no fee payer".to_string() }.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_system_interface::instruction as system_instruction;
+
+    use super::*;
+    use crate::sequencer::block::BLOCKHASH_VALIDITY_SLOTS;
+
+    fn processor(state_manager: &Arc<StateManager>) -> TransactionProcessor {
+        TransactionProcessor::new(state_manager.clone(), AccessPolicy::default(), UnknownProgramPolicy::Reject)
+    }
+
+    fn execution_error<T: fmt::Debug>(result: anyhow::Result<T>) -> ExecutionError {
+        result.unwrap_err().downcast::<ExecutionError>().unwrap()
+    }
+
+    #[tokio::test]
+    async fn refuses_a_failed_transaction_again_once_its_blockhash_expires() {
+        let state_manager = StateManager::temporary();
+        let processor = processor(&state_manager);
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        state_manager.fund(&payer.pubkey(), 1_000_000).await;
+
+        let genesis_hash = Hash::new_from_array(state_manager.genesis_hash().unwrap().unwrap());
+        let tx = StateManager::transfer(&payer, &recipient, 5_000_000, genesis_hash);
+        assert_eq!(execution_error(processor.process_transaction(&tx).await), ExecutionError::InsufficientFunds { instruction_index: 0 });
+
+        // Funded in time, it could still run; once its blockhash expires it can't
+        for _ in 0..=BLOCKHASH_VALIDITY_SLOTS {
+            state_manager.put_empty_block();
+        }
+        state_manager.fund(&payer.pubkey(), 10_000_000).await;
+        assert_eq!(execution_error(processor.process_transaction(&tx).await), ExecutionError::BlockhashNotFound);
+        assert!(state_manager.get_account(&recipient).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_transaction_that_fails_after_paying_its_fee_keeps_it_charged() {
+        let state_manager = StateManager::temporary();
+        let fee_collector = Pubkey::new_unique();
+        let processor = processor(&state_manager).with_fees(FeeSchedule {
+            base_fee_lamports: 5_000,
+            lamports_per_signature: 0,
+            fee_collector: Some(fee_collector),
+        });
+        let payer = Keypair::new();
+        let recipient = Pubkey::new_unique();
+        state_manager.fund(&payer.pubkey(), 1_000_000).await;
+
+        // The balance covers the fee, which leaves too little for the transfer
+        let genesis_hash = Hash::new_from_array(state_manager.genesis_hash().unwrap().unwrap());
+        let tx = StateManager::transfer(&payer, &recipient, 1_000_000, genesis_hash);
+        let processed = processor.process_transaction(&tx).await.unwrap();
+        assert_eq!(processed.error, Some(ExecutionError::InsufficientFunds { instruction_index: 0 }));
+        assert_eq!(state_manager.get_account(&payer.pubkey()).await.unwrap().lamports, 995_000);
+        assert_eq!(state_manager.get_account(&fee_collector).await.unwrap().lamports, 5_000);
+        assert!(state_manager.get_account(&recipient).await.is_none());
+
+        // It was processed, so it can't run again for free
+        let record = state_manager.get_transaction(&processed.signature).unwrap().unwrap();
+        assert_eq!((record.fee, record.error), (5_000, Some(ExecutionError::InsufficientFunds { instruction_index: 0 }.transaction_error())));
+        assert_eq!(execution_error(processor.process_transaction(&tx).await), ExecutionError::AlreadyProcessed);
+
+        // One whose fee payer can't pay isn't processed at all
+        let broke = Keypair::new();
+        state_manager.fund(&broke.pubkey(), 4_000).await;
+        let tx = StateManager::transfer(&broke, &recipient, 1_000, genesis_hash);
+        assert_eq!(execution_error(processor.process_transaction(&tx).await), ExecutionError::InsufficientFundsForFee { fee: 5_000 });
+        assert_eq!(state_manager.get_account(&broke.pubkey()).await.unwrap().lamports, 4_000);
+    }
+
+    #[tokio::test]
+    async fn only_executes_transactions_with_a_recent_blockhash() {
+        let state_manager = StateManager::temporary();
+        let processor = processor(&state_manager);
+        let payer = Keypair::new();
+        state_manager.fund(&payer.pubkey(), 10_000_000).await;
+
+        let garbage = StateManager::transfer(&payer, &Pubkey::new_unique(), 1_000_000, Hash::new_unique());
+        assert_eq!(execution_error(processor.process_transaction(&garbage).await), ExecutionError::BlockhashNotFound);
+
+        let issued = state_manager.put_empty_block();
+        let last_valid = StateManager::transfer(&payer, &Pubkey::new_unique(), 1_000_000, issued.blockhash);
+        let expired = StateManager::transfer(&payer, &Pubkey::new_unique(), 1_000_000, issued.blockhash);
+        while state_manager.batch_count().unwrap() < issued.slot + BLOCKHASH_VALIDITY_SLOTS {
+            state_manager.put_empty_block();
+        }
+        processor.process_transaction(&last_valid).await.unwrap();
+        assert_eq!(execution_error(processor.process_transaction(&last_valid).await), ExecutionError::AlreadyProcessed);
+
+        state_manager.put_empty_block();
+        assert_eq!(execution_error(processor.process_transaction(&expired).await), ExecutionError::BlockhashNotFound);
+
+        // Its signature is forgotten once it couldn't execute again anyway
+        let signature = first_signature(&last_valid).unwrap();
+        for _ in 0..BLOCKHASH_VALIDITY_SLOTS {
+            assert!(state_manager.is_signature_processed(&signature).unwrap());
+            state_manager.put_empty_block();
+        }
+        assert!(!state_manager.is_signature_processed(&signature).unwrap());
+        assert_eq!(execution_error(processor.process_transaction(&last_valid).await), ExecutionError::BlockhashNotFound);
+    }
+
+    #[tokio::test]
+    async fn creates_allocates_and_assigns_accounts() {
+        let state_manager = StateManager::temporary();
+        let processor = processor(&state_manager);
+        let payer = Keypair::new();
+        let created = Keypair::new();
+        let allocated = Keypair::new();
+        let owner = Pubkey::new_unique();
+        state_manager.fund(&payer.pubkey(), 10_000_000).await;
+
+        let instructions = [
+            system_instruction::create_account(&payer.pubkey(), &created.pubkey(), 2_000_000, 64, &owner),
+            system_instruction::transfer(&payer.pubkey(), &allocated.pubkey(), 2_000_000),
+            system_instruction::allocate(&allocated.pubkey(), 32),
+            system_instruction::assign(&allocated.pubkey(), &owner),
+        ];
+        let genesis_hash = Hash::new_from_array(state_manager.genesis_hash().unwrap().unwrap());
+        let tx = Transaction::new_signed_with_payer(
+            &instructions, Some(&payer.pubkey()), &[&payer, &created, &allocated], genesis_hash
+        );
+        processor.process_transaction(&tx).await.unwrap();
+
+        let created = state_manager.get_account(&created.pubkey()).await.unwrap();
+        assert_eq!((created.data.len(), created.owner), (64, owner));
+        let allocated = state_manager.get_account(&allocated.pubkey()).await.unwrap();
+        assert_eq!((allocated.data.len(), allocated.owner), (32, owner));
+    }
 }
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use solana_account_decoder_client_types::token::{real_number_string_trimmed, UiTokenAmount};
+use solana_sdk::{pubkey::Pubkey, transaction::{Transaction, TransactionError, VersionedTransaction}};
+use solana_transaction_status::{
+    ConfirmedTransactionWithStatusMeta, EncodedConfirmedTransactionWithStatusMeta, TransactionStatusMeta,
+    TransactionTokenBalance, TransactionWithStatusMeta, UiTransactionEncoding, VersionedTransactionWithStatusMeta,
+};
+
+/// The batch a transaction was sealed into: the rollup's slot and block.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SealedIn {
+    pub batch_id: u64,
+    /// Unix time the batch was sealed
+    pub block_time: i64,
+}
+
+/// How far a transaction has made it from execution to L1 finality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStage {
+    /// Executed, waiting in the mempool
+    Processed,
+    /// Sealed into a batch
+    Sequenced,
+    /// Its batch landed on L1
+    PostedToL1,
+    /// Its batch is finalized on L1
+    Finalized,
+}
+
+impl TransactionStage {
+    /// Solana's `confirmationStatus` for the stage.
+    pub fn confirmation_status(&self) -> &'static str {
+        match self {
+            TransactionStage::Processed => "processed",
+            TransactionStage::Sequenced | TransactionStage::PostedToL1 => "confirmed",
+            TransactionStage::Finalized => "finalized",
+        }
+    }
+}
+
+/// Balance of a token account the transaction referenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenBalance {
+    /// Position of the token account in the message's account keys
+    pub account_index: u8,
+    pub mint: Pubkey,
+    /// Wallet owning the token account
+    pub owner: Pubkey,
+    pub program_id: Pubkey,
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+impl TokenBalance {
+    fn to_transaction_token_balance(self) -> TransactionTokenBalance {
+        TransactionTokenBalance {
+            account_index: self.account_index,
+            mint: self.mint.to_string(),
+            ui_token_amount: ui_token_amount(self.amount, self.decimals),
+            owner: self.owner.to_string(),
+            program_id: self.program_id.to_string(),
+        }
+    }
+}
+
+/// A raw token amount as Solana's RPC renders it, also scaled by `decimals`.
+pub fn ui_token_amount(amount: u64, decimals: u8) -> UiTokenAmount {
+    let ui_amount_string = real_number_string_trimmed(amount, decimals);
+    UiTokenAmount {
+        ui_amount: ui_amount_string.parse().ok(),
+        decimals,
+        amount: amount.to_string(),
+        ui_amount_string,
+    }
+}
+
+/// One way a transaction changed an address's holdings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Activity {
+    /// Lamports received; `from` are the accounts that lost lamports
+    #[serde(rename_all = "camelCase")]
+    TransferIn { lamports: u64, from: Vec<String> },
+    /// Lamports sent, not counting the fee; `to` are the accounts that gained lamports
+    #[serde(rename_all = "camelCase")]
+    TransferOut { lamports: u64, to: Vec<String> },
+    /// Fee paid as the transaction's fee payer
+    #[serde(rename_all = "camelCase")]
+    Fee { lamports: u64 },
+    /// Tokens received into a token account the address owns
+    #[serde(rename_all = "camelCase")]
+    TokenIn { mint: String, token_account: String, amount: String, decimals: u8 },
+    /// Tokens sent from a token account the address owns
+    #[serde(rename_all = "camelCase")]
+    TokenOut { mint: String, token_account: String, amount: String, decimals: u8 },
+}
+
+/// A processed transaction, kept for `getTransaction` and address activity.
+/// Transactions that failed after paying their fee are recorded too, since
+/// they are sealed into their batch; those that failed before aren't.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub transaction: Transaction,
+    pub fee: u64,
+    /// Lamports of every account key before and after execution, in message order
+    pub pre_balances: Vec<u64>,
+    pub post_balances: Vec<u64>,
+    pub pre_token_balances: Vec<TokenBalance>,
+    pub post_token_balances: Vec<TokenBalance>,
+    /// `None` while the transaction is still in the mempool
+    pub sealed: Option<SealedIn>,
+    /// Why execution failed, leaving only the fee charged
+    pub error: Option<TransactionError>,
+}
+
+impl TransactionRecord {
+    /// Every address whose activity includes this transaction: the account
+    /// keys other than invoked programs, which would otherwise collect every
+    /// transaction, plus the wallets owning token accounts it touched.
+    pub fn addresses(&self) -> Vec<Pubkey> {
+        let message = &self.transaction.message;
+        let mut addresses: Vec<_> = message.account_keys.iter()
+            .enumerate()
+            .filter(|(index, _)| !message.instructions.iter().any(|ix| ix.program_id_index as usize == *index))
+            .map(|(_, key)| *key)
+            .collect();
+        addresses.extend(self.pre_token_balances.iter().chain(&self.post_token_balances).map(|b| b.owner));
+        addresses.sort();
+        addresses.dedup();
+        addresses
+    }
+
+    /// How this transaction changed the holdings of `address`.
+    pub fn activity(&self, address: &Pubkey) -> Vec<Activity> {
+        let keys = &self.transaction.message.account_keys;
+        let delta = |index: usize| {
+            self.post_balances.get(index).copied().unwrap_or(0) as i128
+                - self.pre_balances.get(index).copied().unwrap_or(0) as i128
+        };
+        let counterparties = |gained: bool| -> Vec<String> {
+            keys.iter().enumerate()
+                .filter(|(index, key)| *key != address && if gained { delta(*index) > 0 } else { delta(*index) < 0 })
+                .map(|(_, key)| key.to_string())
+                .collect()
+        };
+
+        let mut activity = Vec::new();
+        if let Some(index) = keys.iter().position(|key| key == address) {
+            let is_fee_payer = index == 0;
+            let fee = if is_fee_payer { self.fee as i128 } else { 0 };
+            // The fee is reported on its own, not as part of a transfer
+            let transferred = delta(index) + fee;
+            if transferred > 0 {
+                activity.push(Activity::TransferIn { lamports: transferred as u64, from: counterparties(false) });
+            } else if transferred < 0 {
+                activity.push(Activity::TransferOut { lamports: transferred.unsigned_abs() as u64, to: counterparties(true) });
+            }
+            if is_fee_payer && self.fee > 0 {
+                activity.push(Activity::Fee { lamports: self.fee });
+            }
+        }
+
+        let mut token_accounts: Vec<_> = self.pre_token_balances.iter()
+            .chain(&self.post_token_balances)
+            .filter(|balance| balance.owner == *address)
+            .map(|balance| (balance.account_index, balance.mint, balance.decimals))
+            .collect();
+        token_accounts.sort();
+        token_accounts.dedup();
+        for (account_index, mint, decimals) in token_accounts {
+            let amount = |balances: &[TokenBalance]| balances.iter()
+                .find(|balance| balance.account_index == account_index && balance.owner == *address)
+                .map_or(0, |balance| balance.amount as i128);
+            let delta = amount(&self.post_token_balances) - amount(&self.pre_token_balances);
+            let token_account = keys.get(account_index as usize).map(|key| key.to_string()).unwrap_or_default();
+            let mint = mint.to_string();
+            if delta > 0 {
+                activity.push(Activity::TokenIn { mint, token_account, amount: delta.to_string(), decimals });
+            } else if delta < 0 {
+                activity.push(Activity::TokenOut { mint, token_account, amount: delta.unsigned_abs().to_string(), decimals });
+            }
+        }
+
+        activity
+    }
+
+    /// The transaction with Solana's status metadata. No program the rollup
+    /// runs logs or invokes others, so those parts are always empty.
+    pub fn with_status_meta(&self) -> TransactionWithStatusMeta {
+        let token_balances = |balances: &[TokenBalance]| balances.iter()
+            .map(|balance| balance.to_transaction_token_balance())
+            .collect();
+        TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+            transaction: VersionedTransaction::from(self.transaction.clone()),
+            meta: TransactionStatusMeta {
+                status: self.error.clone().map_or(Ok(()), Err),
+                fee: self.fee,
+                pre_balances: self.pre_balances.clone(),
+                post_balances: self.post_balances.clone(),
+                inner_instructions: Some(vec![]),
+                log_messages: Some(vec![]),
+                pre_token_balances: Some(token_balances(&self.pre_token_balances)),
+                post_token_balances: Some(token_balances(&self.post_token_balances)),
+                rewards: Some(vec![]),
+                ..TransactionStatusMeta::default()
+            },
+        })
+    }
+
+    /// Solana's `getTransaction` response, or `None` before the transaction
+    /// is sealed.
+    pub fn encode(
+        &self,
+        encoding: UiTransactionEncoding,
+        max_supported_transaction_version: Option<u8>
+    ) -> anyhow::Result<Option<EncodedConfirmedTransactionWithStatusMeta>> {
+        let Some(sealed) = self.sealed else {
+            return Ok(None);
+        };
+
+        let confirmed = ConfirmedTransactionWithStatusMeta {
+            slot: sealed.batch_id,
+            tx_with_meta: self.with_status_meta(),
+            block_time: Some(sealed.block_time),
+        };
+
+        Ok(Some(confirmed.encode(encoding, max_supported_transaction_version)?))
+    }
+}
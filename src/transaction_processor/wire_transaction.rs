@@ -0,0 +1,63 @@
+use std::{ops::Deref, sync::Arc};
+
+use bincode::Options;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_packet::PACKET_DATA_SIZE;
+use solana_sdk::transaction::Transaction;
+
+/// A transaction together with the exact bytes it arrived as. The bytes are
+/// what gets sized, stored and posted to L1, so a transaction is decoded once
+/// at the RPC edge and never re-encoded on the way to a batch.
+#[derive(Debug, Clone)]
+pub struct WireTransaction {
+    tx: Transaction,
+    bytes: Arc<[u8]>,
+}
+
+impl WireTransaction {
+    /// Decodes `bytes`, rejecting trailing data so the kept bytes are always
+    /// the canonical bincode encoding of the transaction. Like L1, anything
+    /// larger than a packet is rejected before decoding.
+    pub fn from_bytes(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        if bytes.len() > PACKET_DATA_SIZE {
+            anyhow::bail!("Transaction is {} bytes, more than the {} byte limit", bytes.len(), PACKET_DATA_SIZE);
+        }
+        let tx = bincode::DefaultOptions::new()
+            .with_limit(PACKET_DATA_SIZE as u64)
+            .with_fixint_encoding()
+            .reject_trailing_bytes()
+            .deserialize(&bytes)?;
+
+        Ok(Self { tx, bytes: bytes.into() })
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.tx
+    }
+
+    pub fn wire_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Deref for WireTransaction {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.tx
+    }
+}
+
+/// Serialized as its wire bytes.
+impl Serialize for WireTransaction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for WireTransaction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Self::from_bytes(bytes).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+use crate::state_manager::state_hasher::{merkle_leaf, merkle_proof, merkle_root, StateHasher};
+
+/// Prefix of every signed withdrawal request, so the signature can't be
+/// passed off as one over a transaction or anything else
+const REQUEST_DOMAIN: &[u8] = b"tiny-rollup:withdrawal-request:v1";
+
+/// A request to release lamports on L1, committed to L1 as a leaf of the
+/// withdrawal tree of the batch it was sealed into.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawalRecord {
+    pub id: u64,
+    pub l2_sender: Pubkey,
+    pub l1_recipient: Pubkey,
+    pub lamports: u64,
+}
+
+impl WithdrawalRecord {
+    pub fn leaf(&self, hasher: &dyn StateHasher) -> anyhow::Result<[u8; 32]> {
+        Ok(merkle_leaf(hasher, &bincode::serialize(self)?))
+    }
+}
+
+/// What the L2 sender signs to withdraw. `nonce` is the sender's choice and
+/// only makes otherwise identical requests distinct; each signature is
+/// accepted once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalRequest {
+    pub l2_sender: Pubkey,
+    pub l1_recipient: Pubkey,
+    pub lamports: u64,
+    pub nonce: u64,
+}
+
+impl WithdrawalRequest {
+    /// The signed bytes: the domain, the chain's genesis hash, so a request
+    /// can't be replayed on another chain, and the bincode request.
+    pub fn message(&self, genesis_hash: &[u8; 32]) -> anyhow::Result<Vec<u8>> {
+        let mut message = REQUEST_DOMAIN.to_vec();
+        message.extend_from_slice(genesis_hash);
+        message.extend(bincode::serialize(self)?);
+        Ok(message)
+    }
+
+    pub fn verify(&self, genesis_hash: &[u8; 32], signature: &Signature) -> anyhow::Result<bool> {
+        Ok(signature.verify(self.l2_sender.as_ref(), &self.message(genesis_hash)?))
+    }
+}
+
+/// Where a withdrawal is: queued for the next block, sealed as leaf `index`
+/// of a batch's withdrawal tree with the lamports debited, or rejected at
+/// sealing without debiting anything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WithdrawalStatus {
+    Pending,
+    Sealed { batch_id: u64, index: usize },
+    Rejected { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalEntry {
+    pub record: WithdrawalRecord,
+    pub status: WithdrawalStatus,
+}
+
+/// Root of the per-batch withdrawal tree. Kept separate from the state root so
+/// claims on L1 only need a proof against this (much smaller) tree.
+pub fn withdrawal_root(hasher: &dyn StateHasher, withdrawals: &[WithdrawalRecord]) -> anyhow::Result<[u8; 32]> {
+    let leaves = withdrawals.iter()
+        .map(|w| w.leaf(hasher))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(merkle_root(hasher, leaves))
+}
+
+/// Siblings of leaf `index` from the bottom of the tree up, pairing the
+/// same way as `withdrawal_root`.
+pub fn withdrawal_proof(
+    hasher: &dyn StateHasher,
+    withdrawals: &[WithdrawalRecord],
+    index: usize
+) -> anyhow::Result<Vec<[u8; 32]>> {
+    let leaves = withdrawals.iter()
+        .map(|w| w.leaf(hasher))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    merkle_proof(hasher, leaves, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_manager::state_hasher::{verify_merkle_proof, Sha256Hasher};
+
+    fn records(count: u64) -> Vec<WithdrawalRecord> {
+        (0..count).map(|id| WithdrawalRecord {
+            id,
+            l2_sender: Pubkey::new_unique(),
+            l1_recipient: Pubkey::new_unique(),
+            lamports: 1_000 + id,
+        }).collect()
+    }
+
+    #[test]
+    fn proves_every_withdrawal_only_at_its_own_index() {
+        let hasher = Sha256Hasher;
+        for count in 1..=5 {
+            let withdrawals = records(count);
+            let root = withdrawal_root(&hasher, &withdrawals).unwrap();
+            for (index, withdrawal) in withdrawals.iter().enumerate() {
+                let leaf = withdrawal.leaf(&hasher).unwrap();
+                let proof = withdrawal_proof(&hasher, &withdrawals, index).unwrap();
+                assert!(verify_merkle_proof(&hasher, &root, &leaf, index, &proof));
+                // Not at the first index past the last leaf, nor one wrapping
+                // around to its own position
+                for other in [withdrawals.len(), index + (1 << proof.len())] {
+                    assert!(!verify_merkle_proof(&hasher, &root, &leaf, other, &proof));
+                }
+            }
+            assert!(withdrawal_proof(&hasher, &withdrawals, withdrawals.len()).is_err());
+        }
+    }
+
+    #[test]
+    fn repeating_the_last_withdrawal_changes_the_root() {
+        let hasher = Sha256Hasher;
+        let mut withdrawals = records(3);
+        let root = withdrawal_root(&hasher, &withdrawals).unwrap();
+        withdrawals.push(withdrawals[2].clone());
+
+        assert_ne!(withdrawal_root(&hasher, &withdrawals).unwrap(), root);
+    }
+}